@@ -0,0 +1,3 @@
+-- 删除库存流水表
+DROP INDEX IF EXISTS idx_stock_movements_stock;
+DROP TABLE IF EXISTS stock_movements;
@@ -0,0 +1,26 @@
+-- 库存流水表（只追加），warehouse_stock 为其投影
+CREATE TABLE stock_movements (
+    movement_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    warehouse_id INTEGER NOT NULL,
+    material_id INTEGER NOT NULL,
+    movement_type TEXT NOT NULL CHECK(movement_type IN ('receipt', 'issue', 'transfer_in', 'transfer_out', 'adjustment')),
+    quantity INTEGER NOT NULL,
+    request_id INTEGER,
+    task_id INTEGER,
+    performed_by INTEGER,
+    note TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (warehouse_id) REFERENCES warehouses(warehouse_id),
+    FOREIGN KEY (material_id) REFERENCES materials(material_id),
+    FOREIGN KEY (request_id) REFERENCES material_requests(request_id),
+    FOREIGN KEY (task_id) REFERENCES production_tasks(task_id),
+    FOREIGN KEY (performed_by) REFERENCES users(user_id)
+);
+
+CREATE INDEX idx_stock_movements_stock ON stock_movements (warehouse_id, material_id, created_at);
+
+-- 把现有库存作为期初余额写入流水，保证可以从流水重建
+INSERT INTO stock_movements (warehouse_id, material_id, movement_type, quantity, note, created_at)
+SELECT warehouse_id, material_id, 'adjustment', quantity, 'opening balance', COALESCE(last_updated, CURRENT_TIMESTAMP)
+FROM warehouse_stock
+WHERE warehouse_id IS NOT NULL AND material_id IS NOT NULL AND quantity IS NOT NULL AND quantity <> 0;
@@ -0,0 +1,37 @@
+DROP VIEW IF EXISTS material_request_summary;
+
+CREATE TABLE material_requests_old (
+    request_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    material_id INTEGER,
+    quantity INTEGER,
+    requested_by INTEGER,
+    warehouse_id INTEGER,
+    request_date TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    status TEXT CHECK(status IN ('pending', 'approved', 'rejected')) DEFAULT 'pending',
+    FOREIGN KEY (material_id) REFERENCES materials(material_id),
+    FOREIGN KEY (requested_by) REFERENCES users(user_id),
+    FOREIGN KEY (warehouse_id) REFERENCES warehouses(warehouse_id)
+);
+
+-- cancelled 在旧的约束中不存在，按 rejected 处理
+INSERT INTO material_requests_old (request_id, material_id, quantity, requested_by, warehouse_id, request_date, status)
+SELECT request_id, material_id, quantity, requested_by, warehouse_id, request_date,
+       CASE WHEN status = 'cancelled' THEN 'rejected' ELSE status END
+FROM material_requests;
+
+DROP TABLE material_requests;
+ALTER TABLE material_requests_old RENAME TO material_requests;
+
+CREATE VIEW material_request_summary AS
+SELECT 
+    r.request_id,
+    m.material_name,
+    r.quantity,
+    r.request_date,
+    u.full_name AS requested_by,
+    w.warehouse_name
+FROM 
+    material_requests r
+JOIN materials m ON r.material_id = m.material_id
+JOIN users u ON r.requested_by = u.user_id
+JOIN warehouses w ON r.warehouse_id = w.warehouse_id;
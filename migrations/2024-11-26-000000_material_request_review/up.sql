@@ -0,0 +1,42 @@
+-- 材料领用审批：增加 cancelled 状态、审批人、审批时间和驳回原因
+-- SQLite 不能修改 CHECK 约束，需要重建表
+DROP VIEW IF EXISTS material_request_summary;
+
+CREATE TABLE material_requests_new (
+    request_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    material_id INTEGER,
+    quantity INTEGER,
+    requested_by INTEGER,
+    warehouse_id INTEGER,
+    request_date TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    status TEXT CHECK(status IN ('pending', 'approved', 'rejected', 'cancelled')) DEFAULT 'pending',
+    reviewed_by INTEGER,
+    reviewed_at TIMESTAMP,
+    rejection_reason TEXT,
+    FOREIGN KEY (material_id) REFERENCES materials(material_id),
+    FOREIGN KEY (requested_by) REFERENCES users(user_id),
+    FOREIGN KEY (warehouse_id) REFERENCES warehouses(warehouse_id),
+    FOREIGN KEY (reviewed_by) REFERENCES users(user_id)
+);
+
+INSERT INTO material_requests_new (request_id, material_id, quantity, requested_by, warehouse_id, request_date, status)
+SELECT request_id, material_id, quantity, requested_by, warehouse_id, request_date, status
+FROM material_requests;
+
+DROP TABLE material_requests;
+ALTER TABLE material_requests_new RENAME TO material_requests;
+
+-- 报表数据生成视图 (例如：材料领用记录汇总)
+CREATE VIEW material_request_summary AS
+SELECT 
+    r.request_id,
+    m.material_name,
+    r.quantity,
+    r.request_date,
+    u.full_name AS requested_by,
+    w.warehouse_name
+FROM 
+    material_requests r
+JOIN materials m ON r.material_id = m.material_id
+JOIN users u ON r.requested_by = u.user_id
+JOIN warehouses w ON r.warehouse_id = w.warehouse_id;
@@ -0,0 +1,3 @@
+-- 删除刷新令牌表
+DROP INDEX IF EXISTS idx_refresh_tokens_user;
+DROP TABLE IF EXISTS refresh_tokens;
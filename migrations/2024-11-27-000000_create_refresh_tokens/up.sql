@@ -0,0 +1,14 @@
+-- 刷新令牌表，只保存令牌的 SHA-256 摘要
+CREATE TABLE refresh_tokens (
+    token_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    expires_at TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP,
+    replaced_by INTEGER,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(user_id),
+    FOREIGN KEY (replaced_by) REFERENCES refresh_tokens(token_id)
+);
+
+CREATE INDEX idx_refresh_tokens_user ON refresh_tokens (user_id);
@@ -0,0 +1,19 @@
+DELETE FROM user_roles WHERE role_id IN (SELECT role_id FROM roles WHERE role_name = 'admin');
+DELETE FROM role_permissions WHERE role_id IN (SELECT role_id FROM roles WHERE role_name = 'admin');
+DELETE FROM roles WHERE role_name = 'admin';
+DELETE FROM permissions WHERE permission_name IN (
+    'warehouse.read', 'warehouse.write',
+    'stock.read', 'stock.write',
+    'user.read', 'user.write',
+    'role.read', 'role.write',
+    'permission.read', 'permission.write',
+    'user_role.read', 'user_role.write',
+    'role_permission.read', 'role_permission.write',
+    'operation_log.read', 'operation_log.write',
+    'production_task.read', 'production_task.write',
+    'production_cost.read', 'production_cost.write',
+    'price_formula.read', 'price_formula.write',
+    'product_specification.read', 'product_specification.write',
+    'material.read', 'material.write',
+    'material_request.read', 'material_request.write', 'material_request.approve'
+);
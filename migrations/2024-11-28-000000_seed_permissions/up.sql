@@ -0,0 +1,45 @@
+-- 权限目录：每个路由模块的读写权限，名称与 auth_guard.rs 中的标记类型一致
+INSERT OR IGNORE INTO permissions (permission_name, description) VALUES
+    ('warehouse.read', 'View warehouses'),
+    ('warehouse.write', 'Create, update and delete warehouses'),
+    ('stock.read', 'View warehouse stock and stock movements'),
+    ('stock.write', 'Receive, issue, adjust, transfer and rebuild stock'),
+    ('user.read', 'View users'),
+    ('user.write', 'Create, update and delete users'),
+    ('role.read', 'View roles'),
+    ('role.write', 'Create, update and delete roles'),
+    ('permission.read', 'View permissions'),
+    ('permission.write', 'Create, update and delete permissions'),
+    ('user_role.read', 'View user role assignments'),
+    ('user_role.write', 'Assign and remove user roles'),
+    ('role_permission.read', 'View role permission assignments'),
+    ('role_permission.write', 'Grant and revoke role permissions'),
+    ('operation_log.read', 'View operation logs'),
+    ('operation_log.write', 'Write operation logs'),
+    ('production_task.read', 'View production tasks'),
+    ('production_task.write', 'Create and update production tasks'),
+    ('production_cost.read', 'View production costs'),
+    ('production_cost.write', 'Create, update and delete production costs'),
+    ('price_formula.read', 'View price formulas'),
+    ('price_formula.write', 'Create, update and delete price formulas'),
+    ('product_specification.read', 'View product specifications'),
+    ('product_specification.write', 'Create, update and delete product specifications'),
+    ('material.read', 'View materials'),
+    ('material.write', 'Create, update and delete materials'),
+    ('material_request.read', 'View material requests'),
+    ('material_request.write', 'Create and cancel material requests'),
+    ('material_request.approve', 'Approve and reject material requests');
+
+-- 管理员角色拥有全部权限
+INSERT OR IGNORE INTO roles (role_name, description) VALUES ('admin', 'System administrator');
+
+INSERT OR IGNORE INTO role_permissions (role_id, permission_id)
+SELECT r.role_id, p.permission_id
+FROM roles r, permissions p
+WHERE r.role_name = 'admin';
+
+-- 已存在的 admin 用户授予管理员角色
+INSERT OR IGNORE INTO user_roles (user_id, role_id)
+SELECT u.user_id, r.role_id
+FROM users u, roles r
+WHERE u.username = 'admin' AND r.role_name = 'admin';
@@ -0,0 +1,9 @@
+DROP INDEX IF EXISTS idx_materials_global_id;
+DROP INDEX IF EXISTS idx_product_specifications_global_id;
+DROP INDEX IF EXISTS idx_price_formulas_global_id;
+DROP INDEX IF EXISTS idx_production_costs_global_id;
+
+ALTER TABLE materials DROP COLUMN global_id;
+ALTER TABLE product_specifications DROP COLUMN global_id;
+ALTER TABLE price_formulas DROP COLUMN global_id;
+ALTER TABLE production_costs DROP COLUMN global_id;
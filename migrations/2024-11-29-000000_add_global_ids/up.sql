@@ -0,0 +1,16 @@
+-- 复制的主数据使用全局唯一 ID，避免各节点 AUTOINCREMENT 主键冲突
+ALTER TABLE materials ADD COLUMN global_id TEXT;
+ALTER TABLE product_specifications ADD COLUMN global_id TEXT;
+ALTER TABLE price_formulas ADD COLUMN global_id TEXT;
+ALTER TABLE production_costs ADD COLUMN global_id TEXT;
+
+-- 为已有记录生成 ID（与 Uuid::simple 相同的 32 位十六进制格式）
+UPDATE materials SET global_id = lower(hex(randomblob(16))) WHERE global_id IS NULL;
+UPDATE product_specifications SET global_id = lower(hex(randomblob(16))) WHERE global_id IS NULL;
+UPDATE price_formulas SET global_id = lower(hex(randomblob(16))) WHERE global_id IS NULL;
+UPDATE production_costs SET global_id = lower(hex(randomblob(16))) WHERE global_id IS NULL;
+
+CREATE UNIQUE INDEX idx_materials_global_id ON materials (global_id);
+CREATE UNIQUE INDEX idx_product_specifications_global_id ON product_specifications (global_id);
+CREATE UNIQUE INDEX idx_price_formulas_global_id ON price_formulas (global_id);
+CREATE UNIQUE INDEX idx_production_costs_global_id ON production_costs (global_id);
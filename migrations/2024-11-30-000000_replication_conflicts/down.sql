@@ -0,0 +1,12 @@
+DELETE FROM role_permissions WHERE permission_id IN (
+    SELECT permission_id FROM permissions WHERE permission_name IN ('replication.read', 'replication.write')
+);
+DELETE FROM permissions WHERE permission_name IN ('replication.read', 'replication.write');
+
+DROP INDEX IF EXISTS idx_replication_conflicts_record;
+DROP TABLE IF EXISTS replication_conflicts;
+
+ALTER TABLE materials DROP COLUMN hlc;
+ALTER TABLE product_specifications DROP COLUMN hlc;
+ALTER TABLE price_formulas DROP COLUMN hlc;
+ALTER TABLE production_costs DROP COLUMN hlc;
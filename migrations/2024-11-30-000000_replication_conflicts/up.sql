@@ -0,0 +1,33 @@
+-- 每行最后一次修改的混合逻辑时钟，用于跨节点合并
+ALTER TABLE materials ADD COLUMN hlc TEXT;
+ALTER TABLE product_specifications ADD COLUMN hlc TEXT;
+ALTER TABLE price_formulas ADD COLUMN hlc TEXT;
+ALTER TABLE production_costs ADD COLUMN hlc TEXT;
+
+-- 自动解决的并发修改，供管理员复核
+CREATE TABLE replication_conflicts (
+    conflict_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    table_name TEXT NOT NULL,
+    global_id TEXT NOT NULL,
+    local_hlc TEXT,
+    remote_hlc TEXT NOT NULL,
+    local_data TEXT,
+    remote_data TEXT,
+    policy TEXT NOT NULL,
+    winner TEXT NOT NULL CHECK(winner IN ('local', 'remote')),
+    resolved_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    reviewed_by INTEGER,
+    reviewed_at TIMESTAMP,
+    FOREIGN KEY (reviewed_by) REFERENCES users(user_id)
+);
+
+CREATE INDEX idx_replication_conflicts_record ON replication_conflicts (table_name, global_id);
+
+INSERT OR IGNORE INTO permissions (permission_name, description) VALUES
+    ('replication.read', 'View replication conflicts'),
+    ('replication.write', 'Review replication conflicts');
+
+INSERT OR IGNORE INTO role_permissions (role_id, permission_id)
+SELECT r.role_id, p.permission_id
+FROM roles r, permissions p
+WHERE r.role_name = 'admin' AND p.permission_name IN ('replication.read', 'replication.write');
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_stock_transfers_pending;
+DROP TABLE IF EXISTS stock_transfers;
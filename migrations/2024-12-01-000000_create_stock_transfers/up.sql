@@ -0,0 +1,24 @@
+-- 节点间调拨单，两端各保存一行，transfer_id 相同。
+-- acked 为 0 表示当前状态尚未得到对端确认，网络任务会重试发送。
+CREATE TABLE stock_transfers (
+    transfer_id TEXT PRIMARY KEY NOT NULL,
+    direction TEXT NOT NULL CHECK(direction IN ('outbound', 'inbound')),
+    peer_id TEXT NOT NULL,
+    warehouse_id INTEGER NOT NULL,
+    material_id INTEGER NOT NULL,
+    quantity INTEGER NOT NULL CHECK(quantity > 0),
+    status TEXT NOT NULL CHECK(status IN ('requested', 'accepted', 'rejected', 'in_transit', 'received', 'cancelled')),
+    acked BOOLEAN NOT NULL DEFAULT 0,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_attempt_at TIMESTAMP,
+    rejection_reason TEXT,
+    note TEXT,
+    requested_by INTEGER,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (warehouse_id) REFERENCES warehouses(warehouse_id),
+    FOREIGN KEY (material_id) REFERENCES materials(material_id),
+    FOREIGN KEY (requested_by) REFERENCES users(user_id)
+);
+
+CREATE INDEX idx_stock_transfers_pending ON stock_transfers (acked, status);
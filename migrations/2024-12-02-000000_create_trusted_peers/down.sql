@@ -0,0 +1,6 @@
+DELETE FROM role_permissions WHERE permission_id IN (
+    SELECT permission_id FROM permissions WHERE permission_name IN ('network.read', 'network.write')
+);
+DELETE FROM permissions WHERE permission_name IN ('network.read', 'network.write');
+
+DROP TABLE IF EXISTS trusted_peers;
@@ -0,0 +1,17 @@
+-- 允许交换网络消息的节点
+CREATE TABLE trusted_peers (
+    peer_id TEXT PRIMARY KEY NOT NULL,
+    label TEXT,
+    added_by INTEGER,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (added_by) REFERENCES users(user_id)
+);
+
+INSERT OR IGNORE INTO permissions (permission_name, description) VALUES
+    ('network.read', 'View network peers'),
+    ('network.write', 'Manage network peers');
+
+INSERT OR IGNORE INTO role_permissions (role_id, permission_id)
+SELECT r.role_id, p.permission_id
+FROM roles r, permissions p
+WHERE r.role_name = 'admin' AND p.permission_name IN ('network.read', 'network.write');
@@ -0,0 +1,4 @@
+DROP TABLE IF EXISTS inbox;
+DROP TABLE IF EXISTS outbox_acks;
+DROP INDEX IF EXISTS idx_outbox_status;
+DROP TABLE IF EXISTS outbox;
@@ -0,0 +1,30 @@
+-- 待发送的网络消息，所有受信任节点确认后才算送达
+CREATE TABLE outbox (
+    message_id TEXT PRIMARY KEY NOT NULL,
+    topic TEXT NOT NULL,
+    message_type TEXT NOT NULL,
+    content TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK(status IN ('pending', 'delivered', 'failed')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_attempt_at TIMESTAMP,
+    delivered_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_outbox_status ON outbox (status, created_at);
+
+-- 各节点对 outbox 消息的确认
+CREATE TABLE outbox_acks (
+    message_id TEXT NOT NULL,
+    peer_id TEXT NOT NULL,
+    acked_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (message_id, peer_id),
+    FOREIGN KEY (message_id) REFERENCES outbox(message_id) ON DELETE CASCADE
+);
+
+-- 已处理的远端消息，重发时只回复确认不重复处理
+CREATE TABLE inbox (
+    message_id TEXT PRIMARY KEY NOT NULL,
+    sender TEXT NOT NULL,
+    received_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
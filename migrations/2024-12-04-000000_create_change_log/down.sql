@@ -0,0 +1,16 @@
+DROP TRIGGER IF EXISTS materials_change_insert;
+DROP TRIGGER IF EXISTS materials_change_update;
+DROP TRIGGER IF EXISTS materials_change_delete;
+DROP TRIGGER IF EXISTS product_specifications_change_insert;
+DROP TRIGGER IF EXISTS product_specifications_change_update;
+DROP TRIGGER IF EXISTS product_specifications_change_delete;
+DROP TRIGGER IF EXISTS price_formulas_change_insert;
+DROP TRIGGER IF EXISTS price_formulas_change_update;
+DROP TRIGGER IF EXISTS price_formulas_change_delete;
+DROP TRIGGER IF EXISTS production_costs_change_insert;
+DROP TRIGGER IF EXISTS production_costs_change_update;
+DROP TRIGGER IF EXISTS production_costs_change_delete;
+
+DROP TABLE IF EXISTS sync_state;
+DROP INDEX IF EXISTS idx_change_log_record;
+DROP TABLE IF EXISTS change_log;
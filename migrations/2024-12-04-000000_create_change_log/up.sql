@@ -0,0 +1,100 @@
+-- 主数据的变更序列，供新节点按序号增量追赶
+CREATE TABLE change_log (
+    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+    table_name TEXT NOT NULL,
+    global_id TEXT NOT NULL,
+    hlc TEXT,
+    deleted BOOLEAN NOT NULL DEFAULT 0,
+    changed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_change_log_record ON change_log (table_name, global_id);
+
+-- 从各节点追赶到的序号
+CREATE TABLE sync_state (
+    peer_id TEXT PRIMARY KEY NOT NULL,
+    last_seq INTEGER NOT NULL DEFAULT 0,
+    synced_at TIMESTAMP
+);
+
+-- 已有记录作为初始变更
+INSERT INTO change_log (table_name, global_id, hlc)
+SELECT 'materials', global_id, hlc FROM materials WHERE global_id IS NOT NULL;
+INSERT INTO change_log (table_name, global_id, hlc)
+SELECT 'product_specifications', global_id, hlc FROM product_specifications WHERE global_id IS NOT NULL;
+INSERT INTO change_log (table_name, global_id, hlc)
+SELECT 'price_formulas', global_id, hlc FROM price_formulas WHERE global_id IS NOT NULL;
+INSERT INTO change_log (table_name, global_id, hlc)
+SELECT 'production_costs', global_id, hlc FROM production_costs WHERE global_id IS NOT NULL;
+
+CREATE TRIGGER materials_change_insert AFTER INSERT ON materials
+WHEN NEW.global_id IS NOT NULL
+BEGIN
+    INSERT INTO change_log (table_name, global_id, hlc) VALUES ('materials', NEW.global_id, NEW.hlc);
+END;
+
+CREATE TRIGGER materials_change_update AFTER UPDATE ON materials
+WHEN NEW.global_id IS NOT NULL
+BEGIN
+    INSERT INTO change_log (table_name, global_id, hlc) VALUES ('materials', NEW.global_id, NEW.hlc);
+END;
+
+CREATE TRIGGER materials_change_delete AFTER DELETE ON materials
+WHEN OLD.global_id IS NOT NULL
+BEGIN
+    INSERT INTO change_log (table_name, global_id, hlc, deleted) VALUES ('materials', OLD.global_id, OLD.hlc, 1);
+END;
+
+CREATE TRIGGER product_specifications_change_insert AFTER INSERT ON product_specifications
+WHEN NEW.global_id IS NOT NULL
+BEGIN
+    INSERT INTO change_log (table_name, global_id, hlc) VALUES ('product_specifications', NEW.global_id, NEW.hlc);
+END;
+
+CREATE TRIGGER product_specifications_change_update AFTER UPDATE ON product_specifications
+WHEN NEW.global_id IS NOT NULL
+BEGIN
+    INSERT INTO change_log (table_name, global_id, hlc) VALUES ('product_specifications', NEW.global_id, NEW.hlc);
+END;
+
+CREATE TRIGGER product_specifications_change_delete AFTER DELETE ON product_specifications
+WHEN OLD.global_id IS NOT NULL
+BEGIN
+    INSERT INTO change_log (table_name, global_id, hlc, deleted) VALUES ('product_specifications', OLD.global_id, OLD.hlc, 1);
+END;
+
+CREATE TRIGGER price_formulas_change_insert AFTER INSERT ON price_formulas
+WHEN NEW.global_id IS NOT NULL
+BEGIN
+    INSERT INTO change_log (table_name, global_id, hlc) VALUES ('price_formulas', NEW.global_id, NEW.hlc);
+END;
+
+CREATE TRIGGER price_formulas_change_update AFTER UPDATE ON price_formulas
+WHEN NEW.global_id IS NOT NULL
+BEGIN
+    INSERT INTO change_log (table_name, global_id, hlc) VALUES ('price_formulas', NEW.global_id, NEW.hlc);
+END;
+
+CREATE TRIGGER price_formulas_change_delete AFTER DELETE ON price_formulas
+WHEN OLD.global_id IS NOT NULL
+BEGIN
+    INSERT INTO change_log (table_name, global_id, hlc, deleted) VALUES ('price_formulas', OLD.global_id, OLD.hlc, 1);
+END;
+
+CREATE TRIGGER production_costs_change_insert AFTER INSERT ON production_costs
+WHEN NEW.global_id IS NOT NULL
+BEGIN
+    INSERT INTO change_log (table_name, global_id, hlc) VALUES ('production_costs', NEW.global_id, NEW.hlc);
+END;
+
+CREATE TRIGGER production_costs_change_update AFTER UPDATE ON production_costs
+WHEN NEW.global_id IS NOT NULL
+BEGIN
+    INSERT INTO change_log (table_name, global_id, hlc) VALUES ('production_costs', NEW.global_id, NEW.hlc);
+END;
+
+CREATE TRIGGER production_costs_change_delete AFTER DELETE ON production_costs
+WHEN OLD.global_id IS NOT NULL
+BEGIN
+    INSERT INTO change_log (table_name, global_id, hlc, deleted) VALUES ('production_costs', OLD.global_id, OLD.hlc, 1);
+END;
@@ -0,0 +1,221 @@
+-- 恢复不带 ON DELETE 行为的外键
+PRAGMA foreign_keys = OFF;
+BEGIN;
+
+DROP VIEW IF EXISTS material_request_summary;
+
+CREATE TABLE warehouse_stock_new (
+    warehouse_id INTEGER,
+    material_id INTEGER,
+    quantity INTEGER,
+    last_updated TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (warehouse_id) REFERENCES warehouses(warehouse_id),
+    FOREIGN KEY (material_id) REFERENCES materials(material_id),
+    PRIMARY KEY (warehouse_id, material_id)
+);
+INSERT INTO warehouse_stock_new SELECT warehouse_id, material_id, quantity, last_updated FROM warehouse_stock;
+DROP TABLE warehouse_stock;
+ALTER TABLE warehouse_stock_new RENAME TO warehouse_stock;
+
+CREATE TABLE user_roles_new (
+    user_id INTEGER,
+    role_id INTEGER,
+    FOREIGN KEY (user_id) REFERENCES users(user_id),
+    FOREIGN KEY (role_id) REFERENCES roles(role_id),
+    PRIMARY KEY (user_id, role_id)
+);
+INSERT INTO user_roles_new SELECT user_id, role_id FROM user_roles
+WHERE user_id IN (SELECT user_id FROM users) AND role_id IN (SELECT role_id FROM roles);
+DROP TABLE user_roles;
+ALTER TABLE user_roles_new RENAME TO user_roles;
+
+CREATE TABLE role_permissions_new (
+    role_id INTEGER,
+    permission_id INTEGER,
+    FOREIGN KEY (role_id) REFERENCES roles(role_id),
+    FOREIGN KEY (permission_id) REFERENCES permissions(permission_id),
+    PRIMARY KEY (role_id, permission_id)
+);
+INSERT INTO role_permissions_new SELECT role_id, permission_id FROM role_permissions
+WHERE role_id IN (SELECT role_id FROM roles) AND permission_id IN (SELECT permission_id FROM permissions);
+DROP TABLE role_permissions;
+ALTER TABLE role_permissions_new RENAME TO role_permissions;
+
+CREATE TABLE production_tasks_new (
+    task_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    product_id INTEGER,
+    quantity INTEGER NOT NULL,
+    due_date DATE,
+    created_by INTEGER,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    status TEXT CHECK(status IN ('not_started', 'in_progress', 'completed')) DEFAULT 'not_started',
+    FOREIGN KEY (product_id) REFERENCES product_specifications(product_id),
+    FOREIGN KEY (created_by) REFERENCES users(user_id)
+);
+INSERT INTO production_tasks_new (task_id, product_id, quantity, due_date, created_by, created_at, status)
+SELECT task_id, product_id, quantity, due_date,
+       CASE WHEN created_by IN (SELECT user_id FROM users) THEN created_by END,
+       created_at, status
+FROM production_tasks;
+DROP TABLE production_tasks;
+ALTER TABLE production_tasks_new RENAME TO production_tasks;
+
+CREATE TABLE material_requests_new (
+    request_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    material_id INTEGER,
+    quantity INTEGER,
+    requested_by INTEGER,
+    warehouse_id INTEGER,
+    request_date TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    status TEXT CHECK(status IN ('pending', 'approved', 'rejected', 'cancelled')) DEFAULT 'pending',
+    reviewed_by INTEGER,
+    reviewed_at TIMESTAMP,
+    rejection_reason TEXT,
+    FOREIGN KEY (material_id) REFERENCES materials(material_id),
+    FOREIGN KEY (requested_by) REFERENCES users(user_id),
+    FOREIGN KEY (warehouse_id) REFERENCES warehouses(warehouse_id),
+    FOREIGN KEY (reviewed_by) REFERENCES users(user_id)
+);
+INSERT INTO material_requests_new (request_id, material_id, quantity, requested_by, warehouse_id, request_date, status, reviewed_by, reviewed_at, rejection_reason)
+SELECT request_id, material_id, quantity, requested_by, warehouse_id, request_date, status,
+       CASE WHEN reviewed_by IN (SELECT user_id FROM users) THEN reviewed_by END,
+       reviewed_at, rejection_reason
+FROM material_requests;
+DROP TABLE material_requests;
+ALTER TABLE material_requests_new RENAME TO material_requests;
+
+CREATE TABLE stock_movements_new (
+    movement_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    warehouse_id INTEGER NOT NULL,
+    material_id INTEGER NOT NULL,
+    movement_type TEXT NOT NULL CHECK(movement_type IN ('receipt', 'issue', 'transfer_in', 'transfer_out', 'adjustment')),
+    quantity INTEGER NOT NULL,
+    request_id INTEGER,
+    task_id INTEGER,
+    performed_by INTEGER,
+    note TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (warehouse_id) REFERENCES warehouses(warehouse_id),
+    FOREIGN KEY (material_id) REFERENCES materials(material_id),
+    FOREIGN KEY (request_id) REFERENCES material_requests(request_id),
+    FOREIGN KEY (task_id) REFERENCES production_tasks(task_id),
+    FOREIGN KEY (performed_by) REFERENCES users(user_id)
+);
+INSERT INTO stock_movements_new (movement_id, warehouse_id, material_id, movement_type, quantity, request_id, task_id, performed_by, note, created_at)
+SELECT movement_id, warehouse_id, material_id, movement_type, quantity, request_id, task_id, performed_by, note, created_at
+FROM stock_movements;
+DROP TABLE stock_movements;
+ALTER TABLE stock_movements_new RENAME TO stock_movements;
+CREATE INDEX idx_stock_movements_stock ON stock_movements (warehouse_id, material_id, created_at);
+
+CREATE TABLE refresh_tokens_new (
+    token_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    expires_at TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP,
+    replaced_by INTEGER,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(user_id),
+    FOREIGN KEY (replaced_by) REFERENCES refresh_tokens(token_id)
+);
+INSERT INTO refresh_tokens_new (token_id, user_id, token_hash, expires_at, revoked_at, replaced_by, created_at)
+SELECT token_id, user_id, token_hash, expires_at, revoked_at,
+       CASE WHEN replaced_by IN (SELECT token_id FROM refresh_tokens) THEN replaced_by END,
+       created_at
+FROM refresh_tokens
+WHERE user_id IN (SELECT user_id FROM users);
+DROP TABLE refresh_tokens;
+ALTER TABLE refresh_tokens_new RENAME TO refresh_tokens;
+CREATE INDEX idx_refresh_tokens_user ON refresh_tokens (user_id);
+
+CREATE TABLE replication_conflicts_new (
+    conflict_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    table_name TEXT NOT NULL,
+    global_id TEXT NOT NULL,
+    local_hlc TEXT,
+    remote_hlc TEXT NOT NULL,
+    local_data TEXT,
+    remote_data TEXT,
+    policy TEXT NOT NULL,
+    winner TEXT NOT NULL CHECK(winner IN ('local', 'remote')),
+    resolved_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    reviewed_by INTEGER,
+    reviewed_at TIMESTAMP,
+    FOREIGN KEY (reviewed_by) REFERENCES users(user_id)
+);
+INSERT INTO replication_conflicts_new (conflict_id, table_name, global_id, local_hlc, remote_hlc, local_data, remote_data, policy, winner, resolved_at, reviewed_by, reviewed_at)
+SELECT conflict_id, table_name, global_id, local_hlc, remote_hlc, local_data, remote_data, policy, winner, resolved_at,
+       CASE WHEN reviewed_by IN (SELECT user_id FROM users) THEN reviewed_by END,
+       reviewed_at
+FROM replication_conflicts;
+DROP TABLE replication_conflicts;
+ALTER TABLE replication_conflicts_new RENAME TO replication_conflicts;
+CREATE INDEX idx_replication_conflicts_record ON replication_conflicts (table_name, global_id);
+
+CREATE TABLE stock_transfers_new (
+    transfer_id TEXT PRIMARY KEY NOT NULL,
+    direction TEXT NOT NULL CHECK(direction IN ('outbound', 'inbound')),
+    peer_id TEXT NOT NULL,
+    warehouse_id INTEGER NOT NULL,
+    material_id INTEGER NOT NULL,
+    quantity INTEGER NOT NULL CHECK(quantity > 0),
+    status TEXT NOT NULL CHECK(status IN ('requested', 'accepted', 'rejected', 'in_transit', 'received', 'cancelled')),
+    acked BOOLEAN NOT NULL DEFAULT 0,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_attempt_at TIMESTAMP,
+    rejection_reason TEXT,
+    note TEXT,
+    requested_by INTEGER,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (warehouse_id) REFERENCES warehouses(warehouse_id),
+    FOREIGN KEY (material_id) REFERENCES materials(material_id),
+    FOREIGN KEY (requested_by) REFERENCES users(user_id)
+);
+INSERT INTO stock_transfers_new (transfer_id, direction, peer_id, warehouse_id, material_id, quantity, status, acked, attempts, last_attempt_at, rejection_reason, note, requested_by, created_at, updated_at)
+SELECT transfer_id, direction, peer_id, warehouse_id, material_id, quantity, status, acked, attempts, last_attempt_at, rejection_reason, note,
+       CASE WHEN requested_by IN (SELECT user_id FROM users) THEN requested_by END,
+       created_at, updated_at
+FROM stock_transfers;
+DROP TABLE stock_transfers;
+ALTER TABLE stock_transfers_new RENAME TO stock_transfers;
+CREATE INDEX idx_stock_transfers_pending ON stock_transfers (acked, status);
+
+CREATE TABLE trusted_peers_new (
+    peer_id TEXT PRIMARY KEY NOT NULL,
+    label TEXT,
+    added_by INTEGER,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (added_by) REFERENCES users(user_id)
+);
+INSERT INTO trusted_peers_new (peer_id, label, added_by, created_at)
+SELECT peer_id, label,
+       CASE WHEN added_by IN (SELECT user_id FROM users) THEN added_by END,
+       created_at
+FROM trusted_peers;
+DROP TABLE trusted_peers;
+ALTER TABLE trusted_peers_new RENAME TO trusted_peers;
+
+-- 报表数据生成视图 (例如：材料领用记录汇总)
+CREATE VIEW material_request_summary AS
+SELECT 
+    r.request_id,
+    m.material_name,
+    r.quantity,
+    r.request_date,
+    u.full_name AS requested_by,
+    w.warehouse_name
+FROM 
+    material_requests r
+JOIN materials m ON r.material_id = m.material_id
+JOIN users u ON r.requested_by = u.user_id
+JOIN warehouses w ON r.warehouse_id = w.warehouse_id;
+
+-- 提交前确认没有违反外键的数据，有则中止
+CREATE TEMP TABLE foreign_key_violations (violations INTEGER CHECK (violations = 0));
+INSERT INTO foreign_key_violations SELECT count(*) FROM pragma_foreign_key_check;
+DROP TABLE foreign_key_violations;
+
+COMMIT;
+PRAGMA foreign_keys = ON;
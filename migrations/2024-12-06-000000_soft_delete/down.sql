@@ -0,0 +1,167 @@
+-- deleted_by 带外键，SQLite 不能 DROP COLUMN，需要重建表；重建会删除表上的触发器和索引，随后重新创建
+PRAGMA foreign_keys = OFF;
+BEGIN;
+
+-- 回滚时已软删除的记录直接物理删除，仍被引用的保留
+DELETE FROM materials WHERE deleted_at IS NOT NULL
+    AND material_id NOT IN (SELECT material_id FROM warehouse_stock WHERE material_id IS NOT NULL)
+    AND material_id NOT IN (SELECT material_id FROM material_requests WHERE material_id IS NOT NULL)
+    AND material_id NOT IN (SELECT material_id FROM stock_movements)
+    AND material_id NOT IN (SELECT material_id FROM stock_transfers);
+DELETE FROM product_specifications WHERE deleted_at IS NOT NULL
+    AND product_id NOT IN (SELECT product_id FROM production_tasks WHERE product_id IS NOT NULL);
+DELETE FROM price_formulas WHERE deleted_at IS NOT NULL;
+DELETE FROM warehouses WHERE deleted_at IS NOT NULL
+    AND warehouse_id NOT IN (SELECT warehouse_id FROM warehouse_stock WHERE warehouse_id IS NOT NULL)
+    AND warehouse_id NOT IN (SELECT warehouse_id FROM material_requests WHERE warehouse_id IS NOT NULL)
+    AND warehouse_id NOT IN (SELECT warehouse_id FROM stock_movements)
+    AND warehouse_id NOT IN (SELECT warehouse_id FROM stock_transfers);
+
+
+DROP VIEW IF EXISTS material_request_summary;
+
+CREATE TABLE materials_new (
+    material_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    material_name TEXT NOT NULL,
+    category TEXT,
+    type TEXT,
+    supplier TEXT,
+    created_by INTEGER,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP, global_id TEXT, hlc TEXT,
+    FOREIGN KEY (created_by) REFERENCES users(user_id)
+);
+INSERT INTO materials_new (material_id, material_name, category, type, supplier, created_by, created_at, global_id, hlc)
+SELECT material_id, material_name, category, type, supplier, created_by, created_at, global_id, hlc FROM materials;
+DROP TABLE materials;
+ALTER TABLE materials_new RENAME TO materials;
+CREATE UNIQUE INDEX idx_materials_global_id ON materials (global_id);
+
+CREATE TABLE product_specifications_new (
+    product_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    product_name TEXT NOT NULL,
+    model TEXT,
+    material_type TEXT,
+    color TEXT,
+    dimensions TEXT,
+    created_by INTEGER,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP, global_id TEXT, hlc TEXT,
+    FOREIGN KEY (created_by) REFERENCES users(user_id)
+);
+INSERT INTO product_specifications_new (product_id, product_name, model, material_type, color, dimensions, created_by, created_at, global_id, hlc)
+SELECT product_id, product_name, model, material_type, color, dimensions, created_by, created_at, global_id, hlc FROM product_specifications;
+DROP TABLE product_specifications;
+ALTER TABLE product_specifications_new RENAME TO product_specifications;
+CREATE UNIQUE INDEX idx_product_specifications_global_id ON product_specifications (global_id);
+
+CREATE TABLE price_formulas_new (
+    formula_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    formula_name TEXT,
+    base_material_cost DOUBLE PRECISION,
+    additional_material_cost DOUBLE PRECISION,
+    galvanization_cost DOUBLE PRECISION,
+    labor_cost DOUBLE PRECISION,
+    management_fee DOUBLE PRECISION,
+    sales_fee DOUBLE PRECISION,
+    manufacturing_fee DOUBLE PRECISION,
+    vat DOUBLE PRECISION,
+    profit DOUBLE PRECISION,
+    created_by INTEGER,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP, global_id TEXT, hlc TEXT,
+    FOREIGN KEY (created_by) REFERENCES users(user_id)
+);
+INSERT INTO price_formulas_new (formula_id, formula_name, base_material_cost, additional_material_cost, galvanization_cost, labor_cost, management_fee, sales_fee, manufacturing_fee, vat, profit, created_by, created_at, global_id, hlc)
+SELECT formula_id, formula_name, base_material_cost, additional_material_cost, galvanization_cost, labor_cost, management_fee, sales_fee, manufacturing_fee, vat, profit, created_by, created_at, global_id, hlc FROM price_formulas;
+DROP TABLE price_formulas;
+ALTER TABLE price_formulas_new RENAME TO price_formulas;
+CREATE UNIQUE INDEX idx_price_formulas_global_id ON price_formulas (global_id);
+
+CREATE TABLE warehouses_new (
+    warehouse_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    localkey TEXT DEFAULT NULL,
+    warehouse_name TEXT NOT NULL,
+    location TEXT NOT NULL,
+    capacity INTEGER,
+    current_stock INTEGER DEFAULT 0,
+    last_updated TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+INSERT INTO warehouses_new (warehouse_id, localkey, warehouse_name, location, capacity, current_stock, last_updated)
+SELECT warehouse_id, localkey, warehouse_name, location, capacity, current_stock, last_updated FROM warehouses;
+DROP TABLE warehouses;
+ALTER TABLE warehouses_new RENAME TO warehouses;
+
+CREATE TRIGGER materials_change_insert AFTER INSERT ON materials
+WHEN NEW.global_id IS NOT NULL
+BEGIN
+    INSERT INTO change_log (table_name, global_id, hlc) VALUES ('materials', NEW.global_id, NEW.hlc);
+END;
+
+CREATE TRIGGER materials_change_update AFTER UPDATE ON materials
+WHEN NEW.global_id IS NOT NULL
+BEGIN
+    INSERT INTO change_log (table_name, global_id, hlc) VALUES ('materials', NEW.global_id, NEW.hlc);
+END;
+
+CREATE TRIGGER materials_change_delete AFTER DELETE ON materials
+WHEN OLD.global_id IS NOT NULL
+BEGIN
+    INSERT INTO change_log (table_name, global_id, hlc, deleted) VALUES ('materials', OLD.global_id, OLD.hlc, 1);
+END;
+
+CREATE TRIGGER product_specifications_change_insert AFTER INSERT ON product_specifications
+WHEN NEW.global_id IS NOT NULL
+BEGIN
+    INSERT INTO change_log (table_name, global_id, hlc) VALUES ('product_specifications', NEW.global_id, NEW.hlc);
+END;
+
+CREATE TRIGGER product_specifications_change_update AFTER UPDATE ON product_specifications
+WHEN NEW.global_id IS NOT NULL
+BEGIN
+    INSERT INTO change_log (table_name, global_id, hlc) VALUES ('product_specifications', NEW.global_id, NEW.hlc);
+END;
+
+CREATE TRIGGER product_specifications_change_delete AFTER DELETE ON product_specifications
+WHEN OLD.global_id IS NOT NULL
+BEGIN
+    INSERT INTO change_log (table_name, global_id, hlc, deleted) VALUES ('product_specifications', OLD.global_id, OLD.hlc, 1);
+END;
+
+CREATE TRIGGER price_formulas_change_insert AFTER INSERT ON price_formulas
+WHEN NEW.global_id IS NOT NULL
+BEGIN
+    INSERT INTO change_log (table_name, global_id, hlc) VALUES ('price_formulas', NEW.global_id, NEW.hlc);
+END;
+
+CREATE TRIGGER price_formulas_change_update AFTER UPDATE ON price_formulas
+WHEN NEW.global_id IS NOT NULL
+BEGIN
+    INSERT INTO change_log (table_name, global_id, hlc) VALUES ('price_formulas', NEW.global_id, NEW.hlc);
+END;
+
+CREATE TRIGGER price_formulas_change_delete AFTER DELETE ON price_formulas
+WHEN OLD.global_id IS NOT NULL
+BEGIN
+    INSERT INTO change_log (table_name, global_id, hlc, deleted) VALUES ('price_formulas', OLD.global_id, OLD.hlc, 1);
+END;
+
+-- 报表数据生成视图 (例如：材料领用记录汇总)
+CREATE VIEW material_request_summary AS
+SELECT 
+    r.request_id,
+    m.material_name,
+    r.quantity,
+    r.request_date,
+    u.full_name AS requested_by,
+    w.warehouse_name
+FROM 
+    material_requests r
+JOIN materials m ON r.material_id = m.material_id
+JOIN users u ON r.requested_by = u.user_id
+JOIN warehouses w ON r.warehouse_id = w.warehouse_id;
+
+-- 提交前确认没有违反外键的数据，有则中止
+CREATE TEMP TABLE foreign_key_violations (violations INTEGER CHECK (violations = 0));
+INSERT INTO foreign_key_violations SELECT count(*) FROM pragma_foreign_key_check;
+DROP TABLE foreign_key_violations;
+
+COMMIT;
+PRAGMA foreign_keys = ON;
@@ -0,0 +1,10 @@
+-- 目录数据和仓库改为软删除：旧的领料单、生产任务仍引用这些记录。
+-- 超过保留期且不再被引用的记录由定时任务物理删除
+ALTER TABLE materials ADD COLUMN deleted_at TIMESTAMP;
+ALTER TABLE materials ADD COLUMN deleted_by INTEGER REFERENCES users(user_id) ON DELETE SET NULL;
+ALTER TABLE product_specifications ADD COLUMN deleted_at TIMESTAMP;
+ALTER TABLE product_specifications ADD COLUMN deleted_by INTEGER REFERENCES users(user_id) ON DELETE SET NULL;
+ALTER TABLE price_formulas ADD COLUMN deleted_at TIMESTAMP;
+ALTER TABLE price_formulas ADD COLUMN deleted_by INTEGER REFERENCES users(user_id) ON DELETE SET NULL;
+ALTER TABLE warehouses ADD COLUMN deleted_at TIMESTAMP;
+ALTER TABLE warehouses ADD COLUMN deleted_by INTEGER REFERENCES users(user_id) ON DELETE SET NULL;
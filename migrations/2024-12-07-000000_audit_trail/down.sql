@@ -0,0 +1,17 @@
+INSERT OR IGNORE INTO permissions (permission_name, description) VALUES
+    ('operation_log.write', 'Write operation logs');
+
+INSERT OR IGNORE INTO role_permissions (role_id, permission_id)
+SELECT r.role_id, p.permission_id
+FROM roles r, permissions p
+WHERE r.role_name = 'admin' AND p.permission_name = 'operation_log.write';
+
+DROP INDEX IF EXISTS idx_operation_logs_entity;
+
+ALTER TABLE operation_logs DROP COLUMN method;
+ALTER TABLE operation_logs DROP COLUMN path;
+ALTER TABLE operation_logs DROP COLUMN entity_type;
+ALTER TABLE operation_logs DROP COLUMN entity_id;
+ALTER TABLE operation_logs DROP COLUMN status;
+ALTER TABLE operation_logs DROP COLUMN changes;
+ALTER TABLE operation_logs DROP COLUMN client_ip;
@@ -0,0 +1,17 @@
+-- 操作日志改由服务端的审计 fairing 写入：每个修改请求一行，
+-- 记录令牌中的用户、实体、HTTP 方法、状态码、前后差异和客户端 IP
+ALTER TABLE operation_logs ADD COLUMN method TEXT;
+ALTER TABLE operation_logs ADD COLUMN path TEXT;
+ALTER TABLE operation_logs ADD COLUMN entity_type TEXT;
+ALTER TABLE operation_logs ADD COLUMN entity_id TEXT;
+ALTER TABLE operation_logs ADD COLUMN status INTEGER;
+ALTER TABLE operation_logs ADD COLUMN changes TEXT;
+ALTER TABLE operation_logs ADD COLUMN client_ip TEXT;
+
+CREATE INDEX idx_operation_logs_entity ON operation_logs (entity_type, entity_id);
+
+-- 客户端不能再写操作日志
+DELETE FROM role_permissions WHERE permission_id IN (
+    SELECT permission_id FROM permissions WHERE permission_name = 'operation_log.write'
+);
+DELETE FROM permissions WHERE permission_name = 'operation_log.write';
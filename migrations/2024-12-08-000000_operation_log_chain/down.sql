@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS idx_operation_log_signatures_log;
+DROP TABLE IF EXISTS operation_log_signatures;
+
+ALTER TABLE operation_logs DROP COLUMN hash;
+ALTER TABLE operation_logs DROP COLUMN prev_hash;
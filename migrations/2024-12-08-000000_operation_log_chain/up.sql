@@ -0,0 +1,17 @@
+-- 操作日志哈希链：hash = sha256(prev_hash + 本行内容)，第一行的 prev_hash 为 64 个 0。
+-- 旧日志在第一次写入新日志时按 log_id 顺序补上哈希
+ALTER TABLE operation_logs ADD COLUMN prev_hash TEXT;
+ALTER TABLE operation_logs ADD COLUMN hash TEXT;
+
+-- 定期用节点的 ed25519 密钥对链头签名，签名内容为 "log_id:hash"
+CREATE TABLE operation_log_signatures (
+    signature_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    log_id INTEGER NOT NULL,
+    hash TEXT NOT NULL,
+    public_key TEXT NOT NULL,
+    signature TEXT NOT NULL,
+    signed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (log_id) REFERENCES operation_logs(log_id) ON DELETE RESTRICT
+);
+
+CREATE INDEX idx_operation_log_signatures_log ON operation_log_signatures (log_id);
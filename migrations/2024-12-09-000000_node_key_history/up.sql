@@ -0,0 +1,6 @@
+-- 本节点轮换前用过的公钥（base64）。操作日志签名只认当前密钥和这里的旧密钥，
+-- 不认签名行自带的公钥，轮换密钥后旧签名仍可校验
+CREATE TABLE node_key_history (
+    public_key TEXT PRIMARY KEY NOT NULL,
+    retired_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
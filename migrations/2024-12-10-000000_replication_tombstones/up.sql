@@ -0,0 +1,10 @@
+-- 复制记录的删除墓碑，每条记录只保留最新的删除时钟。
+-- 物理删除或清理后本地不再有该记录，旧的更新到达时据此判断不再插入；
+-- 本地从未有过的记录收到删除时也写入墓碑，之后到达的旧更新同样忽略
+CREATE TABLE replication_tombstones (
+    table_name TEXT NOT NULL,
+    global_id TEXT NOT NULL,
+    hlc TEXT NOT NULL,
+    deleted_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (table_name, global_id)
+);
@@ -6,9 +6,8 @@ use log::info;
 use rand::distributions::Alphanumeric;
 use rand::Rng;
 
-use bcrypt;
 
-use crate::schema::users;
+use crate::schema::{roles, user_roles, users};
 use crate::models::{DbConn, NewUser};
 
 pub struct AdminInit;
@@ -56,10 +55,30 @@ impl Fairing for AdminInit {
                     .execute(c)
                     .expect("Error saving admin user");
 
+                // 授予迁移中创建的管理员角色
+                let admin_id: i32 = users
+                    .filter(username.eq("admin"))
+                    .select(user_id)
+                    .first(c)
+                    .expect("Error loading admin user");
+                if let Ok(admin_role_id) = roles::table
+                    .filter(roles::role_name.eq("admin"))
+                    .select(roles::role_id)
+                    .first::<i32>(c)
+                {
+                    diesel::insert_into(user_roles::table)
+                        .values((
+                            user_roles::user_id.eq(admin_id),
+                            user_roles::role_id.eq(admin_role_id),
+                        ))
+                        .execute(c)
+                        .expect("Error granting admin role");
+                }
+
                 info!("Created initial admin user with username: 'admin' and password: '{}'", password);
             }
+        }).await;
 
-            Ok(rocket)
-        }).await
+        Ok(rocket)
     }
 }
\ No newline at end of file
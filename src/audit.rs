@@ -0,0 +1,268 @@
+use std::io::Cursor;
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::sql_types::{Nullable, Text};
+use diesel::sqlite::SqliteConnection;
+use log::error;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Header, Method};
+use rocket::{Data, Request, Response};
+use serde_json::{json, Map, Value};
+
+use crate::audit_chain;
+use crate::db;
+use crate::models::NewOperationLog;
+use crate::token::decode_request_token;
+
+// 路由前缀对应的表、主键，以及前缀后的一段是否为记录 ID。
+// 库存接口前缀后是操作名，记录 ID 取自应答，组合主键以 ':' 连接。
+// 未列出的路径（批量操作等）只记录方法、路径和状态
+const ENTITIES: &[(&str, &str, &str, bool)] = &[
+    ("materials", "materials", "material_id", true),
+    ("product_specifications", "product_specifications", "product_id", true),
+    ("price_formulas", "price_formulas", "formula_id", true),
+    ("production_costs", "production_costs", "cost_id", true),
+    ("production_tasks", "production_tasks", "task_id", true),
+    ("material_requests", "material_requests", "request_id", true),
+    ("warehouse_stock", "warehouse_stock", "warehouse_id,material_id", false),
+    ("stock_movements", "stock_movements", "movement_id", false),
+    ("warehouse", "warehouses", "warehouse_id", true),
+    ("user", "users", "user_id", true),
+    ("role", "roles", "role_id", true),
+    ("permissions", "permissions", "permission_id", true),
+    ("stock_transfers", "stock_transfers", "transfer_id", true),
+    ("trusted_peers", "trusted_peers", "peer_id", true),
+    ("outbox", "outbox", "message_id", true),
+    ("replication/conflicts", "replication_conflicts", "conflict_id", true),
+];
+
+// 审计日志写入失败时在应答中标记
+const AUDIT_STATUS_HEADER: &str = "X-Audit-Status";
+
+// 不写入日志的列
+const REDACTED: &[&str] = &["password_hash", "token_hash", "localkey"];
+
+// on_request 中收集、on_response 中写入的审计信息
+struct AuditContext {
+    user_id: Option<i32>,
+    method: Method,
+    path: String,
+    client_ip: Option<String>,
+    entity: Option<Entity>,
+    before: Option<Value>,
+}
+
+#[derive(Clone)]
+struct Entity {
+    entity_type: String,
+    table: &'static str,
+    key: &'static str,
+    id: Option<String>,
+}
+
+#[derive(QueryableByName)]
+struct Column {
+    #[diesel(sql_type = Text)]
+    name: String,
+}
+
+#[derive(QueryableByName)]
+struct Snapshot {
+    #[diesel(sql_type = Nullable<Text>)]
+    data: Option<String>,
+}
+
+fn is_mutating(method: Method) -> bool {
+    matches!(method, Method::Post | Method::Put | Method::Patch | Method::Delete)
+}
+
+// 按路由前缀找出实体，路径不含挂载点 /api
+fn entity_for(path: &str) -> Option<Entity> {
+    let path = path.trim_start_matches("/api").trim_start_matches('/');
+    ENTITIES.iter().find_map(|&(prefix, table, key, id_in_path)| {
+        let rest = path.strip_prefix(prefix)?;
+        if !rest.is_empty() && !rest.starts_with('/') {
+            return None;
+        }
+        let id = rest
+            .trim_start_matches('/')
+            .split('/')
+            .next()
+            .filter(|id| id_in_path && !id.is_empty());
+        Some(Entity {
+            entity_type: table.to_string(),
+            table,
+            key,
+            id: id.map(String::from),
+        })
+    })
+}
+
+// 以 JSON 对象读出一行，敏感列除外
+fn snapshot(c: &mut SqliteConnection, table: &str, key: &str, id: &str) -> QueryResult<Option<Value>> {
+    let columns = diesel::sql_query(format!("SELECT name FROM pragma_table_info('{}')", table))
+        .load::<Column>(c)?;
+    let fields = columns
+        .iter()
+        .filter(|column| !REDACTED.contains(&column.name.as_str()))
+        .map(|column| format!("'{0}', \"{0}\"", column.name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let row = diesel::sql_query(format!("SELECT json_object({}) AS data FROM {} WHERE {} = ?", fields, table, key))
+        .bind::<Text, _>(id)
+        .get_result::<Snapshot>(c)
+        .optional()?;
+    Ok(row.and_then(|row| row.data).and_then(|data| serde_json::from_str(&data).ok()))
+}
+
+async fn load_snapshot(entity: &Entity) -> Option<Value> {
+    let id = entity.id.clone()?;
+    let (table, key) = (entity.table, entity.key);
+    let result = tokio::task::spawn_blocking(move || {
+        let mut c = db::establish_connection().map_err(|e| e.to_string())?;
+        snapshot(&mut c, table, key, &id).map_err(|e| e.to_string())
+    })
+    .await;
+    match result {
+        Ok(Ok(snapshot)) => snapshot,
+        Ok(Err(e)) => {
+            error!("Failed to load audit snapshot of {} {}: {}", entity.table, entity.key, e);
+            None
+        }
+        Err(_) => None,
+    }
+}
+
+// 新建记录时没有旧版本，从应答的 JSON 中取新记录
+async fn response_record(response: &mut Response<'_>) -> Option<Value> {
+    if response.content_type() != Some(ContentType::JSON) {
+        return None;
+    }
+    let body = response.body_mut().to_bytes().await.ok()?;
+    let record = serde_json::from_slice::<Value>(&body).ok();
+    response.set_sized_body(body.len(), Cursor::new(body));
+
+    let mut record = record.filter(Value::is_object)?;
+    if let Some(object) = record.as_object_mut() {
+        object.retain(|field, _| !REDACTED.contains(&field.as_str()));
+    }
+    Some(record)
+}
+
+// 字段级差异：{"字段": {"before": 旧值, "after": 新值}}
+fn diff(before: Option<&Value>, after: Option<&Value>) -> Option<Value> {
+    if before.is_none() && after.is_none() {
+        return None;
+    }
+    let empty = Map::new();
+    let before = before.and_then(Value::as_object).unwrap_or(&empty);
+    let after = after.and_then(Value::as_object).unwrap_or(&empty);
+
+    let mut changes = Map::new();
+    for field in before.keys().chain(after.keys()) {
+        let old = before.get(field).unwrap_or(&Value::Null);
+        let new = after.get(field).unwrap_or(&Value::Null);
+        if old != new && !changes.contains_key(field) {
+            changes.insert(field.clone(), json!({ "before": old, "after": new }));
+        }
+    }
+    Some(Value::Object(changes))
+}
+
+fn id_of(record: &Value, key: &str) -> Option<String> {
+    let parts = key
+        .split(',')
+        .map(|key| match record.get(key)? {
+            Value::String(id) => Some(id.clone()),
+            Value::Null => None,
+            id => Some(id.to_string()),
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(parts.join(":"))
+}
+
+// 自动记录每个修改请求：令牌中的用户、实体、方法、状态码、修改前后的差异和客户端 IP。
+// 失败的请求也会记录，差异只在成功时计算。审计日志在处理函数的事务提交后才写入，
+// 写入失败时不能改写已提交操作的状态码，只记录错误日志并在应答中带上 X-Audit-Status: failed
+pub struct Audit;
+
+#[rocket::async_trait]
+impl Fairing for Audit {
+    fn info(&self) -> Info {
+        Info {
+            name: "Audit Trail",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        let method = request.method();
+        if !is_mutating(method) {
+            return;
+        }
+
+        let path = request.uri().path().to_string();
+        let entity = entity_for(&path);
+        let before = match &entity {
+            Some(entity) => load_snapshot(entity).await,
+            None => None,
+        };
+        let context = AuditContext {
+            user_id: decode_request_token(request).map(|claims| claims.user_id),
+            method,
+            path,
+            // 只取连接的对端地址，X-Real-IP 等请求头可由客户端伪造
+            client_ip: request.remote().map(|addr| addr.ip().to_string()),
+            entity,
+            before,
+        };
+        request.local_cache(|| Some(context));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let context = match request.local_cache(|| None::<AuditContext>) {
+            Some(context) => context,
+            None => return,
+        };
+        let status = response.status();
+
+        let mut entity = context.entity.clone();
+        let mut changes = None;
+        if let Some(entity) = entity.as_mut() {
+            if status.class().is_success() {
+                let after = match entity.id {
+                    Some(_) => load_snapshot(entity).await,
+                    None => response_record(response).await,
+                };
+                if entity.id.is_none() {
+                    entity.id = after.as_ref().and_then(|record| id_of(record, entity.key));
+                }
+                changes = diff(context.before.as_ref(), after.as_ref());
+            }
+        }
+
+        let log = NewOperationLog {
+            user_id: context.user_id,
+            action: format!("{} {}", context.method, context.path),
+            timestamp: Utc::now().naive_utc(),
+            method: context.method.as_str().to_string(),
+            path: context.path.clone(),
+            entity_type: entity.as_ref().map(|entity| entity.entity_type.clone()),
+            entity_id: entity.and_then(|entity| entity.id),
+            status: status.code as i32,
+            changes: changes.map(|changes| changes.to_string()),
+            client_ip: context.client_ip.clone(),
+        };
+        let result = tokio::task::spawn_blocking(move || {
+            let mut c = db::establish_connection().map_err(|e| e.to_string())?;
+            audit_chain::append(&mut c, &log).map_err(|e| e.to_string())
+        })
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result);
+        if let Err(e) = result {
+            error!("Failed to write audit log for {} {}: {}", context.method, context.path, e);
+            response.set_header(Header::new(AUDIT_STATUS_HEADER, "failed"));
+        }
+    }
+}
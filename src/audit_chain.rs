@@ -0,0 +1,440 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use base64::engine::general_purpose;
+use base64::Engine;
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use libp2p::identity::ed25519;
+use log::{error, info, warn};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::sync::broadcast;
+
+use crate::db;
+use crate::models::{NewOperationLog, NewOperationLogSignature, OperationLog, OperationLogSignature};
+use crate::schema::{node_key_history, operation_log_signatures, operation_logs};
+use crate::warehouse;
+
+// 第一条日志的 prev_hash
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+const SIGN_INTERVAL: Duration = Duration::from_secs(10 * 60);
+// 校验时每批读取的日志条数
+const VERIFY_BATCH: i64 = 1000;
+
+// 校验结果，broken 为第一处断链
+#[derive(Debug, Serialize)]
+pub struct ChainReport {
+    pub valid: bool,
+    pub checked: i64,
+    pub signatures_checked: i64,
+    pub last_signed_log_id: Option<i32>,
+    pub broken: Option<BrokenLink>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BrokenLink {
+    pub log_id: Option<i32>,
+    pub reason: &'static str,
+}
+
+fn entry_hash(prev_hash: &str, content: &Value) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(prev_hash.as_bytes());
+    hasher.update(content.to_string().as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+// 参与哈希的内容，新旧两种结构必须得到相同的 JSON
+fn new_entry_content(log: &NewOperationLog) -> Value {
+    json!([
+        log.user_id, log.action, log.timestamp, log.method, log.path,
+        log.entity_type, log.entity_id, log.status, log.changes, log.client_ip,
+    ])
+}
+
+fn entry_content(log: &OperationLog) -> Value {
+    json!([
+        log.user_id, log.action, log.timestamp, log.method, log.path,
+        log.entity_type, log.entity_id, log.status, log.changes, log.client_ip,
+    ])
+}
+
+fn signed_message(log_id: i32, hash: &str) -> String {
+    format!("{}:{}", log_id, hash)
+}
+
+// 链头哈希。还没有任何带哈希的日志时（启用哈希链之前的旧日志），先按 log_id 顺序补上
+fn chain_head(c: &mut SqliteConnection) -> QueryResult<String> {
+    let head = operation_logs::table
+        .filter(operation_logs::hash.is_not_null())
+        .order(operation_logs::log_id.desc())
+        .select(operation_logs::hash)
+        .first::<Option<String>>(c)
+        .optional()?
+        .flatten();
+    if let Some(head) = head {
+        return Ok(head);
+    }
+
+    let legacy = operation_logs::table
+        .order(operation_logs::log_id.asc())
+        .select(OperationLog::as_select())
+        .load(c)?;
+    let mut prev_hash = GENESIS_HASH.to_string();
+    for log in legacy {
+        let hash = entry_hash(&prev_hash, &entry_content(&log));
+        diesel::update(operation_logs::table.filter(operation_logs::log_id.eq(log.log_id)))
+            .set((
+                operation_logs::prev_hash.eq(&prev_hash),
+                operation_logs::hash.eq(&hash),
+            ))
+            .execute(c)?;
+        prev_hash = hash;
+    }
+    Ok(prev_hash)
+}
+
+// 追加一条日志并链到当前链头。立即事务保证并发写入时链不分叉
+pub fn append(c: &mut SqliteConnection, log: &NewOperationLog) -> QueryResult<usize> {
+    c.immediate_transaction(|c| {
+        let prev_hash = chain_head(c)?;
+        let hash = entry_hash(&prev_hash, &new_entry_content(log));
+        diesel::insert_into(operation_logs::table)
+            .values((
+                log,
+                operation_logs::prev_hash.eq(&prev_hash),
+                operation_logs::hash.eq(&hash),
+            ))
+            .execute(c)
+    })
+}
+
+// 用本节点密钥对链头签名，链头已签过时返回 None
+pub fn sign_head(c: &mut SqliteConnection, local_key: &ed25519::Keypair) -> QueryResult<Option<i32>> {
+    let head = operation_logs::table
+        .filter(operation_logs::hash.is_not_null())
+        .order(operation_logs::log_id.desc())
+        .select((operation_logs::log_id, operation_logs::hash))
+        .first::<(Option<i32>, Option<String>)>(c)
+        .optional()?;
+    let (log_id, hash) = match head {
+        Some((Some(log_id), Some(hash))) => (log_id, hash),
+        _ => return Ok(None),
+    };
+
+    let signed = diesel::select(diesel::dsl::exists(
+        operation_log_signatures::table.filter(operation_log_signatures::log_id.eq(log_id))
+    ))
+    .get_result::<bool>(c)?;
+    if signed {
+        return Ok(None);
+    }
+
+    let signature = local_key.sign(signed_message(log_id, &hash).as_bytes());
+    diesel::insert_into(operation_log_signatures::table)
+        .values(&NewOperationLogSignature {
+            log_id,
+            hash,
+            public_key: warehouse::encode_public_key(&local_key.public()),
+            signature: general_purpose::STANDARD.encode(signature),
+            signed_at: Utc::now().naive_utc(),
+        })
+        .execute(c)?;
+    Ok(Some(log_id))
+}
+
+// 本节点的当前公钥和轮换前的旧公钥
+fn trusted_keys(c: &mut SqliteConnection) -> QueryResult<HashSet<String>> {
+    let mut keys = node_key_history::table
+        .select(node_key_history::public_key)
+        .load::<String>(c)?
+        .into_iter()
+        .collect::<HashSet<_>>();
+    match warehouse::get_warehouse_id(c) {
+        Ok(local_key) => {
+            keys.insert(warehouse::encode_public_key(&local_key.public()));
+        }
+        Err(diesel::result::Error::NotFound) => {}
+        Err(e) => return Err(e),
+    }
+    Ok(keys)
+}
+
+// 签名行中的公钥只用来挑出是哪一把本节点密钥，不在 trusted 中的一律视为伪造
+fn signature_valid(signature: &OperationLogSignature, trusted: &HashSet<String>) -> bool {
+    if !trusted.contains(&signature.public_key) {
+        return false;
+    }
+    let public_key = general_purpose::STANDARD
+        .decode(&signature.public_key)
+        .ok()
+        .and_then(|bytes| ed25519::PublicKey::decode(&bytes).ok());
+    let bytes = general_purpose::STANDARD.decode(&signature.signature).ok();
+    match (public_key, bytes) {
+        (Some(public_key), Some(bytes)) => {
+            public_key.verify(signed_message(signature.log_id, &signature.hash).as_bytes(), &bytes)
+        }
+        _ => false,
+    }
+}
+
+// 从第一条日志开始逐条重算哈希，再校验每个签名，返回第一处断链
+pub fn verify(c: &mut SqliteConnection) -> QueryResult<ChainReport> {
+    let mut report = ChainReport {
+        valid: false,
+        checked: 0,
+        signatures_checked: 0,
+        last_signed_log_id: None,
+        broken: None,
+    };
+    let mut hashes = HashMap::new();
+    let mut prev_hash = GENESIS_HASH.to_string();
+    let mut cursor = None;
+
+    loop {
+        let mut query = operation_logs::table
+            .order(operation_logs::log_id.asc())
+            .limit(VERIFY_BATCH)
+            .select(OperationLog::as_select())
+            .into_boxed();
+        if let Some(cursor) = cursor {
+            query = query.filter(operation_logs::log_id.gt(cursor));
+        }
+        let batch = query.load(c)?;
+        if batch.is_empty() {
+            break;
+        }
+
+        for log in &batch {
+            let reason = match (&log.prev_hash, &log.hash) {
+                (None, _) | (_, None) => Some("missing hash"),
+                // 前一条被删除、插入或调换了顺序
+                (Some(prev), _) if *prev != prev_hash => Some("previous hash mismatch"),
+                // 本行内容被修改
+                (_, Some(hash)) if *hash != entry_hash(&prev_hash, &entry_content(log)) => Some("content hash mismatch"),
+                _ => None,
+            };
+            if let Some(reason) = reason {
+                report.broken = Some(BrokenLink { log_id: log.log_id, reason });
+                return Ok(report);
+            }
+            report.checked += 1;
+            prev_hash = log.hash.clone().unwrap_or_default();
+            if let Some(log_id) = log.log_id {
+                hashes.insert(log_id, prev_hash.clone());
+            }
+        }
+        cursor = batch.last().and_then(|log| log.log_id);
+    }
+
+    let trusted = trusted_keys(c)?;
+    let signatures = operation_log_signatures::table
+        .order(operation_log_signatures::log_id.asc())
+        .select(OperationLogSignature::as_select())
+        .load(c)?;
+    for signature in &signatures {
+        let reason = match hashes.get(&signature.log_id) {
+            None => Some("signed entry missing"),
+            Some(hash) if *hash != signature.hash => Some("signed hash mismatch"),
+            _ if !signature_valid(signature, &trusted) => Some("invalid signature"),
+            _ => None,
+        };
+        if let Some(reason) = reason {
+            report.broken = Some(BrokenLink { log_id: Some(signature.log_id), reason });
+            return Ok(report);
+        }
+        report.signatures_checked += 1;
+        report.last_signed_log_id = Some(signature.log_id);
+    }
+
+    report.valid = true;
+    Ok(report)
+}
+
+// 定期对链头签名，收到关闭信号时退出
+pub async fn run_signing_job(mut shutdown_rx: broadcast::Receiver<()>) {
+    let mut interval = tokio::time::interval(SIGN_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = shutdown_rx.recv() => {
+                info!("Shutting down operation log signing job...");
+                break;
+            }
+            _ = interval.tick() => {
+                let result = tokio::task::spawn_blocking(|| {
+                    let mut c = db::establish_connection().map_err(|e| e.to_string())?;
+                    let local_key = match warehouse::get_warehouse_id(&mut c) {
+                        Ok(local_key) => local_key,
+                        Err(e) => {
+                            warn!("No node key to sign the operation log: {}", e);
+                            return Ok(None);
+                        }
+                    };
+                    sign_head(&mut c, &local_key).map_err(|e| e.to_string())
+                })
+                .await;
+                match result {
+                    Ok(Ok(Some(log_id))) => info!("Signed operation log chain up to entry {}", log_id),
+                    Ok(Ok(None)) => {}
+                    Ok(Err(e)) => error!("Failed to sign operation log chain: {}", e),
+                    Err(e) => error!("Signing task panicked: {}", e),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn new_log(action: &str) -> NewOperationLog {
+        NewOperationLog {
+            user_id: None,
+            action: action.to_string(),
+            timestamp: Utc::now().naive_utc(),
+            method: "POST".to_string(),
+            path: "/api/materials".to_string(),
+            entity_type: Some("materials".to_string()),
+            entity_id: Some("1".to_string()),
+            status: 201,
+            changes: None,
+            client_ip: Some("127.0.0.1".to_string()),
+        }
+    }
+
+    fn logs(c: &mut SqliteConnection) -> Vec<OperationLog> {
+        operation_logs::table
+            .order(operation_logs::log_id.asc())
+            .select(OperationLog::as_select())
+            .load(c)
+            .unwrap()
+    }
+
+    fn broken_reason(c: &mut SqliteConnection) -> Option<&'static str> {
+        verify(c).unwrap().broken.map(|broken| broken.reason)
+    }
+
+    #[test]
+    fn entry_hash_depends_on_prev_hash_and_content() {
+        let content = json!([1, "POST /api/materials"]);
+        assert_eq!(entry_hash(GENESIS_HASH, &content), entry_hash(GENESIS_HASH, &content));
+        assert_eq!(entry_hash(GENESIS_HASH, &content).len(), 64);
+        assert_ne!(entry_hash(GENESIS_HASH, &content), entry_hash(&"1".repeat(64), &content));
+        assert_ne!(entry_hash(GENESIS_HASH, &content), entry_hash(GENESIS_HASH, &json!([2, "POST /api/materials"])));
+    }
+
+    #[test]
+    fn new_and_stored_entries_hash_the_same() {
+        let mut c = db::memory_connection();
+        let log = new_log("POST /api/materials");
+        append(&mut c, &log).unwrap();
+        let stored = logs(&mut c).remove(0);
+        assert_eq!(entry_content(&stored), new_entry_content(&log));
+    }
+
+    #[test]
+    fn append_links_each_entry_to_the_previous_one() {
+        let mut c = db::memory_connection();
+        for action in ["first", "second", "third"] {
+            append(&mut c, &new_log(action)).unwrap();
+        }
+        let logs = logs(&mut c);
+        assert_eq!(logs[0].prev_hash.as_deref(), Some(GENESIS_HASH));
+        assert_eq!(logs[1].prev_hash, logs[0].hash);
+        assert_eq!(logs[2].prev_hash, logs[1].hash);
+
+        let report = verify(&mut c).unwrap();
+        assert!(report.valid);
+        assert_eq!(report.checked, 3);
+    }
+
+    #[test]
+    fn verify_detects_tampered_row() {
+        let mut c = db::memory_connection();
+        append(&mut c, &new_log("first")).unwrap();
+        append(&mut c, &new_log("second")).unwrap();
+        let first = logs(&mut c)[0].log_id;
+        diesel::update(operation_logs::table.filter(operation_logs::log_id.eq(first)))
+            .set(operation_logs::status.eq(200))
+            .execute(&mut c)
+            .unwrap();
+
+        let report = verify(&mut c).unwrap();
+        assert!(!report.valid);
+        let broken = report.broken.unwrap();
+        assert_eq!(broken.log_id, first);
+        assert_eq!(broken.reason, "content hash mismatch");
+    }
+
+    #[test]
+    fn verify_detects_deleted_row() {
+        let mut c = db::memory_connection();
+        for action in ["first", "second", "third"] {
+            append(&mut c, &new_log(action)).unwrap();
+        }
+        let second = logs(&mut c)[1].log_id;
+        diesel::delete(operation_logs::table.filter(operation_logs::log_id.eq(second)))
+            .execute(&mut c)
+            .unwrap();
+        assert_eq!(broken_reason(&mut c), Some("previous hash mismatch"));
+    }
+
+    #[test]
+    fn verify_accepts_signature_of_local_key() {
+        let mut c = db::memory_connection();
+        let local_key = warehouse::generate_and_insert_new_local_key(&mut c);
+        append(&mut c, &new_log("first")).unwrap();
+        assert!(sign_head(&mut c, &local_key).unwrap().is_some());
+        // 链头已签过
+        assert!(sign_head(&mut c, &local_key).unwrap().is_none());
+
+        let report = verify(&mut c).unwrap();
+        assert!(report.valid);
+        assert_eq!(report.signatures_checked, 1);
+    }
+
+    #[test]
+    fn verify_rejects_signature_of_foreign_key() {
+        let mut c = db::memory_connection();
+        warehouse::generate_and_insert_new_local_key(&mut c);
+        append(&mut c, &new_log("first")).unwrap();
+        // 攻击者用自己的密钥重签，签名本身与行内公钥一致
+        let forged_key = ed25519::Keypair::generate();
+        sign_head(&mut c, &forged_key).unwrap();
+        assert_eq!(broken_reason(&mut c), Some("invalid signature"));
+    }
+
+    #[test]
+    fn verify_rejects_altered_signature() {
+        let mut c = db::memory_connection();
+        let local_key = warehouse::generate_and_insert_new_local_key(&mut c);
+        append(&mut c, &new_log("first")).unwrap();
+        sign_head(&mut c, &local_key).unwrap();
+        let other = general_purpose::STANDARD.encode(local_key.sign(b"something else"));
+        diesel::update(operation_log_signatures::table)
+            .set(operation_log_signatures::signature.eq(other))
+            .execute(&mut c)
+            .unwrap();
+        assert_eq!(broken_reason(&mut c), Some("invalid signature"));
+    }
+
+    #[test]
+    fn signatures_survive_key_rotation() {
+        let mut c = db::memory_connection();
+        let old_key = warehouse::generate_and_insert_new_local_key(&mut c);
+        append(&mut c, &new_log("first")).unwrap();
+        sign_head(&mut c, &old_key).unwrap();
+
+        let new_key = warehouse::rotate_local_key(&mut c).unwrap();
+        append(&mut c, &new_log("second")).unwrap();
+        sign_head(&mut c, &new_key).unwrap();
+
+        let report = verify(&mut c).unwrap();
+        assert!(report.valid);
+        assert_eq!(report.signatures_checked, 2);
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::HashSet;
+use std::marker::PhantomData;
+use diesel::prelude::*;
+use rocket::request::{FromRequest, Outcome};
+use rocket::http::Status;
+use rocket::Request;
+
+use crate::models::{User, DbConn};
+use crate::schema::{permissions, role_permissions, user_roles, users};
+use crate::token::decode_request_token;
+
+// 权限名称，由下面的标记类型实现
+pub trait PermissionName {
+    const NAME: &'static str;
+}
+
+// 为每个权限生成一个标记类型，名称需与迁移中写入 permissions 表的目录一致
+macro_rules! permissions {
+    ($($marker:ident => $name:literal),* $(,)?) => {
+        $(
+            pub struct $marker;
+
+            impl PermissionName for $marker {
+                const NAME: &'static str = $name;
+            }
+        )*
+    };
+}
+
+permissions! {
+    WarehouseRead => "warehouse.read",
+    WarehouseWrite => "warehouse.write",
+    StockRead => "stock.read",
+    StockWrite => "stock.write",
+    UserRead => "user.read",
+    UserWrite => "user.write",
+    RoleRead => "role.read",
+    RoleWrite => "role.write",
+    PermissionRead => "permission.read",
+    PermissionWrite => "permission.write",
+    UserRoleRead => "user_role.read",
+    UserRoleWrite => "user_role.write",
+    RolePermissionRead => "role_permission.read",
+    RolePermissionWrite => "role_permission.write",
+    OperationLogRead => "operation_log.read",
+    ProductionTaskRead => "production_task.read",
+    ProductionTaskWrite => "production_task.write",
+    ProductionCostRead => "production_cost.read",
+    ProductionCostWrite => "production_cost.write",
+    PriceFormulaRead => "price_formula.read",
+    PriceFormulaWrite => "price_formula.write",
+    ProductSpecificationRead => "product_specification.read",
+    ProductSpecificationWrite => "product_specification.write",
+    MaterialRead => "material.read",
+    MaterialWrite => "material.write",
+    MaterialRequestRead => "material_request.read",
+    MaterialRequestWrite => "material_request.write",
+    MaterialRequestApprove => "material_request.approve",
+    ReplicationRead => "replication.read",
+    ReplicationWrite => "replication.write",
+    NetworkRead => "network.read",
+    NetworkWrite => "network.write",
+}
+
+// 当前请求的用户及其全部权限，每个请求只加载一次
+pub struct CurrentUser {
+    pub user_id: i32,
+    pub username: String,
+    pub permissions: HashSet<String>,
+}
+
+impl CurrentUser {
+    pub fn has<P: PermissionName>(&self) -> bool {
+        self.permissions.contains(P::NAME)
+    }
+}
+
+async fn load_current_user(request: &Request<'_>) -> Result<CurrentUser, Status> {
+    let claims = decode_request_token(request).ok_or(Status::Unauthorized)?;
+    let conn = request
+        .guard::<DbConn>()
+        .await
+        .succeeded()
+        .ok_or(Status::ServiceUnavailable)?;
+
+    conn.run(move |c| {
+        let user: User = users::table
+            .find(claims.user_id)
+            .select(User::as_select())
+            .first(c)
+            .optional()
+            .map_err(|_| Status::InternalServerError)?
+            .ok_or(Status::Unauthorized)?;
+
+        if user.status.as_deref() == Some("inactive") {
+            return Err(Status::Forbidden);
+        }
+
+        // user_roles -> role_permissions -> permissions
+        let role_ids: Vec<i32> = user_roles::table
+            .filter(user_roles::user_id.eq(user.user_id))
+            .select(user_roles::role_id)
+            .load::<Option<i32>>(c)
+            .map_err(|_| Status::InternalServerError)?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        let permission_ids: Vec<i32> = role_permissions::table
+            .filter(role_permissions::role_id.eq_any(role_ids))
+            .select(role_permissions::permission_id)
+            .load::<Option<i32>>(c)
+            .map_err(|_| Status::InternalServerError)?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        let names = permissions::table
+            .filter(permissions::permission_id.eq_any(permission_ids))
+            .select(permissions::permission_name)
+            .load::<String>(c)
+            .map_err(|_| Status::InternalServerError)?;
+
+        Ok(CurrentUser {
+            user_id: user.user_id,
+            username: user.username,
+            permissions: names.into_iter().collect(),
+        })
+    }).await
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r CurrentUser {
+    type Error = String;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let result = request
+            .local_cache_async(async { load_current_user(request).await })
+            .await;
+
+        match result {
+            Ok(user) => Outcome::Success(user),
+            Err(status) => Outcome::Error((*status, "Authentication failed".to_string())),
+        }
+    }
+}
+
+// 要求当前用户拥有权限 P，否则返回 403
+pub struct RequirePermission<P: PermissionName> {
+    pub user_id: i32,
+    pub username: String,
+    _permission: PhantomData<P>,
+}
+
+#[rocket::async_trait]
+impl<'r, P: PermissionName + Send + Sync + 'static> FromRequest<'r> for RequirePermission<P> {
+    type Error = String;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let user = match request.guard::<&CurrentUser>().await {
+            Outcome::Success(user) => user,
+            Outcome::Error(e) => return Outcome::Error(e),
+            Outcome::Forward(status) => return Outcome::Forward(status),
+        };
+
+        if user.has::<P>() {
+            Outcome::Success(RequirePermission {
+                user_id: user.user_id,
+                username: user.username.clone(),
+                _permission: PhantomData,
+            })
+        } else {
+            Outcome::Error((Status::Forbidden, format!("Missing permission: {}", P::NAME)))
+        }
+    }
+}
@@ -0,0 +1,317 @@
+// 仓库节点的命令行管理工具，直接操作与服务相同的数据库（DATABASE_PATH）。
+// 修改数据库的命令应在服务停止时执行，db restore 必须如此
+use std::env;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::process;
+
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use libp2p::identity::PublicKey;
+use libp2p::PeerId;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+
+use app1::db;
+use app1::migrations;
+use app1::models::{NewRole, NewUser};
+use app1::replication::{self, ReplicationMessage};
+use app1::routers::auth::revoke_user_tokens;
+use app1::schema::{roles, user_roles, users};
+use app1::soft_delete;
+use app1::sync_protocol;
+use app1::warehouse;
+
+const USAGE: &str = "\
+Usage: warehouse-admin <command>
+
+Commands:
+  user create <username> [--full-name <name>] [--role <role>]
+  user reset-password <username>
+  user disable <username>
+  role create <role> [--description <text>]
+  role grant <username> <role>
+  node show-peer-id
+  node rotate-key
+  db migrate
+  db backup <file>
+  db restore <file>
+  db purge [--days <n>]
+  export <file>
+  import <file>";
+
+type CliResult = Result<(), Box<dyn Error>>;
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+// 取 --name 后的值
+fn option(args: &[String], name: &str) -> Option<String> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|i| args.get(i + 1))
+        .cloned()
+}
+
+fn random_password() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(12)
+        .map(char::from)
+        .collect()
+}
+
+fn find_user(c: &mut SqliteConnection, username: &str) -> Result<i32, Box<dyn Error>> {
+    users::table
+        .filter(users::username.eq(username))
+        .select(users::user_id)
+        .first(c)
+        .optional()?
+        .ok_or_else(|| format!("user '{}' not found", username).into())
+}
+
+fn find_role(c: &mut SqliteConnection, role_name: &str) -> Result<i32, Box<dyn Error>> {
+    roles::table
+        .filter(roles::role_name.eq(role_name))
+        .select(roles::role_id)
+        .first(c)
+        .optional()?
+        .ok_or_else(|| format!("role '{}' not found", role_name).into())
+}
+
+fn grant_role(c: &mut SqliteConnection, user_id: i32, role_id: i32) -> QueryResult<usize> {
+    diesel::insert_or_ignore_into(user_roles::table)
+        .values((
+            user_roles::user_id.eq(user_id),
+            user_roles::role_id.eq(role_id),
+        ))
+        .execute(c)
+}
+
+fn create_user(c: &mut SqliteConnection, username: &str, args: &[String]) -> CliResult {
+    let role_id = match option(args, "--role") {
+        Some(role_name) => Some(find_role(c, &role_name)?),
+        None => None,
+    };
+    let password = random_password();
+    let new_user = NewUser {
+        username: username.to_string(),
+        password_hash: bcrypt::hash(password.as_bytes(), bcrypt::DEFAULT_COST)?,
+        full_name: option(args, "--full-name"),
+        position: None,
+        contact_info: None,
+        status: Some("active".to_string()),
+    };
+
+    c.transaction(|c| {
+        diesel::insert_into(users::table).values(&new_user).execute(c)?;
+        if let Some(role_id) = role_id {
+            let user_id = users::table
+                .filter(users::username.eq(username))
+                .select(users::user_id)
+                .first::<i32>(c)?;
+            grant_role(c, user_id, role_id)?;
+        }
+        Ok::<_, diesel::result::Error>(())
+    })?;
+
+    println!("Created user '{}' with password: {}", username, password);
+    Ok(())
+}
+
+// 重置密码并吊销刷新令牌，已登录的会话在访问令牌过期后失效
+fn reset_password(c: &mut SqliteConnection, username: &str) -> CliResult {
+    let user_id = find_user(c, username)?;
+    let password = random_password();
+    let password_hash = bcrypt::hash(password.as_bytes(), bcrypt::DEFAULT_COST)?;
+
+    c.transaction(|c| {
+        diesel::update(users::table.filter(users::user_id.eq(user_id)))
+            .set(users::password_hash.eq(password_hash))
+            .execute(c)?;
+        revoke_user_tokens(c, user_id)
+    })?;
+
+    println!("New password for '{}': {}", username, password);
+    Ok(())
+}
+
+fn disable_user(c: &mut SqliteConnection, username: &str) -> CliResult {
+    let user_id = find_user(c, username)?;
+    let revoked = c.transaction(|c| {
+        diesel::update(users::table.filter(users::user_id.eq(user_id)))
+            .set(users::status.eq("inactive"))
+            .execute(c)?;
+        revoke_user_tokens(c, user_id)
+    })?;
+
+    println!("Disabled user '{}', revoked {} refresh tokens", username, revoked);
+    Ok(())
+}
+
+fn create_role(c: &mut SqliteConnection, role_name: &str, args: &[String]) -> CliResult {
+    diesel::insert_into(roles::table)
+        .values(NewRole {
+            role_name: role_name.to_string(),
+            description: option(args, "--description"),
+        })
+        .execute(c)?;
+    println!("Created role '{}'", role_name);
+    Ok(())
+}
+
+fn grant(c: &mut SqliteConnection, username: &str, role_name: &str) -> CliResult {
+    let user_id = find_user(c, username)?;
+    let role_id = find_role(c, role_name)?;
+    if grant_role(c, user_id, role_id)? == 0 {
+        println!("User '{}' already has role '{}'", username, role_name);
+    } else {
+        println!("Granted role '{}' to '{}'", role_name, username);
+    }
+    Ok(())
+}
+
+fn show_peer_id(c: &mut SqliteConnection) -> CliResult {
+    println!("{}", replication::local_peer_id(c)?);
+    Ok(())
+}
+
+fn rotate_key(c: &mut SqliteConnection) -> CliResult {
+    let old_peer_id = replication::local_peer_id(c)?;
+    let local_key = warehouse::rotate_local_key(c)?;
+    let new_peer_id = PeerId::from(PublicKey::Ed25519(local_key.public()));
+    println!("Rotated node key: {} -> {}", old_peer_id, new_peer_id);
+    println!("Other nodes must trust the new peer id before they accept this node's messages");
+    Ok(())
+}
+
+// VACUUM INTO 在数据库打开时也能得到一致的副本
+fn backup(c: &mut SqliteConnection, file: &str) -> CliResult {
+    if Path::new(file).exists() {
+        return Err(format!("'{}' already exists", file).into());
+    }
+    diesel::sql_query(format!("VACUUM INTO '{}'", file.replace('\'', "''"))).execute(c)?;
+    println!("Backed up {} to {}", db::database_path(), file);
+    Ok(())
+}
+
+// 覆盖数据库文件后执行迁移，使旧备份的表结构跟上当前版本
+async fn restore(file: &str) -> CliResult {
+    if !Path::new(file).is_file() {
+        return Err(format!("'{}' does not exist", file).into());
+    }
+    let database_path = db::database_path();
+    for suffix in ["-wal", "-shm"] {
+        let _ = fs::remove_file(format!("{}{}", database_path, suffix));
+    }
+    fs::copy(file, &database_path)?;
+    let mut c = db::establish_connection()?;
+    migrations::run_db_migrations(&mut c).await?;
+    println!("Restored {} from {}", database_path, file);
+    Ok(())
+}
+
+// 立即清理软删除超过保留期的记录，默认保留期同定时任务
+fn purge(c: &mut SqliteConnection, args: &[String]) -> CliResult {
+    let days = match option(args, "--days") {
+        Some(days) => days.parse::<i64>().map_err(|_| format!("invalid --days '{}'", days))?,
+        None => soft_delete::retention_days(),
+    };
+    let before = chrono::Utc::now().naive_utc() - chrono::Duration::days(days);
+    let purged = soft_delete::purge(c, before)?;
+    println!("Purged {} records deleted more than {} days ago", purged, days);
+    Ok(())
+}
+
+// 导出主数据的当前状态，每行一条复制消息，可在其他节点 import
+fn export(c: &mut SqliteConnection, file: &str) -> CliResult {
+    let mut writer = BufWriter::new(File::create(file)?);
+    let mut since = 0;
+    let mut count = 0;
+    loop {
+        let page = sync_protocol::export(c, since)?;
+        for change in &page.changes {
+            serde_json::to_writer(&mut writer, change)?;
+            writer.write_all(b"\n")?;
+            count += 1;
+        }
+        since = page.last_seq;
+        if !page.has_more {
+            break;
+        }
+    }
+    writer.flush()?;
+    println!("Exported {} records to {}", count, file);
+    Ok(())
+}
+
+// 按时钟合并，本地较新的记录不会被覆盖；任一行出错则整体回滚
+fn import(c: &mut SqliteConnection, file: &str) -> CliResult {
+    let mut changes = Vec::new();
+    for (i, line) in BufReader::new(File::open(file)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let change = serde_json::from_str::<ReplicationMessage>(&line)
+            .map_err(|e| format!("line {}: {}", i + 1, e))?;
+        changes.push(change);
+    }
+
+    let count = changes.len();
+    c.transaction(|c| {
+        for change in changes {
+            replication::apply_catch_up(c, change)?;
+        }
+        Ok::<_, diesel::result::Error>(())
+    })?;
+    println!("Imported {} records from {}", count, file);
+    Ok(())
+}
+
+async fn run(args: &[String]) -> CliResult {
+    let command: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    // restore 会替换数据库文件，不能先打开连接
+    if let ["db", "restore", file] = command.as_slice() {
+        return restore(file).await;
+    }
+
+    let mut c = db::establish_connection()?;
+    if let ["db", "migrate"] = command.as_slice() {
+        return migrations::run_db_migrations(&mut c).await;
+    }
+
+    match command.as_slice() {
+        ["user", "create", username, ..] => create_user(&mut c, username, &args[3..]),
+        ["user", "reset-password", username] => reset_password(&mut c, username),
+        ["user", "disable", username] => disable_user(&mut c, username),
+        ["role", "create", role_name, ..] => create_role(&mut c, role_name, &args[3..]),
+        ["role", "grant", username, role_name] => grant(&mut c, username, role_name),
+        ["node", "show-peer-id"] => show_peer_id(&mut c),
+        ["node", "rotate-key"] => rotate_key(&mut c),
+        ["db", "backup", file] => backup(&mut c, file),
+        ["db", "purge", ..] => purge(&mut c, &args[2..]),
+        ["export", file] => export(&mut c, file),
+        ["import", file] => import(&mut c, file),
+        _ => usage(),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() || args[0] == "--help" || args[0] == "-h" {
+        usage();
+    }
+
+    if let Err(e) = run(&args).await {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
@@ -1,34 +1,10 @@
 use rocket::request::{FromRequest, Outcome};
-use rocket::http::{CookieJar, Status};
+use rocket::http::Status;
 use rocket::Request;
-use rocket::serde::Deserialize;
-use std::time::{SystemTime, UNIX_EPOCH};
 use async_trait::async_trait;
-use rocket::serde::Serialize;
-use crate::token::decode_token;
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Claims {
-    sub: String,
-    exp: usize,
-}
-
-impl Claims {
-    fn new(username: &str) -> Self {
-        let now = SystemTime::now();
-        let exp = now
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_secs()
-            + 1800; // Token expires in 30 minutes
-
-        Self {
-            sub: username.to_owned(),
-            exp: exp as usize,
-        }
-    }
-}
+use crate::token::decode_request_token;
 
+// 声明类型统一定义在 token::Claims
 #[derive(Debug)]
 pub struct JwtToken(pub String);
 
@@ -37,15 +13,10 @@ impl<'r> FromRequest<'r> for JwtToken {
     type Error = ();
 
     async fn from_request(request: &'r Request<'_>) -> rocket::request::Outcome<Self, Self::Error> {
-        let cookies: &CookieJar<'_> = request.cookies();
-        if let Some(cookie) = cookies.get("token") {
-            let token = cookie.value();
-            match decode_token(token) {
-                Some(username) => Outcome::Success(JwtToken(username)),
-                None => Outcome::Error((Status::Unauthorized, ())),
-            }
-        } else {
-            Outcome::Error((Status::Unauthorized, ()))
+        // 与 TokenGuard 使用相同的来源和密钥：Authorization 头或 token cookie
+        match decode_request_token(request) {
+            Some(claims) => Outcome::Success(JwtToken(claims.sub)),
+            None => Outcome::Error((Status::Unauthorized, ())),
         }
     }
 }
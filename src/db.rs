@@ -1,8 +1,150 @@
+use std::env;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use diesel::connection::SimpleConnection;
+use diesel::r2d2::{ConnectionManager, CustomizeConnection, PooledConnection};
 use diesel::sqlite::SqliteConnection;
-use diesel::Connection;
+use log::{error, info};
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::{Phase, Rocket};
+
+pub type Pool = diesel::r2d2::Pool<ConnectionManager<SqliteConnection>>;
+pub type PoolError = diesel::r2d2::PoolError;
+pub type Connection = PooledConnection<ConnectionManager<SqliteConnection>>;
+
+// 数据库配置只来自环境变量，服务、swarm 任务和命令行工具共用。
+// 同一台机器上运行多个节点时为每个节点指定不同的 DATABASE_PATH
+const DEFAULT_DATABASE_PATH: &str = "./warehouse.db";
+const DEFAULT_POOL_SIZE: u32 = 8;
+const DEFAULT_BUSY_TIMEOUT_MS: u64 = 5000;
+
+static POOL: OnceLock<Pool> = OnceLock::new();
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+pub fn database_path() -> String {
+    env::var("DATABASE_PATH").unwrap_or_else(|_| DEFAULT_DATABASE_PATH.to_string())
+}
+
+pub fn database_url() -> String {
+    format!("sqlite://{}", database_path())
+}
+
+// 每个连接建立时设置的 pragma
+#[derive(Debug)]
+struct ConnectionOptions {
+    busy_timeout: Duration,
+}
+
+impl CustomizeConnection<SqliteConnection, diesel::r2d2::Error> for ConnectionOptions {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), diesel::r2d2::Error> {
+        conn.batch_execute(&format!(
+            "PRAGMA journal_mode = WAL; PRAGMA foreign_keys = ON; PRAGMA busy_timeout = {};",
+            self.busy_timeout.as_millis()
+        ))
+        .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
+// 进程内共享的连接池，首次调用时按 DATABASE_PATH、DATABASE_POOL_SIZE、DATABASE_BUSY_TIMEOUT_MS 创建
+pub fn pool() -> Result<&'static Pool, PoolError> {
+    if let Some(pool) = POOL.get() {
+        return Ok(pool);
+    }
+
+    let pool = Pool::builder()
+        .max_size(env_or("DATABASE_POOL_SIZE", DEFAULT_POOL_SIZE))
+        .connection_customizer(Box::new(ConnectionOptions {
+            busy_timeout: Duration::from_millis(env_or("DATABASE_BUSY_TIMEOUT_MS", DEFAULT_BUSY_TIMEOUT_MS)),
+        }))
+        .build(ConnectionManager::new(database_url()))?;
+    info!("Opened database {}", database_path());
+    Ok(POOL.get_or_init(|| pool))
+}
+
+// 从共享连接池取一个连接，用完即归还
+pub fn establish_connection() -> Result<Connection, PoolError> {
+    pool()?.get()
+}
+
+// Rocket 请求中使用的连接，与 swarm 任务共用同一个连接池。
+// run 在阻塞线程中执行，用法与 rocket_sync_db_pools 相同
+pub struct DbConn(Arc<Mutex<Connection>>);
+
+impl DbConn {
+    // 点火时把连接池放入 Rocket state
+    pub fn fairing() -> impl Fairing {
+        AdHoc::try_on_ignite("Database Pool", |rocket| async {
+            match pool() {
+                Ok(pool) => Ok(rocket.manage(pool.clone())),
+                Err(e) => {
+                    error!("Failed to open database {}: {}", database_path(), e);
+                    Err(rocket)
+                }
+            }
+        })
+    }
+
+    pub async fn get_one<P: Phase>(rocket: &Rocket<P>) -> Option<DbConn> {
+        let pool = rocket.state::<Pool>()?.clone();
+        Self::from_pool(pool).await
+    }
+
+    async fn from_pool(pool: Pool) -> Option<DbConn> {
+        match tokio::task::spawn_blocking(move || pool.get()).await {
+            Ok(Ok(conn)) => Some(DbConn(Arc::new(Mutex::new(conn)))),
+            Ok(Err(e)) => {
+                error!("Failed to get database connection: {}", e);
+                None
+            }
+            Err(_) => None,
+        }
+    }
+
+    pub async fn run<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut SqliteConnection) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let conn = Arc::clone(&self.0);
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn.lock().unwrap();
+            f(&mut conn)
+        })
+        .await
+        .expect("database task panicked")
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for DbConn {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+        let pool = match request.rocket().state::<Pool>() {
+            Some(pool) => pool.clone(),
+            None => return Outcome::Error((Status::InternalServerError, ())),
+        };
+        match DbConn::from_pool(pool).await {
+            Some(conn) => Outcome::Success(conn),
+            None => Outcome::Error((Status::ServiceUnavailable, ())),
+        }
+    }
+}
+
+// 测试用的内存数据库，已执行全部迁移
+#[cfg(test)]
+pub(crate) fn memory_connection() -> SqliteConnection {
+    use diesel::Connection as _;
+    use diesel_migrations::MigrationHarness;
 
-pub fn establish_connection() -> Result<SqliteConnection, diesel::ConnectionError> {
-    let database_url = "sqlite://./warehouse.db";
-    SqliteConnection::establish(&database_url)
-        .map_err(|e| e.into())
-} 
\ No newline at end of file
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory database");
+    conn.batch_execute("PRAGMA foreign_keys = ON;").expect("enable foreign keys");
+    conn.run_pending_migrations(crate::migrations::MIGRATIONS).expect("run migrations");
+    conn
+}
@@ -0,0 +1,216 @@
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use log::error;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Header, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket::serde::json::Json;
+use rocket::catch;
+use serde::Serialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+// 请求 ID，优先沿用客户端传入的 X-Request-Id，否则生成一个
+pub struct RequestId(pub String);
+
+impl RequestId {
+    pub fn of<'r>(request: &'r Request<'_>) -> &'r str {
+        &request
+            .local_cache(|| {
+                let id = request
+                    .headers()
+                    .get_one(REQUEST_ID_HEADER)
+                    .filter(|id| !id.is_empty() && id.len() <= 128)
+                    .map(String::from)
+                    .unwrap_or_else(|| Uuid::new_v4().to_string());
+                RequestId(id)
+            })
+            .0
+    }
+}
+
+// 在应答中带上 X-Request-Id，与错误体中的 request_id 一致
+pub struct RequestIdHeader;
+
+#[rocket::async_trait]
+impl Fairing for RequestIdHeader {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request ID",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        response.set_header(Header::new(REQUEST_ID_HEADER, RequestId::of(request).to_string()));
+    }
+}
+
+// 接口统一的错误类型，应答体为 {code, message, field, request_id}
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: Status,
+    pub code: &'static str,
+    pub message: String,
+    pub field: Option<String>,
+    pub details: Option<Value>,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    code: &'a str,
+    message: &'a str,
+    field: Option<&'a str>,
+    request_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<&'a Value>,
+}
+
+impl ApiError {
+    pub fn new(status: Status, code: &'static str, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            code,
+            message: message.into(),
+            field: None,
+            details: None,
+        }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        ApiError::new(Status::BadRequest, "bad_request", message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        ApiError::new(Status::NotFound, "not_found", message)
+    }
+
+    pub fn conflict(code: &'static str, message: impl Into<String>) -> Self {
+        ApiError::new(Status::Conflict, code, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        ApiError::new(Status::InternalServerError, "internal_error", message)
+    }
+
+    pub fn with_field(mut self, field: impl Into<String>) -> Self {
+        self.field = Some(field.into());
+        self
+    }
+
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+// SQLite 约束错误形如 "UNIQUE constraint failed: materials.material_name"，取出列名
+fn constraint_field(message: &str) -> Option<String> {
+    let target = message.split_once(": ")?.1;
+    let first = target.split(',').next()?.trim();
+    let column = first.rsplit('.').next()?;
+    column
+        .chars()
+        .all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+        .then(|| column.to_string())
+}
+
+impl From<DieselError> for ApiError {
+    fn from(err: DieselError) -> Self {
+        match err {
+            DieselError::NotFound => ApiError::not_found("record not found"),
+            DieselError::DatabaseError(kind, info) => {
+                let field = info.column_name().map(String::from).or_else(|| constraint_field(info.message()));
+                let error = match kind {
+                    DatabaseErrorKind::UniqueViolation => {
+                        ApiError::conflict("unique_violation", "a record with the same value already exists")
+                    }
+                    DatabaseErrorKind::ForeignKeyViolation => {
+                        ApiError::conflict("foreign_key_violation", "referenced record does not exist or is still referenced")
+                    }
+                    DatabaseErrorKind::CheckViolation => {
+                        ApiError::new(Status::UnprocessableEntity, "check_violation", "value is not allowed")
+                    }
+                    DatabaseErrorKind::NotNullViolation => {
+                        ApiError::new(Status::UnprocessableEntity, "not_null_violation", "required value is missing")
+                    }
+                    _ => {
+                        error!("Database error: {}", info.message());
+                        return ApiError::internal("database error");
+                    }
+                };
+                ApiError { field, ..error }
+            }
+            other => {
+                error!("Database error: {}", other);
+                ApiError::internal("database error")
+            }
+        }
+    }
+}
+
+// 兼容仍以状态码表示错误的地方
+impl From<Status> for ApiError {
+    fn from(status: Status) -> Self {
+        let code = match status.code {
+            400 => "bad_request",
+            401 => "unauthorized",
+            403 => "forbidden",
+            404 => "not_found",
+            409 => "conflict",
+            422 => "unprocessable_entity",
+            503 => "service_unavailable",
+            500..=599 => "internal_error",
+            _ => "error",
+        };
+        ApiError::new(status, code, status.reason().unwrap_or("error"))
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let body = ErrorBody {
+            code: self.code,
+            message: &self.message,
+            field: self.field.as_deref(),
+            request_id: RequestId::of(request),
+            details: self.details.as_ref(),
+        };
+        Response::build_from(Json(body).respond_to(request)?)
+            .status(self.status)
+            .ok()
+    }
+}
+
+// 守卫失败、路由不存在和请求体无法解析时同样返回 JSON 错误
+#[catch(400)]
+pub fn bad_request() -> ApiError {
+    ApiError::bad_request("malformed request")
+}
+
+#[catch(401)]
+pub fn unauthorized() -> ApiError {
+    ApiError::new(Status::Unauthorized, "unauthorized", "missing or invalid access token")
+}
+
+#[catch(403)]
+pub fn forbidden() -> ApiError {
+    ApiError::new(Status::Forbidden, "forbidden", "permission denied")
+}
+
+#[catch(404)]
+pub fn not_found() -> ApiError {
+    ApiError::not_found("resource not found")
+}
+
+#[catch(422)]
+pub fn unprocessable_entity() -> ApiError {
+    ApiError::new(Status::UnprocessableEntity, "unprocessable_entity", "request body could not be parsed")
+}
+
+#[catch(500)]
+pub fn internal_error() -> ApiError {
+    ApiError::internal("internal server error")
+}
@@ -0,0 +1,99 @@
+use libp2p::gossipsub::IdentTopic;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::models::{Material, MaterialRequest, ProductionTask, WarehouseStock};
+use crate::network_setup::{OutboundMessage, Topic};
+
+// 业务事件的 message_type 为 "event." 加事件名，按所属领域发布到对应主题
+pub const EVENT_MESSAGE_PREFIX: &str = "event.";
+
+// 本地提交成功后广播的业务事件，记录中的自增主键只在发出节点有效
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum DomainEvent {
+    MaterialCreated(Material),
+    MaterialUpdated(Material),
+    MaterialDeleted { global_id: Option<String> },
+    MaterialRestored(Material),
+    // 所有库存流水（入库、出库、调整、调拨），stock 为变动后的余额
+    StockAdjusted { movement_type: String, quantity_change: i32, stock: WarehouseStock },
+    RequestCreated(MaterialRequest),
+    RequestApproved(MaterialRequest),
+    RequestRejected(MaterialRequest),
+    RequestCancelled(MaterialRequest),
+    TaskStatusChanged { previous_status: Option<String>, task: ProductionTask },
+}
+
+impl DomainEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DomainEvent::MaterialCreated(_) => "material_created",
+            DomainEvent::MaterialUpdated(_) => "material_updated",
+            DomainEvent::MaterialDeleted { .. } => "material_deleted",
+            DomainEvent::MaterialRestored(_) => "material_restored",
+            DomainEvent::StockAdjusted { .. } => "stock_adjusted",
+            DomainEvent::RequestCreated(_) => "request_created",
+            DomainEvent::RequestApproved(_) => "request_approved",
+            DomainEvent::RequestRejected(_) => "request_rejected",
+            DomainEvent::RequestCancelled(_) => "request_cancelled",
+            DomainEvent::TaskStatusChanged { .. } => "task_status_changed",
+        }
+    }
+
+    pub fn topic(&self) -> &'static str {
+        match self {
+            DomainEvent::MaterialCreated(_)
+            | DomainEvent::MaterialUpdated(_)
+            | DomainEvent::MaterialDeleted { .. }
+            | DomainEvent::MaterialRestored(_) => Topic::Catalog.name(),
+            DomainEvent::StockAdjusted { .. } => Topic::Stock.name(),
+            DomainEvent::RequestCreated(_)
+            | DomainEvent::RequestApproved(_)
+            | DomainEvent::RequestRejected(_)
+            | DomainEvent::RequestCancelled(_) => Topic::Requests.name(),
+            DomainEvent::TaskStatusChanged { .. } => Topic::Production.name(),
+        }
+    }
+
+    pub fn message_type(&self) -> String {
+        format!("{}{}", EVENT_MESSAGE_PREFIX, self.name())
+    }
+}
+
+// Rocket 中托管的事件发送端，消息经 mpsc 通道交给 swarm 任务广播
+pub struct EventSender(mpsc::Sender<OutboundMessage>);
+
+impl EventSender {
+    pub fn new(sender: mpsc::Sender<OutboundMessage>) -> Self {
+        EventSender(sender)
+    }
+
+    // 只在事务提交后调用；广播失败只记录日志
+    pub async fn emit(&self, event: DomainEvent) {
+        let content = match serde_json::to_string(&event) {
+            Ok(content) => content,
+            Err(e) => {
+                error!("Failed to serialize {} event: {}", event.name(), e);
+                return;
+            }
+        };
+
+        let outbound = OutboundMessage {
+            topic: IdentTopic::new(event.topic()),
+            message_type: event.message_type(),
+            content,
+        };
+        if let Err(e) = self.0.send(outbound).await {
+            error!("Failed to queue {} event: {}", event.name(), e);
+        }
+    }
+}
+
+// 处理其他节点的业务事件，目前只记录日志
+pub fn handle(sender: &str, content: &str) -> Result<(), serde_json::Error> {
+    let event = serde_json::from_str::<DomainEvent>(content)?;
+    info!("Event {} from peer {}: {:?}", event.name(), sender, event);
+    Ok(())
+}
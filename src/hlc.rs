@@ -0,0 +1,183 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use log::warn;
+
+// 远端时钟最多允许领先本地物理时钟的毫秒数，超出时不推进本地时钟
+const MAX_DRIFT_MS: u64 = 60_000;
+
+// 时钟状态：(物理毫秒, 逻辑计数)
+type Clock = (u64, u32);
+
+// 本节点的时钟状态
+static CLOCK: Mutex<Clock> = Mutex::new((0, 0));
+
+// 混合逻辑时钟。字段顺序即比较顺序，peer id 用于打破平局。
+// 字符串形式为定长十六进制，可直接存入 TEXT 列；比较时应解析后用 compare，不直接比较字符串
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Hlc {
+    pub wall_ms: u64,
+    pub counter: u32,
+    pub peer: String,
+}
+
+impl Hlc {
+    pub fn parse(value: &str) -> Option<Hlc> {
+        let mut parts = value.splitn(3, '-');
+        let wall_ms = u64::from_str_radix(parts.next()?, 16).ok()?;
+        let counter = u32::from_str_radix(parts.next()?, 16).ok()?;
+        let peer = parts.next()?.to_string();
+        Some(Hlc { wall_ms, counter, peer })
+    }
+}
+
+impl fmt::Display for Hlc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}-{:08x}-{}", self.wall_ms, self.counter, self.peer)
+    }
+}
+
+// 按 (物理时间, 计数, peer) 比较两个时钟字符串。
+// 没有时钟的排在最前，无法解析的排在能解析的之前，再按原字符串区分
+pub fn compare(a: Option<&str>, b: Option<&str>) -> Ordering {
+    let a = a.map(|value| (Hlc::parse(value), value));
+    let b = b.map(|value| (Hlc::parse(value), value));
+    a.cmp(&b)
+}
+
+fn physical_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_millis() as u64
+}
+
+// 计数加一，计数用尽时进位到下一毫秒，时钟仍单调递增
+fn bump(wall_ms: u64, counter: u32) -> Clock {
+    match counter.checked_add(1) {
+        Some(counter) => (wall_ms, counter),
+        None => (wall_ms + 1, 0),
+    }
+}
+
+fn tick_at(clock: &mut Clock, now: u64) {
+    *clock = if now > clock.0 { (now, 0) } else { bump(clock.0, clock.1) };
+}
+
+// 远端时钟领先过多时不推进本地时钟，返回 false
+fn observe_at(clock: &mut Clock, remote: &Hlc, now: u64) -> bool {
+    if remote.wall_ms > now + MAX_DRIFT_MS {
+        return false;
+    }
+
+    let (wall_ms, counter) = *clock;
+    let new_wall_ms = now.max(wall_ms).max(remote.wall_ms);
+    *clock = if new_wall_ms == wall_ms && new_wall_ms == remote.wall_ms {
+        bump(new_wall_ms, counter.max(remote.counter))
+    } else if new_wall_ms == wall_ms {
+        bump(wall_ms, counter)
+    } else if new_wall_ms == remote.wall_ms {
+        bump(remote.wall_ms, remote.counter)
+    } else {
+        (new_wall_ms, 0)
+    };
+    true
+}
+
+// 为本地写入生成新的时间戳
+pub fn tick(peer: &str) -> Hlc {
+    let mut clock = CLOCK.lock().unwrap();
+    tick_at(&mut clock, physical_ms());
+    Hlc {
+        wall_ms: clock.0,
+        counter: clock.1,
+        peer: peer.to_string(),
+    }
+}
+
+// 收到远端时间戳后推进本地时钟，保证之后的本地写入排在其后
+pub fn observe(remote: &Hlc) {
+    let now = physical_ms();
+    if !observe_at(&mut CLOCK.lock().unwrap(), remote, now) {
+        warn!("Ignoring clock from peer {} that is {} ms ahead", remote.peer, remote.wall_ms - now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hlc(wall_ms: u64, counter: u32, peer: &str) -> Hlc {
+        Hlc { wall_ms, counter, peer: peer.to_string() }
+    }
+
+    #[test]
+    fn parse_round_trips_display() {
+        let value = hlc(1_700_000_000_000, 42, "12D3KooWpeer");
+        assert_eq!(Hlc::parse(&value.to_string()), Some(value));
+        assert_eq!(Hlc::parse("not-a-clock"), None);
+    }
+
+    #[test]
+    fn compare_orders_by_wall_counter_then_peer() {
+        let ordered = [
+            hlc(1, 5, "b"),
+            hlc(2, 0, "a"),
+            hlc(2, 1, "a"),
+            hlc(2, 1, "b"),
+        ]
+        .map(|value| value.to_string());
+        for pair in ordered.windows(2) {
+            assert_eq!(compare(Some(&pair[0]), Some(&pair[1])), Ordering::Less);
+        }
+        assert_eq!(compare(Some(&ordered[0]), Some(&ordered[0])), Ordering::Equal);
+        assert_eq!(compare(None, Some(&ordered[0])), Ordering::Less);
+        assert_eq!(compare(Some("garbage"), Some(&ordered[0])), Ordering::Less);
+    }
+
+    #[test]
+    fn tick_is_monotonic_when_physical_clock_goes_back() {
+        let mut clock = (0, 0);
+        tick_at(&mut clock, 1_000);
+        assert_eq!(clock, (1_000, 0));
+        tick_at(&mut clock, 1_000);
+        assert_eq!(clock, (1_000, 1));
+        tick_at(&mut clock, 900);
+        assert_eq!(clock, (1_000, 2));
+        tick_at(&mut clock, 1_001);
+        assert_eq!(clock, (1_001, 0));
+    }
+
+    #[test]
+    fn observe_moves_past_remote_clock() {
+        let mut clock = (1_000, 3);
+        assert!(observe_at(&mut clock, &hlc(2_000, 7, "peer"), 1_500));
+        assert_eq!(clock, (2_000, 8));
+        assert!(observe_at(&mut clock, &hlc(2_000, 2, "peer"), 1_500));
+        assert_eq!(clock, (2_000, 9));
+        // 物理时钟已超过双方时，计数归零
+        assert!(observe_at(&mut clock, &hlc(2_500, 1, "peer"), 3_000));
+        assert_eq!(clock, (3_000, 0));
+    }
+
+    #[test]
+    fn observe_ignores_remote_clock_too_far_ahead() {
+        let mut clock = (1_000, 0);
+        assert!(!observe_at(&mut clock, &hlc(1_000 + MAX_DRIFT_MS + 1, 0, "peer"), 1_000));
+        assert_eq!(clock, (1_000, 0));
+        assert!(observe_at(&mut clock, &hlc(1_000 + MAX_DRIFT_MS, 0, "peer"), 1_000));
+        assert_eq!(clock, (1_000 + MAX_DRIFT_MS, 1));
+    }
+
+    #[test]
+    fn counter_overflow_carries_into_next_millisecond() {
+        let mut clock = (1_000, u32::MAX);
+        tick_at(&mut clock, 1_000);
+        assert_eq!(clock, (1_001, 0));
+
+        let mut clock = (1_000, 5);
+        assert!(observe_at(&mut clock, &hlc(1_000, u32::MAX, "peer"), 1_000));
+        assert_eq!(clock, (1_001, 0));
+    }
+}
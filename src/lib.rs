@@ -4,12 +4,26 @@ pub mod schema;
 pub mod models;
 
 pub mod admin_init;
+pub mod audit;
+pub mod audit_chain;
 pub mod rocket_config;
 pub mod claims;
 pub mod token;
+pub mod auth_guard;
 pub mod migrations;
 pub mod db;
+pub mod error;
+pub mod events;
 pub mod warehouse;
+pub mod hlc;
+pub mod message_auth;
+pub mod network_setup;
+pub mod network_state;
+pub mod outbox;
+pub mod replication;
+pub mod soft_delete;
+pub mod sync_protocol;
+pub mod transfer_protocol;
 pub mod routers;
 
 
@@ -1,9 +1,8 @@
-extern crate base64;
-extern crate rocket;
+use std::env;
 use std::error::Error;
 use chrono::Local;
-use libp2p::floodsub::Topic;
 use async_std::sync::Arc;
+use libp2p::PeerId;
 use log::LevelFilter;
 use log::{error, info, warn};
 use log4rs::append::console::ConsoleAppender;
@@ -17,17 +16,7 @@ use tokio::signal;
 use tokio::sync::mpsc;
 use tokio::sync::Mutex;
 use tokio::task;
-mod admin_init;
-mod claims;
-mod db;
-mod migrations;
-pub mod models;
-mod network_setup;
-mod rocket_config;
-pub mod schema;
-mod token;
-mod warehouse;
-mod routers;
+use app1::{audit_chain, db, events, migrations, network_setup, network_state, outbox, replication, rocket_config, soft_delete};
 
 #[tokio::main]
 async fn main() -> StdResult<(), Box<dyn Error>> {
@@ -61,56 +50,106 @@ async fn main() -> StdResult<(), Box<dyn Error>> {
     // 初始化 log4rs 配置
     init_config(logconfig).unwrap();
 
-    let (tx, mut rx) = mpsc::channel::<(Topic, String)>(32);
+    // --no-network 只提供 HTTP 接口，不启动 libp2p swarm
+    let no_network = env::args().skip(1).any(|arg| arg == "--no-network");
 
-    // 调用 setup_network 函数
-    let swarm = network_setup::setup_network().await?;
-    let swarm = Arc::new(Mutex::new(swarm)); // 包裹在 Arc 和 Mutex 中
-    let (shutdown_tx, shutdown_rx) = tokio::sync::broadcast::channel(1);
+    // 在 Rocket 点火前完成迁移，AdminInit 等 fairing 依赖迁移创建的表
+    let mut connection = db::establish_connection()?;
+    migrations::run_db_migrations(&mut connection).await?;
 
-    // 在 swarm 处理中添加关闭信号监听
-    let swarm_handle = task::spawn({
-        let swarm = Arc::clone(&swarm);
+    let (tx, mut rx) = mpsc::channel::<network_setup::OutboundMessage>(32);
+    let (shutdown_tx, _) = tokio::sync::broadcast::channel::<()>(1);
+
+    let (network, swarm_handle) = if no_network {
+        warn!("Network disabled by --no-network, outbound messages are kept in the outbox");
+        let local_peer_id = replication::local_peer_id(&mut connection)?.parse::<PeerId>()?;
+        // 没有 swarm 任务接收命令，网络操作接口返回 503
+        let (network, _) = network_state::NetworkHandle::new(local_peer_id, &network_setup::subscribed_topics());
         let mut shutdown_rx = shutdown_tx.subscribe();
-        async move {
+        let outbox_handle = task::spawn(async move {
             loop {
                 tokio::select! {
                     _ = shutdown_rx.recv() => {
-                        info!("Shutting down swarm...");
+                        info!("Shutting down outbox writer...");
                         break;
                     }
-                    Some((topic, message)) = rx.recv() => {
-                        info!("Processing message for topic: {:?}", topic);
-                        let mut swarm = swarm.lock().await;
-                        // 直接调用 send_message，不需要 match
-                        let _ = swarm.behaviour_mut().send_message(topic, message);
-                        info!("Message sent through swarm");
-                    }
-                    result = async {
-                        let mut swarm_guard = swarm.lock().await;
-                        network_setup::run_swarm(&mut *swarm_guard).await
-                    } => {
+                    Some(message) = rx.recv() => {
+                        // 写入 outbox，之后联网启动时再发送
+                        let result = db::establish_connection()
+                            .map_err(|e| e.to_string())
+                            .and_then(|mut c| outbox::enqueue(&mut c, message).map_err(|e| e.to_string()));
                         if let Err(e) = result {
-                            error!("Swarm launch failed: {}", e);
+                            error!("Failed to queue message: {}", e);
+                        }
+                    }
+                }
+            }
+        });
+        (network, outbox_handle)
+    } else {
+        // 调用 setup_network 函数
+        let swarm = network_setup::setup_network().await?;
+        // 共享网络状态供 HTTP 接口查询，拨号/断开请求经 command_rx 交给 swarm 任务
+        let (network, mut command_rx) =
+            network_state::NetworkHandle::new(*swarm.local_peer_id(), &network_setup::subscribed_topics());
+        let swarm = Arc::new(Mutex::new(swarm)); // 包裹在 Arc 和 Mutex 中
+
+        // 在 swarm 处理中添加关闭信号监听
+        let swarm_handle = task::spawn({
+            let swarm = Arc::clone(&swarm);
+            let network = network.clone();
+            let mut shutdown_rx = shutdown_tx.subscribe();
+            async move {
+                loop {
+                    tokio::select! {
+                        _ = shutdown_rx.recv() => {
+                            info!("Shutting down swarm...");
                             break;
                         }
+                        Some(message) = rx.recv() => {
+                            info!("Processing message for topic: {:?}", message.topic);
+                            let mut swarm = swarm.lock().await;
+                            // 先写入 outbox，没有节点在线时之后重发
+                            if let Err(e) = network_setup::queue_message(&mut swarm, message) {
+                                error!("Failed to queue message: {:?}", e);
+                            }
+                        }
+                        Some(command) = command_rx.recv() => {
+                            let mut swarm = swarm.lock().await;
+                            network_setup::handle_command(&mut swarm, command);
+                        }
+                        result = async {
+                            let mut swarm_guard = swarm.lock().await;
+                            network_setup::run_swarm(&mut swarm_guard, &network).await
+                        } => {
+                            if let Err(e) = result {
+                                error!("Swarm launch failed: {}", e);
+                                break;
+                            }
+                        }
                     }
                 }
             }
-        }
-    });
+        });
+        (network, swarm_handle)
+    };
     let mut swarm_handle = Some(swarm_handle);
+    // 启动用的连接归还连接池
+    drop(connection);
 
-    // 调用 rocket 函数并启动
-    let rocket = rocket::build()
-        .attach(DbConn::fairing())
-        .mount("/api", routes![
-            routers::user::get_users,
-            routers::user::get_user,
-            routers::user::create_user,
-            routers::user::update_user,
-            routers::user::delete_user,
-        ]);
+    // 定期清理超过保留期的软删除记录
+    task::spawn(soft_delete::run_purge_job(shutdown_tx.subscribe()));
+    // 定期清理已送达的 outbox 消息和 inbox 记录
+    task::spawn(outbox::run_prune_job(shutdown_tx.subscribe()));
+    // 定期对操作日志哈希链的链头签名
+    task::spawn(audit_chain::run_signing_job(shutdown_tx.subscribe()));
+
+    // 使用 rocket_config 中的完整配置和路由表
+    let rocket = rocket_config::rocket()
+        .await
+        .manage(replication::ReplicationSender::new(tx.clone()))
+        .manage(events::EventSender::new(tx.clone()))
+        .manage(network);
 
     // 将 Rocket 的启动任务交给 tokio::spawn
     let rocket_handle = tokio::spawn(async move {
@@ -121,29 +160,6 @@ async fn main() -> StdResult<(), Box<dyn Error>> {
 
     // 处理 rocket_handle
     let mut rocket_handle = Some(rocket_handle);
-    #[cfg(debug_assertions)]
-    {
-        let topic = Topic::new("example_topic");
-        let tx_clone = tx.clone();
-        let mut shutdown_rx = shutdown_tx.subscribe();
-        tokio::spawn(async move {
-            loop {
-                tokio::select! {
-                    _ = shutdown_rx.recv() => {
-                        info!("Shutting down debug message sender...");
-                        break;
-                    }
-                    _ = tokio::time::sleep(tokio::time::Duration::from_secs(10)) => {
-                        info!("Sending periodic debug message..."); // 添加发送前的日志
-                        match tx_clone.send((topic.clone(), "定时消息".to_string())).await {
-                            Ok(_) => info!("Successfully sent periodic message"), // 添加发送成功的日志
-                            Err(e) => error!("Failed to send periodic message: {}", e),
-                        }
-                    }
-                }
-            }
-        });
-    }
     // 处理 SIGINT 信号
     tokio::select! {
         _ = signal::ctrl_c() => {
@@ -0,0 +1,148 @@
+use base64::engine::general_purpose;
+use base64::Engine;
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use libp2p::identity::{ed25519, PublicKey};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+use crate::network_setup::NetworkMessage;
+use crate::schema::trusted_peers;
+
+// 消息时间戳与本地时间相差超过该秒数即视为过期
+const MAX_MESSAGE_AGE_SECS: u64 = 300;
+
+// 时间窗口内见过的 (sender:nonce)。窗口外的消息按过期拒绝，所以只需保留窗口内的记录
+static SEEN_NONCES: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+
+// 线上传输的签名信封，signature 针对 payload 的原始字节
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignedMessage {
+    pub payload: String,
+    pub public_key: String,
+    pub signature: String,
+}
+
+#[derive(Debug)]
+pub enum VerifyError {
+    Malformed,
+    BadSignature,
+    SenderMismatch,
+    Expired,
+    Replayed,
+    Untrusted(PeerId),
+    Database(diesel::result::Error),
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::Malformed => write!(f, "malformed signed message"),
+            VerifyError::BadSignature => write!(f, "invalid signature"),
+            VerifyError::SenderMismatch => write!(f, "signer does not match message source"),
+            VerifyError::Expired => write!(f, "message timestamp outside allowed window"),
+            VerifyError::Replayed => write!(f, "nonce already seen"),
+            VerifyError::Untrusted(peer) => write!(f, "peer {} is not trusted", peer),
+            VerifyError::Database(e) => write!(f, "database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+impl From<diesel::result::Error> for VerifyError {
+    fn from(err: diesel::result::Error) -> Self {
+        VerifyError::Database(err)
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
+
+// 节点是否在 trusted_peers 中
+pub fn is_trusted(c: &mut SqliteConnection, peer: &PeerId) -> QueryResult<bool> {
+    diesel::select(diesel::dsl::exists(
+        trusted_peers::table.filter(trusted_peers::peer_id.eq(peer.to_string()))
+    ))
+    .get_result(c)
+}
+
+// 用本节点密钥签名并序列化消息
+pub fn sign(
+    local_key: &ed25519::Keypair,
+    message_type: &str,
+    content: String,
+    message_id: Option<String>,
+) -> Result<Vec<u8>, serde_json::Error> {
+    let sender = PeerId::from(PublicKey::Ed25519(local_key.public()));
+    let message = NetworkMessage {
+        message_type: message_type.to_string(),
+        content,
+        timestamp: now_secs(),
+        sender: sender.to_string(),
+        nonce: Uuid::new_v4().simple().to_string(),
+        message_id,
+    };
+    let payload = serde_json::to_string(&message)?;
+    let signature = local_key.sign(payload.as_bytes());
+
+    serde_json::to_vec(&SignedMessage {
+        public_key: general_purpose::STANDARD.encode(local_key.public().encode()),
+        signature: general_purpose::STANDARD.encode(signature),
+        payload,
+    })
+}
+
+// 校验签名、来源、时间窗口、nonce 和信任列表，全部通过才返回消息
+pub fn verify(c: &mut SqliteConnection, data: &[u8], source: &PeerId) -> Result<NetworkMessage, VerifyError> {
+    let signed = serde_json::from_slice::<SignedMessage>(data).map_err(|_| VerifyError::Malformed)?;
+    let public_key = general_purpose::STANDARD
+        .decode(&signed.public_key)
+        .ok()
+        .and_then(|bytes| ed25519::PublicKey::decode(&bytes).ok())
+        .ok_or(VerifyError::Malformed)?;
+    let signature = general_purpose::STANDARD
+        .decode(&signed.signature)
+        .map_err(|_| VerifyError::Malformed)?;
+
+    if !public_key.verify(signed.payload.as_bytes(), &signature) {
+        return Err(VerifyError::BadSignature);
+    }
+
+    // source 必须与签名者公钥推导出的 PeerId 一致
+    let signer = PeerId::from(PublicKey::Ed25519(public_key));
+    if &signer != source {
+        return Err(VerifyError::SenderMismatch);
+    }
+
+    let message = serde_json::from_str::<NetworkMessage>(&signed.payload).map_err(|_| VerifyError::Malformed)?;
+    if message.sender != signer.to_string() {
+        return Err(VerifyError::SenderMismatch);
+    }
+
+    if !is_trusted(c, &signer)? {
+        return Err(VerifyError::Untrusted(signer));
+    }
+
+    let now = now_secs();
+    if message.timestamp.abs_diff(now) > MAX_MESSAGE_AGE_SECS {
+        return Err(VerifyError::Expired);
+    }
+
+    let mut seen = SEEN_NONCES.lock().unwrap();
+    seen.retain(|_, timestamp| timestamp.abs_diff(now) <= MAX_MESSAGE_AGE_SECS);
+    if seen.insert(format!("{}:{}", message.sender, message.nonce), message.timestamp).is_some() {
+        return Err(VerifyError::Replayed);
+    }
+
+    Ok(message)
+}
@@ -1,14 +1,21 @@
+use std::error::Error;
 use diesel::sqlite::SqliteConnection;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations};
 use log::{error, info};
 use diesel_migrations::MigrationHarness; // 添加此行以引入 MigrationHarness trait
 
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 
-pub async fn run_db_migrations(conn: &mut SqliteConnection) {
-    const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
-    if let Err(err) = conn.run_pending_migrations(MIGRATIONS) {
-        error!("Error running migrations: {}", err);
-    } else {
-        info!("Database migrations executed successfully");
+// 迁移失败时返回错误，服务不应在表结构不完整时启动
+pub async fn run_db_migrations(conn: &mut SqliteConnection) -> Result<(), Box<dyn Error>> {
+    match conn.run_pending_migrations(MIGRATIONS) {
+        Ok(_) => {
+            info!("Database migrations executed successfully");
+            Ok(())
+        }
+        Err(err) => {
+            error!("Error running migrations: {}", err);
+            Err(err as Box<dyn Error>)
+        }
     }
 } 
\ No newline at end of file
@@ -1,13 +1,10 @@
 use chrono::{NaiveDate, NaiveDateTime};
 use diesel::prelude::*;
 use diesel::deserialize::QueryableByName;
-use diesel::sqlite::SqliteConnection;
 use serde::{Deserialize, Serialize};
-use rocket_sync_db_pools::database;
 
-// Add this type alias for database connectio
-#[database("sqlite_db")]  // This matches the database name in your rocket config
-pub struct DbConn(SqliteConnection);
+// 请求中的数据库连接，来自与 swarm 任务共享的连接池
+pub use crate::db::DbConn;
 
 use crate::schema::*;
 
@@ -23,6 +20,8 @@ pub struct Warehouse {
     pub capacity: Option<i32>,
     pub current_stock: Option<i32>,
     pub last_updated: Option<NaiveDateTime>,
+    pub deleted_at: Option<NaiveDateTime>,
+    pub deleted_by: Option<i32>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Insertable)]
@@ -34,7 +33,7 @@ pub struct NewWarehouse {
     pub capacity: Option<i32>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations, QueryableByName)]
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations, QueryableByName)]
 #[diesel(belongs_to(Warehouse))]
 #[diesel(belongs_to(Material))]
 #[diesel(primary_key(warehouse_id, material_id))]
@@ -55,6 +54,158 @@ pub struct NewWarehouseStock {
     pub quantity: i32,
 }
 
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations)]
+#[diesel(belongs_to(Warehouse))]
+#[diesel(belongs_to(Material))]
+#[diesel(table_name = stock_movements)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[diesel(primary_key(movement_id))]
+pub struct StockMovement {
+    pub movement_id: i32,
+    pub warehouse_id: i32,
+    pub material_id: i32,
+    pub movement_type: String,
+    pub quantity: i32,
+    pub request_id: Option<i32>,
+    pub task_id: Option<i32>,
+    pub performed_by: Option<i32>,
+    pub note: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = stock_movements)]
+pub struct NewStockMovement {
+    pub warehouse_id: i32,
+    pub material_id: i32,
+    pub movement_type: String,
+    pub quantity: i32,
+    pub request_id: Option<i32>,
+    pub task_id: Option<i32>,
+    pub performed_by: Option<i32>,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations)]
+#[diesel(belongs_to(Warehouse))]
+#[diesel(belongs_to(Material))]
+#[diesel(table_name = stock_transfers)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[diesel(primary_key(transfer_id))]
+pub struct StockTransfer {
+    pub transfer_id: String,
+    pub direction: String,
+    pub peer_id: String,
+    pub warehouse_id: i32,
+    pub material_id: i32,
+    pub quantity: i32,
+    pub status: String,
+    pub acked: bool,
+    pub attempts: i32,
+    pub last_attempt_at: Option<NaiveDateTime>,
+    pub rejection_reason: Option<String>,
+    pub note: Option<String>,
+    pub requested_by: Option<i32>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = stock_transfers)]
+pub struct NewStockTransfer {
+    pub transfer_id: String,
+    pub direction: String,
+    pub peer_id: String,
+    pub warehouse_id: i32,
+    pub material_id: i32,
+    pub quantity: i32,
+    pub status: String,
+    pub acked: bool,
+    pub rejection_reason: Option<String>,
+    pub note: Option<String>,
+    pub requested_by: Option<i32>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = change_log)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[diesel(primary_key(seq))]
+pub struct ChangeLogEntry {
+    pub seq: i32,
+    pub table_name: String,
+    pub global_id: String,
+    pub hlc: Option<String>,
+    pub deleted: bool,
+    pub changed_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = sync_state)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[diesel(primary_key(peer_id))]
+pub struct SyncState {
+    pub peer_id: String,
+    pub last_seq: i32,
+    pub synced_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = outbox)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[diesel(primary_key(message_id))]
+pub struct OutboxMessage {
+    pub message_id: String,
+    pub topic: String,
+    pub message_type: String,
+    pub content: String,
+    pub status: String,
+    pub attempts: i32,
+    pub last_attempt_at: Option<NaiveDateTime>,
+    pub delivered_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = outbox)]
+pub struct NewOutboxMessage {
+    pub message_id: String,
+    pub topic: String,
+    pub message_type: String,
+    pub content: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations)]
+#[diesel(belongs_to(OutboxMessage, foreign_key = message_id))]
+#[diesel(table_name = outbox_acks)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[diesel(primary_key(message_id, peer_id))]
+pub struct OutboxAck {
+    pub message_id: String,
+    pub peer_id: String,
+    pub acked_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = trusted_peers)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[diesel(primary_key(peer_id))]
+pub struct TrustedPeer {
+    pub peer_id: String,
+    pub label: Option<String>,
+    pub added_by: Option<i32>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = trusted_peers)]
+pub struct NewTrustedPeer {
+    pub peer_id: String,
+    pub label: Option<String>,
+    pub added_by: Option<i32>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Queryable, Selectable, Identifiable)]
 #[diesel(table_name = users)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
@@ -81,6 +232,54 @@ pub struct NewUser {
     pub status: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations)]
+#[diesel(belongs_to(User))]
+#[diesel(table_name = refresh_tokens)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[diesel(primary_key(token_id))]
+pub struct RefreshToken {
+    pub token_id: i32,
+    pub user_id: i32,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub replaced_by: Option<i32>,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = replication_conflicts)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[diesel(primary_key(conflict_id))]
+pub struct ReplicationConflict {
+    pub conflict_id: i32,
+    pub table_name: String,
+    pub global_id: String,
+    pub local_hlc: Option<String>,
+    pub remote_hlc: String,
+    pub local_data: Option<String>,
+    pub remote_data: Option<String>,
+    pub policy: String,
+    pub winner: String,
+    pub resolved_at: NaiveDateTime,
+    pub reviewed_by: Option<i32>,
+    pub reviewed_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = replication_conflicts)]
+pub struct NewReplicationConflict {
+    pub table_name: String,
+    pub global_id: String,
+    pub local_hlc: Option<String>,
+    pub remote_hlc: String,
+    pub local_data: Option<String>,
+    pub remote_data: Option<String>,
+    pub policy: String,
+    pub winner: String,
+    pub resolved_at: NaiveDateTime,
+}
+
 #[derive(Debug, Serialize, Deserialize, Queryable, Selectable, Identifiable)]
 #[diesel(table_name = roles)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
@@ -161,16 +360,58 @@ pub struct OperationLog {
     pub user_id: Option<i32>,
     pub action: Option<String>,
     pub timestamp: Option<NaiveDateTime>,
+    pub method: Option<String>,
+    pub path: Option<String>,
+    pub entity_type: Option<String>,
+    pub entity_id: Option<String>,
+    pub status: Option<i32>,
+    pub changes: Option<String>,
+    pub client_ip: Option<String>,
+    pub prev_hash: Option<String>,
+    pub hash: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Insertable)]
+// 由审计 fairing 写入，客户端不能直接创建。哈希由 audit_chain::append 计算
+#[derive(Debug, Insertable)]
 #[diesel(table_name = operation_logs)]
 pub struct NewOperationLog {
-    pub user_id: i32,
+    pub user_id: Option<i32>,
     pub action: String,
+    pub timestamp: NaiveDateTime,
+    pub method: String,
+    pub path: String,
+    pub entity_type: Option<String>,
+    pub entity_id: Option<String>,
+    pub status: i32,
+    pub changes: Option<String>,
+    pub client_ip: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations)]
+// 对操作日志链头的签名
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable, Identifiable)]
+#[diesel(table_name = operation_log_signatures)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[diesel(primary_key(signature_id))]
+pub struct OperationLogSignature {
+    pub signature_id: Option<i32>,
+    pub log_id: i32,
+    pub hash: String,
+    pub public_key: String,
+    pub signature: String,
+    pub signed_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = operation_log_signatures)]
+pub struct NewOperationLogSignature {
+    pub log_id: i32,
+    pub hash: String,
+    pub public_key: String,
+    pub signature: String,
+    pub signed_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations)]
 #[diesel(belongs_to(ProductSpecification, foreign_key = product_id))]
 #[diesel(belongs_to(User, foreign_key = created_by))]
 #[diesel(table_name = production_tasks)]
@@ -195,7 +436,7 @@ pub struct NewProductionTask {
     pub created_by: i32,
 }
 
-#[derive(Debug, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations)]
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations)]
 #[diesel(belongs_to(User, foreign_key = created_by))]
 #[diesel(table_name = production_costs)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
@@ -206,6 +447,8 @@ pub struct ProductionCost {
     pub cost_per_unit: f64,
     pub created_by: Option<i32>,
     pub created_at: Option<NaiveDateTime>,
+    pub global_id: Option<String>,
+    pub hlc: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Insertable)]
@@ -216,7 +459,7 @@ pub struct NewProductionCost {
     pub created_by: i32,
 }
 
-#[derive(QueryableByName, Debug, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations)]
+#[derive(QueryableByName, Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations)]
 #[diesel(belongs_to(User, foreign_key = created_by))]
 #[diesel(table_name = price_formulas)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
@@ -235,6 +478,10 @@ pub struct PriceFormula {
     pub profit: Option<f64>,
     pub created_by: Option<i32>,
     pub created_at: Option<NaiveDateTime>,
+    pub global_id: Option<String>,
+    pub hlc: Option<String>,
+    pub deleted_at: Option<NaiveDateTime>,
+    pub deleted_by: Option<i32>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Insertable)]
@@ -253,7 +500,7 @@ pub struct NewPriceFormula {
     pub created_by: i32,
 }
 
-#[derive(Debug, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations)]
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations)]
 #[diesel(belongs_to(User, foreign_key = created_by))]
 #[diesel(table_name = product_specifications)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
@@ -267,6 +514,10 @@ pub struct ProductSpecification {
     pub dimensions: Option<String>,
     pub created_by: Option<i32>,
     pub created_at: Option<NaiveDateTime>,
+    pub global_id: Option<String>,
+    pub hlc: Option<String>,
+    pub deleted_at: Option<NaiveDateTime>,
+    pub deleted_by: Option<i32>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Insertable)]
@@ -280,7 +531,7 @@ pub struct NewProductSpecification {
     pub created_by: i32,
 }
 
-#[derive(Debug, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations)]
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations)]
 #[diesel(belongs_to(User, foreign_key = created_by))]
 #[diesel(table_name = materials)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
@@ -293,6 +544,10 @@ pub struct Material {
     pub supplier: Option<String>,
     pub created_by: Option<i32>,
     pub created_at: Option<NaiveDateTime>,
+    pub global_id: Option<String>,
+    pub hlc: Option<String>,
+    pub deleted_at: Option<NaiveDateTime>,
+    pub deleted_by: Option<i32>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Insertable)]
@@ -305,7 +560,7 @@ pub struct NewMaterial {
     pub created_by: i32,
 }
 
-#[derive(Debug, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations)]
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Identifiable, Associations)]
 #[diesel(belongs_to(Material))]
 #[diesel(belongs_to(User, foreign_key = requested_by))]
 #[diesel(belongs_to(Warehouse))]
@@ -320,14 +575,16 @@ pub struct MaterialRequest {
     pub warehouse_id: Option<i32>,
     pub request_date: Option<NaiveDateTime>,
     pub status: Option<String>,
+    pub reviewed_by: Option<i32>,
+    pub reviewed_at: Option<NaiveDateTime>,
+    pub rejection_reason: Option<String>,
 }
 
+// 申请人取自当前登录用户，不由请求体指定
 #[derive(Debug, Serialize, Deserialize, Insertable)]
 #[diesel(table_name = material_requests)]
 pub struct NewMaterialRequest {
     pub material_id: i32,
     pub quantity: i32,
-    pub requested_by: i32,
     pub warehouse_id: i32,
-    pub status: String,
 }
\ No newline at end of file
@@ -1,48 +1,153 @@
 use crate::db;
-use crate::migrations;
+use crate::events;
+use crate::message_auth::{self, VerifyError};
+use crate::network_state::{NetworkCommand, NetworkHandle};
+use crate::outbox::{self, Ack};
+use crate::replication::{self, ReplicationMessage};
+use crate::sync_protocol::{self, SyncBehaviour, SyncCodec, SyncEvent, SyncProtocol};
+use crate::transfer_protocol::{self, TransferBehaviour, TransferCodec, TransferEvent, TransferProtocol};
 use crate::warehouse;
 use futures::stream::StreamExt;
 use libp2p::development_transport;
-use libp2p::floodsub::Floodsub;
-use libp2p::floodsub::FloodsubEvent;
-use libp2p::floodsub::Topic;
+use libp2p::gossipsub::error::PublishError;
+use libp2p::gossipsub::{
+    Gossipsub, GossipsubConfigBuilder, GossipsubEvent, GossipsubMessage, IdentTopic, MessageAcceptance,
+    MessageAuthenticity, MessageId, PeerScoreParams, PeerScoreThresholds, TopicScoreParams,
+    ValidationMode,
+};
 use libp2p::identity::PublicKey;
 use libp2p::kad::store::MemoryStore;
 use libp2p::kad::KademliaEvent;
 use libp2p::kad::{Kademlia, KademliaConfig};
 use libp2p::mdns::MdnsEvent;
 use libp2p::mdns::{Mdns, MdnsConfig};
-use libp2p::ping::{Ping, PingConfig, PingEvent};
+use libp2p::ping::{Ping, PingConfig, PingEvent, PingSuccess};
+use libp2p::request_response::{ProtocolSupport, RequestResponse, RequestResponseConfig};
+use libp2p::swarm::dial_opts::DialOpts;
 use libp2p::swarm::SwarmBuilder;
 use libp2p::swarm::SwarmEvent;
 use libp2p::NetworkBehaviour;
 use libp2p::{identity, Multiaddr, PeerId};
 use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use diesel::sqlite::SqliteConnection;
+use diesel::Connection;
 use std::collections::HashSet;
 use std::env;
 use std::error::Error;
+use std::iter;
+use std::sync::OnceLock;
 use std::time::Duration;
 
+// 按业务领域划分的 gossipsub 主题，复制消息和业务事件发布到所属领域。
+// 主题名可通过环境变量配置，例如 GOSSIP_TOPIC_STOCK=plant-a.stock，未配置时使用领域名
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topic {
+    Stock,
+    Requests,
+    Catalog,
+    Production,
+    // 确认消息使用的主题，确认本身不进 outbox
+    Acks,
+}
+
+impl Topic {
+    // 本节点订阅的主题，订阅后才能收到其他节点的消息
+    pub const ALL: [Topic; 5] = [Topic::Stock, Topic::Requests, Topic::Catalog, Topic::Production, Topic::Acks];
+
+    fn domain(self) -> &'static str {
+        match self {
+            Topic::Stock => "stock",
+            Topic::Requests => "requests",
+            Topic::Catalog => "catalog",
+            Topic::Production => "production",
+            Topic::Acks => "acks",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        &configured_topics()[self as usize]
+    }
+}
+
+static TOPIC_NAMES: OnceLock<Vec<String>> = OnceLock::new();
+
+// 首次使用时读取配置。主题名重复时无法按主题校验消息类型，改用默认名
+fn configured_topics() -> &'static [String] {
+    TOPIC_NAMES.get_or_init(|| {
+        let names: Vec<String> = Topic::ALL
+            .iter()
+            .map(|topic| {
+                let key = format!("GOSSIP_TOPIC_{}", topic.domain().to_uppercase());
+                env::var(&key)
+                    .ok()
+                    .filter(|name| !name.trim().is_empty())
+                    .unwrap_or_else(|| topic.domain().to_string())
+            })
+            .collect();
+        if names.iter().collect::<HashSet<_>>().len() == names.len() {
+            names
+        } else {
+            error!("GOSSIP_TOPIC_* must name distinct topics, using the default topic names");
+            Topic::ALL.iter().map(|topic| topic.domain().to_string()).collect()
+        }
+    })
+}
+
+pub fn subscribed_topics() -> Vec<&'static str> {
+    Topic::ALL.iter().map(|topic| topic.name()).collect()
+}
+
+// 分数低于 graylist 阈值的节点会被断开并加入黑名单，直到本节点重启
+const GRAYLIST_THRESHOLD: f64 = -80.0;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NetworkMessage {
     pub message_type: String,
     pub content: String,
     pub timestamp: u64,
     pub sender: String,
+    // 防重放的随机值
+    pub nonce: String,
+    // outbox 消息的 ID，重发时不变，接收方据此去重并回复确认
+    #[serde(default)]
+    pub message_id: Option<String>,
+}
+
+// 待广播的消息，经 mpsc 通道交给 swarm 任务发送
+#[derive(Debug)]
+pub struct OutboundMessage {
+    pub topic: IdentTopic,
+    pub message_type: String,
+    pub content: String,
 }
 
 pub enum KMBehaviourEvent {
     Mdns(MdnsEvent),
     Kademlia(KademliaEvent),
     Ping(PingEvent),
-    Floodsub(FloodsubEvent),
+    Gossipsub(GossipsubEvent),
+    Transfer(TransferEvent),
+    Sync(SyncEvent),
 }
 
 // 为 KMBehaviourEvent ��现 From 特征
-impl From<FloodsubEvent> for KMBehaviourEvent {
-    fn from(event: FloodsubEvent) -> Self {
-        KMBehaviourEvent::Floodsub(event)
+impl From<GossipsubEvent> for KMBehaviourEvent {
+    fn from(event: GossipsubEvent) -> Self {
+        KMBehaviourEvent::Gossipsub(event)
+    }
+}
+
+impl From<TransferEvent> for KMBehaviourEvent {
+    fn from(event: TransferEvent) -> Self {
+        KMBehaviourEvent::Transfer(event)
+    }
+}
+
+impl From<SyncEvent> for KMBehaviourEvent {
+    fn from(event: SyncEvent) -> Self {
+        KMBehaviourEvent::Sync(event)
     }
 }
 
@@ -70,109 +175,236 @@ pub struct KMBehaviour {
     pub kademlia: Kademlia<MemoryStore>,
     pub mdns: Mdns,
     pub ping: Ping,
-    pub floodsub: Floodsub,
+    pub gossipsub: Gossipsub,
+    // 仓库间调拨使用点对点的请求-应答协议
+    pub transfer: TransferBehaviour,
+    // 新节点加入时从对端追赶已有数据
+    pub sync: SyncBehaviour,
 }
 
 impl KMBehaviour {
     // 初始化订阅主题
-    pub fn init_subscriptions(&mut self, topics: Vec<&str>) {
+    pub fn init_subscriptions(&mut self, topics: Vec<&str>) -> Result<(), Box<dyn Error>> {
         for topic_str in topics {
-            let topic = Topic::new(topic_str);
-            self.floodsub.subscribe(topic);
+            self.gossipsub.subscribe(&IdentTopic::new(topic_str))?;
         }
+        Ok(())
     }
 
-    // 发送消息的增强版本
-    pub fn send_message(&mut self, topic: Topic, content: String) -> Result<(), Box<dyn Error>> {
+    // 发送消息的增强版本，消息用本节点密钥签名
+    pub fn send_message(&mut self, topic: IdentTopic, message_type: &str, content: String) -> Result<(), Box<dyn Error>> {
         let mut connection = db::establish_connection()?;
         let local_key = warehouse::get_warehouse_id(&mut connection)?;
-        let local_peer_id = PeerId::from(PublicKey::Ed25519(local_key.public()));
-        let message = NetworkMessage {
-            message_type: "generic".to_string(),
-            content,
-            timestamp: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-            sender: local_peer_id.to_string(),
-        };
-        // 序列化消息
-        match serde_json::to_vec(&message) {
-            Ok(message_bytes) => {
-                self.floodsub.publish(topic, message_bytes);
-                Ok(())
-            }
-            Err(e) => Err(Box::new(e)),
-        }
+        let message_bytes = message_auth::sign(&local_key, message_type, content, None)?;
+        self.gossipsub.publish(topic, message_bytes)?;
+        Ok(())
     }
 
-    pub fn handle_message(&self, message: &[u8], source: &PeerId) -> Result<(), Box<dyn Error>> {
-        let mut connection = db::establish_connection()?;
-        let local_key = warehouse::get_warehouse_id(&mut connection)?;
-        let local_peer_id = PeerId::from(PublicKey::Ed25519(local_key.public()));
-        // 如果是自己发送的消息，则忽略
-        if source == &local_peer_id {
-            debug!("Ignoring self-sent message");
+    // 处理已通过 validate_message 校验的消息
+    pub fn handle_message(
+        &mut self,
+        connection: &mut SqliteConnection,
+        network_message: NetworkMessage,
+        source: &PeerId,
+    ) -> Result<(), Box<dyn Error>> {
+        info!(
+            "Received message from peer {}: {:?}",
+            source, network_message
+        );
+
+        if network_message.message_type == outbox::ACK_MESSAGE_TYPE {
+            let ack = serde_json::from_str::<Ack>(&network_message.content)?;
+            outbox::record_ack(connection, &ack.message_id, source)?;
             return Ok(());
         }
 
-        match serde_json::from_slice::<NetworkMessage>(message) {
-            Ok(network_message) => {
-                info!(
-                    "Received message from peer {}: {:?}",
-                    source, network_message
-                );
-
-                match network_message.message_type.as_str() {
-                    "generic" => {
-                        info!("Generic message content: {}", network_message.content);
-                        Ok(())
-                    }
-                    "discovery" => {
-                        info!("Discovery message from peer: {}", source);
-                        // 这里可以添加对等点发现逻辑
-                        Ok(())
-                    }
-                    _ => {
-                        warn!("Unknown message type: {}", network_message.message_type);
-                        Ok(())
-                    }
+        // 去重检查、应用消息和记录已处理在同一事务内，崩溃时不会丢失或重复处理
+        let message_id = network_message.message_id.clone();
+        connection.transaction::<_, Box<dyn Error>, _>(|c| {
+            // 重发的消息已处理过，只需再次确认
+            if let Some(message_id) = &message_id {
+                if outbox::already_received(c, message_id)? {
+                    debug!("Message {} already processed", message_id);
+                    return Ok(());
+                }
+            }
+
+            match network_message.message_type.as_str() {
+                replication::REPLICATION_MESSAGE_TYPE => {
+                    let replication_message =
+                        serde_json::from_str::<ReplicationMessage>(&network_message.content)?;
+                    replication::apply(c, replication_message)?;
+                }
+                message_type if message_type.starts_with(events::EVENT_MESSAGE_PREFIX) => {
+                    events::handle(&network_message.sender, &network_message.content)?;
+                }
+                _ => {
+                    warn!("Unknown message type: {}", network_message.message_type);
                 }
             }
-            Err(e) => Err(Box::new(e)),
+
+            if let Some(message_id) = &message_id {
+                outbox::mark_received(c, message_id, &network_message.sender)?;
+            }
+            Ok(())
+        })?;
+
+        // 提交后才确认，失败时发送方会重发
+        match message_id {
+            Some(message_id) => self.send_ack(message_id),
+            None => Ok(()),
         }
     }
 
-    // 添加节点发现广播
-    pub fn broadcast_discovery(&mut self) -> Result<(), Box<dyn Error>> {
-        let mut connection = db::establish_connection()?;
-        let local_key = warehouse::get_warehouse_id(&mut connection)?;
-        let local_peer_id = PeerId::from(PublicKey::Ed25519(local_key.public()));
-        let message = NetworkMessage {
-            message_type: "discovery".to_string(),
-            content: "Node discovery broadcast".to_string(),
-            timestamp: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-            sender: local_peer_id.to_string(),
-        };
-
-        let topic = Topic::new("discovery");
-        if let Ok(message_bytes) = serde_json::to_vec(&message) {
-            self.floodsub.publish(topic, message_bytes);
-            info!("Sent discovery broadcast");
+    fn send_ack(&mut self, message_id: String) -> Result<(), Box<dyn Error>> {
+        let content = serde_json::to_string(&Ack { message_id })?;
+        self.send_message(IdentTopic::new(Topic::Acks.name()), outbox::ACK_MESSAGE_TYPE, content)
+    }
+}
+
+// 消息内容的哈希作为 gossipsub 的消息 ID，同一消息从不同节点转发来时只处理一次
+fn message_id(message: &GossipsubMessage) -> MessageId {
+    MessageId::from(Sha256::digest(&message.data).to_vec())
+}
+
+// 消息类型必须与发布的主题一致，防止借用其他领域的主题转发
+fn matches_topic(topic: &str, network_message: &NetworkMessage) -> bool {
+    let message_type = network_message.message_type.as_str();
+    if topic == Topic::Acks.name() || message_type == outbox::ACK_MESSAGE_TYPE {
+        return topic == Topic::Acks.name() && message_type == outbox::ACK_MESSAGE_TYPE;
+    }
+
+    if message_type == replication::REPLICATION_MESSAGE_TYPE {
+        return serde_json::from_str::<ReplicationMessage>(&network_message.content)
+            .is_ok_and(|message| message.topic() == topic);
+    }
+
+    if message_type.starts_with(events::EVENT_MESSAGE_PREFIX) {
+        return serde_json::from_str::<events::DomainEvent>(&network_message.content)
+            .is_ok_and(|event| event.topic() == topic && event.message_type() == message_type);
+    }
+
+    false
+}
+
+// gossipsub 转发前的校验。Reject 会降低转发节点的分数，Ignore 只是不再转发
+fn validate_message(connection: &mut SqliteConnection, message: &GossipsubMessage) -> Result<NetworkMessage, MessageAcceptance> {
+    let source = message.source.as_ref().ok_or(MessageAcceptance::Reject)?;
+
+    // 未签名、签名无效、重放或不在信任列表中的消息不处理也不转发
+    let network_message = message_auth::verify(connection, &message.data, source).map_err(|e| {
+        warn!("Dropping message from peer {}: {}", source, e);
+        match e {
+            VerifyError::Untrusted(_) | VerifyError::Expired | VerifyError::Database(_) => MessageAcceptance::Ignore,
+            _ => MessageAcceptance::Reject,
         }
-        Ok(())
+    })?;
+
+    if !matches_topic(message.topic.as_str(), &network_message) {
+        warn!(
+            "Dropping {} message from peer {} on topic {}",
+            network_message.message_type, source, message.topic
+        );
+        return Err(MessageAcceptance::Reject);
     }
+
+    Ok(network_message)
+}
+
+// 先上报校验结果让 gossipsub 决定是否转发，再处理通过校验的消息
+fn handle_gossip(
+    swarm: &mut SwarmType,
+    propagation_source: &PeerId,
+    message_id: &MessageId,
+    message: &GossipsubMessage,
+) -> Result<(), Box<dyn Error>> {
+    // 取不到数据库连接时同样要给出校验结果，否则消息会一直等到校验超时
+    let mut connection = match db::establish_connection() {
+        Ok(connection) => connection,
+        Err(e) => {
+            swarm
+                .behaviour_mut()
+                .gossipsub
+                .report_message_validation_result(message_id, propagation_source, MessageAcceptance::Ignore)?;
+            return Err(e.into());
+        }
+    };
+    let (acceptance, network_message) = match validate_message(&mut connection, message) {
+        Ok(network_message) => (MessageAcceptance::Accept, Some(network_message)),
+        Err(acceptance) => (acceptance, None),
+    };
+    swarm
+        .behaviour_mut()
+        .gossipsub
+        .report_message_validation_result(message_id, propagation_source, acceptance)?;
+
+    match (network_message, &message.source) {
+        (Some(network_message), Some(source)) => {
+            swarm.behaviour_mut().handle_message(&mut connection, network_message, source)
+        }
+        _ => Ok(()),
+    }
+}
+
+// 分数过低的节点多次转发了无效消息，断开并拉黑
+fn drop_misbehaving_peers(swarm: &mut SwarmType, network: &NetworkHandle) {
+    let peers: Vec<PeerId> = swarm.connected_peers().cloned().collect();
+    for peer_id in peers {
+        let score = swarm.behaviour().gossipsub.peer_score(&peer_id);
+        network.score(&peer_id, score);
+        if score.is_some_and(|score| score < GRAYLIST_THRESHOLD) {
+            warn!("Disconnecting misbehaving peer {:?} with score {:?}", peer_id, score);
+            swarm.behaviour_mut().gossipsub.blacklist_peer(&peer_id);
+            let _ = swarm.disconnect_peer_id(peer_id);
+        }
+    }
+}
+
+// 低流量网络中不按转发数量扣分，只对无效消息扣分
+fn topic_score_params() -> TopicScoreParams {
+    TopicScoreParams {
+        topic_weight: 1.0,
+        time_in_mesh_weight: 0.01,
+        time_in_mesh_quantum: Duration::from_secs(1),
+        time_in_mesh_cap: 3600.0,
+        mesh_message_deliveries_weight: 0.0,
+        mesh_failure_penalty_weight: 0.0,
+        invalid_message_deliveries_weight: -10.0,
+        invalid_message_deliveries_decay: 0.3,
+        ..TopicScoreParams::default()
+    }
+}
+
+fn build_gossipsub(local_key: &identity::ed25519::Keypair) -> Result<Gossipsub, Box<dyn Error>> {
+    let config = GossipsubConfigBuilder::default()
+        .validation_mode(ValidationMode::Strict)
+        // 校验通过后才转发
+        .validate_messages()
+        .message_id_fn(message_id)
+        .build()?;
+    let mut gossipsub = Gossipsub::new(
+        MessageAuthenticity::Signed(identity::Keypair::Ed25519(local_key.clone())),
+        config,
+    )?;
+
+    let mut params = PeerScoreParams::default();
+    for topic in subscribed_topics() {
+        params.topics.insert(IdentTopic::new(topic).hash(), topic_score_params());
+    }
+    let thresholds = PeerScoreThresholds {
+        graylist_threshold: GRAYLIST_THRESHOLD,
+        ..PeerScoreThresholds::default()
+    };
+    gossipsub.with_peer_score(params, thresholds)?;
+    Ok(gossipsub)
 }
 
 pub type SwarmType = libp2p::swarm::Swarm<KMBehaviour>; // 定义 SwarmType 为 libp2p::swarm::Swarm<KMBehaviour>
 
+// 调用前需先执行数据库迁移
 pub async fn setup_network() -> Result<SwarmType, Box<dyn Error>> {
  // 创建本地PeerId
     let mut connection = db::establish_connection()?;
-    migrations::run_db_migrations(&mut connection).await;
     let local_key = match warehouse::get_warehouse_id(&mut connection) {
         Ok(id) => id,
         Err(err) => {
@@ -182,26 +414,40 @@ pub async fn setup_network() -> Result<SwarmType, Box<dyn Error>> {
     };
     let local_peer_id = PeerId::from(PublicKey::Ed25519(local_key.public()));
     info!("Local peer id: {:?}", local_peer_id);
-    let floodsub = Floodsub::new(local_peer_id.clone());
+    let gossipsub = build_gossipsub(&local_key)?;
     // 创建传输层
     let transport = development_transport(libp2p::identity::Keypair::Ed25519(local_key)).await?;
 
     // 创建Kademlia DHT
-    let store = MemoryStore::new(local_peer_id.clone());
+    let store = MemoryStore::new(local_peer_id);
     let kademlia_config = KademliaConfig::default();
-    let kademlia = Kademlia::with_config(local_peer_id.clone(), store, kademlia_config);
+    let kademlia = Kademlia::with_config(local_peer_id, store, kademlia_config);
 
     // 创建mDNS
     let mdns = Mdns::new(MdnsConfig::default()).await?;
 
     let ping = Ping::new(PingConfig::new().with_keep_alive(true));
 
+    let transfer = RequestResponse::new(
+        TransferCodec,
+        iter::once((TransferProtocol, ProtocolSupport::Full)),
+        RequestResponseConfig::default(),
+    );
+
+    let sync = RequestResponse::new(
+        SyncCodec,
+        iter::once((SyncProtocol, ProtocolSupport::Full)),
+        RequestResponseConfig::default(),
+    );
+
     // 创建网络行为
     let behaviour = KMBehaviour {
         kademlia,
         mdns,
         ping,
-        floodsub,
+        gossipsub,
+        transfer,
+        sync,
     };
 
     // 构建Swarm
@@ -211,6 +457,9 @@ pub async fn setup_network() -> Result<SwarmType, Box<dyn Error>> {
         }))
         .build();
 
+    // 订阅主题，否则收不到其他节点的消息
+    swarm.behaviour_mut().init_subscriptions(subscribed_topics())?;
+
     // 获取环境变量中的 bootstrap_peer_id
     let bootstrap_peer_id_str = match env::var("BOOTSTRAP_PEER_ID") {
         Ok(val) => {
@@ -281,24 +530,116 @@ pub async fn setup_network() -> Result<SwarmType, Box<dyn Error>> {
     Ok(swarm) // 返回 Swarm 实例
 }
 
-pub async fn run_swarm(swarm: &mut SwarmType) -> Result<(), Box<dyn Error + Send + 'static>> {
-    let mut discovered_peers = HashSet::new();
-    let mut discovery_interval = tokio::time::interval(Duration::from_secs(30));
+// 消息先写入 outbox 再尝试发送，确认前会一直重发
+pub fn queue_message(swarm: &mut SwarmType, message: OutboundMessage) -> Result<(), Box<dyn Error>> {
+    let mut connection = db::establish_connection()?;
+    let message_id = outbox::enqueue(&mut connection, message)?;
+    debug!("Queued outbox message {}", message_id);
+    flush_outbox(swarm)
+}
+
+// 发送到期的 outbox 消息。主题上没有节点时不计入重试次数
+pub fn flush_outbox(swarm: &mut SwarmType) -> Result<(), Box<dyn Error>> {
+    if swarm.connected_peers().next().is_none() {
+        return Ok(());
+    }
+
+    let mut connection = db::establish_connection()?;
+    let local_key = warehouse::get_warehouse_id(&mut connection)?;
+    for message in outbox::due(&mut connection)? {
+        let message_bytes = message_auth::sign(
+            &local_key,
+            &message.message_type,
+            message.content.clone(),
+            Some(message.message_id.clone()),
+        )?;
+        match swarm.behaviour_mut().gossipsub.publish(IdentTopic::new(message.topic.clone()), message_bytes) {
+            Ok(_) => outbox::mark_attempt(&mut connection, &message)?,
+            Err(PublishError::InsufficientPeers) => debug!("No peers on topic {} yet", message.topic),
+            Err(e) => return Err(Box::new(e)),
+        }
+    }
+    Ok(())
+}
+
+// 节点重新连接后，让它尚未确认的消息在下一轮立即重发
+fn reschedule_outbox(peer_id: &PeerId) -> Result<(), Box<dyn Error>> {
+    let mut connection = db::establish_connection()?;
+    outbox::peer_reconnected(&mut connection, peer_id)?;
+    Ok(())
+}
+
+// 执行 HTTP 侧请求的拨号或断开操作
+pub fn handle_command(swarm: &mut SwarmType, command: NetworkCommand) {
+    match command {
+        NetworkCommand::Dial { peer_id, address } => {
+            let opts = match address {
+                Some(address) => {
+                    swarm.behaviour_mut().kademlia.add_address(&peer_id, address.clone());
+                    DialOpts::peer_id(peer_id).addresses(vec![address]).build()
+                }
+                None => DialOpts::peer_id(peer_id).build(),
+            };
+            match swarm.dial(opts) {
+                Ok(()) => info!("Dialing peer {:?} on request", peer_id),
+                Err(e) => error!("❌ Failed to dial peer {:?}: {:?}", peer_id, e),
+            }
+        }
+        NetworkCommand::Disconnect(peer_id) => {
+            if swarm.disconnect_peer_id(peer_id).is_err() {
+                warn!("Peer {:?} is not connected", peer_id);
+            }
+        }
+        NetworkCommand::Sync { peer_id, full } => {
+            if let Err(e) = sync_protocol::request_sync(&mut swarm.behaviour_mut().sync, &peer_id, full) {
+                error!("Failed to request catch-up from {:?}: {:?}", peer_id, e);
+            }
+        }
+    }
+}
+
+// 调拨协议在 swarm 任务中长期占用一个池连接，出错后丢弃，下次使用时重新获取
+fn transfer_connection(slot: &mut Option<db::Connection>) -> Result<&mut db::Connection, db::PoolError> {
+    if slot.is_none() {
+        *slot = Some(db::establish_connection()?);
+    }
+    Ok(slot.as_mut().expect("connection was just acquired"))
+}
+
+pub async fn run_swarm(swarm: &mut SwarmType, network: &NetworkHandle) -> Result<(), Box<dyn Error + Send + 'static>> {
+    let mut score_interval = tokio::time::interval(Duration::from_secs(30));
+    let mut transfer_interval = tokio::time::interval(Duration::from_secs(5));
+    let mut outbox_interval = tokio::time::interval(Duration::from_secs(5));
+    // 与引导节点首次建立连接时先追赶其数据，之后依靠实时复制
+    let bootstrap_peer = env::var("BOOTSTRAP_PEER_ID").ok().and_then(|id| id.parse::<PeerId>().ok());
+    let mut connection: Option<db::Connection> = None;
 
     loop {
         tokio::select! {
-            _ = discovery_interval.tick() => {
-                if let Err(e) = swarm.behaviour_mut().broadcast_discovery() {
-                    error!("Failed to broadcast discovery: {:?}", e);
+            _ = score_interval.tick() => {
+                drop_misbehaving_peers(swarm, network);
+            }
+            _ = transfer_interval.tick() => {
+                let result = transfer_connection(&mut connection)
+                    .map_err(Into::into)
+                    .and_then(|c| transfer_protocol::send_pending(c, &mut swarm.behaviour_mut().transfer));
+                if let Err(e) = result {
+                    error!("Failed to send pending transfers: {:?}", e);
+                    connection = None;
+                }
+            }
+            _ = outbox_interval.tick() => {
+                if let Err(e) = flush_outbox(swarm) {
+                    error!("Failed to flush outbox: {:?}", e);
                 }
             }
             event = swarm.select_next_some() => {
                 match event {
                     SwarmEvent::Behaviour(KMBehaviourEvent::Mdns(MdnsEvent::Discovered(peers))) => {
-                        for (peer_id, _) in peers {
-                            if discovered_peers.insert(peer_id.clone()) {
+                        for (peer_id, address) in peers {
+                            if network.discovered(&peer_id, Some(&address)) {
                                 info!("🔍 Discovered new peer via mDNS: {:?}", peer_id);
-                                if let Err(e) = swarm.dial(peer_id.clone()) {
+                                if let Err(e) = swarm.dial(peer_id) {
                                     error!("❌ Failed to dial discovered peer: {:?}", e);
                                 }
                             }
@@ -307,33 +648,70 @@ pub async fn run_swarm(swarm: &mut SwarmType) -> Result<(), Box<dyn Error + Send
                     SwarmEvent::Behaviour(KMBehaviourEvent::Mdns(MdnsEvent::Expired(peers))) => {
                         for (peer_id, _) in peers {
                             info!("Expired peer via mDNS: {:?}", peer_id);
+                            network.expired(&peer_id);
                         }
                     }
                     SwarmEvent::Behaviour(KMBehaviourEvent::Kademlia(KademliaEvent::RoutingUpdated {
                         peer,
                         ..
-                    })) => {
-                        if discovered_peers.insert(peer.clone()) {
-                            info!("Discovered peer via Kademlia: {:?}", peer);
-                            if let Err(e) = swarm.dial(peer) {
-                                info!("Failed to dial discovered peer: {:?}", e);
-                            }
+                    })) if network.discovered(&peer, None) => {
+                        info!("Discovered peer via Kademlia: {:?}", peer);
+                        if let Err(e) = swarm.dial(peer) {
+                            info!("Failed to dial discovered peer: {:?}", e);
                         }
                     }
-                    SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+                    SwarmEvent::NewListenAddr { address, .. } => {
+                        info!("Listening on {:?}", address);
+                        network.listening(&address);
+                    }
+                    SwarmEvent::ExpiredListenAddr { address, .. } => {
+                        network.stopped_listening(&address);
+                    }
+                    SwarmEvent::ConnectionEstablished { peer_id, endpoint, num_established, .. } => {
                         info!("🔗 Connected to peer: {:?}", peer_id);
+                        network.connected(&peer_id, endpoint.get_remote_address(), num_established.get());
+                        if let Err(e) = reschedule_outbox(&peer_id) {
+                            error!("Failed to reschedule outbox for {:?}: {:?}", peer_id, e);
+                        }
+                        if bootstrap_peer == Some(peer_id) && num_established.get() == 1 {
+                            if let Err(e) = sync_protocol::request_sync(&mut swarm.behaviour_mut().sync, &peer_id, false) {
+                                error!("Failed to request catch-up from {:?}: {:?}", peer_id, e);
+                            }
+                        }
                     }
-                    SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+                    SwarmEvent::ConnectionClosed { peer_id, cause, num_established, .. } => {
                         info!("❌ Connection closed with peer {:?}: {:?}", peer_id, cause);
+                        network.disconnected(&peer_id, num_established);
                     }
                     SwarmEvent::Behaviour(KMBehaviourEvent::Ping(event)) => {
                         info!("Ping: {:?}", event);
+                        if let Ok(PingSuccess::Ping { rtt }) = event.result {
+                            network.ping(&event.peer, rtt);
+                        }
                     }
-                    SwarmEvent::Behaviour(KMBehaviourEvent::Floodsub(FloodsubEvent::Message(message))) => {
-                        if let Err(e) = swarm.behaviour().handle_message(&message.data, &message.source) {
+                    SwarmEvent::Behaviour(KMBehaviourEvent::Gossipsub(GossipsubEvent::Message {
+                        propagation_source,
+                        message_id,
+                        message,
+                    })) => {
+                        if let Err(e) = handle_gossip(swarm, &propagation_source, &message_id, &message) {
                             error!("Error handling message: {:?}", e);
                         }
                     }
+                    SwarmEvent::Behaviour(KMBehaviourEvent::Transfer(event)) => {
+                        let result = transfer_connection(&mut connection)
+                            .map_err(Into::into)
+                            .and_then(|c| transfer_protocol::handle_event(c, &mut swarm.behaviour_mut().transfer, event));
+                        if let Err(e) = result {
+                            error!("Error handling transfer event: {:?}", e);
+                            connection = None;
+                        }
+                    }
+                    SwarmEvent::Behaviour(KMBehaviourEvent::Sync(event)) => {
+                        if let Err(e) = sync_protocol::handle_event(&mut swarm.behaviour_mut().sync, event) {
+                            error!("Error handling sync event: {:?}", e);
+                        }
+                    }
                     _ => {}
                 }
             }
@@ -0,0 +1,167 @@
+use chrono::{NaiveDateTime, Utc};
+use libp2p::{Multiaddr, PeerId};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+// 单个节点的状态
+#[derive(Debug, Clone, Serialize)]
+pub struct PeerStatus {
+    pub peer_id: String,
+    pub addresses: BTreeSet<String>,
+    // 通过 mDNS 或 Kademlia 发现
+    pub discovered: bool,
+    pub connected: bool,
+    pub connections: u32,
+    pub last_rtt_ms: Option<u64>,
+    pub last_seen: Option<NaiveDateTime>,
+    // gossipsub 分数，低于阈值的节点会被断开
+    pub score: Option<f64>,
+}
+
+// 本节点的网络概况
+#[derive(Debug, Clone, Serialize)]
+pub struct NetworkStatus {
+    pub local_peer_id: String,
+    pub listen_addrs: BTreeSet<String>,
+    pub topics: Vec<String>,
+    pub known_peers: usize,
+    pub connected_peers: usize,
+}
+
+// 由 HTTP 侧发给 swarm 任务执行的操作
+#[derive(Debug)]
+pub enum NetworkCommand {
+    Dial { peer_id: PeerId, address: Option<Multiaddr> },
+    Disconnect(PeerId),
+    // full 为 true 时忽略已记录的进度，从头追赶
+    Sync { peer_id: PeerId, full: bool },
+}
+
+#[derive(Debug)]
+struct NetworkState {
+    local_peer_id: PeerId,
+    listen_addrs: BTreeSet<String>,
+    topics: Vec<String>,
+    peers: BTreeMap<PeerId, PeerStatus>,
+}
+
+// swarm 任务写入、Rocket 读取的共享网络状态。锁只在同步代码中短暂持有
+#[derive(Debug, Clone)]
+pub struct NetworkHandle {
+    state: Arc<RwLock<NetworkState>>,
+    commands: mpsc::Sender<NetworkCommand>,
+}
+
+impl NetworkHandle {
+    pub fn new(local_peer_id: PeerId, topics: &[&str]) -> (NetworkHandle, mpsc::Receiver<NetworkCommand>) {
+        let (commands, receiver) = mpsc::channel(16);
+        let state = NetworkState {
+            local_peer_id,
+            listen_addrs: BTreeSet::new(),
+            topics: topics.iter().map(|t| t.to_string()).collect(),
+            peers: BTreeMap::new(),
+        };
+        (NetworkHandle { state: Arc::new(RwLock::new(state)), commands }, receiver)
+    }
+
+    pub fn status(&self) -> NetworkStatus {
+        let state = self.state.read().unwrap();
+        NetworkStatus {
+            local_peer_id: state.local_peer_id.to_string(),
+            listen_addrs: state.listen_addrs.clone(),
+            topics: state.topics.clone(),
+            known_peers: state.peers.len(),
+            connected_peers: state.peers.values().filter(|p| p.connected).count(),
+        }
+    }
+
+    pub fn peers(&self) -> Vec<PeerStatus> {
+        self.state.read().unwrap().peers.values().cloned().collect()
+    }
+
+    pub fn peer(&self, peer_id: &PeerId) -> Option<PeerStatus> {
+        self.state.read().unwrap().peers.get(peer_id).cloned()
+    }
+
+    pub async fn send(&self, command: NetworkCommand) -> Result<(), mpsc::error::SendError<NetworkCommand>> {
+        self.commands.send(command).await
+    }
+
+    fn update<R>(&self, peer_id: &PeerId, f: impl FnOnce(&mut PeerStatus) -> R) -> R {
+        let mut state = self.state.write().unwrap();
+        let peer = state.peers.entry(*peer_id).or_insert_with(|| PeerStatus {
+            peer_id: peer_id.to_string(),
+            addresses: BTreeSet::new(),
+            discovered: false,
+            connected: false,
+            connections: 0,
+            last_rtt_ms: None,
+            last_seen: None,
+            score: None,
+        });
+        f(peer)
+    }
+
+    // 既未发现也未连接的节点不再保留
+    fn prune(&self, peer_id: &PeerId) {
+        let mut state = self.state.write().unwrap();
+        if state.peers.get(peer_id).is_some_and(|p| !p.discovered && !p.connected) {
+            state.peers.remove(peer_id);
+        }
+    }
+
+    // 返回该节点此前是否未被发现
+    pub fn discovered(&self, peer_id: &PeerId, address: Option<&Multiaddr>) -> bool {
+        self.update(peer_id, |peer| {
+            if let Some(address) = address {
+                peer.addresses.insert(address.to_string());
+            }
+            !std::mem::replace(&mut peer.discovered, true)
+        })
+    }
+
+    pub fn expired(&self, peer_id: &PeerId) {
+        self.update(peer_id, |peer| peer.discovered = false);
+        self.prune(peer_id);
+    }
+
+    pub fn connected(&self, peer_id: &PeerId, address: &Multiaddr, connections: u32) {
+        self.update(peer_id, |peer| {
+            peer.addresses.insert(address.to_string());
+            peer.connected = true;
+            peer.connections = connections;
+            peer.last_seen = Some(Utc::now().naive_utc());
+        });
+    }
+
+    pub fn disconnected(&self, peer_id: &PeerId, remaining: u32) {
+        self.update(peer_id, |peer| {
+            peer.connected = remaining > 0;
+            peer.connections = remaining;
+            peer.last_seen = Some(Utc::now().naive_utc());
+        });
+        self.prune(peer_id);
+    }
+
+    pub fn ping(&self, peer_id: &PeerId, rtt: Duration) {
+        self.update(peer_id, |peer| {
+            peer.last_rtt_ms = Some(rtt.as_millis() as u64);
+            peer.last_seen = Some(Utc::now().naive_utc());
+        });
+    }
+
+    pub fn score(&self, peer_id: &PeerId, score: Option<f64>) {
+        self.update(peer_id, |peer| peer.score = score);
+    }
+
+    pub fn listening(&self, address: &Multiaddr) {
+        self.state.write().unwrap().listen_addrs.insert(address.to_string());
+    }
+
+    pub fn stopped_listening(&self, address: &Multiaddr) {
+        self.state.write().unwrap().listen_addrs.remove(&address.to_string());
+    }
+}
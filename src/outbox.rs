@@ -0,0 +1,324 @@
+use std::env;
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use libp2p::PeerId;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::db;
+use crate::models::{NewOutboxMessage, OutboxMessage};
+use crate::network_setup::OutboundMessage;
+use crate::replication;
+use crate::schema::{inbox, outbox, outbox_acks, trusted_peers};
+
+// 确认消息的 message_type，发布到 Topic::Acks，确认本身不进 outbox
+pub const ACK_MESSAGE_TYPE: &str = "ack";
+
+// outbox 状态，与 outbox.status 的 CHECK 约束一致
+pub const PENDING: &str = "pending";
+pub const DELIVERED: &str = "delivered";
+pub const FAILED: &str = "failed";
+
+// 重发间隔从 5 秒开始翻倍，最长 5 分钟；超过次数后标记为 failed
+const RETRY_BASE_SECS: i64 = 5;
+const RETRY_MAX_SECS: i64 = 300;
+const MAX_ATTEMPTS: i32 = 50;
+
+// 已送达的 outbox 和 inbox 记录保留 7 天。保留期须远长于发送方的重发窗口，
+// 否则清理后收到的重发会被再次处理
+const DEFAULT_RETENTION_DAYS: i64 = 7;
+const PRUNE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Ack {
+    pub message_id: String,
+}
+
+// 供 API 展示的积压情况
+#[derive(Debug, Serialize)]
+pub struct OutboxStats {
+    pub pending: i64,
+    pub delivered: i64,
+    pub failed: i64,
+    pub oldest_pending_at: Option<NaiveDateTime>,
+}
+
+// 持久化待发送的消息，返回 message_id
+pub fn enqueue(c: &mut SqliteConnection, message: OutboundMessage) -> QueryResult<String> {
+    let message_id = replication::new_global_id();
+    diesel::insert_into(outbox::table)
+        .values(NewOutboxMessage {
+            message_id: message_id.clone(),
+            topic: message.topic.to_string(),
+            message_type: message.message_type,
+            content: message.content,
+        })
+        .execute(c)?;
+    Ok(message_id)
+}
+
+// 到了重发时间的待送达消息
+pub fn due(c: &mut SqliteConnection) -> QueryResult<Vec<OutboxMessage>> {
+    let now = Utc::now().naive_utc();
+    let pending = outbox::table
+        .filter(outbox::status.eq(PENDING))
+        .order(outbox::created_at.asc())
+        .select(OutboxMessage::as_select())
+        .load(c)?;
+
+    Ok(pending
+        .into_iter()
+        .filter(|message| match message.last_attempt_at {
+            Some(last_attempt_at) => {
+                let backoff = (RETRY_BASE_SECS << message.attempts.clamp(0, 6)).min(RETRY_MAX_SECS);
+                last_attempt_at + Duration::seconds(backoff) <= now
+            }
+            None => true,
+        })
+        .collect())
+}
+
+// 记录一次发送，次数用尽后不再重发
+pub fn mark_attempt(c: &mut SqliteConnection, message: &OutboxMessage) -> QueryResult<()> {
+    let attempts = message.attempts + 1;
+    let status = if attempts >= MAX_ATTEMPTS {
+        warn!("Outbox message {} not acknowledged after {} attempts", message.message_id, attempts);
+        FAILED
+    } else {
+        PENDING
+    };
+
+    diesel::update(
+        outbox::table
+            .filter(outbox::message_id.eq(&message.message_id))
+            .filter(outbox::status.eq(PENDING))
+    )
+    .set((
+        outbox::attempts.eq(attempts),
+        outbox::last_attempt_at.eq(Utc::now().naive_utc()),
+        outbox::status.eq(status),
+    ))
+    .execute(c)?;
+    Ok(())
+}
+
+// 节点重新连上后，尚未被它确认的消息立即重发
+pub fn peer_reconnected(c: &mut SqliteConnection, peer: &PeerId) -> QueryResult<usize> {
+    let acked = outbox_acks::table
+        .filter(outbox_acks::peer_id.eq(peer.to_string()))
+        .select(outbox_acks::message_id);
+
+    diesel::update(
+        outbox::table
+            .filter(outbox::status.eq(PENDING))
+            .filter(diesel::dsl::not(outbox::message_id.eq_any(acked)))
+    )
+    .set(outbox::last_attempt_at.eq(None::<NaiveDateTime>))
+    .execute(c)
+}
+
+// 记录节点的确认，所有受信任节点都确认后标记为已送达
+pub fn record_ack(c: &mut SqliteConnection, message_id: &str, peer: &PeerId) -> QueryResult<()> {
+    c.transaction(|c| {
+        let exists = diesel::select(diesel::dsl::exists(
+            outbox::table.filter(outbox::message_id.eq(message_id))
+        ))
+        .get_result::<bool>(c)?;
+        // 其他节点消息的确认也会广播到这里
+        if !exists {
+            return Ok(());
+        }
+
+        diesel::insert_or_ignore_into(outbox_acks::table)
+            .values((
+                outbox_acks::message_id.eq(message_id),
+                outbox_acks::peer_id.eq(peer.to_string()),
+                outbox_acks::acked_at.eq(Utc::now().naive_utc()),
+            ))
+            .execute(c)?;
+
+        let acked = outbox_acks::table
+            .filter(outbox_acks::message_id.eq(message_id))
+            .select(outbox_acks::peer_id);
+        let unacked = trusted_peers::table
+            .filter(diesel::dsl::not(trusted_peers::peer_id.eq_any(acked)))
+            .count()
+            .get_result::<i64>(c)?;
+
+        if unacked == 0 {
+            diesel::update(
+                outbox::table
+                    .filter(outbox::message_id.eq(message_id))
+                    .filter(outbox::status.ne(DELIVERED))
+            )
+            .set((
+                outbox::status.eq(DELIVERED),
+                outbox::delivered_at.eq(Utc::now().naive_utc()),
+            ))
+            .execute(c)?;
+        }
+        Ok(())
+    })
+}
+
+// 远端消息是否已处理过
+pub fn already_received(c: &mut SqliteConnection, message_id: &str) -> QueryResult<bool> {
+    diesel::select(diesel::dsl::exists(
+        inbox::table.filter(inbox::message_id.eq(message_id))
+    ))
+    .get_result(c)
+}
+
+// 与应用消息在同一事务内调用，崩溃时不会丢失消息或重复处理
+pub fn mark_received(c: &mut SqliteConnection, message_id: &str, sender: &str) -> QueryResult<()> {
+    diesel::insert_or_ignore_into(inbox::table)
+        .values((
+            inbox::message_id.eq(message_id),
+            inbox::sender.eq(sender),
+            inbox::received_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(c)?;
+    Ok(())
+}
+
+// 已送达消息和处理记录的保留天数，可通过 OUTBOX_RETENTION_DAYS 配置
+pub fn retention_days() -> i64 {
+    env::var("OUTBOX_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_RETENTION_DAYS)
+}
+
+// 删除 before 之前送达的 outbox 消息及其确认，以及 before 之前收到的 inbox 记录。
+// 未送达和已失败的消息保留，便于排查。返回删除的行数
+pub fn prune(c: &mut SqliteConnection, before: NaiveDateTime) -> QueryResult<usize> {
+    c.transaction(|c| {
+        let delivered = outbox::table
+            .filter(outbox::status.eq(DELIVERED))
+            .filter(outbox::delivered_at.lt(before))
+            .select(outbox::message_id);
+        diesel::delete(outbox_acks::table.filter(outbox_acks::message_id.eq_any(delivered))).execute(c)?;
+        let outbox_rows = diesel::delete(
+            outbox::table
+                .filter(outbox::status.eq(DELIVERED))
+                .filter(outbox::delivered_at.lt(before))
+        )
+        .execute(c)?;
+        let inbox_rows = diesel::delete(inbox::table.filter(inbox::received_at.lt(before))).execute(c)?;
+        Ok(outbox_rows + inbox_rows)
+    })
+}
+
+// 定期清理超过保留期的 outbox 和 inbox 记录，收到关闭信号时退出
+pub async fn run_prune_job(mut shutdown_rx: broadcast::Receiver<()>) {
+    let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = shutdown_rx.recv() => {
+                info!("Shutting down outbox prune job...");
+                break;
+            }
+            _ = interval.tick() => {
+                let before = Utc::now().naive_utc() - Duration::days(retention_days());
+                let result = tokio::task::spawn_blocking(move || {
+                    let mut c = db::establish_connection().map_err(|e| e.to_string())?;
+                    prune(&mut c, before).map_err(|e| e.to_string())
+                })
+                .await;
+                match result {
+                    Ok(Ok(0)) => {}
+                    Ok(Ok(pruned)) => info!("Pruned {} outbox and inbox records older than {}", pruned, before),
+                    Ok(Err(e)) => error!("Failed to prune outbox: {}", e),
+                    Err(e) => error!("Outbox prune task panicked: {}", e),
+                }
+            }
+        }
+    }
+}
+
+pub fn stats(c: &mut SqliteConnection) -> QueryResult<OutboxStats> {
+    let counts = outbox::table
+        .group_by(outbox::status)
+        .select((outbox::status, diesel::dsl::count_star()))
+        .load::<(String, i64)>(c)?;
+    let count = |status: &str| {
+        counts.iter().find(|(s, _)| s == status).map_or(0, |(_, n)| *n)
+    };
+
+    let oldest_pending_at = outbox::table
+        .filter(outbox::status.eq(PENDING))
+        .select(diesel::dsl::min(outbox::created_at))
+        .first::<Option<NaiveDateTime>>(c)?;
+
+    Ok(OutboxStats {
+        pending: count(PENDING),
+        delivered: count(DELIVERED),
+        failed: count(FAILED),
+        oldest_pending_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert_message(c: &mut SqliteConnection, message_id: &str, delivered_at: Option<NaiveDateTime>) {
+        diesel::insert_into(outbox::table)
+            .values(NewOutboxMessage {
+                message_id: message_id.to_string(),
+                topic: "catalog".to_string(),
+                message_type: replication::REPLICATION_MESSAGE_TYPE.to_string(),
+                content: "{}".to_string(),
+            })
+            .execute(c)
+            .unwrap();
+        if let Some(delivered_at) = delivered_at {
+            diesel::update(outbox::table.filter(outbox::message_id.eq(message_id)))
+                .set((outbox::status.eq(DELIVERED), outbox::delivered_at.eq(delivered_at)))
+                .execute(c)
+                .unwrap();
+            diesel::insert_into(outbox_acks::table)
+                .values((
+                    outbox_acks::message_id.eq(message_id),
+                    outbox_acks::peer_id.eq("peer"),
+                    outbox_acks::acked_at.eq(delivered_at),
+                ))
+                .execute(c)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn prune_keeps_undelivered_and_recent_records() {
+        let mut c = db::memory_connection();
+        let now = Utc::now().naive_utc();
+        let old = now - Duration::days(30);
+        insert_message(&mut c, "old-delivered", Some(old));
+        insert_message(&mut c, "recent-delivered", Some(now));
+        insert_message(&mut c, "pending", None);
+        diesel::update(outbox::table.filter(outbox::message_id.eq("pending")))
+            .set(outbox::created_at.eq(old))
+            .execute(&mut c)
+            .unwrap();
+        mark_received(&mut c, "old-received", "peer").unwrap();
+        diesel::update(inbox::table)
+            .set(inbox::received_at.eq(old))
+            .execute(&mut c)
+            .unwrap();
+        mark_received(&mut c, "recent-received", "peer").unwrap();
+
+        assert_eq!(prune(&mut c, now - Duration::days(7)).unwrap(), 2);
+
+        let remaining: Vec<String> = outbox::table
+            .order(outbox::message_id)
+            .select(outbox::message_id)
+            .load(&mut c)
+            .unwrap();
+        assert_eq!(remaining, ["pending", "recent-delivered"]);
+        assert_eq!(outbox_acks::table.count().get_result::<i64>(&mut c).unwrap(), 1);
+        assert!(!already_received(&mut c, "old-received").unwrap());
+        assert!(already_received(&mut c, "recent-received").unwrap());
+    }
+}
@@ -0,0 +1,964 @@
+use std::env;
+use std::sync::OnceLock;
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{Integer, Nullable, Text};
+use diesel::sqlite::SqliteConnection;
+use libp2p::gossipsub::IdentTopic;
+use libp2p::identity::PublicKey;
+use libp2p::PeerId;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::hlc::{self, Hlc};
+use crate::models::{Material, NewReplicationConflict, PriceFormula, ProductSpecification, ProductionCost};
+use crate::network_setup::{OutboundMessage, Topic};
+use crate::schema::{materials, price_formulas, product_specifications, production_costs, replication_conflicts, replication_tombstones};
+use crate::warehouse;
+
+// 主数据复制使用的 NetworkMessage.message_type
+pub const REPLICATION_MESSAGE_TYPE: &str = "replication";
+
+// 冲突记录中的胜出方
+pub const WINNER_LOCAL: &str = "local";
+pub const WINNER_REMOTE: &str = "remote";
+
+static LOCAL_PEER_ID: OnceLock<String> = OnceLock::new();
+
+// 新记录的全局 ID，各节点的自增主键只在本地有效
+pub fn new_global_id() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+// 本节点的 peer id，首次调用时从 ThisWarehouse 的密钥计算
+pub fn local_peer_id(c: &mut SqliteConnection) -> QueryResult<String> {
+    if let Some(peer_id) = LOCAL_PEER_ID.get() {
+        return Ok(peer_id.clone());
+    }
+
+    let local_key = match warehouse::get_warehouse_id(c) {
+        Ok(key) => key,
+        Err(diesel::result::Error::NotFound) => warehouse::generate_and_insert_new_local_key(c),
+        Err(e) => return Err(e),
+    };
+    let peer_id = PeerId::from(PublicKey::Ed25519(local_key.public())).to_string();
+    Ok(LOCAL_PEER_ID.get_or_init(|| peer_id).clone())
+}
+
+// 为本地写入生成混合逻辑时钟
+pub fn next_hlc(c: &mut SqliteConnection) -> QueryResult<String> {
+    Ok(hlc::tick(&local_peer_id(c)?).to_string())
+}
+
+// 本地修改要求的记录状态，不做软删除的表用 Any
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowState {
+    Active,
+    Deleted,
+    Any,
+}
+
+// 本地修改前后的版本：修改前的时钟随消息发出，接收端据此判断是否并发修改
+#[derive(Debug)]
+pub struct LocalVersion {
+    pub global_id: Option<String>,
+    pub previous_hlc: Option<String>,
+    pub hlc: String,
+}
+
+#[derive(QueryableByName)]
+struct StoredVersion {
+    #[diesel(sql_type = Nullable<Text>)]
+    global_id: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    hlc: Option<String>,
+}
+
+// 修改、删除或恢复本地记录前调用，须与修改在同一事务中：
+// 取出 global_id 和修改前的时钟并写入新时钟。记录不存在或状态不符时返回 NotFound
+pub fn stamp_local_write(
+    c: &mut SqliteConnection,
+    entity: ReplicatedEntity,
+    local_id: i32,
+    state: RowState,
+) -> QueryResult<LocalVersion> {
+    let condition = match state {
+        RowState::Active => "AND deleted_at IS NULL",
+        RowState::Deleted => "AND deleted_at IS NOT NULL",
+        RowState::Any => "",
+    };
+    let stored = diesel::sql_query(format!(
+        "SELECT global_id, hlc FROM {} WHERE {} = ? {}",
+        entity.table_name(), entity.key_column(), condition
+    ))
+    .bind::<Integer, _>(local_id)
+    .get_result::<StoredVersion>(c)?;
+
+    let hlc = next_hlc(c)?;
+    diesel::sql_query(format!("UPDATE {} SET hlc = ? WHERE {} = ?", entity.table_name(), entity.key_column()))
+        .bind::<Text, _>(&hlc)
+        .bind::<Integer, _>(local_id)
+        .execute(c)?;
+    Ok(LocalVersion {
+        global_id: stored.global_id,
+        previous_hlc: stored.hlc,
+        hlc,
+    })
+}
+
+// 复制的记录，本地主键和 created_by 在接收端会被忽略
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "entity", content = "record", rename_all = "snake_case")]
+pub enum ReplicatedRecord {
+    Material(Material),
+    ProductSpecification(ProductSpecification),
+    PriceFormula(PriceFormula),
+    ProductionCost(ProductionCost),
+}
+
+impl ReplicatedRecord {
+    fn hlc(&self) -> Option<&str> {
+        match self {
+            ReplicatedRecord::Material(record) => record.hlc(),
+            ReplicatedRecord::ProductSpecification(record) => record.hlc(),
+            ReplicatedRecord::PriceFormula(record) => record.hlc(),
+            ReplicatedRecord::ProductionCost(record) => record.hlc(),
+        }
+    }
+
+    fn global_id(&self) -> Option<&str> {
+        match self {
+            ReplicatedRecord::Material(record) => record.global_id.as_deref(),
+            ReplicatedRecord::ProductSpecification(record) => record.global_id.as_deref(),
+            ReplicatedRecord::PriceFormula(record) => record.global_id.as_deref(),
+            ReplicatedRecord::ProductionCost(record) => record.global_id.as_deref(),
+        }
+    }
+
+    // 已软删除的记录，追赶快照中按删除发送
+    pub fn deleted(&self) -> bool {
+        match self {
+            ReplicatedRecord::Material(record) => record.deleted_at.is_some(),
+            ReplicatedRecord::ProductSpecification(record) => record.deleted_at.is_some(),
+            ReplicatedRecord::PriceFormula(record) => record.deleted_at.is_some(),
+            ReplicatedRecord::ProductionCost(_) => false,
+        }
+    }
+
+    fn entity(&self) -> ReplicatedEntity {
+        match self {
+            ReplicatedRecord::Material(_) => ReplicatedEntity::Material,
+            ReplicatedRecord::ProductSpecification(_) => ReplicatedEntity::ProductSpecification,
+            ReplicatedRecord::PriceFormula(_) => ReplicatedEntity::PriceFormula,
+            ReplicatedRecord::ProductionCost(_) => ReplicatedEntity::ProductionCost,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplicatedEntity {
+    Material,
+    ProductSpecification,
+    PriceFormula,
+    ProductionCost,
+}
+
+impl ReplicatedEntity {
+    pub fn table_name(&self) -> &'static str {
+        match self {
+            ReplicatedEntity::Material => "materials",
+            ReplicatedEntity::ProductSpecification => "product_specifications",
+            ReplicatedEntity::PriceFormula => "price_formulas",
+            ReplicatedEntity::ProductionCost => "production_costs",
+        }
+    }
+
+    // 本地自增主键列
+    pub fn key_column(&self) -> &'static str {
+        match self {
+            ReplicatedEntity::Material => "material_id",
+            ReplicatedEntity::ProductSpecification => "product_id",
+            ReplicatedEntity::PriceFormula => "formula_id",
+            ReplicatedEntity::ProductionCost => "cost_id",
+        }
+    }
+
+    pub fn from_table_name(table_name: &str) -> Option<ReplicatedEntity> {
+        match table_name {
+            "materials" => Some(ReplicatedEntity::Material),
+            "product_specifications" => Some(ReplicatedEntity::ProductSpecification),
+            "price_formulas" => Some(ReplicatedEntity::PriceFormula),
+            "production_costs" => Some(ReplicatedEntity::ProductionCost),
+            _ => None,
+        }
+    }
+
+    // 生产成本属于生产领域，其余属于产品目录
+    pub fn topic(&self) -> &'static str {
+        match self {
+            ReplicatedEntity::ProductionCost => Topic::Production.name(),
+            _ => Topic::Catalog.name(),
+        }
+    }
+}
+
+// previous_hlc 是发送方修改前该行的时钟，用于判断是否与本地修改并发
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum ReplicationMessage {
+    Upsert {
+        record: Box<ReplicatedRecord>,
+        previous_hlc: Option<String>,
+    },
+    Delete {
+        entity: ReplicatedEntity,
+        global_id: String,
+        hlc: String,
+        previous_hlc: Option<String>,
+    },
+}
+
+impl ReplicationMessage {
+    pub fn topic(&self) -> &'static str {
+        match self {
+            ReplicationMessage::Upsert { record, .. } => record.entity().topic(),
+            ReplicationMessage::Delete { entity, .. } => entity.topic(),
+        }
+    }
+}
+
+// 并发修改的合并策略，默认按时钟后写者胜出。
+// 可通过环境变量按表配置，例如 REPLICATION_POLICY_PRICE_FORMULAS=prefer_local
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    LastWriterWins,
+    PreferLocal,
+    PreferRemote,
+}
+
+impl MergePolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MergePolicy::LastWriterWins => "last_writer_wins",
+            MergePolicy::PreferLocal => "prefer_local",
+            MergePolicy::PreferRemote => "prefer_remote",
+        }
+    }
+
+    fn parse(value: &str) -> Option<MergePolicy> {
+        match value {
+            "last_writer_wins" => Some(MergePolicy::LastWriterWins),
+            "prefer_local" => Some(MergePolicy::PreferLocal),
+            "prefer_remote" => Some(MergePolicy::PreferRemote),
+            _ => None,
+        }
+    }
+
+    pub fn for_entity(entity: ReplicatedEntity) -> MergePolicy {
+        let key = format!("REPLICATION_POLICY_{}", entity.table_name().to_uppercase());
+        match env::var(&key) {
+            Ok(value) => MergePolicy::parse(&value).unwrap_or_else(|| {
+                warn!("Unknown merge policy {:?} in {}, using last_writer_wins", value, key);
+                MergePolicy::LastWriterWins
+            }),
+            Err(_) => MergePolicy::LastWriterWins,
+        }
+    }
+}
+
+// 交给 swarm 任务广播复制消息，作为 Rocket state 管理
+#[derive(Clone)]
+pub struct ReplicationSender(mpsc::Sender<OutboundMessage>);
+
+impl ReplicationSender {
+    pub fn new(sender: mpsc::Sender<OutboundMessage>) -> Self {
+        ReplicationSender(sender)
+    }
+
+    // 广播失败只记录日志，不影响本地写入
+    pub async fn publish(&self, message: ReplicationMessage) {
+        let content = match serde_json::to_string(&message) {
+            Ok(content) => content,
+            Err(e) => {
+                error!("Failed to serialize replication message: {}", e);
+                return;
+            }
+        };
+
+        let outbound = OutboundMessage {
+            topic: IdentTopic::new(message.topic()),
+            message_type: REPLICATION_MESSAGE_TYPE.to_string(),
+            content,
+        };
+        if let Err(e) = self.0.send(outbound).await {
+            error!("Failed to queue replication message: {}", e);
+        }
+    }
+
+    pub async fn upsert(&self, record: ReplicatedRecord, previous_hlc: Option<String>) {
+        self.publish(ReplicationMessage::Upsert { record: Box::new(record), previous_hlc }).await
+    }
+
+    pub async fn delete(&self, entity: ReplicatedEntity, global_id: String, hlc: String, previous_hlc: Option<String>) {
+        self.publish(ReplicationMessage::Delete { entity, global_id, hlc, previous_hlc }).await
+    }
+}
+
+// 带行级时钟的复制记录
+trait Versioned: Serialize {
+    fn hlc(&self) -> Option<&str>;
+
+    // 已软删除，不做软删除的表总是 false
+    fn deleted(&self) -> bool {
+        false
+    }
+}
+
+impl Versioned for Material {
+    fn hlc(&self) -> Option<&str> {
+        self.hlc.as_deref()
+    }
+
+    fn deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+}
+
+impl Versioned for ProductSpecification {
+    fn hlc(&self) -> Option<&str> {
+        self.hlc.as_deref()
+    }
+
+    fn deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+}
+
+impl Versioned for PriceFormula {
+    fn hlc(&self) -> Option<&str> {
+        self.hlc.as_deref()
+    }
+
+    fn deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+}
+
+impl Versioned for ProductionCost {
+    fn hlc(&self) -> Option<&str> {
+        self.hlc.as_deref()
+    }
+}
+
+// 应用来自其他节点的复制消息，按 global_id 匹配本地记录
+pub fn apply(c: &mut SqliteConnection, message: ReplicationMessage) -> QueryResult<()> {
+    c.transaction(|c| match message {
+        ReplicationMessage::Upsert { record, previous_hlc } => {
+            let previous_hlc = previous_hlc.as_deref();
+            match *record {
+                ReplicatedRecord::Material(record) => match record.global_id.clone() {
+                    Some(global_id) => apply_material(c, &global_id, Remote::Upsert(record), previous_hlc),
+                    None => missing_global_id("material"),
+                },
+                ReplicatedRecord::ProductSpecification(record) => match record.global_id.clone() {
+                    Some(global_id) => apply_product_specification(c, &global_id, Remote::Upsert(record), previous_hlc),
+                    None => missing_global_id("product specification"),
+                },
+                ReplicatedRecord::PriceFormula(record) => match record.global_id.clone() {
+                    Some(global_id) => apply_price_formula(c, &global_id, Remote::Upsert(record), previous_hlc),
+                    None => missing_global_id("price formula"),
+                },
+                ReplicatedRecord::ProductionCost(record) => match record.global_id.clone() {
+                    Some(global_id) => apply_production_cost(c, &global_id, Remote::Upsert(record), previous_hlc),
+                    None => missing_global_id("production cost"),
+                },
+            }
+        }
+        ReplicationMessage::Delete { entity, global_id, hlc, previous_hlc } => {
+            let previous_hlc = previous_hlc.as_deref();
+            match entity {
+                ReplicatedEntity::Material => apply_material(c, &global_id, Remote::Deleted(hlc), previous_hlc),
+                ReplicatedEntity::ProductSpecification => apply_product_specification(c, &global_id, Remote::Deleted(hlc), previous_hlc),
+                ReplicatedEntity::PriceFormula => apply_price_formula(c, &global_id, Remote::Deleted(hlc), previous_hlc),
+                ReplicatedEntity::ProductionCost => apply_production_cost(c, &global_id, Remote::Deleted(hlc), previous_hlc),
+            }
+        }
+    })
+}
+
+// 记录的当前版本，不存在时返回 None
+pub fn current_record(c: &mut SqliteConnection, entity: ReplicatedEntity, global_id: &str) -> QueryResult<Option<ReplicatedRecord>> {
+    let record = match entity {
+        ReplicatedEntity::Material => materials::table
+            .filter(materials::global_id.eq(global_id))
+            .select(Material::as_select())
+            .first(c)
+            .optional()?
+            .map(ReplicatedRecord::Material),
+        ReplicatedEntity::ProductSpecification => product_specifications::table
+            .filter(product_specifications::global_id.eq(global_id))
+            .select(ProductSpecification::as_select())
+            .first(c)
+            .optional()?
+            .map(ReplicatedRecord::ProductSpecification),
+        ReplicatedEntity::PriceFormula => price_formulas::table
+            .filter(price_formulas::global_id.eq(global_id))
+            .select(PriceFormula::as_select())
+            .first(c)
+            .optional()?
+            .map(ReplicatedRecord::PriceFormula),
+        ReplicatedEntity::ProductionCost => production_costs::table
+            .filter(production_costs::global_id.eq(global_id))
+            .select(ProductionCost::as_select())
+            .first(c)
+            .optional()?
+            .map(ReplicatedRecord::ProductionCost),
+    };
+    Ok(record)
+}
+
+// 应用追赶快照中的变更。快照不带修改前的时钟，这里按时钟比较：
+// 远端较新时视为基于本地当前版本的修改，否则保留本地版本（本地修改会经 outbox 发给对方）。
+// 删除在时钟相同时胜出：物理删除在变更日志中记下的可能是删除前的时钟
+pub fn apply_catch_up(c: &mut SqliteConnection, message: ReplicationMessage) -> QueryResult<()> {
+    let (entity, global_id, remote_hlc) = match &message {
+        ReplicationMessage::Upsert { record, .. } => match record.global_id() {
+            Some(global_id) => (record.entity(), global_id.to_string(), record.hlc().unwrap_or_default().to_string()),
+            None => return missing_global_id(record.entity().table_name()),
+        },
+        ReplicationMessage::Delete { entity, global_id, hlc, .. } => (*entity, global_id.clone(), hlc.clone()),
+    };
+
+    let local = match current_record(c, entity, &global_id)? {
+        Some(local) => local,
+        None => return apply(c, message),
+    };
+    let local_hlc = local.hlc().map(String::from);
+    let stale = match &message {
+        ReplicationMessage::Upsert { .. } => hlc::compare(local_hlc.as_deref(), Some(&remote_hlc)).is_ge(),
+        ReplicationMessage::Delete { .. } => local.deleted() || hlc::compare(local_hlc.as_deref(), Some(&remote_hlc)).is_gt(),
+    };
+    if stale {
+        return Ok(());
+    }
+
+    let message = match message {
+        ReplicationMessage::Upsert { record, .. } => ReplicationMessage::Upsert { record, previous_hlc: local_hlc },
+        ReplicationMessage::Delete { entity, global_id, hlc, .. } => ReplicationMessage::Delete { entity, global_id, hlc, previous_hlc: local_hlc },
+    };
+    apply(c, message)
+}
+
+// 记录的删除墓碑时钟，没有删除过时返回 None
+pub fn tombstone_hlc(c: &mut SqliteConnection, entity: ReplicatedEntity, global_id: &str) -> QueryResult<Option<String>> {
+    replication_tombstones::table
+        .filter(replication_tombstones::table_name.eq(entity.table_name()))
+        .filter(replication_tombstones::global_id.eq(global_id))
+        .select(replication_tombstones::hlc)
+        .first(c)
+        .optional()
+}
+
+// 写入删除墓碑，已有更新的删除时钟时保留原值。
+// 本地物理删除、清理软删除记录和应用远端删除时都要调用
+pub fn record_tombstone(c: &mut SqliteConnection, entity: ReplicatedEntity, global_id: &str, hlc: &str) -> QueryResult<()> {
+    if hlc::compare(tombstone_hlc(c, entity, global_id)?.as_deref(), Some(hlc)).is_ge() {
+        return Ok(());
+    }
+    diesel::replace_into(replication_tombstones::table)
+        .values((
+            replication_tombstones::table_name.eq(entity.table_name()),
+            replication_tombstones::global_id.eq(global_id),
+            replication_tombstones::hlc.eq(hlc),
+            replication_tombstones::deleted_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(c)?;
+    Ok(())
+}
+
+fn missing_global_id(entity: &str) -> QueryResult<()> {
+    warn!("Ignoring replicated {} without global_id", entity);
+    Ok(())
+}
+
+// 远端的新版本：更新后的记录，或带删除时钟的删除
+enum Remote<T> {
+    Upsert(T),
+    Deleted(String),
+}
+
+impl<T: Versioned> Remote<T> {
+    fn hlc(&self) -> &str {
+        match self {
+            Remote::Upsert(record) => record.hlc().unwrap_or_default(),
+            Remote::Deleted(hlc) => hlc,
+        }
+    }
+
+    fn data(&self) -> Option<String> {
+        match self {
+            Remote::Upsert(record) => serde_json::to_string(record).ok(),
+            Remote::Deleted(_) => None,
+        }
+    }
+}
+
+// 判断是否采用远端版本，采用远端删除时写入墓碑
+fn accept_remote<T: Versioned>(
+    c: &mut SqliteConnection,
+    entity: ReplicatedEntity,
+    global_id: &str,
+    local: Option<&T>,
+    remote: &Remote<T>,
+    previous_hlc: Option<&str>,
+) -> QueryResult<bool> {
+    let accepted = remote_wins(c, entity, global_id, local, remote, previous_hlc)?;
+    if let Remote::Deleted(hlc) = remote {
+        // 本地没有该记录时同样保留删除，之后到达的旧更新不再插入
+        if accepted || local.is_none() {
+            record_tombstone(c, entity, global_id, hlc)?;
+        }
+    }
+    Ok(accepted)
+}
+
+// 远端修改不是基于本地当前版本时视为并发修改，
+// 按表的合并策略决定胜出方并写入 replication_conflicts 供复核。
+fn remote_wins<T: Versioned>(
+    c: &mut SqliteConnection,
+    entity: ReplicatedEntity,
+    global_id: &str,
+    local: Option<&T>,
+    remote: &Remote<T>,
+    previous_hlc: Option<&str>,
+) -> QueryResult<bool> {
+    let remote_hlc = remote.hlc();
+    if let Some(parsed) = Hlc::parse(remote_hlc) {
+        hlc::observe(&parsed);
+    }
+
+    let local = match local {
+        Some(local) => local,
+        // 本地没有该记录：更新晚于墓碑（或没有墓碑）才插入，删除无可删
+        None => {
+            return match remote {
+                Remote::Upsert(_) => Ok(tombstone_hlc(c, entity, global_id)?
+                    .is_none_or(|tombstone| hlc::compare(Some(remote_hlc), Some(&tombstone)).is_gt())),
+                Remote::Deleted(_) => Ok(false),
+            };
+        }
+    };
+
+    let local_hlc = local.hlc();
+    if local_hlc == Some(remote_hlc) {
+        // 同一版本：只有尚未删除时才应用删除
+        return Ok(matches!(remote, Remote::Deleted(_)) && !local.deleted());
+    }
+    if local_hlc == previous_hlc {
+        return Ok(true);
+    }
+
+    let policy = MergePolicy::for_entity(entity);
+    let remote_wins = match policy {
+        MergePolicy::LastWriterWins => hlc::compare(Some(remote_hlc), local_hlc).is_gt(),
+        MergePolicy::PreferLocal => false,
+        MergePolicy::PreferRemote => true,
+    };
+    let winner = if remote_wins { WINNER_REMOTE } else { WINNER_LOCAL };
+    warn!(
+        "Concurrent edit of {} {} resolved by {} in favour of {}",
+        entity.table_name(), global_id, policy.as_str(), winner
+    );
+
+    diesel::insert_into(replication_conflicts::table)
+        .values(NewReplicationConflict {
+            table_name: entity.table_name().to_string(),
+            global_id: global_id.to_string(),
+            local_hlc: local_hlc.map(String::from),
+            remote_hlc: remote_hlc.to_string(),
+            local_data: serde_json::to_string(local).ok(),
+            remote_data: remote.data(),
+            policy: policy.as_str().to_string(),
+            winner: winner.to_string(),
+            resolved_at: Utc::now().naive_utc(),
+        })
+        .execute(c)?;
+
+    Ok(remote_wins)
+}
+
+fn apply_material(
+    c: &mut SqliteConnection,
+    global_id: &str,
+    remote: Remote<Material>,
+    previous_hlc: Option<&str>,
+) -> QueryResult<()> {
+    let local = materials::table
+        .filter(materials::global_id.eq(global_id))
+        .select(Material::as_select())
+        .first(c)
+        .optional()?;
+    if !accept_remote(c, ReplicatedEntity::Material, global_id, local.as_ref(), &remote, previous_hlc)? {
+        return Ok(());
+    }
+
+    let record = match remote {
+        Remote::Upsert(record) => record,
+        Remote::Deleted(hlc) => {
+            // 其他节点的删除在本地同样是软删除，本地的领料单、生产任务仍可引用该记录
+            diesel::update(materials::table.filter(materials::global_id.eq(global_id)))
+                .set((
+                    materials::deleted_at.eq(Utc::now().naive_utc()),
+                    materials::deleted_by.eq(None::<i32>),
+                    materials::hlc.eq(hlc),
+                ))
+                .execute(c)?;
+            return Ok(());
+        }
+    };
+    let values = (
+        materials::material_name.eq(record.material_name),
+        materials::category.eq(record.category),
+        materials::type_.eq(record.type_),
+        materials::supplier.eq(record.supplier),
+        materials::hlc.eq(record.hlc),
+        // 采用远端版本同时撤销本地的软删除
+        materials::deleted_at.eq(None::<NaiveDateTime>),
+        materials::deleted_by.eq(None::<i32>),
+    );
+
+    if local.is_some() {
+        diesel::update(materials::table.filter(materials::global_id.eq(global_id)))
+            .set(values)
+            .execute(c)?;
+    } else {
+        diesel::insert_into(materials::table)
+            .values((
+                values,
+                materials::created_at.eq(record.created_at),
+                materials::global_id.eq(global_id),
+            ))
+            .execute(c)?;
+    }
+    Ok(())
+}
+
+fn apply_product_specification(
+    c: &mut SqliteConnection,
+    global_id: &str,
+    remote: Remote<ProductSpecification>,
+    previous_hlc: Option<&str>,
+) -> QueryResult<()> {
+    let local = product_specifications::table
+        .filter(product_specifications::global_id.eq(global_id))
+        .select(ProductSpecification::as_select())
+        .first(c)
+        .optional()?;
+    if !accept_remote(c, ReplicatedEntity::ProductSpecification, global_id, local.as_ref(), &remote, previous_hlc)? {
+        return Ok(());
+    }
+
+    let record = match remote {
+        Remote::Upsert(record) => record,
+        Remote::Deleted(hlc) => {
+            // 其他节点的删除在本地同样是软删除，本地的领料单、生产任务仍可引用该记录
+            diesel::update(product_specifications::table.filter(product_specifications::global_id.eq(global_id)))
+                .set((
+                    product_specifications::deleted_at.eq(Utc::now().naive_utc()),
+                    product_specifications::deleted_by.eq(None::<i32>),
+                    product_specifications::hlc.eq(hlc),
+                ))
+                .execute(c)?;
+            return Ok(());
+        }
+    };
+    let values = (
+        product_specifications::product_name.eq(record.product_name),
+        product_specifications::model.eq(record.model),
+        product_specifications::material_type.eq(record.material_type),
+        product_specifications::color.eq(record.color),
+        product_specifications::dimensions.eq(record.dimensions),
+        product_specifications::hlc.eq(record.hlc),
+        // 采用远端版本同时撤销本地的软删除
+        product_specifications::deleted_at.eq(None::<NaiveDateTime>),
+        product_specifications::deleted_by.eq(None::<i32>),
+    );
+
+    if local.is_some() {
+        diesel::update(
+            product_specifications::table.filter(product_specifications::global_id.eq(global_id))
+        )
+        .set(values)
+        .execute(c)?;
+    } else {
+        diesel::insert_into(product_specifications::table)
+            .values((
+                values,
+                product_specifications::created_at.eq(record.created_at),
+                product_specifications::global_id.eq(global_id),
+            ))
+            .execute(c)?;
+    }
+    Ok(())
+}
+
+fn apply_price_formula(
+    c: &mut SqliteConnection,
+    global_id: &str,
+    remote: Remote<PriceFormula>,
+    previous_hlc: Option<&str>,
+) -> QueryResult<()> {
+    let local = price_formulas::table
+        .filter(price_formulas::global_id.eq(global_id))
+        .select(PriceFormula::as_select())
+        .first(c)
+        .optional()?;
+    if !accept_remote(c, ReplicatedEntity::PriceFormula, global_id, local.as_ref(), &remote, previous_hlc)? {
+        return Ok(());
+    }
+
+    let record = match remote {
+        Remote::Upsert(record) => record,
+        Remote::Deleted(hlc) => {
+            // 其他节点的删除在本地同样是软删除，本地的领料单、生产任务仍可引用该记录
+            diesel::update(price_formulas::table.filter(price_formulas::global_id.eq(global_id)))
+                .set((
+                    price_formulas::deleted_at.eq(Utc::now().naive_utc()),
+                    price_formulas::deleted_by.eq(None::<i32>),
+                    price_formulas::hlc.eq(hlc),
+                ))
+                .execute(c)?;
+            return Ok(());
+        }
+    };
+    let values = (
+        price_formulas::formula_name.eq(record.formula_name),
+        price_formulas::base_material_cost.eq(record.base_material_cost),
+        price_formulas::additional_material_cost.eq(record.additional_material_cost),
+        price_formulas::galvanization_cost.eq(record.galvanization_cost),
+        price_formulas::labor_cost.eq(record.labor_cost),
+        price_formulas::management_fee.eq(record.management_fee),
+        price_formulas::sales_fee.eq(record.sales_fee),
+        price_formulas::manufacturing_fee.eq(record.manufacturing_fee),
+        price_formulas::vat.eq(record.vat),
+        price_formulas::profit.eq(record.profit),
+        price_formulas::hlc.eq(record.hlc),
+        // 采用远端版本同时撤销本地的软删除
+        price_formulas::deleted_at.eq(None::<NaiveDateTime>),
+        price_formulas::deleted_by.eq(None::<i32>),
+    );
+
+    if local.is_some() {
+        diesel::update(price_formulas::table.filter(price_formulas::global_id.eq(global_id)))
+            .set(values)
+            .execute(c)?;
+    } else {
+        diesel::insert_into(price_formulas::table)
+            .values((
+                values,
+                price_formulas::created_at.eq(record.created_at),
+                price_formulas::global_id.eq(global_id),
+            ))
+            .execute(c)?;
+    }
+    Ok(())
+}
+
+fn apply_production_cost(
+    c: &mut SqliteConnection,
+    global_id: &str,
+    remote: Remote<ProductionCost>,
+    previous_hlc: Option<&str>,
+) -> QueryResult<()> {
+    let local = production_costs::table
+        .filter(production_costs::global_id.eq(global_id))
+        .select(ProductionCost::as_select())
+        .first(c)
+        .optional()?;
+    if !accept_remote(c, ReplicatedEntity::ProductionCost, global_id, local.as_ref(), &remote, previous_hlc)? {
+        return Ok(());
+    }
+
+    let record = match remote {
+        Remote::Upsert(record) => record,
+        Remote::Deleted(hlc) => {
+            // 先写入删除时钟，变更日志记下的就是这次删除的时钟
+            let target = production_costs::table.filter(production_costs::global_id.eq(global_id));
+            diesel::update(target).set(production_costs::hlc.eq(hlc)).execute(c)?;
+            diesel::delete(target).execute(c)?;
+            return Ok(());
+        }
+    };
+    let values = (
+        production_costs::process_type.eq(record.process_type),
+        production_costs::cost_per_unit.eq(record.cost_per_unit),
+        production_costs::hlc.eq(record.hlc),
+    );
+
+    if local.is_some() {
+        diesel::update(production_costs::table.filter(production_costs::global_id.eq(global_id)))
+            .set(values)
+            .execute(c)?;
+    } else {
+        diesel::insert_into(production_costs::table)
+            .values((
+                values,
+                production_costs::created_at.eq(record.created_at),
+                production_costs::global_id.eq(global_id),
+            ))
+            .execute(c)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::models::ReplicationConflict;
+
+    fn clock(wall_ms: u64, peer: &str) -> String {
+        Hlc { wall_ms, counter: 0, peer: peer.to_string() }.to_string()
+    }
+
+    fn insert_material(c: &mut SqliteConnection, global_id: &str, hlc: &str) -> Material {
+        diesel::insert_into(materials::table)
+            .values((
+                materials::material_name.eq("local"),
+                materials::global_id.eq(global_id),
+                materials::hlc.eq(hlc),
+            ))
+            .execute(c)
+            .unwrap();
+        load_material(c, global_id).unwrap()
+    }
+
+    fn load_material(c: &mut SqliteConnection, global_id: &str) -> Option<Material> {
+        materials::table
+            .filter(materials::global_id.eq(global_id))
+            .select(Material::as_select())
+            .first(c)
+            .optional()
+            .unwrap()
+    }
+
+    // 基于 previous_hlc 修改后的远端版本
+    fn remote_material(local: &Material, name: &str, hlc: &str, previous_hlc: Option<&str>) -> ReplicationMessage {
+        let mut record = local.clone();
+        record.material_id = None;
+        record.material_name = name.to_string();
+        record.hlc = Some(hlc.to_string());
+        ReplicationMessage::Upsert {
+            record: Box::new(ReplicatedRecord::Material(record)),
+            previous_hlc: previous_hlc.map(String::from),
+        }
+    }
+
+    fn conflicts(c: &mut SqliteConnection, global_id: &str) -> Vec<ReplicationConflict> {
+        replication_conflicts::table
+            .filter(replication_conflicts::global_id.eq(global_id))
+            .select(ReplicationConflict::as_select())
+            .load(c)
+            .unwrap()
+    }
+
+    #[test]
+    fn equal_clocks_are_won_by_the_larger_peer_id() {
+        let mut c = db::memory_connection();
+        let local = insert_material(&mut c, "m1", &clock(1_000, "peer-b"));
+
+        apply(&mut c, remote_material(&local, "from a", &clock(1_000, "peer-a"), None)).unwrap();
+        assert_eq!(load_material(&mut c, "m1").unwrap().material_name, "local");
+
+        apply(&mut c, remote_material(&local, "from c", &clock(1_000, "peer-c"), None)).unwrap();
+        assert_eq!(load_material(&mut c, "m1").unwrap().material_name, "from c");
+    }
+
+    #[test]
+    fn prefer_local_policy_keeps_the_local_row() {
+        let mut c = db::memory_connection();
+        env::set_var("REPLICATION_POLICY_PRICE_FORMULAS", "prefer_local");
+        diesel::insert_into(price_formulas::table)
+            .values((
+                price_formulas::formula_name.eq("local"),
+                price_formulas::global_id.eq("f1"),
+                price_formulas::hlc.eq(clock(1_000, "peer-a")),
+            ))
+            .execute(&mut c)
+            .unwrap();
+        let load = |c: &mut SqliteConnection| {
+            price_formulas::table
+                .filter(price_formulas::global_id.eq("f1"))
+                .select(PriceFormula::as_select())
+                .first(c)
+                .unwrap()
+        };
+        let mut remote = load(&mut c);
+        remote.formula_id = None;
+        remote.formula_name = Some("remote".to_string());
+        remote.hlc = Some(clock(2_000, "peer-b"));
+        let message = ReplicationMessage::Upsert {
+            record: Box::new(ReplicatedRecord::PriceFormula(remote)),
+            previous_hlc: None,
+        };
+
+        apply(&mut c, message).unwrap();
+
+        assert_eq!(load(&mut c).formula_name.as_deref(), Some("local"));
+        let conflicts = conflicts(&mut c, "f1");
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].policy, "prefer_local");
+        assert_eq!(conflicts[0].winner, WINNER_LOCAL);
+    }
+
+    #[test]
+    fn overwritten_concurrent_edit_is_recorded_as_conflict() {
+        let mut c = db::memory_connection();
+        let base = clock(1_000, "peer-a");
+        let local = insert_material(&mut c, "m1", &clock(2_000, "peer-a"));
+
+        // 远端基于更早的版本修改，但时钟更新
+        apply(&mut c, remote_material(&local, "remote", &clock(3_000, "peer-b"), Some(&base))).unwrap();
+
+        assert_eq!(load_material(&mut c, "m1").unwrap().material_name, "remote");
+        let recorded = conflicts(&mut c, "m1");
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(recorded[0].winner, WINNER_REMOTE);
+        assert_eq!(recorded[0].local_hlc, local.hlc);
+        assert!(recorded[0].local_data.as_deref().is_some_and(|data| data.contains("\"local\"")));
+
+        // 基于本地当前版本的修改不是冲突
+        let current = load_material(&mut c, "m1").unwrap();
+        let next = remote_material(&current, "next", &clock(4_000, "peer-b"), current.hlc.as_deref());
+        apply(&mut c, next).unwrap();
+        assert_eq!(load_material(&mut c, "m1").unwrap().material_name, "next");
+        assert_eq!(conflicts(&mut c, "m1").len(), 1);
+    }
+
+    #[test]
+    fn upsert_older_than_tombstone_does_not_resurrect_the_row() {
+        let mut c = db::memory_connection();
+        let local = insert_material(&mut c, "m1", &clock(1_000, "peer-a"));
+        diesel::delete(materials::table).execute(&mut c).unwrap();
+
+        // 删除先于更新到达，本地已没有该记录
+        let delete = ReplicationMessage::Delete {
+            entity: ReplicatedEntity::Material,
+            global_id: "m1".to_string(),
+            hlc: clock(3_000, "peer-b"),
+            previous_hlc: None,
+        };
+        apply(&mut c, delete).unwrap();
+        assert_eq!(tombstone_hlc(&mut c, ReplicatedEntity::Material, "m1").unwrap(), Some(clock(3_000, "peer-b")));
+
+        apply(&mut c, remote_material(&local, "stale", &clock(2_000, "peer-c"), None)).unwrap();
+        assert!(load_material(&mut c, "m1").is_none());
+
+        // 晚于删除的更新重新创建记录
+        apply(&mut c, remote_material(&local, "recreated", &clock(4_000, "peer-c"), None)).unwrap();
+        assert_eq!(load_material(&mut c, "m1").unwrap().material_name, "recreated");
+    }
+}
@@ -1,12 +1,15 @@
 use rocket::{Build, Rocket};
 use rocket_cors::{AllowedHeaders, AllowedOrigins, CorsOptions};
-use std::collections::{HashMap, HashSet};
-use rocket::figment::{providers::{Env, Serialized}, Figment};
+use std::collections::HashSet;
+use rocket::figment::{providers::Env, Figment};
 use rocket::Config;
 use crate::models::DbConn;
 use rocket::http::Method;
 use crate::admin_init::AdminInit;
-use rocket::routes;
+use crate::audit::Audit;
+use crate::error::{self, RequestIdHeader};
+use crate::token::JwtKeys;
+use rocket::{catchers, routes};
 
 // 导入所有路由模块
 use crate::routers::{
@@ -23,57 +26,75 @@ use crate::routers::{
     product_specification,
     material,
     material_request,
+    warehouse_stock,
+    stock_movement,
+    auth,
+    replication_conflict,
+    stock_transfer,
+    trusted_peer,
+    network,
+    outbox,
 };
 
 // 将 rocket 函数移到这里
+// 主数据路由需要 replication::ReplicationSender，业务写入路由需要 events::EventSender，
+// 网络路由需要 network_state::NetworkHandle，均由调用方通过 manage 提供
 pub async fn rocket() -> Rocket<Build> {
     // 从默认配置创建 Figment 实例
     let figment = Figment::from(Config::default())
         .merge(("port", 0))
-        // 合并自定义的数据库配置
-        .merge(Serialized::default("databases", {
-            let mut databases: HashMap<&str, HashMap<&str, &str>> = HashMap::new();
-            let mut db_config: HashMap<&str, &str> = HashMap::new();
-            db_config.insert("url", "sqlite://./warehouse.db");
-            databases.insert("sqlite_db", db_config);
-            databases
-        }))
-        // 合并环境变量配置，前缀为 "APP_"
-        .merge(Env::prefixed("APP_"));
+        // 合并环境变量配置，前缀为 "APP_"，嵌套键用 "__" 分隔（如 APP_JWT__SECRET）
+        .merge(Env::prefixed("APP_").split("__"));
 
     // 使用自定义的配置启动 Rocket 应用程序
     let mut rocket = rocket::custom(figment)
-        // 附加数据库连接
+        // 附加数据库连接池，路径等配置见 db 模块
         .attach(DbConn::fairing())
         .attach(AdminInit) // 使用 AdminInit
+        .attach(JwtKeys::fairing())
+        // 修改请求的审计日志，需要 JwtKeys 解析令牌
+        .attach(Audit)
+        .attach(RequestIdHeader)
+        // 错误统一为 JSON 应答
+        .register("/", catchers![
+            error::bad_request,
+            error::unauthorized,
+            error::forbidden,
+            error::not_found,
+            error::unprocessable_entity,
+            error::internal_error,
+        ])
         // 挂载路由
         .mount("/", routes![])
         .mount(
             "/api",
             routes![
+                // Auth routes
+                auth::login,
+                auth::refresh,
+                auth::logout,
+
                 // Role routes
                 role::list_roles,
                 role::get_role,
                 role::create_role,
                 role::update_role,
                 role::delete_role,
-                role::search_roles,
 
                 // User routes
-                user::list_users,
+                user::get_users,
                 user::get_user,
                 user::create_user,
                 user::update_user,
                 user::delete_user,
-                user::search_users,
 
                 // Warehouse routes
-                warehouse::list_warehouses,
+                warehouse::get_warehouses,
                 warehouse::get_warehouse,
                 warehouse::create_warehouse,
                 warehouse::update_warehouse,
                 warehouse::delete_warehouse,
-                warehouse::search_warehouses,
+                warehouse::restore_warehouse,
 
                 // Permission routes
                 permission::list_permissions,
@@ -81,37 +102,39 @@ pub async fn rocket() -> Rocket<Build> {
                 permission::create_permission,
                 permission::update_permission,
                 permission::delete_permission,
-                permission::search_permissions,
 
                 // Operation Log routes
                 operation_log::list_operation_logs,
-                operation_log::create_operation_log,
+                operation_log::get_user_operation_logs,
                 operation_log::get_operation_log,
                 operation_log::search_operation_logs,
+                operation_log::export_operation_logs,
+                operation_log::verify_operation_logs,
 
                 // Production Task routes
                 production_task::list_production_tasks,
                 production_task::get_production_task,
                 production_task::create_production_task,
-                production_task::update_production_task,
-                production_task::delete_production_task,
-                production_task::search_production_tasks,
+                production_task::get_tasks_by_product,
+                production_task::get_tasks_by_status,
+                production_task::update_task_status,
 
                 // User Role routes
                 user_role::list_user_roles,
-                user_role::get_user_role,
+                user_role::get_user_roles,
+                user_role::get_role_users,
                 user_role::create_user_role,
                 user_role::delete_user_role,
-                user_role::get_roles_by_user,
-                user_role::get_users_by_role,
+                user_role::set_user_roles,
 
                 // Role Permission routes
                 role_permission::list_role_permissions,
-                role_permission::get_role_permission,
+                role_permission::get_role_permissions,
+                role_permission::get_permission_roles,
                 role_permission::create_role_permission,
                 role_permission::delete_role_permission,
-                role_permission::get_permissions_by_role,
-                role_permission::get_roles_by_permission,
+                role_permission::set_role_permissions,
+                role_permission::check_role_permission,
 
                 // Production Cost routes
                 production_cost::list_production_costs,
@@ -119,7 +142,8 @@ pub async fn rocket() -> Rocket<Build> {
                 production_cost::create_production_cost,
                 production_cost::update_production_cost,
                 production_cost::delete_production_cost,
-                production_cost::search_production_costs,
+                production_cost::get_costs_by_process,
+                production_cost::get_latest_cost,
 
                 // Price Formula routes
                 price_formula::list_price_formulas,
@@ -127,18 +151,22 @@ pub async fn rocket() -> Rocket<Build> {
                 price_formula::create_price_formula,
                 price_formula::update_price_formula,
                 price_formula::delete_price_formula,
-                price_formula::search_price_formulas,
+                price_formula::restore_price_formula,
+                price_formula::get_formula_by_name,
+                price_formula::get_latest_formula,
+                price_formula::calculate_price,
 
                 // Product Specification routes
                 product_specification::list_product_specifications,
                 product_specification::get_product_specification,
-                product_specification::get_product_specification_by_name,
+                product_specification::get_specification_by_name,
                 product_specification::get_specifications_by_material,
                 product_specification::get_specifications_by_model,
                 product_specification::create_product_specification,
                 product_specification::update_product_specification,
                 product_specification::delete_product_specification,
-                product_specification::search_product_specifications,
+                product_specification::restore_product_specification,
+                product_specification::search_specifications,
 
                 // Material routes
                 material::list_materials,
@@ -149,6 +177,7 @@ pub async fn rocket() -> Rocket<Build> {
                 material::create_material,
                 material::update_material,
                 material::delete_material,
+                material::restore_material,
                 material::search_materials,
                 material::list_suppliers,
                 material::list_categories,
@@ -162,6 +191,58 @@ pub async fn rocket() -> Rocket<Build> {
                 material_request::get_requests_by_requester,
                 material_request::create_material_request,
                 material_request::search_material_requests,
+                material_request::approve_material_request,
+                material_request::reject_material_request,
+                material_request::cancel_material_request,
+
+                // Warehouse Stock routes
+                warehouse_stock::get_stock_by_warehouse,
+                warehouse_stock::get_stock_by_material,
+                warehouse_stock::get_warehouse_stock,
+                warehouse_stock::receive_stock,
+                warehouse_stock::issue_stock,
+
+                // Stock Movement routes
+                stock_movement::search_stock_movements,
+                stock_movement::get_stock_movement,
+                stock_movement::adjust_stock,
+                stock_movement::transfer_stock,
+                stock_movement::get_balances_as_of,
+                stock_movement::rebuild_stock,
+
+                // Replication Conflict routes
+                replication_conflict::list_conflicts,
+                replication_conflict::get_conflict,
+                replication_conflict::review_conflict,
+
+                // Stock Transfer routes
+                stock_transfer::search_stock_transfers,
+                stock_transfer::get_in_transit,
+                stock_transfer::get_stock_transfer,
+                stock_transfer::create_stock_transfer,
+                stock_transfer::ship_stock_transfer,
+                stock_transfer::receive_stock_transfer,
+                stock_transfer::cancel_stock_transfer,
+
+                // Trusted Peer routes
+                trusted_peer::list_trusted_peers,
+                trusted_peer::add_trusted_peer,
+                trusted_peer::remove_trusted_peer,
+
+                // Network routes
+                network::get_network_status,
+                network::get_peers,
+                network::get_peer,
+                network::dial_peer,
+                network::disconnect_peer,
+                network::get_sync_state,
+                network::sync_peer,
+
+                // Outbox routes
+                outbox::get_outbox_stats,
+                outbox::list_outbox,
+                outbox::get_outbox_message,
+                outbox::retry_outbox_message,
             ],
         );
 
@@ -0,0 +1,258 @@
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use rocket::serde::json::Json;
+use rocket::http::{Cookie, CookieJar, SameSite, Status};
+use rocket::{post, State};
+use chrono::{Duration, Utc};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::ApiError;
+use crate::models::{User, RefreshToken, DbConn};
+use crate::schema::{refresh_tokens, roles, user_roles, users, warehouses};
+use crate::token::{Claims, JwtKeys};
+
+#[derive(Debug, Deserialize)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: String,
+    pub expires_in: u64,
+}
+
+// 数据库中只保存刷新令牌的摘要
+fn hash_refresh_token(raw: &str) -> String {
+    format!("{:x}", Sha256::digest(raw.as_bytes()))
+}
+
+// 生成并保存新的刷新令牌，返回 (token_id, 原始令牌)
+fn issue_refresh_token(
+    c: &mut SqliteConnection,
+    user_id: i32,
+    ttl_secs: u64,
+) -> Result<(i32, String), diesel::result::Error> {
+    let raw: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(48)
+        .map(char::from)
+        .collect();
+    let hash = hash_refresh_token(&raw);
+    let now = Utc::now().naive_utc();
+
+    diesel::insert_into(refresh_tokens::table)
+        .values((
+            refresh_tokens::user_id.eq(user_id),
+            refresh_tokens::token_hash.eq(&hash),
+            refresh_tokens::expires_at.eq(now + Duration::seconds(ttl_secs as i64)),
+            refresh_tokens::created_at.eq(now),
+        ))
+        .execute(c)?;
+
+    let token_id = refresh_tokens::table
+        .filter(refresh_tokens::token_hash.eq(&hash))
+        .select(refresh_tokens::token_id)
+        .first(c)?;
+    Ok((token_id, raw))
+}
+
+// 吊销某用户所有仍有效的刷新令牌
+pub fn revoke_user_tokens(c: &mut SqliteConnection, user_id: i32) -> Result<usize, diesel::result::Error> {
+    diesel::update(
+        refresh_tokens::table
+            .filter(refresh_tokens::user_id.eq(user_id))
+            .filter(refresh_tokens::revoked_at.is_null())
+    )
+    .set(refresh_tokens::revoked_at.eq(Utc::now().naive_utc()))
+    .execute(c)
+}
+
+// 组装访问令牌声明：角色名称和本节点仓库
+fn build_claims(
+    c: &mut SqliteConnection,
+    user: &User,
+    ttl_secs: u64,
+) -> Result<Claims, diesel::result::Error> {
+    let role_names = user_roles::table
+        .inner_join(roles::table)
+        .filter(user_roles::user_id.eq(user.user_id))
+        .select(roles::role_name)
+        .load::<String>(c)?;
+
+    let warehouse_id = warehouses::table
+        .filter(warehouses::warehouse_name.eq("ThisWarehouse"))
+        .select(warehouses::warehouse_id)
+        .first::<i32>(c)
+        .optional()?;
+
+    Ok(Claims::new(&user.username, user.user_id, role_names, warehouse_id, ttl_secs))
+}
+
+// 签发刷新令牌并组装访问令牌声明，返回 (新刷新令牌 id, 声明, 原始刷新令牌)
+fn prepare_tokens(
+    c: &mut SqliteConnection,
+    user: &User,
+    access_ttl_secs: u64,
+    refresh_ttl_secs: u64,
+) -> Result<(i32, Claims, String), diesel::result::Error> {
+    let claims = build_claims(c, user, access_ttl_secs)?;
+    let (token_id, refresh_token) = issue_refresh_token(c, user.user_id, refresh_ttl_secs)?;
+    Ok((token_id, claims, refresh_token))
+}
+
+// 访问令牌在 conn.run 之外签名，签名密钥属于 Rocket state
+fn token_response(keys: &JwtKeys, claims: &Claims, refresh_token: String) -> Result<TokenResponse, ApiError> {
+    Ok(TokenResponse {
+        access_token: keys.encode(claims).map_err(|_| ApiError::internal("failed to sign access token"))?,
+        refresh_token,
+        token_type: "Bearer".to_string(),
+        expires_in: keys.access_ttl_secs,
+    })
+}
+
+// 用户不存在和密码错误返回同样的错误
+fn invalid_credentials() -> ApiError {
+    ApiError::new(Status::Unauthorized, "invalid_credentials", "invalid username or password")
+}
+
+// 同时写入 cookie，供 claims::JwtToken 使用
+fn set_token_cookie(cookies: &CookieJar<'_>, access_token: String) {
+    cookies.add(
+        Cookie::build(("token", access_token))
+            .http_only(true)
+            .same_site(SameSite::Lax),
+    );
+}
+
+#[post("/auth/login", data = "<credentials>")]
+pub async fn login(
+    conn: DbConn,
+    keys: &State<JwtKeys>,
+    cookies: &CookieJar<'_>,
+    credentials: Json<LoginRequest>
+) -> Result<Json<TokenResponse>, ApiError> {
+    let credentials = credentials.into_inner();
+    let (access_ttl_secs, refresh_ttl_secs) = (keys.access_ttl_secs, keys.refresh_ttl_secs);
+
+    let (claims, refresh_token) = conn.run(move |c| {
+        let user: User = users::table
+            .filter(users::username.eq(&credentials.username))
+            .select(User::as_select())
+            .first(c)
+            .optional()
+            .map_err(ApiError::from)?
+            .ok_or_else(invalid_credentials)?;
+
+        if !bcrypt::verify(&credentials.password, &user.password_hash).unwrap_or(false) {
+            return Err(invalid_credentials());
+        }
+        if user.status.as_deref() == Some("inactive") {
+            return Err(ApiError::new(Status::Forbidden, "user_inactive", "user is inactive"));
+        }
+
+        let (_, claims, refresh_token) = prepare_tokens(c, &user, access_ttl_secs, refresh_ttl_secs)
+            .map_err(ApiError::from)?;
+        Ok((claims, refresh_token))
+    }).await?;
+
+    let response = token_response(keys, &claims, refresh_token)?;
+    set_token_cookie(cookies, response.access_token.clone());
+    Ok(Json(response))
+}
+
+// 轮换刷新令牌：旧令牌被吊销并指向新令牌。
+// 已吊销的令牌再次出现说明可能被盗用，此时吊销该用户的全部令牌。
+#[post("/auth/refresh", data = "<request>")]
+pub async fn refresh(
+    conn: DbConn,
+    keys: &State<JwtKeys>,
+    cookies: &CookieJar<'_>,
+    request: Json<RefreshRequest>
+) -> Result<Json<TokenResponse>, ApiError> {
+    let hash = hash_refresh_token(&request.refresh_token);
+    let (access_ttl_secs, refresh_ttl_secs) = (keys.access_ttl_secs, keys.refresh_ttl_secs);
+
+    let (claims, refresh_token) = conn.run(move |c| {
+        c.transaction(|c| {
+            let stored = refresh_tokens::table
+                .filter(refresh_tokens::token_hash.eq(&hash))
+                .select(RefreshToken::as_select())
+                .first(c)
+                .optional()?;
+
+            let stored = match stored {
+                Some(stored) => stored,
+                None => return Ok(None),
+            };
+
+            let now = Utc::now().naive_utc();
+            if stored.revoked_at.is_some() {
+                revoke_user_tokens(c, stored.user_id)?;
+                return Ok(None);
+            }
+            if stored.expires_at < now {
+                return Ok(None);
+            }
+
+            let user: User = users::table
+                .find(stored.user_id)
+                .select(User::as_select())
+                .first(c)?;
+            if user.status.as_deref() == Some("inactive") {
+                revoke_user_tokens(c, user.user_id)?;
+                return Ok(None);
+            }
+
+            let (new_token_id, claims, refresh_token) =
+                prepare_tokens(c, &user, access_ttl_secs, refresh_ttl_secs)?;
+            diesel::update(refresh_tokens::table.find(stored.token_id))
+                .set((
+                    refresh_tokens::revoked_at.eq(now),
+                    refresh_tokens::replaced_by.eq(new_token_id),
+                ))
+                .execute(c)?;
+
+            Ok::<_, diesel::result::Error>(Some((claims, refresh_token)))
+        })
+    }).await
+    .map_err(ApiError::from)?
+    .ok_or_else(|| ApiError::new(Status::Unauthorized, "invalid_refresh_token", "refresh token is invalid or expired"))?;
+
+    let response = token_response(keys, &claims, refresh_token)?;
+    set_token_cookie(cookies, response.access_token.clone());
+    Ok(Json(response))
+}
+
+#[post("/auth/logout", data = "<request>")]
+pub async fn logout(
+    conn: DbConn,
+    cookies: &CookieJar<'_>,
+    request: Json<RefreshRequest>
+) -> Result<Status, ApiError> {
+    let hash = hash_refresh_token(&request.refresh_token);
+    cookies.remove(Cookie::from("token"));
+
+    conn.run(move |c| {
+        diesel::update(
+            refresh_tokens::table
+                .filter(refresh_tokens::token_hash.eq(hash))
+                .filter(refresh_tokens::revoked_at.is_null())
+        )
+        .set(refresh_tokens::revoked_at.eq(Utc::now().naive_utc()))
+        .execute(c)
+    }).await
+    .map(|_| Status::NoContent)
+    .map_err(ApiError::from)
+}
@@ -0,0 +1,157 @@
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use diesel::sql_types::{BigInt, Integer};
+use diesel::sqlite::SqliteConnection;
+use rocket::request::Request;
+use rocket::response::{self, Responder};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::error::ApiError;
+
+// 删除时会阻止父记录删除的外键（RESTRICT 或未声明 ON DELETE），
+// CASCADE 和 SET NULL 的引用不会阻止删除，不在此列。
+// 用户删除和软删除记录的清理依赖此表，修改外键的迁移需要同步：(父表, 子表, 子表外键列)
+const REFERENCES: &[(&str, &str, &str)] = &[
+    ("warehouses", "warehouse_stock", "warehouse_id"),
+    ("warehouses", "material_requests", "warehouse_id"),
+    ("warehouses", "stock_movements", "warehouse_id"),
+    ("warehouses", "stock_transfers", "warehouse_id"),
+    ("materials", "warehouse_stock", "material_id"),
+    ("materials", "material_requests", "material_id"),
+    ("materials", "stock_movements", "material_id"),
+    ("materials", "stock_transfers", "material_id"),
+    ("product_specifications", "production_tasks", "product_id"),
+    ("production_tasks", "stock_movements", "task_id"),
+    ("material_requests", "stock_movements", "request_id"),
+    ("operation_logs", "operation_log_signatures", "log_id"),
+    ("users", "material_requests", "requested_by"),
+    ("users", "stock_movements", "performed_by"),
+    ("users", "operation_logs", "user_id"),
+    ("users", "materials", "created_by"),
+    ("users", "product_specifications", "created_by"),
+    ("users", "price_formulas", "created_by"),
+    ("users", "production_costs", "created_by"),
+];
+
+// 引用待删除记录的子表及行数
+#[derive(Debug, Serialize)]
+pub struct Dependent {
+    pub table: &'static str,
+    pub column: &'static str,
+    pub count: i64,
+}
+
+#[derive(QueryableByName)]
+struct Count {
+    #[diesel(sql_type = BigInt)]
+    count: i64,
+}
+
+// 查询仍引用 table 中 id 这条记录的子表
+pub fn blocking_dependents(c: &mut SqliteConnection, table: &str, id: i32) -> QueryResult<Vec<Dependent>> {
+    let mut dependents = Vec::new();
+    for &(_, child, column) in REFERENCES.iter().filter(|(parent, _, _)| *parent == table) {
+        let count = diesel::sql_query(format!("SELECT COUNT(*) AS count FROM {} WHERE {} = ?", child, column))
+            .bind::<Integer, _>(id)
+            .get_result::<Count>(c)?
+            .count;
+        if count > 0 {
+            dependents.push(Dependent { table: child, column, count });
+        }
+    }
+    Ok(dependents)
+}
+
+// 删除接口的错误，被引用时返回 409，details.dependents 列出引用方
+#[derive(Debug)]
+pub enum DeleteError {
+    NotFound,
+    Blocked(Vec<Dependent>),
+    Database(DieselError),
+}
+
+impl From<DieselError> for DeleteError {
+    fn from(err: DieselError) -> Self {
+        match err {
+            DieselError::NotFound => DeleteError::NotFound,
+            other => DeleteError::Database(other),
+        }
+    }
+}
+
+impl From<DeleteError> for ApiError {
+    fn from(err: DeleteError) -> Self {
+        match err {
+            DeleteError::NotFound => ApiError::not_found("record not found"),
+            DeleteError::Blocked(dependents) => {
+                ApiError::conflict("record_referenced", "record is still referenced")
+                    .with_details(json!({ "dependents": dependents }))
+            }
+            DeleteError::Database(e) => e.into(),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for DeleteError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        ApiError::from(self).respond_to(request)
+    }
+}
+
+// 先检查引用再删除，其余表改为软删除后只有用户删除使用。检查与删除之间并发插入的引用由外键拦截，同样返回 409
+pub fn delete_checked<F>(c: &mut SqliteConnection, table: &str, id: i32, delete: F) -> Result<usize, DeleteError>
+where
+    F: FnOnce(&mut SqliteConnection) -> QueryResult<usize>,
+{
+    let dependents = blocking_dependents(c, table, id)?;
+    if !dependents.is_empty() {
+        return Err(DeleteError::Blocked(dependents));
+    }
+    match delete(c) {
+        Ok(0) => Err(DeleteError::NotFound),
+        Ok(rows) => Ok(rows),
+        Err(DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _)) => {
+            Err(DeleteError::Blocked(blocking_dependents(c, table, id)?))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::sql_types::Text;
+
+    #[derive(QueryableByName)]
+    struct ForeignKey {
+        #[diesel(sql_type = Text)]
+        parent: String,
+        #[diesel(sql_type = Text)]
+        child: String,
+        #[diesel(sql_type = Text)]
+        column: String,
+    }
+
+    #[test]
+    fn references_match_blocking_foreign_keys() {
+        let mut c = crate::db::memory_connection();
+        let mut expected = diesel::sql_query(
+            "SELECT p.\"table\" AS parent, m.name AS child, p.\"from\" AS column \
+             FROM sqlite_master m, pragma_foreign_key_list(m.name) p \
+             WHERE m.type = 'table' AND p.on_delete IN ('RESTRICT', 'NO ACTION')",
+        )
+        .load::<ForeignKey>(&mut c)
+        .unwrap()
+        .into_iter()
+        .map(|fk| (fk.parent, fk.child, fk.column))
+        .collect::<Vec<_>>();
+        let mut actual = REFERENCES
+            .iter()
+            .map(|&(parent, child, column)| (parent.to_string(), child.to_string(), column.to_string()))
+            .collect::<Vec<_>>();
+        expected.sort();
+        actual.sort();
+        assert_eq!(actual, expected);
+    }
+}
@@ -1,153 +1,209 @@
 use diesel::prelude::*;
 use rocket::serde::json::Json;
 use rocket::http::Status;
-use rocket::{get, post, put, delete};
-use chrono::Utc;
+use rocket::{get, post, put, delete, State};
+use chrono::{NaiveDateTime, Utc};
 
+use crate::error::ApiError;
 use crate::models::{Material, NewMaterial, DbConn};
 use crate::schema::materials;
-use crate::token::TokenGuard;
+use crate::auth_guard::{RequirePermission, MaterialRead, MaterialWrite};
+use crate::routers::pagination::{sort_by, ListQuery, Page};
+use crate::replication::{self, ReplicatedEntity, ReplicatedRecord, ReplicationSender, RowState};
+use crate::events::{DomainEvent, EventSender};
 
-#[get("/materials")]
-pub async fn list_materials(conn: DbConn, _token: TokenGuard) -> Result<Json<Vec<Material>>, Status> {
-    conn.run(|c| {
-        materials::table
+// 默认不含已删除的材料，include_deleted=true 时一并返回
+#[get("/materials?<include_deleted>")]
+pub async fn list_materials(
+    conn: DbConn,
+    _perm: RequirePermission<MaterialRead>,
+    list: ListQuery,
+    include_deleted: Option<bool>
+) -> Result<Json<Page>, ApiError> {
+    conn.run(move |c| {
+        let filtered = || {
+            let mut query = materials::table.into_boxed();
+            if !include_deleted.unwrap_or(false) {
+                query = query.filter(materials::deleted_at.is_null());
+            }
+            query
+        };
+        let total = filtered()
+            .count()
+            .get_result::<i64>(c)
+            .map_err(ApiError::from)?;
+        let (query, keyset) = sort_by!(filtered(), list, ("created_at", true), materials::material_id, [
+            "material_id" => materials::material_id,
+            "material_name" => materials::material_name,
+            "category" => materials::category,
+            "type_" => materials::type_,
+            "supplier" => materials::supplier,
+            "created_at" => materials::created_at,
+        ])?;
+        query
             .select(Material::as_select())
-            .order(materials::created_at.desc())
+            .offset(list.offset)
+            .limit(list.limit)
             .load(c)
+            .map_err(ApiError::from)
+            .and_then(|items| list.page(&keyset, items, total))
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
 }
 
 #[get("/materials/<material_id>")]
 pub async fn get_material(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<MaterialRead>,
     material_id: i32
-) -> Result<Json<Material>, Status> {
+) -> Result<Json<Material>, ApiError> {
     conn.run(move |c| {
         materials::table
-            .find(material_id)
+            .filter(materials::material_id.eq(material_id))
             .select(Material::as_select())
             .first(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::NotFound)
+    .map_err(ApiError::from)
 }
 
 #[get("/materials/by_name/<material_name>")]
 pub async fn get_material_by_name(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<MaterialRead>,
     material_name: String
-) -> Result<Json<Material>, Status> {
+) -> Result<Json<Material>, ApiError> {
     conn.run(move |c| {
         materials::table
             .filter(materials::material_name.eq(material_name))
+            .filter(materials::deleted_at.is_null())
             .select(Material::as_select())
             .first(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::NotFound)
+    .map_err(ApiError::from)
 }
 
 #[get("/materials/by_category/<category>")]
 pub async fn get_materials_by_category(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<MaterialRead>,
     category: String
-) -> Result<Json<Vec<Material>>, Status> {
+) -> Result<Json<Vec<Material>>, ApiError> {
     conn.run(move |c| {
         materials::table
             .filter(materials::category.eq(category))
+            .filter(materials::deleted_at.is_null())
             .select(Material::as_select())
             .order(materials::created_at.desc())
             .load(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
 
 #[get("/materials/by_supplier/<supplier>")]
 pub async fn get_materials_by_supplier(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<MaterialRead>,
     supplier: String
-) -> Result<Json<Vec<Material>>, Status> {
+) -> Result<Json<Vec<Material>>, ApiError> {
     conn.run(move |c| {
         materials::table
             .filter(materials::supplier.eq(supplier))
+            .filter(materials::deleted_at.is_null())
             .select(Material::as_select())
             .order(materials::created_at.desc())
             .load(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
 
 #[post("/materials", data = "<material>")]
 pub async fn create_material(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<MaterialWrite>,
+    replication: &State<ReplicationSender>,
+    events: &State<EventSender>,
     material: Json<NewMaterial>
-) -> Result<Json<Material>, Status> {
+) -> Result<Json<Material>, ApiError> {
     // 检查材料名称是否已存在
+    let material = material.into_inner();
+    let material_name = material.material_name.clone();
     let exists = conn.run(move |c| {
         materials::table
-            .filter(materials::material_name.eq(&material.material_name))
+            .filter(materials::material_name.eq(material_name))
+            // 已删除的材料不占用名称
+            .filter(materials::deleted_at.is_null())
             .count()
             .get_result::<i64>(c)
     }).await;
 
     if let Ok(count) = exists {
         if count > 0 {
-            return Err(Status::Conflict);
+            return Err(ApiError::conflict("duplicate_name", "material name already exists").with_field("material_name"));
         }
     }
 
+    let global_id = replication::new_global_id();
     let material_with_timestamp = (
-        materials::material_name.eq(&material.material_name),
-        materials::category.eq(&material.category),
-        materials::type_.eq(&material.type_),
-        materials::supplier.eq(&material.supplier),
+        materials::material_name.eq(material.material_name),
+        materials::category.eq(material.category),
+        materials::type_.eq(material.type_),
+        materials::supplier.eq(material.supplier),
         materials::created_by.eq(material.created_by),
         materials::created_at.eq(Utc::now().naive_utc()),
+        materials::global_id.eq(global_id.clone()),
     );
 
-    conn.run(move |c| {
+    let created: Material = conn.run(move |c| {
+        let hlc = replication::next_hlc(c)?;
         diesel::insert_into(materials::table)
-            .values(material_with_timestamp)
-            .get_result(c)
+            .values((material_with_timestamp, materials::hlc.eq(hlc)))
+            .execute(c)?;
+        materials::table
+            .filter(materials::global_id.eq(global_id))
+            .select(Material::as_select())
+            .first(c)
     }).await
-    .map(Json)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)?;
+
+    replication.upsert(ReplicatedRecord::Material(created.clone()), None).await;
+    events.emit(DomainEvent::MaterialCreated(created.clone())).await;
+    Ok(Json(created))
 }
 
 #[put("/materials/<material_id>", data = "<material>")]
 pub async fn update_material(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<MaterialWrite>,
+    replication: &State<ReplicationSender>,
+    events: &State<EventSender>,
     material_id: i32,
     material: Json<NewMaterial>
-) -> Result<Json<Material>, Status> {
+) -> Result<Json<Material>, ApiError> {
     // 检查新的材料名称是否与其他材料冲突
+    let material_name = material.material_name.clone();
     let exists = conn.run(move |c| {
         materials::table
-            .filter(materials::material_name.eq(&material.material_name))
+            .filter(materials::material_name.eq(material_name))
             .filter(materials::material_id.ne(material_id))
+            .filter(materials::deleted_at.is_null())
             .count()
             .get_result::<i64>(c)
     }).await;
 
     if let Ok(count) = exists {
         if count > 0 {
-            return Err(Status::Conflict);
+            return Err(ApiError::conflict("duplicate_name", "material name already exists").with_field("material_name"));
         }
     }
 
-    conn.run(move |c| {
-        diesel::update(materials::table.find(material_id))
+    let (updated, previous_hlc) = conn.run(move |c| c.transaction(|c| {
+        // 修改前的时钟随消息发出，接收端据此判断是否并发修改
+        let version = replication::stamp_local_write(c, ReplicatedEntity::Material, material_id, RowState::Active)?;
+        diesel::update(materials::table.filter(materials::material_id.eq(material_id)))
             .set((
                 materials::material_name.eq(&material.material_name),
                 materials::category.eq(&material.category),
@@ -155,101 +211,190 @@ pub async fn update_material(
                 materials::supplier.eq(&material.supplier),
                 materials::created_by.eq(material.created_by),
             ))
-            .get_result(c)
-    }).await
-    .map(Json)
-    .map_err(|_| Status::NotFound)
+            .execute(c)?;
+        let updated: Material = materials::table
+            .filter(materials::material_id.eq(material_id))
+            .select(Material::as_select())
+            .first(c)?;
+        Ok::<_, diesel::result::Error>((updated, version.previous_hlc))
+    })).await
+    .map_err(ApiError::from)?;
+
+    replication.upsert(ReplicatedRecord::Material(updated.clone()), previous_hlc).await;
+    events.emit(DomainEvent::MaterialUpdated(updated.clone())).await;
+    Ok(Json(updated))
 }
 
+// 软删除，已有的领料单和库存流水仍可引用该材料。超过保留期后由定时任务物理删除
 #[delete("/materials/<material_id>")]
 pub async fn delete_material(
     conn: DbConn,
-    _token: TokenGuard,
+    perm: RequirePermission<MaterialWrite>,
+    replication: &State<ReplicationSender>,
+    events: &State<EventSender>,
     material_id: i32
-) -> Result<Status, Status> {
-    conn.run(move |c| {
-        diesel::delete(materials::table.find(material_id))
-            .execute(c)
+) -> Result<Status, ApiError> {
+    let user_id = perm.user_id;
+    // 删除前取出 global_id 和时钟，用于通知其他节点
+    let (global_id, hlc, previous_hlc) = conn.run(move |c| {
+        c.transaction(|c| {
+            let version = replication::stamp_local_write(c, ReplicatedEntity::Material, material_id, RowState::Active)?;
+            diesel::update(materials::table.filter(materials::material_id.eq(material_id)))
+                .set((
+                    materials::deleted_at.eq(Utc::now().naive_utc()),
+                    materials::deleted_by.eq(user_id),
+                ))
+                .execute(c)?;
+            Ok::<_, diesel::result::Error>((version.global_id, version.hlc, version.previous_hlc))
+        })
     }).await
-    .map(|affected| {
-        if affected > 0 {
-            Status::NoContent
-        } else {
-            Status::NotFound
-        }
-    })
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)?;
+
+    if let Some(global_id) = global_id.clone() {
+        replication.delete(ReplicatedEntity::Material, global_id, hlc, previous_hlc).await;
+    }
+    events.emit(DomainEvent::MaterialDeleted { global_id }).await;
+    Ok(Status::NoContent)
+}
+
+// 恢复软删除的材料，作为一次修改复制到其他节点
+#[post("/materials/<material_id>/restore")]
+pub async fn restore_material(
+    conn: DbConn,
+    _perm: RequirePermission<MaterialWrite>,
+    replication: &State<ReplicationSender>,
+    events: &State<EventSender>,
+    material_id: i32
+) -> Result<Json<Material>, ApiError> {
+    let (restored, previous_hlc) = conn.run(move |c| {
+        c.transaction(|c| {
+            let material_name = materials::table
+                .filter(materials::material_id.eq(material_id))
+                .filter(materials::deleted_at.is_not_null())
+                .select(materials::material_name)
+                .first::<String>(c)?;
+            // 删除期间名称可能已被新材料使用
+            let taken = materials::table
+                .filter(materials::material_name.eq(&material_name))
+                .filter(materials::deleted_at.is_null())
+                .count()
+                .get_result::<i64>(c)?;
+            if taken > 0 {
+                return Err(ApiError::conflict("duplicate_name", "material name already exists").with_field("material_name"));
+            }
+            let version = replication::stamp_local_write(c, ReplicatedEntity::Material, material_id, RowState::Deleted)?;
+            diesel::update(materials::table.filter(materials::material_id.eq(material_id)))
+                .set((
+                    materials::deleted_at.eq(None::<NaiveDateTime>),
+                    materials::deleted_by.eq(None::<i32>),
+                ))
+                .execute(c)?;
+            let restored: Material = materials::table
+                .filter(materials::material_id.eq(material_id))
+                .select(Material::as_select())
+                .first(c)?;
+            Ok::<_, ApiError>((restored, version.previous_hlc))
+        })
+    }).await?;
+
+    replication.upsert(ReplicatedRecord::Material(restored.clone()), previous_hlc).await;
+    events.emit(DomainEvent::MaterialRestored(restored.clone())).await;
+    Ok(Json(restored))
 }
 
-// 搜索材料
-#[get("/materials/search?<query>&<category>&<supplier>")]
+// 搜索材料，分页、排序和字段选择同 /materials
+#[get("/materials/search?<query>&<category>&<supplier>&<include_deleted>")]
 pub async fn search_materials(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<MaterialRead>,
+    list: ListQuery,
     query: Option<String>,
     category: Option<String>,
-    supplier: Option<String>
-) -> Result<Json<Vec<Material>>, Status> {
+    supplier: Option<String>,
+    include_deleted: Option<bool>
+) -> Result<Json<Page>, ApiError> {
     conn.run(move |c| {
-        let mut query_builder = materials::table
-            .into_boxed();
-
-        if let Some(q) = query {
-            query_builder = query_builder.filter(
-                materials::material_name.like(format!("%{}%", q))
-                    .or(materials::type_.like(format!("%{}%", q)))
-            );
-        }
+        let filtered = || {
+            let mut query_builder = materials::table
+                .into_boxed();
 
-        if let Some(cat) = category {
-            query_builder = query_builder.filter(
-                materials::category.eq(cat)
-            );
-        }
+            if !include_deleted.unwrap_or(false) {
+                query_builder = query_builder.filter(materials::deleted_at.is_null());
+            }
 
-        if let Some(sup) = supplier {
-            query_builder = query_builder.filter(
-                materials::supplier.eq(sup)
-            );
-        }
+            if let Some(q) = &query {
+                query_builder = query_builder.filter(
+                    materials::material_name.like(format!("%{}%", q))
+                        .or(materials::type_.like(format!("%{}%", q)))
+                );
+            }
 
-        query_builder
-            .order(materials::created_at.desc())
+            if let Some(cat) = &category {
+                query_builder = query_builder.filter(
+                    materials::category.eq(cat.clone())
+                );
+            }
+
+            if let Some(sup) = &supplier {
+                query_builder = query_builder.filter(
+                    materials::supplier.eq(sup.clone())
+                );
+            }
+            query_builder
+        };
+        let total = filtered()
+            .count()
+            .get_result::<i64>(c)
+            .map_err(ApiError::from)?;
+        let (query, keyset) = sort_by!(filtered(), list, ("created_at", true), materials::material_id, [
+            "material_id" => materials::material_id,
+            "material_name" => materials::material_name,
+            "category" => materials::category,
+            "type_" => materials::type_,
+            "supplier" => materials::supplier,
+            "created_at" => materials::created_at,
+        ])?;
+        query
             .select(Material::as_select())
+            .offset(list.offset)
+            .limit(list.limit)
             .load(c)
+            .map_err(ApiError::from)
+            .and_then(|items| list.page(&keyset, items, total))
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
 }
 
 // 获取所有供应商列表
 #[get("/materials/suppliers")]
-pub async fn list_suppliers(conn: DbConn, _token: TokenGuard) -> Result<Json<Vec<String>>, Status> {
+pub async fn list_suppliers(conn: DbConn, _perm: RequirePermission<MaterialRead>) -> Result<Json<Vec<String>>, ApiError> {
     conn.run(|c| {
         materials::table
             .select(materials::supplier)
             .filter(materials::supplier.is_not_null())
+            .filter(materials::deleted_at.is_null())
             .distinct()
             .load::<Option<String>>(c)
     }).await
     .map(|suppliers| {
-        Json(suppliers.into_iter().filter_map(|s| s).collect())
+        Json(suppliers.into_iter().flatten().collect())
     })
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
 
 // 获取所有类别列表
 #[get("/materials/categories")]
-pub async fn list_categories(conn: DbConn, _token: TokenGuard) -> Result<Json<Vec<String>>, Status> {
+pub async fn list_categories(conn: DbConn, _perm: RequirePermission<MaterialRead>) -> Result<Json<Vec<String>>, ApiError> {
     conn.run(|c| {
         materials::table
             .select(materials::category)
             .filter(materials::category.is_not_null())
+            .filter(materials::deleted_at.is_null())
             .distinct()
             .load::<Option<String>>(c)
     }).await
     .map(|categories| {
-        Json(categories.into_iter().filter_map(|c| c).collect())
+        Json(categories.into_iter().flatten().collect())
     })
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
@@ -1,47 +1,198 @@
 use diesel::prelude::*;
-use rocket::serde::json::Json;
 use rocket::http::Status;
-use rocket::{get, post};
+use rocket::serde::json::Json;
+use rocket::{get, post, put, FromForm, State};
 use chrono::Utc;
+use diesel::sqlite::SqliteConnection;
+use serde::Deserialize;
 
-use crate::models::{MaterialRequest, NewMaterialRequest, DbConn};
+use crate::error::ApiError;
+use crate::models::{MaterialRequest, NewMaterialRequest, NewStockMovement, DbConn};
 use crate::schema::material_requests;
-use crate::token::TokenGuard;
+use crate::routers::stock_movement::{self, record_movement};
+use crate::routers::warehouse_stock::StockError;
+use crate::auth_guard::{CurrentUser, RequirePermission, MaterialRequestApprove, MaterialRequestRead, MaterialRequestWrite};
+use crate::routers::pagination::{sort_by, ListQuery, Page};
+use crate::events::{DomainEvent, EventSender};
+use crate::soft_delete;
 
-#[get("/material_requests")]
-pub async fn list_material_requests(conn: DbConn, _token: TokenGuard) -> Result<Json<Vec<MaterialRequest>>, Status> {
-    conn.run(|c| {
+// 领用状态，与 material_requests.status 的 CHECK 约束一致
+pub const PENDING: &str = "pending";
+pub const APPROVED: &str = "approved";
+pub const REJECTED: &str = "rejected";
+pub const CANCELLED: &str = "cancelled";
+
+// 驳回请求
+#[derive(Debug, Deserialize)]
+pub struct RejectRequest {
+    pub reason: String,
+}
+
+// 审批流程错误
+#[derive(Debug)]
+pub enum RequestError {
+    NotFound,
+    // 只有申请人本人或有审批权限的用户可以取消
+    Forbidden,
+    InvalidTransition,
+    InsufficientStock,
+    QuantityOverflow,
+    Database(diesel::result::Error),
+}
+
+impl From<diesel::result::Error> for RequestError {
+    fn from(err: diesel::result::Error) -> Self {
+        match err {
+            diesel::result::Error::NotFound => RequestError::NotFound,
+            other => RequestError::Database(other),
+        }
+    }
+}
+
+impl From<StockError> for RequestError {
+    fn from(err: StockError) -> Self {
+        match err {
+            StockError::NotFound => RequestError::NotFound,
+            StockError::InsufficientStock => RequestError::InsufficientStock,
+            StockError::QuantityOverflow => RequestError::QuantityOverflow,
+            StockError::Database(e) => RequestError::Database(e),
+        }
+    }
+}
+
+impl From<RequestError> for ApiError {
+    fn from(err: RequestError) -> Self {
+        match err {
+            RequestError::NotFound => ApiError::not_found("material request not found"),
+            RequestError::Forbidden => {
+                ApiError::new(Status::Forbidden, "forbidden", "only the requester or an approver can cancel this request")
+            }
+            RequestError::InvalidTransition => {
+                ApiError::conflict("invalid_transition", "only pending requests can change status").with_field("status")
+            }
+            RequestError::InsufficientStock => {
+                ApiError::conflict("insufficient_stock", "not enough stock").with_field("quantity")
+            }
+            RequestError::QuantityOverflow => StockError::QuantityOverflow.into(),
+            RequestError::Database(e) => e.into(),
+        }
+    }
+}
+
+// 只有 pending 状态可以流转，approved/rejected/cancelled 均为终态
+fn transition(
+    c: &mut SqliteConnection,
+    request_id: i32,
+    to: &str,
+    reviewed_by: Option<i32>,
+    rejection_reason: Option<String>,
+) -> Result<MaterialRequest, RequestError> {
+    let request: MaterialRequest = material_requests::table
+        .filter(material_requests::request_id.eq(request_id))
+        .select(MaterialRequest::as_select())
+        .first(c)?;
+
+    if request.status.as_deref().unwrap_or(PENDING) != PENDING {
+        return Err(RequestError::InvalidTransition);
+    }
+
+    // 审批通过时扣减库存，与状态变更在同一事务内
+    if to == APPROVED {
+        let (material_id, warehouse_id, quantity) =
+            match (request.material_id, request.warehouse_id, request.quantity) {
+                (Some(m), Some(w), Some(q)) if q > 0 => (m, w, q),
+                _ => return Err(RequestError::InvalidTransition),
+            };
+        record_movement(c, NewStockMovement {
+            warehouse_id,
+            material_id,
+            movement_type: stock_movement::ISSUE.to_string(),
+            quantity: -quantity,
+            request_id: Some(request_id),
+            task_id: None,
+            performed_by: reviewed_by,
+            note: None,
+        })?;
+    }
+
+    let updated = diesel::update(
         material_requests::table
+            .filter(material_requests::request_id.eq(request_id))
+            .filter(material_requests::status.eq(PENDING).or(material_requests::status.is_null()))
+    )
+    .set((
+        material_requests::status.eq(to),
+        material_requests::reviewed_by.eq(reviewed_by),
+        material_requests::reviewed_at.eq(Utc::now().naive_utc()),
+        material_requests::rejection_reason.eq(rejection_reason),
+    ))
+    .execute(c)?;
+
+    if updated == 0 {
+        return Err(RequestError::InvalidTransition);
+    }
+
+    let request = material_requests::table
+        .filter(material_requests::request_id.eq(request_id))
+        .select(MaterialRequest::as_select())
+        .first(c)?;
+    Ok(request)
+}
+
+#[get("/material_requests")]
+pub async fn list_material_requests(
+    conn: DbConn,
+    _perm: RequirePermission<MaterialRequestRead>,
+    list: ListQuery
+) -> Result<Json<Page>, ApiError> {
+    conn.run(move |c| {
+        let filtered = || material_requests::table.into_boxed();
+        let total = filtered()
+            .count()
+            .get_result::<i64>(c)
+            .map_err(ApiError::from)?;
+        let (query, keyset) = sort_by!(filtered(), list, ("request_date", true), material_requests::request_id, [
+            "request_id" => material_requests::request_id,
+            "material_id" => material_requests::material_id,
+            "warehouse_id" => material_requests::warehouse_id,
+            "quantity" => material_requests::quantity,
+            "status" => material_requests::status,
+            "request_date" => material_requests::request_date,
+            "reviewed_at" => material_requests::reviewed_at,
+        ])?;
+        query
             .select(MaterialRequest::as_select())
-            .order(material_requests::request_date.desc())
+            .offset(list.offset)
+            .limit(list.limit)
             .load(c)
+            .map_err(ApiError::from)
+            .and_then(|items| list.page(&keyset, items, total))
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
 }
 
 #[get("/material_requests/<request_id>")]
 pub async fn get_material_request(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<MaterialRequestRead>,
     request_id: i32
-) -> Result<Json<MaterialRequest>, Status> {
+) -> Result<Json<MaterialRequest>, ApiError> {
     conn.run(move |c| {
         material_requests::table
-            .find(request_id)
+            .filter(material_requests::request_id.eq(request_id))
             .select(MaterialRequest::as_select())
             .first(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::NotFound)
+    .map_err(ApiError::from)
 }
 
 #[get("/material_requests/by_material/<material_id>")]
 pub async fn get_requests_by_material(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<MaterialRequestRead>,
     material_id: i32
-) -> Result<Json<Vec<MaterialRequest>>, Status> {
+) -> Result<Json<Vec<MaterialRequest>>, ApiError> {
     conn.run(move |c| {
         material_requests::table
             .filter(material_requests::material_id.eq(material_id))
@@ -50,15 +201,15 @@ pub async fn get_requests_by_material(
             .load(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
 
 #[get("/material_requests/by_warehouse/<warehouse_id>")]
 pub async fn get_requests_by_warehouse(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<MaterialRequestRead>,
     warehouse_id: i32
-) -> Result<Json<Vec<MaterialRequest>>, Status> {
+) -> Result<Json<Vec<MaterialRequest>>, ApiError> {
     conn.run(move |c| {
         material_requests::table
             .filter(material_requests::warehouse_id.eq(warehouse_id))
@@ -67,15 +218,15 @@ pub async fn get_requests_by_warehouse(
             .load(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
 
 #[get("/material_requests/by_status/<status>")]
 pub async fn get_requests_by_status(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<MaterialRequestRead>,
     status: String
-) -> Result<Json<Vec<MaterialRequest>>, Status> {
+) -> Result<Json<Vec<MaterialRequest>>, ApiError> {
     conn.run(move |c| {
         material_requests::table
             .filter(material_requests::status.eq(status))
@@ -84,15 +235,15 @@ pub async fn get_requests_by_status(
             .load(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
 
 #[get("/material_requests/by_requester/<requested_by>")]
 pub async fn get_requests_by_requester(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<MaterialRequestRead>,
     requested_by: i32
-) -> Result<Json<Vec<MaterialRequest>>, Status> {
+) -> Result<Json<Vec<MaterialRequest>>, ApiError> {
     conn.run(move |c| {
         material_requests::table
             .filter(material_requests::requested_by.eq(requested_by))
@@ -101,89 +252,383 @@ pub async fn get_requests_by_requester(
             .load(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
 
 #[post("/material_requests", data = "<request>")]
 pub async fn create_material_request(
     conn: DbConn,
-    _token: TokenGuard,
+    perm: RequirePermission<MaterialRequestWrite>,
+    events: &State<EventSender>,
     request: Json<NewMaterialRequest>
-) -> Result<Json<MaterialRequest>, Status> {
+) -> Result<Json<MaterialRequest>, ApiError> {
     let request_with_date = (
         material_requests::material_id.eq(request.material_id),
         material_requests::quantity.eq(request.quantity),
-        material_requests::requested_by.eq(request.requested_by),
+        material_requests::requested_by.eq(perm.user_id),
         material_requests::warehouse_id.eq(request.warehouse_id),
-        material_requests::status.eq(&request.status),
+        material_requests::status.eq(PENDING), // 新建的领用一律为 pending
         material_requests::request_date.eq(Utc::now().naive_utc()),
     );
 
-    conn.run(move |c| {
-        diesel::insert_into(material_requests::table)
-            .values(request_with_date)
-            .get_result(c)
-    }).await
-    .map(Json)
-    .map_err(|_| Status::InternalServerError)
+    let (material_id, warehouse_id) = (request.material_id, request.warehouse_id);
+    let created: MaterialRequest = conn.run(move |c| {
+        c.transaction(|c| {
+            // 不能为已删除的材料或仓库新建领料单
+            if !soft_delete::material_active(c, material_id)? {
+                return Err(ApiError::not_found("material not found").with_field("material_id"));
+            }
+            if !soft_delete::warehouse_active(c, warehouse_id)? {
+                return Err(ApiError::not_found("warehouse not found").with_field("warehouse_id"));
+            }
+            diesel::insert_into(material_requests::table)
+                .values(request_with_date)
+                .execute(c)?;
+            // SQLite 不支持 RETURNING，在同一事务内取回刚插入的记录
+            material_requests::table
+                .order(material_requests::request_id.desc())
+                .select(MaterialRequest::as_select())
+                .first(c)
+                .map_err(ApiError::from)
+        })
+    }).await?;
+
+    events.emit(DomainEvent::RequestCreated(created.clone())).await;
+    Ok(Json(created))
 }
 
-// 搜索材料请求
-#[get("/material_requests/search?<material_id>&<warehouse_id>&<status>&<start_date>&<end_date>")]
-pub async fn search_material_requests(
-    conn: DbConn,
-    _token: TokenGuard,
+// 材料请求的搜索条件，日期为 YYYY-MM-DD
+#[derive(Debug, FromForm)]
+pub struct MaterialRequestFilter {
     material_id: Option<i32>,
     warehouse_id: Option<i32>,
     status: Option<String>,
     start_date: Option<String>,
-    end_date: Option<String>
-) -> Result<Json<Vec<MaterialRequest>>, Status> {
+    end_date: Option<String>,
+}
+
+// 搜索材料请求，分页、排序和字段选择同 /material_requests
+#[get("/material_requests/search?<filter..>")]
+pub async fn search_material_requests(
+    conn: DbConn,
+    _perm: RequirePermission<MaterialRequestRead>,
+    list: ListQuery,
+    filter: MaterialRequestFilter
+) -> Result<Json<Page>, ApiError> {
     use chrono::NaiveDateTime;
 
+    let MaterialRequestFilter { material_id, warehouse_id, status, start_date, end_date } = filter;
     conn.run(move |c| {
-        let mut query_builder = material_requests::table
-            .into_boxed();
+        let filtered = || {
+            let mut query_builder = material_requests::table
+                .into_boxed();
 
-        if let Some(mid) = material_id {
-            query_builder = query_builder.filter(
-                material_requests::material_id.eq(mid)
-            );
-        }
-
-        if let Some(wid) = warehouse_id {
-            query_builder = query_builder.filter(
-                material_requests::warehouse_id.eq(wid)
-            );
-        }
-
-        if let Some(s) = status {
-            query_builder = query_builder.filter(
-                material_requests::status.eq(s)
-            );
-        }
+            if let Some(mid) = material_id {
+                query_builder = query_builder.filter(
+                    material_requests::material_id.eq(mid)
+                );
+            }
 
-        if let Some(start) = start_date {
-            if let Ok(start_dt) = NaiveDateTime::parse_from_str(&format!("{} 00:00:00", start), "%Y-%m-%d %H:%M:%S") {
+            if let Some(wid) = warehouse_id {
                 query_builder = query_builder.filter(
-                    material_requests::request_date.ge(start_dt)
+                    material_requests::warehouse_id.eq(wid)
                 );
             }
-        }
 
-        if let Some(end) = end_date {
-            if let Ok(end_dt) = NaiveDateTime::parse_from_str(&format!("{} 23:59:59", end), "%Y-%m-%d %H:%M:%S") {
+            if let Some(s) = &status {
                 query_builder = query_builder.filter(
-                    material_requests::request_date.le(end_dt)
+                    material_requests::status.eq(s.clone())
                 );
             }
-        }
 
-        query_builder
-            .order(material_requests::request_date.desc())
+            if let Some(start) = &start_date {
+                if let Ok(start_dt) = NaiveDateTime::parse_from_str(&format!("{} 00:00:00", start), "%Y-%m-%d %H:%M:%S") {
+                    query_builder = query_builder.filter(
+                        material_requests::request_date.ge(start_dt)
+                    );
+                }
+            }
+
+            if let Some(end) = &end_date {
+                if let Ok(end_dt) = NaiveDateTime::parse_from_str(&format!("{} 23:59:59", end), "%Y-%m-%d %H:%M:%S") {
+                    query_builder = query_builder.filter(
+                        material_requests::request_date.le(end_dt)
+                    );
+                }
+            }
+            query_builder
+        };
+        let total = filtered()
+            .count()
+            .get_result::<i64>(c)
+            .map_err(ApiError::from)?;
+        let (query, keyset) = sort_by!(filtered(), list, ("request_date", true), material_requests::request_id, [
+            "request_id" => material_requests::request_id,
+            "material_id" => material_requests::material_id,
+            "warehouse_id" => material_requests::warehouse_id,
+            "quantity" => material_requests::quantity,
+            "status" => material_requests::status,
+            "request_date" => material_requests::request_date,
+            "reviewed_at" => material_requests::reviewed_at,
+        ])?;
+        query
             .select(MaterialRequest::as_select())
+            .offset(list.offset)
+            .limit(list.limit)
             .load(c)
+            .map_err(ApiError::from)
+            .and_then(|items| list.page(&keyset, items, total))
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
+}
+
+// 审批通过并扣减库存，库存不足返回 409
+#[put("/material_requests/<request_id>/approve")]
+pub async fn approve_material_request(
+    conn: DbConn,
+    perm: RequirePermission<MaterialRequestApprove>,
+    events: &State<EventSender>,
+    request_id: i32
+) -> Result<Json<MaterialRequest>, ApiError> {
+    let user_id = perm.user_id;
+    let approved = conn.run(move |c| {
+        c.transaction(|c| {
+            let reviewer = Some(user_id);
+            transition(c, request_id, APPROVED, reviewer, None)
+        })
+    }).await
+    .map_err(ApiError::from)?;
+
+    events.emit(DomainEvent::RequestApproved(approved.clone())).await;
+    Ok(Json(approved))
+}
+
+#[put("/material_requests/<request_id>/reject", data = "<rejection>")]
+pub async fn reject_material_request(
+    conn: DbConn,
+    perm: RequirePermission<MaterialRequestApprove>,
+    events: &State<EventSender>,
+    request_id: i32,
+    rejection: Json<RejectRequest>
+) -> Result<Json<MaterialRequest>, ApiError> {
+    if rejection.reason.trim().is_empty() {
+        return Err(ApiError::bad_request("rejection reason is required").with_field("reason"));
+    }
+
+    let user_id = perm.user_id;
+    let rejected = conn.run(move |c| {
+        c.transaction(|c| {
+            let reviewer = Some(user_id);
+            transition(c, request_id, REJECTED, reviewer, Some(rejection.into_inner().reason))
+        })
+    }).await
+    .map_err(ApiError::from)?;
+
+    events.emit(DomainEvent::RequestRejected(rejected.clone())).await;
+    Ok(Json(rejected))
+}
+
+// 申请人可以取消自己的领料单，有审批权限的用户可以取消任何人的
+fn cancel(c: &mut SqliteConnection, request_id: i32, user_id: i32, approver: bool) -> Result<MaterialRequest, RequestError> {
+    let requested_by = material_requests::table
+        .filter(material_requests::request_id.eq(request_id))
+        .select(material_requests::requested_by)
+        .first::<Option<i32>>(c)?;
+    if !approver && requested_by != Some(user_id) {
+        return Err(RequestError::Forbidden);
+    }
+    transition(c, request_id, CANCELLED, Some(user_id), None)
+}
+
+#[put("/material_requests/<request_id>/cancel")]
+pub async fn cancel_material_request(
+    conn: DbConn,
+    perm: RequirePermission<MaterialRequestWrite>,
+    user: &CurrentUser,
+    events: &State<EventSender>,
+    request_id: i32
+) -> Result<Json<MaterialRequest>, ApiError> {
+    let user_id = perm.user_id;
+    let approver = user.has::<MaterialRequestApprove>();
+    let cancelled = conn.run(move |c| {
+        c.transaction(|c| cancel(c, request_id, user_id, approver))
+    }).await
+    .map_err(ApiError::from)?;
+
+    events.emit(DomainEvent::RequestCancelled(cancelled.clone())).await;
+    Ok(Json(cancelled))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::models::{StockMovement, WarehouseStock};
+    use crate::routers::warehouse_stock::apply_stock_change;
+    use crate::schema::{materials, stock_movements, users, warehouse_stock};
+    use crate::warehouse;
+
+    struct Fixture {
+        c: SqliteConnection,
+        warehouse_id: i32,
+        material_id: i32,
+        requester: i32,
+        approver: i32,
+    }
+
+    fn insert_user(c: &mut SqliteConnection, username: &str) -> i32 {
+        diesel::insert_into(users::table)
+            .values((users::username.eq(username), users::password_hash.eq("")))
+            .execute(c)
+            .unwrap();
+        users::table
+            .filter(users::username.eq(username))
+            .select(users::user_id)
+            .first(c)
+            .unwrap()
+    }
+
+    // 库存为 stock 的仓库和材料，以及申请人和审批人
+    fn fixture(stock: i32) -> Fixture {
+        let mut c = db::memory_connection();
+        warehouse::generate_and_insert_new_local_key(&mut c);
+        let warehouse_id = warehouse::this_warehouse_id(&mut c).unwrap();
+        diesel::insert_into(materials::table)
+            .values(materials::material_name.eq("steel"))
+            .execute(&mut c)
+            .unwrap();
+        let material_id = materials::table
+            .select(materials::material_id)
+            .first::<Option<i32>>(&mut c)
+            .unwrap()
+            .unwrap();
+        apply_stock_change(&mut c, warehouse_id, material_id, stock).unwrap();
+        let requester = insert_user(&mut c, "requester");
+        let approver = insert_user(&mut c, "approver");
+        Fixture { c, warehouse_id, material_id, requester, approver }
+    }
+
+    fn create_request(f: &mut Fixture, quantity: i32) -> i32 {
+        diesel::insert_into(material_requests::table)
+            .values((
+                material_requests::material_id.eq(f.material_id),
+                material_requests::warehouse_id.eq(f.warehouse_id),
+                material_requests::quantity.eq(quantity),
+                material_requests::requested_by.eq(f.requester),
+                material_requests::status.eq(PENDING),
+            ))
+            .execute(&mut f.c)
+            .unwrap();
+        material_requests::table
+            .order(material_requests::request_id.desc())
+            .select(material_requests::request_id)
+            .first::<Option<i32>>(&mut f.c)
+            .unwrap()
+            .unwrap()
+    }
+
+    // 与处理函数一样在事务内流转
+    fn review(f: &mut Fixture, request_id: i32, to: &str) -> Result<MaterialRequest, RequestError> {
+        let reviewer = Some(f.approver);
+        f.c.transaction(|c| transition(c, request_id, to, reviewer, None))
+    }
+
+    fn stock(f: &mut Fixture) -> i32 {
+        warehouse_stock::table
+            .filter(warehouse_stock::warehouse_id.eq(f.warehouse_id))
+            .filter(warehouse_stock::material_id.eq(f.material_id))
+            .select(WarehouseStock::as_select())
+            .first(&mut f.c)
+            .unwrap()
+            .quantity
+            .unwrap_or(0)
+    }
+
+    fn status(f: &mut Fixture, request_id: i32) -> Option<String> {
+        material_requests::table
+            .filter(material_requests::request_id.eq(request_id))
+            .select(material_requests::status)
+            .first(&mut f.c)
+            .unwrap()
+    }
+
+    fn movements(f: &mut Fixture, request_id: i32) -> Vec<StockMovement> {
+        stock_movements::table
+            .filter(stock_movements::request_id.eq(request_id))
+            .select(StockMovement::as_select())
+            .load(&mut f.c)
+            .unwrap()
+    }
+
+    #[test]
+    fn approval_issues_stock_once() {
+        let mut f = fixture(10);
+        let request_id = create_request(&mut f, 4);
+
+        let approved = review(&mut f, request_id, APPROVED).unwrap();
+
+        assert_eq!(approved.status.as_deref(), Some(APPROVED));
+        assert_eq!(approved.reviewed_by, Some(f.approver));
+        assert_eq!(stock(&mut f), 6);
+        let movements = movements(&mut f, request_id);
+        assert_eq!(movements.len(), 1);
+        assert_eq!(movements[0].movement_type, stock_movement::ISSUE);
+        assert_eq!(movements[0].quantity, -4);
+    }
+
+    #[test]
+    fn approval_without_enough_stock_changes_nothing() {
+        let mut f = fixture(3);
+        let request_id = create_request(&mut f, 4);
+
+        let err = review(&mut f, request_id, APPROVED).unwrap_err();
+
+        assert!(matches!(err, RequestError::InsufficientStock));
+        assert_eq!(ApiError::from(err).status, Status::Conflict);
+        assert_eq!(stock(&mut f), 3);
+        assert_eq!(status(&mut f, request_id).as_deref(), Some(PENDING));
+        assert!(movements(&mut f, request_id).is_empty());
+    }
+
+    #[test]
+    fn reviewed_requests_cannot_change_status() {
+        let mut f = fixture(10);
+        let approved = create_request(&mut f, 4);
+        review(&mut f, approved, APPROVED).unwrap();
+        let rejected = create_request(&mut f, 4);
+        review(&mut f, rejected, REJECTED).unwrap();
+
+        for request_id in [approved, rejected] {
+            for to in [APPROVED, REJECTED, CANCELLED] {
+                let err = review(&mut f, request_id, to).unwrap_err();
+                assert!(matches!(err, RequestError::InvalidTransition));
+            }
+            let requester = f.requester;
+            let err = f.c.transaction(|c| cancel(c, request_id, requester, false)).unwrap_err();
+            assert!(matches!(err, RequestError::InvalidTransition));
+        }
+        assert_eq!(status(&mut f, approved).as_deref(), Some(APPROVED));
+        assert_eq!(status(&mut f, rejected).as_deref(), Some(REJECTED));
+        assert_eq!(stock(&mut f), 6);
+        assert_eq!(movements(&mut f, approved).len(), 1);
+    }
+
+    #[test]
+    fn only_requester_or_approver_can_cancel() {
+        let mut f = fixture(10);
+        let request_id = create_request(&mut f, 4);
+        let other = insert_user(&mut f.c, "other");
+
+        let err = f.c.transaction(|c| cancel(c, request_id, other, false)).unwrap_err();
+        assert!(matches!(err, RequestError::Forbidden));
+        assert_eq!(status(&mut f, request_id).as_deref(), Some(PENDING));
+
+        let requester = f.requester;
+        let cancelled = f.c.transaction(|c| cancel(c, request_id, requester, false)).unwrap();
+        assert_eq!(cancelled.status.as_deref(), Some(CANCELLED));
+
+        let request_id = create_request(&mut f, 4);
+        let cancelled = f.c.transaction(|c| cancel(c, request_id, other, true)).unwrap();
+        assert_eq!(cancelled.status.as_deref(), Some(CANCELLED));
+    }
 }
@@ -11,3 +11,13 @@ pub mod price_formula;
 pub mod product_specification;
 pub mod material;
 pub mod material_request;
+pub mod warehouse_stock;
+pub mod stock_movement;
+pub mod auth;
+pub mod replication_conflict;
+pub mod stock_transfer;
+pub mod trusted_peer;
+pub mod network;
+pub mod outbox;
+pub mod dependents;
+pub mod pagination;
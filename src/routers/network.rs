@@ -0,0 +1,117 @@
+use diesel::prelude::*;
+use rocket::serde::json::Json;
+use rocket::http::Status;
+use rocket::{get, post, State};
+use libp2p::{Multiaddr, PeerId};
+
+use crate::error::ApiError;
+use crate::models::{SyncState, DbConn};
+use crate::schema::sync_state;
+use crate::network_state::{NetworkCommand, NetworkHandle, NetworkStatus, PeerStatus};
+use crate::auth_guard::{RequirePermission, NetworkRead, NetworkWrite};
+
+fn invalid_peer_id() -> ApiError {
+    ApiError::bad_request("invalid peer id").with_field("peer_id")
+}
+
+// swarm 任务已退出，例如以 --no-network 启动
+fn network_unavailable() -> ApiError {
+    ApiError::new(Status::ServiceUnavailable, "network_unavailable", "network task is not running")
+}
+
+// 本节点 PeerId、监听地址和订阅主题
+#[get("/network/status")]
+pub async fn get_network_status(
+    network: &State<NetworkHandle>,
+    _perm: RequirePermission<NetworkRead>,
+) -> Json<NetworkStatus> {
+    Json(network.status())
+}
+
+// 已发现或已连接的节点
+#[get("/network/peers")]
+pub async fn get_peers(
+    network: &State<NetworkHandle>,
+    _perm: RequirePermission<NetworkRead>,
+) -> Json<Vec<PeerStatus>> {
+    Json(network.peers())
+}
+
+#[get("/network/peers/<peer_id>")]
+pub async fn get_peer(
+    network: &State<NetworkHandle>,
+    _perm: RequirePermission<NetworkRead>,
+    peer_id: String,
+) -> Result<Json<PeerStatus>, ApiError> {
+    let peer_id = peer_id.parse::<PeerId>().map_err(|_| invalid_peer_id())?;
+    network.peer(&peer_id).map(Json).ok_or_else(|| ApiError::not_found("peer not found"))
+}
+
+// 拨号在 swarm 任务中异步进行，结果通过 /network/peers 查看
+#[post("/network/peers/<peer_id>/dial?<address>")]
+pub async fn dial_peer(
+    network: &State<NetworkHandle>,
+    _perm: RequirePermission<NetworkWrite>,
+    peer_id: String,
+    address: Option<String>,
+) -> Result<Status, ApiError> {
+    let peer_id = peer_id.parse::<PeerId>().map_err(|_| invalid_peer_id())?;
+    let address = match address {
+        Some(a) => Some(a.parse::<Multiaddr>().map_err(|_| ApiError::bad_request("invalid multiaddr").with_field("address"))?),
+        None => None,
+    };
+
+    network.send(NetworkCommand::Dial { peer_id, address }).await
+        .map(|_| Status::Accepted)
+        .map_err(|_| network_unavailable())
+}
+
+#[post("/network/peers/<peer_id>/disconnect")]
+pub async fn disconnect_peer(
+    network: &State<NetworkHandle>,
+    _perm: RequirePermission<NetworkWrite>,
+    peer_id: String,
+) -> Result<Status, ApiError> {
+    let peer_id = peer_id.parse::<PeerId>().map_err(|_| invalid_peer_id())?;
+    if network.peer(&peer_id).is_none_or(|p| !p.connected) {
+        return Err(ApiError::not_found("peer is not connected"));
+    }
+
+    network.send(NetworkCommand::Disconnect(peer_id)).await
+        .map(|_| Status::Accepted)
+        .map_err(|_| network_unavailable())
+}
+
+// 各对端的追赶进度
+#[get("/network/sync")]
+pub async fn get_sync_state(
+    conn: DbConn,
+    _perm: RequirePermission<NetworkRead>,
+) -> Result<Json<Vec<SyncState>>, ApiError> {
+    conn.run(|c| {
+        sync_state::table
+            .order(sync_state::synced_at.desc())
+            .select(SyncState::as_select())
+            .load(c)
+    }).await
+    .map(Json)
+    .map_err(ApiError::from)
+}
+
+// 从对端追赶数据，full 时重新拉取全量
+#[post("/network/peers/<peer_id>/sync?<full>")]
+pub async fn sync_peer(
+    network: &State<NetworkHandle>,
+    _perm: RequirePermission<NetworkWrite>,
+    peer_id: String,
+    full: Option<bool>,
+) -> Result<Status, ApiError> {
+    let peer_id = peer_id.parse::<PeerId>().map_err(|_| invalid_peer_id())?;
+    if network.peer(&peer_id).is_none_or(|p| !p.connected) {
+        return Err(ApiError::not_found("peer is not connected"));
+    }
+
+    network.send(NetworkCommand::Sync { peer_id, full: full.unwrap_or(false) }).await
+        .map(|_| Status::Accepted)
+        .map_err(|_| network_unavailable())
+}
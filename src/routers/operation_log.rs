@@ -1,26 +1,178 @@
+use chrono::{NaiveDate, NaiveDateTime};
 use diesel::prelude::*;
+use diesel::sqlite::{Sqlite, SqliteConnection};
 use rocket::serde::json::Json;
-use rocket::http::Status;
-use chrono::Utc;
-use rocket::{get, post};
-use crate::models::{OperationLog, NewOperationLog, DbConn};
+use rocket::http::{ContentType, Header};
+use rocket::{get, FromForm, Responder};
+use serde::Serialize;
+use crate::audit_chain::{self, ChainReport};
+use crate::error::ApiError;
+use crate::models::{OperationLog, DbConn};
 use crate::schema::operation_logs;
-use crate::token::TokenGuard;
+use crate::auth_guard::{RequirePermission, OperationLogRead};
 
-#[get("/operation_logs")]
-pub async fn list_operation_logs(conn: DbConn, _token: TokenGuard) -> Result<Json<Vec<OperationLog>>, Status> {
-    conn.run(|c| {
-        operation_logs::table
-            .select(OperationLog::as_select())
-            .order(operation_logs::timestamp.desc())
-            .load(c)
-    }).await
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 500;
+// 导出时每批读取的条数
+const EXPORT_BATCH: i64 = 1000;
+// 单次导出的最大条数，导出在内存中拼接，超出时要求缩小过滤范围
+const MAX_EXPORT_ROWS: i64 = 100_000;
+
+// 搜索和导出共用的过滤条件，日期可以是 YYYY-MM-DD 或完整时间
+#[derive(Debug, FromForm)]
+pub struct OperationLogFilter {
+    user_id: Option<i32>,
+    action: Option<String>,
+    method: Option<String>,
+    entity_type: Option<String>,
+    entity_id: Option<String>,
+    status: Option<i32>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+}
+
+// 按 log_id 从新到旧分页，next_cursor 作为下一页的 cursor，为空表示没有更多
+#[derive(Debug, Serialize)]
+pub struct OperationLogPage {
+    pub logs: Vec<OperationLog>,
+    pub next_cursor: Option<i32>,
+}
+
+#[derive(Responder)]
+pub struct OperationLogExport {
+    body: String,
+    content_type: ContentType,
+    disposition: Header<'static>,
+}
+
+// 解析日期边界，仅有日期时开始取当天 0 点、结束取当天最后一秒
+fn parse_bound(value: &str, end: bool) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S"))
+        .ok()
+        .or_else(|| {
+            let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+            if end { date.and_hms_opt(23, 59, 59) } else { date.and_hms_opt(0, 0, 0) }
+        })
+}
+
+fn invalid_date(field: &str) -> ApiError {
+    ApiError::bad_request("expected YYYY-MM-DD or YYYY-MM-DDTHH:MM:SS").with_field(field)
+}
+
+type DateRange = (Option<NaiveDateTime>, Option<NaiveDateTime>);
+
+// 解析过滤条件中的日期范围，无法解析时返回 400
+fn date_range(filter: &OperationLogFilter) -> Result<DateRange, ApiError> {
+    let start = match &filter.start_date {
+        Some(start) => Some(parse_bound(start, false).ok_or_else(|| invalid_date("start_date"))?),
+        None => None,
+    };
+    let end = match &filter.end_date {
+        Some(end) => Some(parse_bound(end, true).ok_or_else(|| invalid_date("end_date"))?),
+        None => None,
+    };
+    Ok((start, end))
+}
+
+// 按过滤条件构造查询
+fn filtered(filter: &OperationLogFilter, (start, end): DateRange) -> operation_logs::BoxedQuery<'static, Sqlite> {
+    let mut query = operation_logs::table.into_boxed();
+
+    if let Some(user_id) = filter.user_id {
+        query = query.filter(operation_logs::user_id.eq(user_id));
+    }
+    if let Some(action) = &filter.action {
+        query = query.filter(operation_logs::action.like(format!("%{}%", action)));
+    }
+    if let Some(method) = &filter.method {
+        query = query.filter(operation_logs::method.eq(method.to_uppercase()));
+    }
+    if let Some(entity_type) = &filter.entity_type {
+        query = query.filter(operation_logs::entity_type.eq(entity_type.clone()));
+    }
+    if let Some(entity_id) = &filter.entity_id {
+        query = query.filter(operation_logs::entity_id.eq(entity_id.clone()));
+    }
+    if let Some(status) = filter.status {
+        query = query.filter(operation_logs::status.eq(status));
+    }
+    if let Some(start) = start {
+        query = query.filter(operation_logs::timestamp.ge(start));
+    }
+    if let Some(end) = end {
+        query = query.filter(operation_logs::timestamp.le(end));
+    }
+    query
+}
+
+fn load_page(
+    c: &mut SqliteConnection,
+    query: operation_logs::BoxedQuery<'static, Sqlite>,
+    cursor: Option<i32>,
+    limit: i64,
+) -> QueryResult<OperationLogPage> {
+    let mut query = query.order(operation_logs::log_id.desc()).limit(limit);
+    if let Some(cursor) = cursor {
+        query = query.filter(operation_logs::log_id.lt(cursor));
+    }
+    let logs = query.load::<OperationLog>(c)?;
+    // 取满一页才可能还有下一页
+    let next_cursor = if logs.len() as i64 == limit {
+        logs.last().and_then(|log| log.log_id)
+    } else {
+        None
+    };
+    Ok(OperationLogPage { logs, next_cursor })
+}
+
+fn page_size(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn csv_row(log: &OperationLog) -> String {
+    let fields = [
+        log.log_id.map(|v| v.to_string()),
+        log.user_id.map(|v| v.to_string()),
+        log.timestamp.map(|v| v.format("%Y-%m-%d %H:%M:%S%.f").to_string()),
+        log.method.clone(),
+        log.path.clone(),
+        log.action.clone(),
+        log.entity_type.clone(),
+        log.entity_id.clone(),
+        log.status.map(|v| v.to_string()),
+        log.client_ip.clone(),
+        log.changes.clone(),
+        log.prev_hash.clone(),
+        log.hash.clone(),
+    ];
+    fields
+        .iter()
+        .map(|field| csv_field(field.as_deref().unwrap_or("")))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+const CSV_HEADER: &str = "log_id,user_id,timestamp,method,path,action,entity_type,entity_id,status,client_ip,changes,prev_hash,hash";
+
+#[get("/operation_logs?<cursor>&<limit>")]
+pub async fn list_operation_logs(conn: DbConn, _perm: RequirePermission<OperationLogRead>, cursor: Option<i32>, limit: Option<i64>) -> Result<Json<OperationLogPage>, ApiError> {
+    conn.run(move |c| load_page(c, operation_logs::table.into_boxed(), cursor, page_size(limit))).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
 
+// 某用户的全部操作日志，从新到旧
 #[get("/operation_logs/<user_id>")]
-pub async fn get_user_operation_logs(conn: DbConn, _token: TokenGuard, user_id: i32) -> Result<Json<Vec<OperationLog>>, Status> {
+pub async fn get_user_operation_logs(conn: DbConn, _perm: RequirePermission<OperationLogRead>, user_id: i32) -> Result<Json<Vec<OperationLog>>, ApiError> {
     conn.run(move |c| {
         operation_logs::table
             .filter(operation_logs::user_id.eq(user_id))
@@ -29,23 +181,107 @@ pub async fn get_user_operation_logs(conn: DbConn, _token: TokenGuard, user_id:
             .load(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
 
-#[post("/operation_logs", data = "<log>")]
-pub async fn create_operation_log(conn: DbConn, _token: TokenGuard, log: Json<NewOperationLog>) -> Result<Status, Status> {
-    // Insert with current timestamp
-    let log_with_timestamp = (
-        operation_logs::user_id.eq(log.user_id),
-        operation_logs::action.eq(&log.action),
-        operation_logs::timestamp.eq(Utc::now().naive_utc()),
-    );
-
+#[get("/operation_logs/entry/<log_id>")]
+pub async fn get_operation_log(conn: DbConn, _perm: RequirePermission<OperationLogRead>, log_id: i32) -> Result<Json<OperationLog>, ApiError> {
     conn.run(move |c| {
-        diesel::insert_into(operation_logs::table)
-            .values(log_with_timestamp)
-            .execute(c)
+        operation_logs::table
+            .filter(operation_logs::log_id.eq(log_id))
+            .select(OperationLog::as_select())
+            .first(c)
     }).await
-    .map(|_| Status::Created)
-    .map_err(|_| Status::InternalServerError)
+    .map(Json)
+    .map_err(ApiError::from)
+}
+
+// 按用户、操作、实体和日期范围搜索，游标分页
+#[get("/operation_logs/search?<cursor>&<limit>&<filter..>")]
+pub async fn search_operation_logs(
+    conn: DbConn,
+    _perm: RequirePermission<OperationLogRead>,
+    filter: OperationLogFilter,
+    cursor: Option<i32>,
+    limit: Option<i64>
+) -> Result<Json<OperationLogPage>, ApiError> {
+    let query = filtered(&filter, date_range(&filter)?);
+    conn.run(move |c| load_page(c, query, cursor, page_size(limit))).await
+    .map(Json)
+    .map_err(ApiError::from)
+}
+
+// 按搜索条件导出全部结果，format 为 csv 或 jsonl，按 log_id 从旧到新。
+// 结果超过 MAX_EXPORT_ROWS 条时返回 400
+#[get("/operation_logs/export?<format>&<filter..>")]
+pub async fn export_operation_logs(
+    conn: DbConn,
+    _perm: RequirePermission<OperationLogRead>,
+    filter: OperationLogFilter,
+    format: String
+) -> Result<OperationLogExport, ApiError> {
+    let (content_type, extension) = match format.as_str() {
+        "csv" => (ContentType::CSV, "csv"),
+        "jsonl" => (ContentType::new("application", "x-ndjson"), "jsonl"),
+        _ => return Err(ApiError::bad_request("format must be csv or jsonl").with_field("format")),
+    };
+    let range = date_range(&filter)?;
+
+    let body = conn.run(move |c| {
+        let total: i64 = filtered(&filter, range).count().get_result(c)?;
+        if total > MAX_EXPORT_ROWS {
+            return Err(ApiError::bad_request(format!(
+                "export is limited to {} rows, narrow the filter", MAX_EXPORT_ROWS
+            ))
+            .with_details(serde_json::json!({ "total": total, "max_rows": MAX_EXPORT_ROWS })));
+        }
+
+        let mut body = String::new();
+        if extension == "csv" {
+            body.push_str(CSV_HEADER);
+            body.push('\n');
+        }
+        let mut cursor = None;
+        loop {
+            let mut query = filtered(&filter, range)
+                .order(operation_logs::log_id.asc())
+                .limit(EXPORT_BATCH);
+            if let Some(cursor) = cursor {
+                query = query.filter(operation_logs::log_id.gt(cursor));
+            }
+            let batch = query.load::<OperationLog>(c)?;
+            if batch.is_empty() {
+                break;
+            }
+            for log in &batch {
+                if extension == "csv" {
+                    body.push_str(&csv_row(log));
+                } else {
+                    let line = serde_json::to_string(log)
+                        .map_err(|_| ApiError::internal("failed to serialize operation log"))?;
+                    body.push_str(&line);
+                }
+                body.push('\n');
+            }
+            cursor = batch.last().and_then(|log| log.log_id);
+        }
+        Ok::<_, ApiError>(body)
+    }).await?;
+
+    Ok(OperationLogExport {
+        body,
+        content_type,
+        disposition: Header::new(
+            "Content-Disposition",
+            format!("attachment; filename=\"operation_logs.{}\"", extension),
+        ),
+    })
+}
+
+// 重算操作日志哈希链并校验签名，报告第一处断链
+#[get("/operation_logs/verify")]
+pub async fn verify_operation_logs(conn: DbConn, _perm: RequirePermission<OperationLogRead>) -> Result<Json<ChainReport>, ApiError> {
+    conn.run(audit_chain::verify).await
+    .map(Json)
+    .map_err(ApiError::from)
 }
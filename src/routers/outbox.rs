@@ -0,0 +1,115 @@
+use diesel::prelude::*;
+use rocket::serde::json::Json;
+use rocket::{get, post};
+use serde::Serialize;
+
+use crate::error::ApiError;
+use crate::models::{OutboxAck, OutboxMessage, DbConn};
+use crate::schema::{outbox, outbox_acks};
+use crate::outbox::{self as outbox_queue, OutboxStats, FAILED, PENDING};
+use crate::auth_guard::{RequirePermission, NetworkRead, NetworkWrite};
+
+// 消息及各节点的确认
+#[derive(Debug, Serialize)]
+pub struct OutboxMessageDetail {
+    #[serde(flatten)]
+    pub message: OutboxMessage,
+    pub acks: Vec<OutboxAck>,
+}
+
+// 待送达/已送达/失败数量，pending 持续增长说明本节点与其他节点不同步
+#[get("/outbox/stats")]
+pub async fn get_outbox_stats(conn: DbConn, _perm: RequirePermission<NetworkRead>) -> Result<Json<OutboxStats>, ApiError> {
+    conn.run(outbox_queue::stats).await
+        .map(Json)
+        .map_err(ApiError::from)
+}
+
+#[get("/outbox?<status>&<message_type>")]
+pub async fn list_outbox(
+    conn: DbConn,
+    _perm: RequirePermission<NetworkRead>,
+    status: Option<String>,
+    message_type: Option<String>
+) -> Result<Json<Vec<OutboxMessage>>, ApiError> {
+    conn.run(move |c| {
+        let mut query_builder = outbox::table
+            .into_boxed();
+
+        if let Some(s) = status {
+            query_builder = query_builder.filter(
+                outbox::status.eq(s)
+            );
+        }
+
+        if let Some(t) = message_type {
+            query_builder = query_builder.filter(
+                outbox::message_type.eq(t)
+            );
+        }
+
+        query_builder
+            .order(outbox::created_at.desc())
+            .select(OutboxMessage::as_select())
+            .load(c)
+    }).await
+    .map(Json)
+    .map_err(ApiError::from)
+}
+
+#[get("/outbox/<message_id>")]
+pub async fn get_outbox_message(
+    conn: DbConn,
+    _perm: RequirePermission<NetworkRead>,
+    message_id: String
+) -> Result<Json<OutboxMessageDetail>, ApiError> {
+    conn.run(move |c| {
+        let message = outbox::table
+            .filter(outbox::message_id.eq(&message_id))
+            .select(OutboxMessage::as_select())
+            .first(c)?;
+        let acks = outbox_acks::table
+            .filter(outbox_acks::message_id.eq(&message_id))
+            .order(outbox_acks::acked_at.asc())
+            .select(OutboxAck::as_select())
+            .load(c)?;
+        Ok::<_, diesel::result::Error>(OutboxMessageDetail { message, acks })
+    }).await
+    .map(Json)
+    .map_err(ApiError::from)
+}
+
+// 将失败的消息重新放回待发送队列
+#[post("/outbox/<message_id>/retry")]
+pub async fn retry_outbox_message(
+    conn: DbConn,
+    _perm: RequirePermission<NetworkWrite>,
+    message_id: String
+) -> Result<Json<OutboxMessage>, ApiError> {
+    conn.run(move |c| {
+        let updated = diesel::update(
+            outbox::table
+                .filter(outbox::message_id.eq(&message_id))
+                .filter(outbox::status.eq(FAILED))
+        )
+        .set((
+            outbox::status.eq(PENDING),
+            outbox::attempts.eq(0),
+            outbox::last_attempt_at.eq(None::<chrono::NaiveDateTime>),
+        ))
+        .execute(c)?;
+
+        if updated == 0 {
+            return Ok(None);
+        }
+
+        outbox::table
+            .filter(outbox::message_id.eq(&message_id))
+            .select(OutboxMessage::as_select())
+            .first(c)
+            .map(Some)
+    }).await
+    .map_err(ApiError::from)?
+    .map(Json)
+    .ok_or_else(|| ApiError::conflict("not_failed", "only failed messages can be retried"))
+}
@@ -0,0 +1,426 @@
+use base64::engine::general_purpose;
+use base64::Engine;
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::{Column, Expression};
+use diesel::sql_types::{Date, Double, Integer, Nullable, Text, Timestamp};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use serde::de::{self, DeserializeOwned, Deserializer, Visitor};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::error::ApiError;
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 500;
+
+// 列表接口共用的查询参数：
+// limit 每页条数；cursor 为上一页返回的 next_cursor，也可直接用 offset；
+// sort=列名[:asc|desc]，可选列由各接口的白名单决定；fields=逗号分隔的返回字段
+#[derive(Debug)]
+pub struct ListQuery {
+    pub limit: i64,
+    pub offset: i64,
+    cursor: Option<Cursor>,
+    sort: Option<(String, bool)>,
+    fields: Option<Vec<String>>,
+    path: String,
+    // 除翻页参数外的原始查询参数，用于生成下一页链接
+    params: Vec<String>,
+}
+
+// 列表接口的响应
+#[derive(Debug, Serialize)]
+pub struct Page {
+    pub items: Vec<Value>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+    pub next_cursor: Option<String>,
+    pub next: Option<String>,
+}
+
+// 键集游标：上一页最后一行的排序列值和主键，下一页从其后开始，
+// 翻页期间插入或删除的行不会导致重复或遗漏
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Cursor {
+    pub sort: String,
+    pub descending: bool,
+    pub value: Value,
+    pub key: Value,
+}
+
+fn encode_cursor(cursor: &Cursor) -> String {
+    general_purpose::URL_SAFE_NO_PAD.encode(serde_json::to_vec(cursor).unwrap_or_default())
+}
+
+fn decode_cursor(cursor: &str) -> Option<Cursor> {
+    let bytes = general_purpose::URL_SAFE_NO_PAD.decode(cursor).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+// 排序所用的列和主键，由 sort_by! 给出，用于生成下一页的游标
+#[derive(Debug)]
+pub struct Keyset {
+    pub sort: &'static str,
+    pub descending: bool,
+    pub key: &'static str,
+}
+
+// 可作为排序列的 SQL 类型及游标中对应的值类型
+pub trait SortValue {
+    type Value: DeserializeOwned + Clone;
+}
+
+impl SortValue for Integer {
+    type Value = i32;
+}
+
+impl SortValue for Text {
+    type Value = String;
+}
+
+impl SortValue for Double {
+    type Value = f64;
+}
+
+impl SortValue for Timestamp {
+    type Value = NaiveDateTime;
+}
+
+impl SortValue for Date {
+    type Value = NaiveDate;
+}
+
+impl<T: SortValue + diesel::sql_types::SqlType> SortValue for Nullable<T> {
+    type Value = T::Value;
+}
+
+// 按列的类型解析游标中的值，类型不符时返回 400
+pub fn cursor_value<C>(_column: &C, value: Value) -> Result<<C::SqlType as SortValue>::Value, ApiError>
+where
+    C: Expression,
+    C::SqlType: SortValue,
+{
+    serde_json::from_value(value).map_err(|_| ApiError::bad_request("invalid cursor").with_field("cursor"))
+}
+
+pub fn column_name<C: Column>(_column: &C) -> &'static str {
+    C::NAME
+}
+
+// 取结构体反序列化时声明的字段名，用于校验 fields 参数
+fn field_names<T: DeserializeOwned>() -> &'static [&'static str] {
+    struct Fields<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> Deserializer<'de> for Fields<'_> {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(de::Error::custom("fields collected"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(Fields(&mut fields));
+    fields
+}
+
+impl ListQuery {
+    // 参数格式错误时返回 None
+    fn parse(request: &Request<'_>) -> Option<Self> {
+        let limit = match request.query_value::<i64>("limit") {
+            Some(limit) => limit.ok()?.clamp(1, MAX_LIMIT),
+            None => DEFAULT_LIMIT,
+        };
+        let (cursor, offset) = match (request.query_value::<&str>("cursor"), request.query_value::<i64>("offset")) {
+            (Some(cursor), _) => (Some(decode_cursor(cursor.ok()?)?), 0),
+            (None, Some(offset)) => (None, Some(offset.ok()?).filter(|offset| *offset >= 0)?),
+            (None, None) => (None, 0),
+        };
+        let sort = match request.query_value::<&str>("sort") {
+            Some(sort) => {
+                let sort = sort.ok()?;
+                let (column, descending) = match sort.split_once(':') {
+                    Some((column, "asc")) => (column, false),
+                    Some((column, "desc")) => (column, true),
+                    Some(_) => return None,
+                    None => (sort, false),
+                };
+                Some((column.to_string(), descending))
+            }
+            None => None,
+        };
+        let fields = match request.query_value::<&str>("fields") {
+            Some(fields) => Some(
+                fields.ok()?
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|field| !field.is_empty())
+                    .map(String::from)
+                    .collect(),
+            ),
+            None => None,
+        };
+        let params = request
+            .uri()
+            .query()
+            .map(|query| {
+                query
+                    .as_str()
+                    .split('&')
+                    .filter(|param| !param.starts_with("cursor=") && !param.starts_with("offset="))
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Some(ListQuery {
+            limit,
+            offset,
+            cursor,
+            sort,
+            fields,
+            path: request.uri().path().to_string(),
+            params,
+        })
+    }
+
+    // 请求的排序，未指定时用接口的默认排序 (列名, 是否降序)
+    pub fn sort_or<'a>(&'a self, default: (&'a str, bool)) -> (&'a str, bool) {
+        self.sort
+            .as_ref()
+            .map(|(column, descending)| (column.as_str(), *descending))
+            .unwrap_or(default)
+    }
+
+    // 请求携带的游标，排序与本次请求不一致时返回 400
+    pub fn cursor_for(&self, sort: &str, descending: bool) -> Result<Option<&Cursor>, ApiError> {
+        match &self.cursor {
+            Some(cursor) if cursor.sort != sort || cursor.descending != descending => {
+                Err(ApiError::bad_request("cursor does not match the requested sort").with_field("cursor"))
+            }
+            cursor => Ok(cursor.as_ref()),
+        }
+    }
+
+    // 组装响应：按 fields 裁剪字段，取满一页时给出 next_cursor 和链接。
+    // fields 中有 T 不存在的字段时返回 400
+    pub fn page<T: Serialize + DeserializeOwned>(&self, keyset: &Keyset, items: Vec<T>, total: i64) -> Result<Page, ApiError> {
+        if let Some(fields) = &self.fields {
+            let known = field_names::<T>();
+            let unknown = fields
+                .iter()
+                .filter(|field| !known.contains(&field.as_str()))
+                .collect::<Vec<_>>();
+            if !unknown.is_empty() {
+                return Err(ApiError::bad_request("unknown fields")
+                    .with_field("fields")
+                    .with_details(json!({ "unknown": unknown, "allowed": known })));
+            }
+        }
+
+        let full = items.len() as i64 == self.limit;
+        let mut items = items
+            .into_iter()
+            .map(serde_json::to_value)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| ApiError::internal("failed to serialize record"))?;
+
+        let next_cursor = items.last().filter(|_| full).map(|last| {
+            encode_cursor(&Cursor {
+                sort: keyset.sort.to_string(),
+                descending: keyset.descending,
+                value: last.get(keyset.sort).cloned().unwrap_or(Value::Null),
+                key: last.get(keyset.key).cloned().unwrap_or(Value::Null),
+            })
+        });
+        if let Some(fields) = &self.fields {
+            for item in items.iter_mut() {
+                if let Some(object) = item.as_object_mut() {
+                    object.retain(|field, _| fields.contains(field));
+                }
+            }
+        }
+
+        let next = next_cursor.as_ref().map(|cursor| {
+            let mut params = self.params.clone();
+            params.push(format!("cursor={}", cursor));
+            format!("{}?{}", self.path, params.join("&"))
+        });
+
+        Ok(Page {
+            items,
+            total,
+            limit: self.limit,
+            offset: self.offset,
+            next_cursor,
+            next,
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ListQuery {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+        match ListQuery::parse(request) {
+            Some(query) => Outcome::Success(query),
+            None => Outcome::Error((Status::BadRequest, ())),
+        }
+    }
+}
+
+// 按 sort 参数排序，列不在白名单中时返回 400。相同值再按主键排序，保证翻页稳定；
+// 有游标时只取游标之后的行。SQLite 升序时 NULL 在前、降序时在后，条件与之一致。
+// 结果为 (查询, Keyset)，Keyset 交给 ListQuery::page 生成下一页游标：
+// sort_by!(query, list, ("created_at", true), materials::material_id, ["material_name" => materials::material_name, ...])
+macro_rules! sort_by {
+    ($query:expr, $list:expr, $default:expr, $key:expr, [$($name:literal => $column:expr),+ $(,)?]) => {
+        match $list.sort_or($default) {
+            $(
+                ($name, descending) => (|| {
+                    use crate::routers::pagination::{column_name, cursor_value, Keyset};
+                    let keyset = Keyset { sort: $name, descending, key: column_name(&$key) };
+                    let mut query = if descending {
+                        $query.order($column.desc()).then_order_by($key.desc())
+                    } else {
+                        $query.order($column.asc()).then_order_by($key.asc())
+                    };
+                    if let Some(cursor) = $list.cursor_for($name, descending)? {
+                        let key = cursor_value(&$key, cursor.key.clone())?;
+                        query = match (cursor.value.clone(), descending) {
+                            (serde_json::Value::Null, false) => query.filter(
+                                $column.is_not_null().or($column.is_null().and($key.gt(key))),
+                            ),
+                            (serde_json::Value::Null, true) => query.filter($column.is_null().and($key.lt(key))),
+                            (value, false) => {
+                                let value = cursor_value(&$column, value)?;
+                                query.filter($column.gt(value.clone()).or($column.eq(value).and($key.gt(key))))
+                            }
+                            (value, true) => {
+                                let value = cursor_value(&$column, value)?;
+                                query.filter(
+                                    $column.lt(value.clone())
+                                        .or($column.is_null())
+                                        .or($column.eq(value).and($key.lt(key))),
+                                )
+                            }
+                        };
+                    }
+                    Ok((query, keyset))
+                })(),
+            )+
+            _ => Err(crate::error::ApiError::bad_request("unsupported sort column").with_field("sort")),
+        }
+    };
+}
+
+pub(crate) use sort_by;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::prelude::*;
+    use crate::models::Material;
+    use crate::schema::materials;
+
+    fn list_query(sort: &str, descending: bool, cursor: Option<String>) -> ListQuery {
+        ListQuery {
+            limit: 2,
+            offset: 0,
+            cursor: cursor.map(|cursor| decode_cursor(&cursor).unwrap()),
+            sort: Some((sort.to_string(), descending)),
+            fields: None,
+            path: "/materials".to_string(),
+            params: Vec::new(),
+        }
+    }
+
+    // 按 category 翻完所有页，返回依次得到的 material_id
+    fn walk(c: &mut SqliteConnection, descending: bool) -> Vec<i32> {
+        let mut seen = Vec::new();
+        let mut cursor = None;
+        loop {
+            let list = list_query("category", descending, cursor);
+            let (query, keyset) = sort_by!(materials::table.into_boxed(), list, ("category", false), materials::material_id, [
+                "category" => materials::category,
+            ])
+            .unwrap();
+            let items = query.select(Material::as_select()).limit(list.limit).load::<Material>(c).unwrap();
+            let page = list.page(&keyset, items, 0).unwrap();
+            seen.extend(page.items.iter().map(|item| item["material_id"].as_i64().unwrap() as i32));
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => return seen,
+            }
+        }
+    }
+
+    #[test]
+    fn cursor_walks_every_row_once_in_sort_order() {
+        let mut c = crate::db::memory_connection();
+        for (name, category) in [("a", Some("x")), ("b", None), ("c", Some("x")), ("d", Some("w")), ("e", None)] {
+            diesel::insert_into(materials::table)
+                .values((materials::material_name.eq(name), materials::category.eq(category)))
+                .execute(&mut c)
+                .unwrap();
+        }
+        let ids = |c: &mut SqliteConnection, names: &[&str]| -> Vec<i32> {
+            names
+                .iter()
+                .map(|name| {
+                    materials::table
+                        .filter(materials::material_name.eq(*name))
+                        .select(materials::material_id)
+                        .first::<Option<i32>>(c)
+                        .unwrap()
+                        .unwrap()
+                })
+                .collect()
+        };
+        // 升序 NULL 在前，降序 NULL 在后，相同值按主键
+        let ascending = ids(&mut c, &["b", "e", "d", "a", "c"]);
+        let descending = ids(&mut c, &["c", "a", "d", "e", "b"]);
+        assert_eq!(walk(&mut c, false), ascending);
+        assert_eq!(walk(&mut c, true), descending);
+    }
+
+    #[test]
+    fn cursor_must_match_the_requested_sort() {
+        let list = list_query("category", false, Some(encode_cursor(&Cursor {
+            sort: "material_name".to_string(),
+            descending: false,
+            value: Value::from("a"),
+            key: Value::from(1),
+        })));
+        assert!(list.cursor_for("category", false).is_err());
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let mut list = list_query("category", false, None);
+        let keyset = Keyset { sort: "category", descending: false, key: "material_id" };
+        list.fields = Some(vec!["material_name".to_string()]);
+        assert!(list.page::<Material>(&keyset, Vec::new(), 0).is_ok());
+        list.fields = Some(vec!["material_name".to_string(), "password".to_string()]);
+        assert!(list.page::<Material>(&keyset, Vec::new(), 0).is_err());
+    }
+}
@@ -3,53 +3,71 @@ use rocket::serde::json::Json;
 use rocket::http::Status;
 use rocket::{get, post, put, delete}; 
 
+use crate::error::ApiError;
 use crate::models::{Permission, NewPermission, DbConn};
 use crate::schema::permissions;
-use crate::token::TokenGuard;
+use crate::auth_guard::{RequirePermission, PermissionRead, PermissionWrite};
+use crate::routers::pagination::{sort_by, ListQuery, Page};
 
 #[get("/permissions")]
-pub async fn list_permissions(conn: DbConn, _token: TokenGuard) -> Result<Json<Vec<Permission>>, Status> {
-    conn.run(|c| {
-        permissions::table
+pub async fn list_permissions(
+    conn: DbConn,
+    _perm: RequirePermission<PermissionRead>,
+    list: ListQuery
+) -> Result<Json<Page>, ApiError> {
+    conn.run(move |c| {
+        let filtered = || permissions::table.into_boxed();
+        let total = filtered()
+            .count()
+            .get_result::<i64>(c)
+            .map_err(ApiError::from)?;
+        let (query, keyset) = sort_by!(filtered(), list, ("permission_id", false), permissions::permission_id, [
+            "permission_id" => permissions::permission_id,
+            "permission_name" => permissions::permission_name,
+        ])?;
+        query
             .select(Permission::as_select())
+            .offset(list.offset)
+            .limit(list.limit)
             .load(c)
+            .map_err(ApiError::from)
+            .and_then(|items| list.page(&keyset, items, total))
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
 }
 
 #[get("/permissions/<id>")]
-pub async fn get_permission(conn: DbConn, _token: TokenGuard, id: i32) -> Result<Json<Permission>, Status> {
+pub async fn get_permission(conn: DbConn, _perm: RequirePermission<PermissionRead>, id: i32) -> Result<Json<Permission>, ApiError> {
     conn.run(move |c| {
         permissions::table
-            .find(id)
+            .filter(permissions::permission_id.eq(id))
             .select(Permission::as_select())
             .first(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::NotFound)
+    .map_err(ApiError::from)
 }
 
 #[post("/permissions", data = "<permission>")]
-pub async fn create_permission(conn: DbConn, _token: TokenGuard, permission: Json<NewPermission>) -> Result<Status, Status> {
+pub async fn create_permission(conn: DbConn, _perm: RequirePermission<PermissionWrite>, permission: Json<NewPermission>) -> Result<Status, ApiError> {
     conn.run(|c| {
         diesel::insert_into(permissions::table)
             .values(permission.into_inner())
             .execute(c)
     }).await
     .map(|_| Status::Created)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
 
 #[put("/permissions/<id>", data = "<permission>")]
 pub async fn update_permission(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<PermissionWrite>,
     id: i32,
     permission: Json<NewPermission>
-) -> Result<Status, Status> {
+) -> Result<Status, ApiError> {
     conn.run(move |c| {
-        diesel::update(permissions::table.find(id))
+        diesel::update(permissions::table.filter(permissions::permission_id.eq(id)))
             .set((
                 permissions::permission_name.eq(&permission.permission_name),
                 permissions::description.eq(&permission.description),
@@ -57,15 +75,15 @@ pub async fn update_permission(
             .execute(c)
     }).await
     .map(|_| Status::Ok)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
 
 #[delete("/permissions/<id>")]
-pub async fn delete_permission(conn: DbConn, _token: TokenGuard, id: i32) -> Result<Status, Status> {
+pub async fn delete_permission(conn: DbConn, _perm: RequirePermission<PermissionWrite>, id: i32) -> Result<Status, ApiError> {
     conn.run(move |c| {
-        diesel::delete(permissions::table.find(id))
+        diesel::delete(permissions::table.filter(permissions::permission_id.eq(id)))
             .execute(c)
     }).await
     .map(|_| Status::NoContent)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
@@ -1,53 +1,84 @@
 use diesel::prelude::*;
 use rocket::serde::json::Json;
 use rocket::http::Status;
-use rocket::{get, post, put, delete};
-use chrono::Utc;
+use rocket::{get, post, put, delete, State};
+use chrono::{NaiveDateTime, Utc};
 
+use crate::error::ApiError;
 use crate::models::{PriceFormula, NewPriceFormula, DbConn};
 use crate::schema::price_formulas;
-use crate::token::TokenGuard;
+use crate::auth_guard::{RequirePermission, PriceFormulaRead, PriceFormulaWrite};
+use crate::routers::pagination::{sort_by, ListQuery, Page};
+use crate::replication::{self, ReplicatedEntity, ReplicatedRecord, ReplicationSender, RowState};
 
-#[get("/price_formulas")]
-pub async fn list_price_formulas(conn: DbConn, _token: TokenGuard) -> Result<Json<Vec<PriceFormula>>, Status> {
-    conn.run(|c| {
-        price_formulas::table
+// 默认不含已删除的公式，include_deleted=true 时一并返回
+#[get("/price_formulas?<include_deleted>")]
+pub async fn list_price_formulas(
+    conn: DbConn,
+    _perm: RequirePermission<PriceFormulaRead>,
+    list: ListQuery,
+    include_deleted: Option<bool>
+) -> Result<Json<Page>, ApiError> {
+    conn.run(move |c| {
+        let filtered = || {
+            let mut query = price_formulas::table.into_boxed();
+            if !include_deleted.unwrap_or(false) {
+                query = query.filter(price_formulas::deleted_at.is_null());
+            }
+            query
+        };
+        let total = filtered()
+            .count()
+            .get_result::<i64>(c)
+            .map_err(ApiError::from)?;
+        let (query, keyset) = sort_by!(filtered(), list, ("created_at", true), price_formulas::formula_id, [
+            "formula_id" => price_formulas::formula_id,
+            "formula_name" => price_formulas::formula_name,
+            "base_material_cost" => price_formulas::base_material_cost,
+            "labor_cost" => price_formulas::labor_cost,
+            "profit" => price_formulas::profit,
+            "created_at" => price_formulas::created_at,
+        ])?;
+        query
             .select(PriceFormula::as_select())
-            .order(price_formulas::created_at.desc())
+            .offset(list.offset)
+            .limit(list.limit)
             .load(c)
+            .map_err(ApiError::from)
+            .and_then(|items| list.page(&keyset, items, total))
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
 }
 
 #[get("/price_formulas/<formula_id>")]
-pub async fn get_price_formula(conn: DbConn, _token: TokenGuard, formula_id: i32) -> Result<Json<PriceFormula>, Status> {
+pub async fn get_price_formula(conn: DbConn, _perm: RequirePermission<PriceFormulaRead>, formula_id: i32) -> Result<Json<PriceFormula>, ApiError> {
     conn.run(move |c| {
         price_formulas::table
-            .find(formula_id)
+            .filter(price_formulas::formula_id.eq(formula_id))
             .select(PriceFormula::as_select())
             .first(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::NotFound)
+    .map_err(ApiError::from)
 }
 
 #[get("/price_formulas/by_name/<formula_name>")]
-pub async fn get_formula_by_name(conn: DbConn, _token: TokenGuard, formula_name: String) -> Result<Json<PriceFormula>, Status> {
+pub async fn get_formula_by_name(conn: DbConn, _perm: RequirePermission<PriceFormulaRead>, formula_name: String) -> Result<Json<PriceFormula>, ApiError> {
     conn.run(move |c| {
         price_formulas::table
             .filter(price_formulas::formula_name.eq(formula_name))
+            .filter(price_formulas::deleted_at.is_null())
             .select(PriceFormula::as_select())
             .first(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::NotFound)
+    .map_err(ApiError::from)
 }
 
 #[post("/price_formulas", data = "<formula>")]
-pub async fn create_price_formula(conn: DbConn, _token: TokenGuard, formula: Json<NewPriceFormula>) -> Result<Json<PriceFormula>, Status> {
+pub async fn create_price_formula(conn: DbConn, _perm: RequirePermission<PriceFormulaWrite>, replication: &State<ReplicationSender>, formula: Json<NewPriceFormula>) -> Result<Json<PriceFormula>, ApiError> {
     // 检查公式名称是否已存在
-    if let Some(ref name) = formula.formula_name {
+    if let Some(name) = formula.formula_name.clone() {
         let exists = conn.run(move |c| {
             price_formulas::table
                 .filter(price_formulas::formula_name.eq(name))
@@ -57,13 +88,15 @@ pub async fn create_price_formula(conn: DbConn, _token: TokenGuard, formula: Jso
 
         if let Ok(count) = exists {
             if count > 0 {
-                return Err(Status::Conflict);
+                return Err(ApiError::conflict("duplicate_name", "formula name already exists").with_field("formula_name"));
             }
         }
     }
 
+    let global_id = replication::new_global_id();
+    let formula = formula.into_inner();
     let formula_with_timestamp = (
-        price_formulas::formula_name.eq(&formula.formula_name),
+        price_formulas::formula_name.eq(formula.formula_name),
         price_formulas::base_material_cost.eq(formula.base_material_cost),
         price_formulas::additional_material_cost.eq(formula.additional_material_cost),
         price_formulas::galvanization_cost.eq(formula.galvanization_cost),
@@ -75,26 +108,35 @@ pub async fn create_price_formula(conn: DbConn, _token: TokenGuard, formula: Jso
         price_formulas::profit.eq(formula.profit),
         price_formulas::created_by.eq(formula.created_by),
         price_formulas::created_at.eq(Utc::now().naive_utc()),
+        price_formulas::global_id.eq(global_id.clone()),
     );
 
-    conn.run(move |c| {
+    let created: PriceFormula = conn.run(move |c| {
+        let hlc = replication::next_hlc(c)?;
         diesel::insert_into(price_formulas::table)
-            .values(formula_with_timestamp)
-            .get_result(c)
+            .values((formula_with_timestamp, price_formulas::hlc.eq(hlc)))
+            .execute(c)?;
+        price_formulas::table
+            .filter(price_formulas::global_id.eq(global_id))
+            .select(PriceFormula::as_select())
+            .first(c)
     }).await
-    .map(Json)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)?;
+
+    replication.upsert(ReplicatedRecord::PriceFormula(created.clone()), None).await;
+    Ok(Json(created))
 }
 
 #[put("/price_formulas/<formula_id>", data = "<formula>")]
 pub async fn update_price_formula(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<PriceFormulaWrite>,
+    replication: &State<ReplicationSender>,
     formula_id: i32,
     formula: Json<NewPriceFormula>
-) -> Result<Json<PriceFormula>, Status> {
+) -> Result<Json<PriceFormula>, ApiError> {
     // 如果更新了公式名称，检查新名称是否与其他公式冲突
-    if let Some(ref name) = formula.formula_name {
+    if let Some(name) = formula.formula_name.clone() {
         let exists = conn.run(move |c| {
             price_formulas::table
                 .filter(price_formulas::formula_name.eq(name))
@@ -105,13 +147,15 @@ pub async fn update_price_formula(
 
         if let Ok(count) = exists {
             if count > 0 {
-                return Err(Status::Conflict);
+                return Err(ApiError::conflict("duplicate_name", "formula name already exists").with_field("formula_name"));
             }
         }
     }
 
-    conn.run(move |c| {
-        diesel::update(price_formulas::table.find(formula_id))
+    let (updated, previous_hlc) = conn.run(move |c| c.transaction(|c| {
+        // 修改前的时钟随消息发出，接收端据此判断是否并发修改
+        let version = replication::stamp_local_write(c, ReplicatedEntity::PriceFormula, formula_id, RowState::Active)?;
+        diesel::update(price_formulas::table.filter(price_formulas::formula_id.eq(formula_id)))
             .set((
                 price_formulas::formula_name.eq(&formula.formula_name),
                 price_formulas::base_material_cost.eq(formula.base_material_cost),
@@ -125,39 +169,81 @@ pub async fn update_price_formula(
                 price_formulas::profit.eq(formula.profit),
                 price_formulas::created_by.eq(formula.created_by),
             ))
-            .get_result(c)
-    }).await
-    .map(Json)
-    .map_err(|_| Status::NotFound)
+            .execute(c)?;
+        let updated: PriceFormula = price_formulas::table
+            .filter(price_formulas::formula_id.eq(formula_id))
+            .select(PriceFormula::as_select())
+            .first(c)?;
+        Ok::<_, diesel::result::Error>((updated, version.previous_hlc))
+    })).await
+    .map_err(ApiError::from)?;
+
+    replication.upsert(ReplicatedRecord::PriceFormula(updated.clone()), previous_hlc).await;
+    Ok(Json(updated))
 }
 
+// 软删除，超过保留期后由定时任务物理删除
 #[delete("/price_formulas/<formula_id>")]
-pub async fn delete_price_formula(conn: DbConn, _token: TokenGuard, formula_id: i32) -> Result<Status, Status> {
-    conn.run(move |c| {
-        diesel::delete(price_formulas::table.find(formula_id))
-            .execute(c)
+pub async fn delete_price_formula(conn: DbConn, perm: RequirePermission<PriceFormulaWrite>, replication: &State<ReplicationSender>, formula_id: i32) -> Result<Status, ApiError> {
+    let user_id = perm.user_id;
+    // 删除前取出 global_id 和时钟，用于通知其他节点
+    let (global_id, hlc, previous_hlc) = conn.run(move |c| {
+        c.transaction(|c| {
+            let version = replication::stamp_local_write(c, ReplicatedEntity::PriceFormula, formula_id, RowState::Active)?;
+            diesel::update(price_formulas::table.filter(price_formulas::formula_id.eq(formula_id)))
+                .set((
+                    price_formulas::deleted_at.eq(Utc::now().naive_utc()),
+                    price_formulas::deleted_by.eq(user_id),
+                ))
+                .execute(c)?;
+            Ok::<_, diesel::result::Error>((version.global_id, version.hlc, version.previous_hlc))
+        })
     }).await
-    .map(|affected| {
-        if affected > 0 {
-            Status::NoContent
-        } else {
-            Status::NotFound
-        }
-    })
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)?;
+
+    if let Some(global_id) = global_id {
+        replication.delete(ReplicatedEntity::PriceFormula, global_id, hlc, previous_hlc).await;
+    }
+    Ok(Status::NoContent)
+}
+
+// 恢复软删除的公式，作为一次修改复制到其他节点
+#[post("/price_formulas/<formula_id>/restore")]
+pub async fn restore_price_formula(conn: DbConn, _perm: RequirePermission<PriceFormulaWrite>, replication: &State<ReplicationSender>, formula_id: i32) -> Result<Json<PriceFormula>, ApiError> {
+    let (restored, previous_hlc) = conn.run(move |c| {
+        c.transaction(|c| {
+            let version = replication::stamp_local_write(c, ReplicatedEntity::PriceFormula, formula_id, RowState::Deleted)?;
+            diesel::update(price_formulas::table.filter(price_formulas::formula_id.eq(formula_id)))
+                .set((
+                    price_formulas::deleted_at.eq(None::<NaiveDateTime>),
+                    price_formulas::deleted_by.eq(None::<i32>),
+                ))
+                .execute(c)?;
+            let restored: PriceFormula = price_formulas::table
+                .filter(price_formulas::formula_id.eq(formula_id))
+                .select(PriceFormula::as_select())
+                .first(c)?;
+            Ok::<_, diesel::result::Error>((restored, version.previous_hlc))
+        })
+    }).await
+    .map_err(ApiError::from)?;
+
+    replication.upsert(ReplicatedRecord::PriceFormula(restored.clone()), previous_hlc).await;
+    Ok(Json(restored))
 }
 
 // 获取最新的价格公式
 #[get("/price_formulas/latest")]
-pub async fn get_latest_formula(conn: DbConn, _token: TokenGuard) -> Result<Json<PriceFormula>, Status> {
+pub async fn get_latest_formula(conn: DbConn, _perm: RequirePermission<PriceFormulaRead>) -> Result<Json<PriceFormula>, ApiError> {
     conn.run(|c| {
         price_formulas::table
+            .filter(price_formulas::deleted_at.is_null())
             .order(price_formulas::created_at.desc())
             .select(PriceFormula::as_select())
             .first(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::NotFound)
+    .map_err(ApiError::from)
 }
 
 // 计算总价格
@@ -184,17 +270,17 @@ pub struct PriceBreakdown {
 #[get("/price_formulas/<formula_id>/calculate/<base_price>")]
 pub async fn calculate_price(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<PriceFormulaRead>,
     formula_id: i32,
     base_price: f64
-) -> Result<Json<PriceCalculation>, Status> {
+) -> Result<Json<PriceCalculation>, ApiError> {
     let formula = conn.run(move |c| {
         price_formulas::table
-            .find(formula_id)
+            .filter(price_formulas::formula_id.eq(formula_id))
             .select(PriceFormula::as_select())
             .first::<PriceFormula>(c)
     }).await
-    .map_err(|_| Status::NotFound)?;
+    .map_err(ApiError::from)?;
 
     let breakdown = PriceBreakdown {
         base_material: base_price * formula.base_material_cost.unwrap_or(0.0),
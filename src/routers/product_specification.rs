@@ -1,141 +1,188 @@
 use diesel::prelude::*;
 use rocket::serde::json::Json;
 use rocket::http::Status;
-use rocket::{get, post, put, delete};
-use chrono::Utc;
+use rocket::{get, post, put, delete, State};
+use chrono::{NaiveDateTime, Utc};
 
+use crate::error::ApiError;
 use crate::models::{ProductSpecification, NewProductSpecification, DbConn};
 use crate::schema::product_specifications;
-use crate::token::TokenGuard;
+use crate::auth_guard::{RequirePermission, ProductSpecificationRead, ProductSpecificationWrite};
+use crate::routers::pagination::{sort_by, ListQuery, Page};
+use crate::replication::{self, ReplicatedEntity, ReplicatedRecord, ReplicationSender, RowState};
 
-#[get("/product_specifications")]
-pub async fn list_product_specifications(conn: DbConn, _token: TokenGuard) -> Result<Json<Vec<ProductSpecification>>, Status> {
-    conn.run(|c| {
-        product_specifications::table
+// 默认不含已删除的产品规格，include_deleted=true 时一并返回
+#[get("/product_specifications?<include_deleted>")]
+pub async fn list_product_specifications(
+    conn: DbConn,
+    _perm: RequirePermission<ProductSpecificationRead>,
+    list: ListQuery,
+    include_deleted: Option<bool>
+) -> Result<Json<Page>, ApiError> {
+    conn.run(move |c| {
+        let filtered = || {
+            let mut query = product_specifications::table.into_boxed();
+            if !include_deleted.unwrap_or(false) {
+                query = query.filter(product_specifications::deleted_at.is_null());
+            }
+            query
+        };
+        let total = filtered()
+            .count()
+            .get_result::<i64>(c)
+            .map_err(ApiError::from)?;
+        let (query, keyset) = sort_by!(filtered(), list, ("created_at", true), product_specifications::product_id, [
+            "product_id" => product_specifications::product_id,
+            "product_name" => product_specifications::product_name,
+            "model" => product_specifications::model,
+            "material_type" => product_specifications::material_type,
+            "color" => product_specifications::color,
+            "created_at" => product_specifications::created_at,
+        ])?;
+        query
             .select(ProductSpecification::as_select())
-            .order(product_specifications::created_at.desc())
+            .offset(list.offset)
+            .limit(list.limit)
             .load(c)
+            .map_err(ApiError::from)
+            .and_then(|items| list.page(&keyset, items, total))
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
 }
 
 #[get("/product_specifications/<product_id>")]
 pub async fn get_product_specification(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<ProductSpecificationRead>,
     product_id: i32
-) -> Result<Json<ProductSpecification>, Status> {
+) -> Result<Json<ProductSpecification>, ApiError> {
     conn.run(move |c| {
         product_specifications::table
-            .find(product_id)
+            .filter(product_specifications::product_id.eq(product_id))
             .select(ProductSpecification::as_select())
             .first(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::NotFound)
+    .map_err(ApiError::from)
 }
 
 #[get("/product_specifications/by_name/<product_name>")]
 pub async fn get_specification_by_name(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<ProductSpecificationRead>,
     product_name: String
-) -> Result<Json<ProductSpecification>, Status> {
+) -> Result<Json<ProductSpecification>, ApiError> {
     conn.run(move |c| {
         product_specifications::table
             .filter(product_specifications::product_name.eq(product_name))
+            .filter(product_specifications::deleted_at.is_null())
             .select(ProductSpecification::as_select())
             .first(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::NotFound)
+    .map_err(ApiError::from)
 }
 
 #[get("/product_specifications/by_material/<material_type>")]
 pub async fn get_specifications_by_material(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<ProductSpecificationRead>,
     material_type: String
-) -> Result<Json<Vec<ProductSpecification>>, Status> {
+) -> Result<Json<Vec<ProductSpecification>>, ApiError> {
     conn.run(move |c| {
         product_specifications::table
             .filter(product_specifications::material_type.eq(material_type))
+            .filter(product_specifications::deleted_at.is_null())
             .select(ProductSpecification::as_select())
             .order(product_specifications::created_at.desc())
             .load(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
 
 #[get("/product_specifications/by_model/<model>")]
 pub async fn get_specifications_by_model(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<ProductSpecificationRead>,
     model: String
-) -> Result<Json<Vec<ProductSpecification>>, Status> {
+) -> Result<Json<Vec<ProductSpecification>>, ApiError> {
     conn.run(move |c| {
         product_specifications::table
             .filter(product_specifications::model.eq(model))
+            .filter(product_specifications::deleted_at.is_null())
             .select(ProductSpecification::as_select())
             .order(product_specifications::created_at.desc())
             .load(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
 
 #[post("/product_specifications", data = "<specification>")]
 pub async fn create_product_specification(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<ProductSpecificationWrite>,
+    replication: &State<ReplicationSender>,
     specification: Json<NewProductSpecification>
-) -> Result<Json<ProductSpecification>, Status> {
+) -> Result<Json<ProductSpecification>, ApiError> {
     // 检查产品名称是否已存在
+    let specification = specification.into_inner();
+    let product_name = specification.product_name.clone();
     let exists = conn.run(move |c| {
         product_specifications::table
-            .filter(product_specifications::product_name.eq(&specification.product_name))
+            .filter(product_specifications::product_name.eq(product_name))
             .count()
             .get_result::<i64>(c)
     }).await;
 
     if let Ok(count) = exists {
         if count > 0 {
-            return Err(Status::Conflict);
+            return Err(ApiError::conflict("duplicate_name", "product name already exists").with_field("product_name"));
         }
     }
 
+    let global_id = replication::new_global_id();
     let spec_with_timestamp = (
-        product_specifications::product_name.eq(&specification.product_name),
-        product_specifications::model.eq(&specification.model),
-        product_specifications::material_type.eq(&specification.material_type),
-        product_specifications::color.eq(&specification.color),
-        product_specifications::dimensions.eq(&specification.dimensions),
+        product_specifications::product_name.eq(specification.product_name),
+        product_specifications::model.eq(specification.model),
+        product_specifications::material_type.eq(specification.material_type),
+        product_specifications::color.eq(specification.color),
+        product_specifications::dimensions.eq(specification.dimensions),
         product_specifications::created_by.eq(specification.created_by),
         product_specifications::created_at.eq(Utc::now().naive_utc()),
+        product_specifications::global_id.eq(global_id.clone()),
     );
 
-    conn.run(move |c| {
+    let created: ProductSpecification = conn.run(move |c| {
+        let hlc = replication::next_hlc(c)?;
         diesel::insert_into(product_specifications::table)
-            .values(spec_with_timestamp)
-            .get_result(c)
+            .values((spec_with_timestamp, product_specifications::hlc.eq(hlc)))
+            .execute(c)?;
+        product_specifications::table
+            .filter(product_specifications::global_id.eq(global_id))
+            .select(ProductSpecification::as_select())
+            .first(c)
     }).await
-    .map(Json)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)?;
+
+    replication.upsert(ReplicatedRecord::ProductSpecification(created.clone()), None).await;
+    Ok(Json(created))
 }
 
 #[put("/product_specifications/<product_id>", data = "<specification>")]
 pub async fn update_product_specification(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<ProductSpecificationWrite>,
+    replication: &State<ReplicationSender>,
     product_id: i32,
     specification: Json<NewProductSpecification>
-) -> Result<Json<ProductSpecification>, Status> {
+) -> Result<Json<ProductSpecification>, ApiError> {
     // 检查新的产品名称是否与其他产品冲突
+    let product_name = specification.product_name.clone();
     let exists = conn.run(move |c| {
         product_specifications::table
-            .filter(product_specifications::product_name.eq(&specification.product_name))
+            .filter(product_specifications::product_name.eq(product_name))
             .filter(product_specifications::product_id.ne(product_id))
             .count()
             .get_result::<i64>(c)
@@ -143,12 +190,14 @@ pub async fn update_product_specification(
 
     if let Ok(count) = exists {
         if count > 0 {
-            return Err(Status::Conflict);
+            return Err(ApiError::conflict("duplicate_name", "product name already exists").with_field("product_name"));
         }
     }
 
-    conn.run(move |c| {
-        diesel::update(product_specifications::table.find(product_id))
+    let (updated, previous_hlc) = conn.run(move |c| c.transaction(|c| {
+        // 修改前的时钟随消息发出，接收端据此判断是否并发修改
+        let version = replication::stamp_local_write(c, ReplicatedEntity::ProductSpecification, product_id, RowState::Active)?;
+        diesel::update(product_specifications::table.filter(product_specifications::product_id.eq(product_id)))
             .set((
                 product_specifications::product_name.eq(&specification.product_name),
                 product_specifications::model.eq(&specification.model),
@@ -157,68 +206,137 @@ pub async fn update_product_specification(
                 product_specifications::dimensions.eq(&specification.dimensions),
                 product_specifications::created_by.eq(specification.created_by),
             ))
-            .get_result(c)
-    }).await
-    .map(Json)
-    .map_err(|_| Status::NotFound)
+            .execute(c)?;
+        let updated: ProductSpecification = product_specifications::table
+            .filter(product_specifications::product_id.eq(product_id))
+            .select(ProductSpecification::as_select())
+            .first(c)?;
+        Ok::<_, diesel::result::Error>((updated, version.previous_hlc))
+    })).await
+    .map_err(ApiError::from)?;
+
+    replication.upsert(ReplicatedRecord::ProductSpecification(updated.clone()), previous_hlc).await;
+    Ok(Json(updated))
 }
 
+// 软删除，已有的生产任务仍可引用该产品。超过保留期后由定时任务物理删除
 #[delete("/product_specifications/<product_id>")]
 pub async fn delete_product_specification(
     conn: DbConn,
-    _token: TokenGuard,
+    perm: RequirePermission<ProductSpecificationWrite>,
+    replication: &State<ReplicationSender>,
     product_id: i32
-) -> Result<Status, Status> {
-    conn.run(move |c| {
-        diesel::delete(product_specifications::table.find(product_id))
-            .execute(c)
+) -> Result<Status, ApiError> {
+    let user_id = perm.user_id;
+    // 删除前取出 global_id 和时钟，用于通知其他节点
+    let (global_id, hlc, previous_hlc) = conn.run(move |c| {
+        c.transaction(|c| {
+            let version = replication::stamp_local_write(c, ReplicatedEntity::ProductSpecification, product_id, RowState::Active)?;
+            diesel::update(product_specifications::table.filter(product_specifications::product_id.eq(product_id)))
+                .set((
+                    product_specifications::deleted_at.eq(Utc::now().naive_utc()),
+                    product_specifications::deleted_by.eq(user_id),
+                ))
+                .execute(c)?;
+            Ok::<_, diesel::result::Error>((version.global_id, version.hlc, version.previous_hlc))
+        })
     }).await
-    .map(|affected| {
-        if affected > 0 {
-            Status::NoContent
-        } else {
-            Status::NotFound
-        }
-    })
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)?;
+
+    if let Some(global_id) = global_id {
+        replication.delete(ReplicatedEntity::ProductSpecification, global_id, hlc, previous_hlc).await;
+    }
+    Ok(Status::NoContent)
 }
 
-// 搜索产品规格
-#[get("/product_specifications/search?<query>&<material_type>&<model>")]
+// 恢复软删除的产品规格，作为一次修改复制到其他节点
+#[post("/product_specifications/<product_id>/restore")]
+pub async fn restore_product_specification(
+    conn: DbConn,
+    _perm: RequirePermission<ProductSpecificationWrite>,
+    replication: &State<ReplicationSender>,
+    product_id: i32
+) -> Result<Json<ProductSpecification>, ApiError> {
+    let (restored, previous_hlc) = conn.run(move |c| {
+        c.transaction(|c| {
+            let version = replication::stamp_local_write(c, ReplicatedEntity::ProductSpecification, product_id, RowState::Deleted)?;
+            diesel::update(product_specifications::table.filter(product_specifications::product_id.eq(product_id)))
+                .set((
+                    product_specifications::deleted_at.eq(None::<NaiveDateTime>),
+                    product_specifications::deleted_by.eq(None::<i32>),
+                ))
+                .execute(c)?;
+            let restored: ProductSpecification = product_specifications::table
+                .filter(product_specifications::product_id.eq(product_id))
+                .select(ProductSpecification::as_select())
+                .first(c)?;
+            Ok::<_, diesel::result::Error>((restored, version.previous_hlc))
+        })
+    }).await
+    .map_err(ApiError::from)?;
+
+    replication.upsert(ReplicatedRecord::ProductSpecification(restored.clone()), previous_hlc).await;
+    Ok(Json(restored))
+}
+
+// 搜索产品规格，分页、排序和字段选择同 /product_specifications
+#[get("/product_specifications/search?<query>&<material_type>&<model>&<include_deleted>")]
 pub async fn search_specifications(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<ProductSpecificationRead>,
+    list: ListQuery,
     query: Option<String>,
     material_type: Option<String>,
-    model: Option<String>
-) -> Result<Json<Vec<ProductSpecification>>, Status> {
+    model: Option<String>,
+    include_deleted: Option<bool>
+) -> Result<Json<Page>, ApiError> {
     conn.run(move |c| {
-        let mut query_builder = product_specifications::table
-            .into_boxed();
+        let filtered = || {
+            let mut query_builder = product_specifications::table
+                .into_boxed();
 
-        if let Some(q) = query {
-            query_builder = query_builder.filter(
-                product_specifications::product_name.like(format!("%{}%", q))
-            );
-        }
+            if !include_deleted.unwrap_or(false) {
+                query_builder = query_builder.filter(product_specifications::deleted_at.is_null());
+            }
 
-        if let Some(mat_type) = material_type {
-            query_builder = query_builder.filter(
-                product_specifications::material_type.eq(mat_type)
-            );
-        }
+            if let Some(q) = &query {
+                query_builder = query_builder.filter(
+                    product_specifications::product_name.like(format!("%{}%", q))
+                );
+            }
 
-        if let Some(m) = model {
-            query_builder = query_builder.filter(
-                product_specifications::model.eq(m)
-            );
-        }
+            if let Some(mat_type) = &material_type {
+                query_builder = query_builder.filter(
+                    product_specifications::material_type.eq(mat_type.clone())
+                );
+            }
 
-        query_builder
-            .order(product_specifications::created_at.desc())
+            if let Some(m) = &model {
+                query_builder = query_builder.filter(
+                    product_specifications::model.eq(m.clone())
+                );
+            }
+            query_builder
+        };
+        let total = filtered()
+            .count()
+            .get_result::<i64>(c)
+            .map_err(ApiError::from)?;
+        let (query, keyset) = sort_by!(filtered(), list, ("created_at", true), product_specifications::product_id, [
+            "product_id" => product_specifications::product_id,
+            "product_name" => product_specifications::product_name,
+            "model" => product_specifications::model,
+            "material_type" => product_specifications::material_type,
+            "color" => product_specifications::color,
+            "created_at" => product_specifications::created_at,
+        ])?;
+        query
             .select(ProductSpecification::as_select())
+            .offset(list.offset)
+            .limit(list.limit)
             .load(c)
+            .map_err(ApiError::from)
+            .and_then(|items| list.page(&keyset, items, total))
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
 }
@@ -1,39 +1,59 @@
 use diesel::prelude::*;
 use rocket::serde::json::Json;
 use rocket::http::Status;
-use rocket::{get, post, put, delete};
+use rocket::{get, post, put, delete, State};
 use chrono::Utc;
 
+use crate::error::ApiError;
 use crate::models::{ProductionCost, NewProductionCost, DbConn};
 use crate::schema::production_costs;
-use crate::token::TokenGuard;
+use crate::auth_guard::{RequirePermission, ProductionCostRead, ProductionCostWrite};
+use crate::routers::pagination::{sort_by, ListQuery, Page};
+use crate::replication::{self, LocalVersion, ReplicatedEntity, ReplicatedRecord, ReplicationSender, RowState};
 
 #[get("/production_costs")]
-pub async fn list_production_costs(conn: DbConn, _token: TokenGuard) -> Result<Json<Vec<ProductionCost>>, Status> {
-    conn.run(|c| {
-        production_costs::table
+pub async fn list_production_costs(
+    conn: DbConn,
+    _perm: RequirePermission<ProductionCostRead>,
+    list: ListQuery
+) -> Result<Json<Page>, ApiError> {
+    conn.run(move |c| {
+        let filtered = || production_costs::table.into_boxed();
+        let total = filtered()
+            .count()
+            .get_result::<i64>(c)
+            .map_err(ApiError::from)?;
+        let (query, keyset) = sort_by!(filtered(), list, ("created_at", true), production_costs::cost_id, [
+            "cost_id" => production_costs::cost_id,
+            "process_type" => production_costs::process_type,
+            "cost_per_unit" => production_costs::cost_per_unit,
+            "created_at" => production_costs::created_at,
+        ])?;
+        query
             .select(ProductionCost::as_select())
-            .order(production_costs::created_at.desc())
+            .offset(list.offset)
+            .limit(list.limit)
             .load(c)
+            .map_err(ApiError::from)
+            .and_then(|items| list.page(&keyset, items, total))
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
 }
 
 #[get("/production_costs/<cost_id>")]
-pub async fn get_production_cost(conn: DbConn, _token: TokenGuard, cost_id: i32) -> Result<Json<ProductionCost>, Status> {
+pub async fn get_production_cost(conn: DbConn, _perm: RequirePermission<ProductionCostRead>, cost_id: i32) -> Result<Json<ProductionCost>, ApiError> {
     conn.run(move |c| {
         production_costs::table
-            .find(cost_id)
+            .filter(production_costs::cost_id.eq(cost_id))
             .select(ProductionCost::as_select())
             .first(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::NotFound)
+    .map_err(ApiError::from)
 }
 
 #[get("/production_costs/by_process/<process_type>")]
-pub async fn get_costs_by_process(conn: DbConn, _token: TokenGuard, process_type: String) -> Result<Json<Vec<ProductionCost>>, Status> {
+pub async fn get_costs_by_process(conn: DbConn, _perm: RequirePermission<ProductionCostRead>, process_type: String) -> Result<Json<Vec<ProductionCost>>, ApiError> {
     conn.run(move |c| {
         production_costs::table
             .filter(production_costs::process_type.eq(process_type))
@@ -42,66 +62,99 @@ pub async fn get_costs_by_process(conn: DbConn, _token: TokenGuard, process_type
             .load(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
 
 #[post("/production_costs", data = "<cost>")]
-pub async fn create_production_cost(conn: DbConn, _token: TokenGuard, cost: Json<NewProductionCost>) -> Result<Json<ProductionCost>, Status> {
+pub async fn create_production_cost(conn: DbConn, _perm: RequirePermission<ProductionCostWrite>, replication: &State<ReplicationSender>, cost: Json<NewProductionCost>) -> Result<Json<ProductionCost>, ApiError> {
+    let global_id = replication::new_global_id();
+    let cost = cost.into_inner();
     let cost_with_timestamp = (
-        production_costs::process_type.eq(&cost.process_type),
+        production_costs::process_type.eq(cost.process_type),
         production_costs::cost_per_unit.eq(cost.cost_per_unit),
         production_costs::created_by.eq(cost.created_by),
         production_costs::created_at.eq(Utc::now().naive_utc()),
+        production_costs::global_id.eq(global_id.clone()),
     );
 
-    conn.run(move |c| {
+    let created: ProductionCost = conn.run(move |c| {
+        let hlc = replication::next_hlc(c)?;
         diesel::insert_into(production_costs::table)
-            .values(cost_with_timestamp)
-            .get_result(c)
+            .values((cost_with_timestamp, production_costs::hlc.eq(hlc)))
+            .execute(c)?;
+        production_costs::table
+            .filter(production_costs::global_id.eq(global_id))
+            .select(ProductionCost::as_select())
+            .first(c)
     }).await
-    .map(Json)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)?;
+
+    replication.upsert(ReplicatedRecord::ProductionCost(created.clone()), None).await;
+    Ok(Json(created))
 }
 
 #[put("/production_costs/<cost_id>", data = "<cost>")]
 pub async fn update_production_cost(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<ProductionCostWrite>,
+    replication: &State<ReplicationSender>,
     cost_id: i32,
     cost: Json<NewProductionCost>
-) -> Result<Json<ProductionCost>, Status> {
-    conn.run(move |c| {
-        diesel::update(production_costs::table.find(cost_id))
+) -> Result<Json<ProductionCost>, ApiError> {
+    let (updated, previous_hlc) = conn.run(move |c| c.transaction(|c| {
+        // 修改前的时钟随消息发出，接收端据此判断是否并发修改
+        let version = replication::stamp_local_write(c, ReplicatedEntity::ProductionCost, cost_id, RowState::Any)?;
+        diesel::update(production_costs::table.filter(production_costs::cost_id.eq(cost_id)))
             .set((
                 production_costs::process_type.eq(&cost.process_type),
                 production_costs::cost_per_unit.eq(cost.cost_per_unit),
                 production_costs::created_by.eq(cost.created_by),
             ))
-            .get_result(c)
-    }).await
-    .map(Json)
-    .map_err(|_| Status::NotFound)
+            .execute(c)?;
+        let updated: ProductionCost = production_costs::table
+            .filter(production_costs::cost_id.eq(cost_id))
+            .select(ProductionCost::as_select())
+            .first(c)?;
+        Ok::<_, diesel::result::Error>((updated, version.previous_hlc))
+    })).await
+    .map_err(ApiError::from)?;
+
+    replication.upsert(ReplicatedRecord::ProductionCost(updated.clone()), previous_hlc).await;
+    Ok(Json(updated))
 }
 
 #[delete("/production_costs/<cost_id>")]
-pub async fn delete_production_cost(conn: DbConn, _token: TokenGuard, cost_id: i32) -> Result<Status, Status> {
-    conn.run(move |c| {
-        diesel::delete(production_costs::table.find(cost_id))
-            .execute(c)
-    }).await
-    .map(|affected| {
-        if affected > 0 {
-            Status::NoContent
-        } else {
-            Status::NotFound
+pub async fn delete_production_cost(conn: DbConn, _perm: RequirePermission<ProductionCostWrite>, replication: &State<ReplicationSender>, cost_id: i32) -> Result<Status, ApiError> {
+    // 删除前取出 global_id 和时钟，用于通知其他节点
+    let deleted = conn.run(move |c| c.transaction(|c| {
+        // 先写入删除时钟再删除，变更日志记下的就是这次删除的时钟
+        let version = replication::stamp_local_write(c, ReplicatedEntity::ProductionCost, cost_id, RowState::Any).optional()?;
+        let LocalVersion { global_id, previous_hlc, hlc } = match version {
+            Some(version) => version,
+            None => return Ok(None),
+        };
+        diesel::delete(production_costs::table.filter(production_costs::cost_id.eq(cost_id))).execute(c)?;
+        if let Some(global_id) = &global_id {
+            replication::record_tombstone(c, ReplicatedEntity::ProductionCost, global_id, &hlc)?;
+        }
+        Ok::<_, diesel::result::Error>(Some((global_id, hlc, previous_hlc)))
+    })).await
+    .map_err(ApiError::from)?;
+
+    match deleted {
+        Some((global_id, hlc, previous_hlc)) => {
+            if let Some(global_id) = global_id {
+                replication.delete(ReplicatedEntity::ProductionCost, global_id, hlc, previous_hlc).await;
+            }
+            Ok(Status::NoContent)
         }
-    })
-    .map_err(|_| Status::InternalServerError)
+        None => Err(ApiError::not_found("production cost not found")),
+    }
 }
 
 // 获取最新的生产成本记录
 #[get("/production_costs/latest/<process_type>")]
-pub async fn get_latest_cost(conn: DbConn, _token: TokenGuard, process_type: String) -> Result<Json<ProductionCost>, Status> {
+pub async fn get_latest_cost(conn: DbConn, _perm: RequirePermission<ProductionCostRead>, process_type: String) -> Result<Json<ProductionCost>, ApiError> {
     conn.run(move |c| {
         production_costs::table
             .filter(production_costs::process_type.eq(process_type))
@@ -110,5 +163,5 @@ pub async fn get_latest_cost(conn: DbConn, _token: TokenGuard, process_type: Str
             .first(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::NotFound)
+    .map_err(ApiError::from)
 }
@@ -1,39 +1,73 @@
 use diesel::prelude::*;
 use rocket::serde::json::Json;
 use rocket::http::Status;
-use rocket::{get, post};
+use rocket::{get, post, put, State};
 use chrono::Utc;
+use serde::Deserialize;
 
+use crate::error::ApiError;
 use crate::models::{ProductionTask, NewProductionTask, DbConn};
 use crate::schema::production_tasks;
-use crate::token::TokenGuard;
+use crate::auth_guard::{RequirePermission, ProductionTaskRead, ProductionTaskWrite};
+use crate::routers::pagination::{sort_by, ListQuery, Page};
+use crate::events::{DomainEvent, EventSender};
+use crate::soft_delete;
+
+// 任务状态，与 production_tasks.status 的 CHECK 约束一致
+pub const NOT_STARTED: &str = "not_started";
+pub const IN_PROGRESS: &str = "in_progress";
+pub const COMPLETED: &str = "completed";
+
+#[derive(Debug, Deserialize)]
+pub struct TaskStatusUpdate {
+    pub status: String,
+}
 
 #[get("/production_tasks")]
-pub async fn list_production_tasks(conn: DbConn, _token: TokenGuard) -> Result<Json<Vec<ProductionTask>>, Status> {
-    conn.run(|c| {
-        production_tasks::table
+pub async fn list_production_tasks(
+    conn: DbConn,
+    _perm: RequirePermission<ProductionTaskRead>,
+    list: ListQuery
+) -> Result<Json<Page>, ApiError> {
+    conn.run(move |c| {
+        let filtered = || production_tasks::table.into_boxed();
+        let total = filtered()
+            .count()
+            .get_result::<i64>(c)
+            .map_err(ApiError::from)?;
+        let (query, keyset) = sort_by!(filtered(), list, ("created_at", true), production_tasks::task_id, [
+            "task_id" => production_tasks::task_id,
+            "product_id" => production_tasks::product_id,
+            "quantity" => production_tasks::quantity,
+            "due_date" => production_tasks::due_date,
+            "status" => production_tasks::status,
+            "created_at" => production_tasks::created_at,
+        ])?;
+        query
             .select(ProductionTask::as_select())
-            .order(production_tasks::created_at.desc())
+            .offset(list.offset)
+            .limit(list.limit)
             .load(c)
+            .map_err(ApiError::from)
+            .and_then(|items| list.page(&keyset, items, total))
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
 }
 
 #[get("/production_tasks/<task_id>")]
-pub async fn get_production_task(conn: DbConn, _token: TokenGuard, task_id: i32) -> Result<Json<ProductionTask>, Status> {
+pub async fn get_production_task(conn: DbConn, _perm: RequirePermission<ProductionTaskRead>, task_id: i32) -> Result<Json<ProductionTask>, ApiError> {
     conn.run(move |c| {
         production_tasks::table
-            .find(task_id)
+            .filter(production_tasks::task_id.eq(task_id))
             .select(ProductionTask::as_select())
             .first(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::NotFound)
+    .map_err(ApiError::from)
 }
 
 #[get("/production_tasks/by_product/<product_id>")]
-pub async fn get_tasks_by_product(conn: DbConn, _token: TokenGuard, product_id: i32) -> Result<Json<Vec<ProductionTask>>, Status> {
+pub async fn get_tasks_by_product(conn: DbConn, _perm: RequirePermission<ProductionTaskRead>, product_id: i32) -> Result<Json<Vec<ProductionTask>>, ApiError> {
     conn.run(move |c| {
         production_tasks::table
             .filter(production_tasks::product_id.eq(product_id))
@@ -42,11 +76,11 @@ pub async fn get_tasks_by_product(conn: DbConn, _token: TokenGuard, product_id:
             .load(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
 
 #[get("/production_tasks/by_status/<status>")]
-pub async fn get_tasks_by_status(conn: DbConn, _token: TokenGuard, status: String) -> Result<Json<Vec<ProductionTask>>, Status> {
+pub async fn get_tasks_by_status(conn: DbConn, _perm: RequirePermission<ProductionTaskRead>, status: String) -> Result<Json<Vec<ProductionTask>>, ApiError> {
     conn.run(move |c| {
         production_tasks::table
             .filter(production_tasks::status.eq(status))
@@ -55,25 +89,70 @@ pub async fn get_tasks_by_status(conn: DbConn, _token: TokenGuard, status: Strin
             .load(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
 
 #[post("/production_tasks", data = "<task>")]
-pub async fn create_production_task(conn: DbConn, _token: TokenGuard, task: Json<NewProductionTask>) -> Result<Status, Status> {
+pub async fn create_production_task(conn: DbConn, _perm: RequirePermission<ProductionTaskWrite>, task: Json<NewProductionTask>) -> Result<Status, ApiError> {
     let task_with_timestamp = (
         production_tasks::product_id.eq(task.product_id),
         production_tasks::quantity.eq(task.quantity),
         production_tasks::due_date.eq(task.due_date),
         production_tasks::created_by.eq(task.created_by),
         production_tasks::created_at.eq(Utc::now().naive_utc()),
-        production_tasks::status.eq("pending"), // 默认状态为 pending
+        production_tasks::status.eq(NOT_STARTED), // 新建任务一律为 not_started
     );
 
+    let product_id = task.product_id;
     conn.run(move |c| {
-        diesel::insert_into(production_tasks::table)
-            .values(task_with_timestamp)
-            .execute(c)
+        c.transaction(|c| {
+            // 不能为已删除的产品新建任务
+            if !soft_delete::product_active(c, product_id)? {
+                return Err(ApiError::not_found("product not found").with_field("product_id"));
+            }
+            diesel::insert_into(production_tasks::table)
+                .values(task_with_timestamp)
+                .execute(c)
+                .map_err(ApiError::from)
+        })
     }).await
     .map(|_| Status::Created)
-    .map_err(|_| Status::InternalServerError)
+}
+
+// 更新任务状态，状态确有变化时广播 TaskStatusChanged
+#[put("/production_tasks/<task_id>/status", data = "<update>")]
+pub async fn update_task_status(
+    conn: DbConn,
+    _perm: RequirePermission<ProductionTaskWrite>,
+    events: &State<EventSender>,
+    task_id: i32,
+    update: Json<TaskStatusUpdate>
+) -> Result<Json<ProductionTask>, ApiError> {
+    let status = update.into_inner().status;
+    if ![NOT_STARTED, IN_PROGRESS, COMPLETED].contains(&status.as_str()) {
+        return Err(ApiError::bad_request("unknown task status").with_field("status"));
+    }
+
+    let (previous_status, task) = conn.run(move |c| {
+        c.transaction(|c| {
+            let previous_status = production_tasks::table
+                .filter(production_tasks::task_id.eq(task_id))
+                .select(production_tasks::status)
+                .first::<Option<String>>(c)?;
+            diesel::update(production_tasks::table.filter(production_tasks::task_id.eq(task_id)))
+                .set(production_tasks::status.eq(status))
+                .execute(c)?;
+            let task: ProductionTask = production_tasks::table
+                .filter(production_tasks::task_id.eq(task_id))
+                .select(ProductionTask::as_select())
+                .first(c)?;
+            Ok::<_, diesel::result::Error>((previous_status, task))
+        })
+    }).await
+    .map_err(ApiError::from)?;
+
+    if previous_status != task.status {
+        events.emit(DomainEvent::TaskStatusChanged { previous_status, task: task.clone() }).await;
+    }
+    Ok(Json(task))
 }
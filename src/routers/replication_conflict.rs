@@ -0,0 +1,91 @@
+use diesel::prelude::*;
+use rocket::serde::json::Json;
+use rocket::{get, put};
+use chrono::Utc;
+
+use crate::error::ApiError;
+use crate::models::{ReplicationConflict, DbConn};
+use crate::schema::replication_conflicts;
+use crate::auth_guard::{RequirePermission, ReplicationRead, ReplicationWrite};
+
+// 搜索自动解决的复制冲突，reviewed=false 只返回未复核的冲突
+#[get("/replication/conflicts?<table_name>&<global_id>&<reviewed>")]
+pub async fn list_conflicts(
+    conn: DbConn,
+    _perm: RequirePermission<ReplicationRead>,
+    table_name: Option<String>,
+    global_id: Option<String>,
+    reviewed: Option<bool>
+) -> Result<Json<Vec<ReplicationConflict>>, ApiError> {
+    conn.run(move |c| {
+        let mut query_builder = replication_conflicts::table
+            .into_boxed();
+
+        if let Some(t) = table_name {
+            query_builder = query_builder.filter(
+                replication_conflicts::table_name.eq(t)
+            );
+        }
+
+        if let Some(gid) = global_id {
+            query_builder = query_builder.filter(
+                replication_conflicts::global_id.eq(gid)
+            );
+        }
+
+        if let Some(r) = reviewed {
+            query_builder = if r {
+                query_builder.filter(replication_conflicts::reviewed_at.is_not_null())
+            } else {
+                query_builder.filter(replication_conflicts::reviewed_at.is_null())
+            };
+        }
+
+        query_builder
+            .order(replication_conflicts::conflict_id.desc())
+            .select(ReplicationConflict::as_select())
+            .load(c)
+    }).await
+    .map(Json)
+    .map_err(ApiError::from)
+}
+
+#[get("/replication/conflicts/<conflict_id>")]
+pub async fn get_conflict(
+    conn: DbConn,
+    _perm: RequirePermission<ReplicationRead>,
+    conflict_id: i32
+) -> Result<Json<ReplicationConflict>, ApiError> {
+    conn.run(move |c| {
+        replication_conflicts::table
+            .find(conflict_id)
+            .select(ReplicationConflict::as_select())
+            .first(c)
+    }).await
+    .map(Json)
+    .map_err(ApiError::from)
+}
+
+// 标记冲突已复核，不改变合并结果
+#[put("/replication/conflicts/<conflict_id>/review")]
+pub async fn review_conflict(
+    conn: DbConn,
+    perm: RequirePermission<ReplicationWrite>,
+    conflict_id: i32
+) -> Result<Json<ReplicationConflict>, ApiError> {
+    let user_id = perm.user_id;
+    conn.run(move |c| {
+        diesel::update(replication_conflicts::table.find(conflict_id))
+            .set((
+                replication_conflicts::reviewed_by.eq(user_id),
+                replication_conflicts::reviewed_at.eq(Utc::now().naive_utc()),
+            ))
+            .execute(c)?;
+        replication_conflicts::table
+            .find(conflict_id)
+            .select(ReplicationConflict::as_select())
+            .first(c)
+    }).await
+    .map(Json)
+    .map_err(ApiError::from)
+}
@@ -2,26 +2,41 @@ use rocket::serde::json::Json;
 use rocket::http::Status;
 use rocket::{get, post, put, delete}; 
 use diesel::prelude::*;
+use crate::error::ApiError;
 use crate::models::{Role, NewRole, DbConn};
 use crate::schema::roles;
-use crate::token::TokenGuard;
+use crate::auth_guard::{RequirePermission, RoleRead, RoleWrite};
+use crate::routers::pagination::{sort_by, ListQuery, Page};
 
 #[get("/roles")]
-pub async fn get_roles(conn: DbConn) -> Result<Json<Vec<Role>>, Status> {
-    let roles = conn.run(|c| {
-        roles::table
+pub async fn list_roles(
+    conn: DbConn,
+    _perm: RequirePermission<RoleRead>,
+    list: ListQuery
+) -> Result<Json<Page>, ApiError> {
+    conn.run(move |c| {
+        let filtered = || roles::table.into_boxed();
+        let total = filtered()
+            .count()
+            .get_result::<i64>(c)
+            .map_err(ApiError::from)?;
+        let (query, keyset) = sort_by!(filtered(), list, ("role_id", false), roles::role_id, [
+            "role_id" => roles::role_id,
+            "role_name" => roles::role_name,
+        ])?;
+        query
             .select(Role::as_select())
+            .offset(list.offset)
+            .limit(list.limit)
             .load(c)
-    }).await;
-
-    match roles {
-        Ok(roles_list) => Ok(Json(roles_list)),
-        Err(_) => Err(Status::InternalServerError)
-    }
+            .map_err(ApiError::from)
+            .and_then(|items| list.page(&keyset, items, total))
+    }).await
+    .map(Json)
 }
 
 #[get("/role/<role_id>")]
-pub async fn get_role(conn: DbConn, role_id: i32) -> Result<Json<Role>, Status> {
+pub async fn get_role(conn: DbConn, _perm: RequirePermission<RoleRead>, role_id: i32) -> Result<Json<Role>, ApiError> {
     let role = conn.run(move |c| {
         roles::table
             .find(role_id)
@@ -31,7 +46,7 @@ pub async fn get_role(conn: DbConn, role_id: i32) -> Result<Json<Role>, Status>
 
     match role {
         Ok(role_data) => Ok(Json(role_data)),
-        Err(_) => Err(Status::NotFound)
+        Err(e) => Err(e.into())
     }
 }
 
@@ -39,8 +54,8 @@ pub async fn get_role(conn: DbConn, role_id: i32) -> Result<Json<Role>, Status>
 pub async fn create_role(
     conn: DbConn,
     role: Json<NewRole>,
-    token: TokenGuard,
-) -> Result<Status, Status> {
+    _perm: RequirePermission<RoleWrite>,
+) -> Result<Status, ApiError> {
     let result = conn.run(move |c| {
         diesel::insert_into(roles::table)
             .values(&role.into_inner())
@@ -49,7 +64,7 @@ pub async fn create_role(
 
     match result {
         Ok(_) => Ok(Status::Created),
-        Err(_) => Err(Status::InternalServerError)
+        Err(e) => Err(e.into())
     }
 }
 
@@ -58,8 +73,8 @@ pub async fn update_role(
     conn: DbConn,
     role_id: i32,
     role: Json<NewRole>,
-    token: TokenGuard,
-) -> Result<Status, Status> {
+    _perm: RequirePermission<RoleWrite>,
+) -> Result<Status, ApiError> {
     let result = conn.run(move |c| {
         diesel::update(roles::table.find(role_id))
             .set((
@@ -70,9 +85,9 @@ pub async fn update_role(
     }).await;
 
     match result {
-        Ok(1) => Ok(Status::Ok),
-        Ok(0) => Err(Status::NotFound),
-        _ => Err(Status::InternalServerError)
+        Ok(0) => Err(ApiError::not_found("role not found")),
+        Ok(_) => Ok(Status::Ok),
+        Err(e) => Err(e.into())
     }
 }
 
@@ -80,16 +95,16 @@ pub async fn update_role(
 pub async fn delete_role(
     conn: DbConn,
     role_id: i32,
-    token: TokenGuard,
-) -> Result<Status, Status> {
+    _perm: RequirePermission<RoleWrite>,
+) -> Result<Status, ApiError> {
     let result = conn.run(move |c| {
         diesel::delete(roles::table.find(role_id))
             .execute(c)
     }).await;
 
     match result {
-        Ok(1) => Ok(Status::Ok),
-        Ok(0) => Err(Status::NotFound),
-        _ => Err(Status::InternalServerError)
+        Ok(0) => Err(ApiError::not_found("role not found")),
+        Ok(_) => Ok(Status::Ok),
+        Err(e) => Err(e.into())
     }
 }
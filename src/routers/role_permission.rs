@@ -3,23 +3,24 @@ use rocket::serde::json::Json;
 use rocket::http::Status;
 use rocket::{get, post, delete};
 
+use crate::error::ApiError;
 use crate::models::{RolePermission, NewRolePermission, DbConn};
 use crate::schema::role_permissions;
-use crate::token::TokenGuard;
+use crate::auth_guard::{RequirePermission, RolePermissionRead, RolePermissionWrite};
 
 #[get("/role_permissions")]
-pub async fn list_role_permissions(conn: DbConn, _token: TokenGuard) -> Result<Json<Vec<RolePermission>>, Status> {
+pub async fn list_role_permissions(conn: DbConn, _perm: RequirePermission<RolePermissionRead>) -> Result<Json<Vec<RolePermission>>, ApiError> {
     conn.run(|c| {
         role_permissions::table
             .select(RolePermission::as_select())
             .load(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
 
 #[get("/role_permissions/by_role/<role_id>")]
-pub async fn get_role_permissions(conn: DbConn, _token: TokenGuard, role_id: i32) -> Result<Json<Vec<RolePermission>>, Status> {
+pub async fn get_role_permissions(conn: DbConn, _perm: RequirePermission<RolePermissionRead>, role_id: i32) -> Result<Json<Vec<RolePermission>>, ApiError> {
     conn.run(move |c| {
         role_permissions::table
             .filter(role_permissions::role_id.eq(role_id))
@@ -27,11 +28,11 @@ pub async fn get_role_permissions(conn: DbConn, _token: TokenGuard, role_id: i32
             .load(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
 
 #[get("/role_permissions/by_permission/<permission_id>")]
-pub async fn get_permission_roles(conn: DbConn, _token: TokenGuard, permission_id: i32) -> Result<Json<Vec<RolePermission>>, Status> {
+pub async fn get_permission_roles(conn: DbConn, _perm: RequirePermission<RolePermissionRead>, permission_id: i32) -> Result<Json<Vec<RolePermission>>, ApiError> {
     conn.run(move |c| {
         role_permissions::table
             .filter(role_permissions::permission_id.eq(permission_id))
@@ -39,24 +40,23 @@ pub async fn get_permission_roles(conn: DbConn, _token: TokenGuard, permission_i
             .load(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
 
 #[post("/role_permissions", data = "<role_permission>")]
-pub async fn create_role_permission(conn: DbConn, _token: TokenGuard, role_permission: Json<NewRolePermission>) -> Result<Status, Status> {
+pub async fn create_role_permission(conn: DbConn, _perm: RequirePermission<RolePermissionWrite>, role_permission: Json<NewRolePermission>) -> Result<Status, ApiError> {
     // 首先检查是否已存在相同的角色权限关联
+    let (role_id, permission_id) = (role_permission.role_id, role_permission.permission_id);
     let exists = conn.run(move |c| {
         role_permissions::table
-            .filter(role_permissions::role_id.eq(role_permission.role_id))
-            .filter(role_permissions::permission_id.eq(role_permission.permission_id))
+            .filter(role_permissions::role_id.eq(role_id))
+            .filter(role_permissions::permission_id.eq(permission_id))
             .count()
             .get_result::<i64>(c)
     }).await;
 
     match exists {
-        Ok(count) if count > 0 => {
-            return Err(Status::Conflict); // 返回409表示已存在
-        }
+        Ok(count) if count > 0 => Err(ApiError::conflict("already_granted", "permission is already granted to the role")),
         Ok(_) => {
             // 不存在，继续创建
             conn.run(move |c| {
@@ -65,19 +65,19 @@ pub async fn create_role_permission(conn: DbConn, _token: TokenGuard, role_permi
                     .execute(c)
             }).await
             .map(|_| Status::Created)
-            .map_err(|_| Status::InternalServerError)
+            .map_err(ApiError::from)
         }
-        Err(_) => Err(Status::InternalServerError)
+        Err(e) => Err(e.into())
     }
 }
 
 #[delete("/role_permissions/<role_id>/<permission_id>")]
 pub async fn delete_role_permission(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<RolePermissionWrite>,
     role_id: i32,
     permission_id: i32
-) -> Result<Status, Status> {
+) -> Result<Status, ApiError> {
     conn.run(move |c| {
         diesel::delete(
             role_permissions::table
@@ -86,24 +86,24 @@ pub async fn delete_role_permission(
         )
         .execute(c)
     }).await
-    .map(|affected| {
+    .map_err(ApiError::from)
+    .and_then(|affected| {
         if affected > 0 {
-            Status::NoContent
+            Ok(Status::NoContent)
         } else {
-            Status::NotFound
+            Err(ApiError::not_found("role permission not found"))
         }
     })
-    .map_err(|_| Status::InternalServerError)
 }
 
 // 批量设置角色权限（替换现有的所有权限）
 #[post("/role_permissions/batch/<role_id>", data = "<permission_ids>")]
 pub async fn set_role_permissions(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<RolePermissionWrite>,
     role_id: i32,
     permission_ids: Json<Vec<i32>>
-) -> Result<Status, Status> {
+) -> Result<Status, ApiError> {
     conn.run(move |c| {
         c.transaction(|c| {
             // 删除角色现有的所有权限
@@ -129,17 +129,17 @@ pub async fn set_role_permissions(
         })
     }).await
     .map(|_| Status::Ok)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
 
 // 检查角色是否具有特定权限
 #[get("/role_permissions/check/<role_id>/<permission_id>")]
 pub async fn check_role_permission(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<RolePermissionRead>,
     role_id: i32,
     permission_id: i32
-) -> Result<Status, Status> {
+) -> Result<Status, ApiError> {
     conn.run(move |c| {
         role_permissions::table
             .filter(role_permissions::role_id.eq(role_id))
@@ -147,12 +147,12 @@ pub async fn check_role_permission(
             .count()
             .get_result::<i64>(c)
     }).await
-    .map(|count| {
+    .map_err(ApiError::from)
+    .and_then(|count| {
         if count > 0 {
-            Status::Ok
+            Ok(Status::Ok)
         } else {
-            Status::NotFound
+            Err(ApiError::not_found("role permission not found"))
         }
     })
-    .map_err(|_| Status::InternalServerError)
 }
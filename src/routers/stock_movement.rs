@@ -0,0 +1,372 @@
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use rocket::serde::json::Json;
+use rocket::{get, post, FromForm, State};
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::ApiError;
+use crate::models::{StockMovement, NewStockMovement, WarehouseStock, DbConn};
+use crate::schema::{stock_movements, warehouse_stock, warehouses};
+use crate::routers::warehouse_stock::{apply_stock_change, sync_warehouse_total, to_quantity, StockError};
+use crate::auth_guard::{RequirePermission, StockRead, StockWrite};
+use crate::events::{DomainEvent, EventSender};
+
+// 流水类型，与 stock_movements.movement_type 的 CHECK 约束一致
+pub const RECEIPT: &str = "receipt";
+pub const ISSUE: &str = "issue";
+pub const TRANSFER_IN: &str = "transfer_in";
+pub const TRANSFER_OUT: &str = "transfer_out";
+pub const ADJUSTMENT: &str = "adjustment";
+
+// 库存调整请求，quantity 为带符号的增量
+#[derive(Debug, Deserialize)]
+pub struct NewAdjustment {
+    pub warehouse_id: i32,
+    pub material_id: i32,
+    pub quantity: i32,
+    pub note: Option<String>,
+}
+
+// 仓库间调拨请求
+#[derive(Debug, Deserialize)]
+pub struct NewTransfer {
+    pub from_warehouse_id: i32,
+    pub to_warehouse_id: i32,
+    pub material_id: i32,
+    pub quantity: i32,
+    pub note: Option<String>,
+}
+
+// 某时刻的库存余额
+#[derive(Debug, Serialize)]
+pub struct StockBalance {
+    pub warehouse_id: i32,
+    pub material_id: i32,
+    pub quantity: i64,
+    pub as_of: NaiveDateTime,
+}
+
+// 写入一条流水并更新 warehouse_stock 投影，调用方负责开启事务
+pub fn record_movement(
+    c: &mut SqliteConnection,
+    movement: NewStockMovement,
+) -> Result<WarehouseStock, StockError> {
+    let stock = apply_stock_change(c, movement.warehouse_id, movement.material_id, movement.quantity)?;
+
+    diesel::insert_into(stock_movements::table)
+        .values((
+            &movement,
+            stock_movements::created_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(c)?;
+
+    Ok(stock)
+}
+
+// 解析 as_of 参数，支持完整时间或仅日期（取当天结束）
+fn parse_as_of(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S"))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(23, 59, 59))
+        })
+}
+
+// 库存流水的搜索条件，日期格式为 YYYY-MM-DD
+#[derive(Debug, FromForm)]
+pub struct StockMovementFilter {
+    warehouse_id: Option<i32>,
+    material_id: Option<i32>,
+    movement_type: Option<String>,
+    request_id: Option<i32>,
+    task_id: Option<i32>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+}
+
+// 搜索库存流水
+#[get("/stock_movements?<filter..>")]
+pub async fn search_stock_movements(
+    conn: DbConn,
+    _perm: RequirePermission<StockRead>,
+    filter: StockMovementFilter
+) -> Result<Json<Vec<StockMovement>>, ApiError> {
+    let StockMovementFilter { warehouse_id, material_id, movement_type, request_id, task_id, start_date, end_date } = filter;
+    conn.run(move |c| {
+        let mut query_builder = stock_movements::table
+            .into_boxed();
+
+        if let Some(wid) = warehouse_id {
+            query_builder = query_builder.filter(
+                stock_movements::warehouse_id.eq(wid)
+            );
+        }
+
+        if let Some(mid) = material_id {
+            query_builder = query_builder.filter(
+                stock_movements::material_id.eq(mid)
+            );
+        }
+
+        if let Some(t) = movement_type {
+            query_builder = query_builder.filter(
+                stock_movements::movement_type.eq(t)
+            );
+        }
+
+        if let Some(rid) = request_id {
+            query_builder = query_builder.filter(
+                stock_movements::request_id.eq(rid)
+            );
+        }
+
+        if let Some(tid) = task_id {
+            query_builder = query_builder.filter(
+                stock_movements::task_id.eq(tid)
+            );
+        }
+
+        if let Some(start) = start_date {
+            if let Ok(start_dt) = NaiveDateTime::parse_from_str(&format!("{} 00:00:00", start), "%Y-%m-%d %H:%M:%S") {
+                query_builder = query_builder.filter(
+                    stock_movements::created_at.ge(start_dt)
+                );
+            }
+        }
+
+        if let Some(end) = end_date {
+            if let Ok(end_dt) = NaiveDateTime::parse_from_str(&format!("{} 23:59:59", end), "%Y-%m-%d %H:%M:%S") {
+                query_builder = query_builder.filter(
+                    stock_movements::created_at.le(end_dt)
+                );
+            }
+        }
+
+        query_builder
+            .order(stock_movements::movement_id.desc())
+            .select(StockMovement::as_select())
+            .load(c)
+    }).await
+    .map(Json)
+    .map_err(ApiError::from)
+}
+
+#[get("/stock_movements/<movement_id>")]
+pub async fn get_stock_movement(
+    conn: DbConn,
+    _perm: RequirePermission<StockRead>,
+    movement_id: i32
+) -> Result<Json<StockMovement>, ApiError> {
+    conn.run(move |c| {
+        stock_movements::table
+            .find(movement_id)
+            .select(StockMovement::as_select())
+            .first(c)
+    }).await
+    .map(Json)
+    .map_err(ApiError::from)
+}
+
+// 盘点调整，quantity 可正可负
+#[post("/stock_movements/adjust", data = "<adjustment>")]
+pub async fn adjust_stock(
+    conn: DbConn,
+    perm: RequirePermission<StockWrite>,
+    events: &State<EventSender>,
+    adjustment: Json<NewAdjustment>
+) -> Result<Json<WarehouseStock>, ApiError> {
+    if adjustment.quantity == 0 {
+        return Err(ApiError::bad_request("quantity must not be zero").with_field("quantity"));
+    }
+
+    let user_id = perm.user_id;
+    let quantity_change = adjustment.quantity;
+    let stock = conn.run(move |c| {
+        c.transaction(|c| {
+            let performed_by = Some(user_id);
+            let adjustment = adjustment.into_inner();
+            record_movement(c, NewStockMovement {
+                warehouse_id: adjustment.warehouse_id,
+                material_id: adjustment.material_id,
+                movement_type: ADJUSTMENT.to_string(),
+                quantity: adjustment.quantity,
+                request_id: None,
+                task_id: None,
+                performed_by,
+                note: adjustment.note,
+            })
+        })
+    }).await
+    .map_err(ApiError::from)?;
+
+    events.emit(DomainEvent::StockAdjusted {
+        movement_type: ADJUSTMENT.to_string(),
+        quantity_change,
+        stock: stock.clone(),
+    }).await;
+    Ok(Json(stock))
+}
+
+// 同一节点内的仓库间调拨，两条流水在同一事务内写入
+#[post("/stock_movements/transfer", data = "<transfer>")]
+pub async fn transfer_stock(
+    conn: DbConn,
+    perm: RequirePermission<StockWrite>,
+    events: &State<EventSender>,
+    transfer: Json<NewTransfer>
+) -> Result<Json<Vec<WarehouseStock>>, ApiError> {
+    if transfer.quantity <= 0 {
+        return Err(ApiError::bad_request("quantity must be positive").with_field("quantity"));
+    }
+    if transfer.from_warehouse_id == transfer.to_warehouse_id {
+        return Err(ApiError::bad_request("source and destination warehouse must differ").with_field("to_warehouse_id"));
+    }
+
+    let user_id = perm.user_id;
+    let quantity = transfer.quantity;
+    let (source, destination) = conn.run(move |c| {
+        c.transaction(|c| {
+            let performed_by = Some(user_id);
+            let transfer = transfer.into_inner();
+            let source = record_movement(c, NewStockMovement {
+                warehouse_id: transfer.from_warehouse_id,
+                material_id: transfer.material_id,
+                movement_type: TRANSFER_OUT.to_string(),
+                quantity: -transfer.quantity,
+                request_id: None,
+                task_id: None,
+                performed_by,
+                note: transfer.note.clone(),
+            })?;
+            let destination = record_movement(c, NewStockMovement {
+                warehouse_id: transfer.to_warehouse_id,
+                material_id: transfer.material_id,
+                movement_type: TRANSFER_IN.to_string(),
+                quantity: transfer.quantity,
+                request_id: None,
+                task_id: None,
+                performed_by,
+                note: transfer.note,
+            })?;
+            Ok::<_, StockError>((source, destination))
+        })
+    }).await
+    .map_err(ApiError::from)?;
+
+    events.emit(DomainEvent::StockAdjusted {
+        movement_type: TRANSFER_OUT.to_string(),
+        quantity_change: -quantity,
+        stock: source.clone(),
+    }).await;
+    events.emit(DomainEvent::StockAdjusted {
+        movement_type: TRANSFER_IN.to_string(),
+        quantity_change: quantity,
+        stock: destination.clone(),
+    }).await;
+    Ok(Json(vec![source, destination]))
+}
+
+// 从流水重算任意时刻的库存余额，不修改数据
+#[get("/stock_movements/balances?<as_of>&<warehouse_id>&<material_id>")]
+pub async fn get_balances_as_of(
+    conn: DbConn,
+    _perm: RequirePermission<StockRead>,
+    as_of: Option<String>,
+    warehouse_id: Option<i32>,
+    material_id: Option<i32>
+) -> Result<Json<Vec<StockBalance>>, ApiError> {
+    let as_of = match as_of {
+        Some(value) => parse_as_of(&value).ok_or_else(|| ApiError::bad_request("expected YYYY-MM-DD or YYYY-MM-DDTHH:MM:SS").with_field("as_of"))?,
+        None => Utc::now().naive_utc(),
+    };
+
+    conn.run(move |c| {
+        let mut query_builder = stock_movements::table
+            .filter(stock_movements::created_at.le(as_of))
+            .group_by((stock_movements::warehouse_id, stock_movements::material_id))
+            .select((
+                stock_movements::warehouse_id,
+                stock_movements::material_id,
+                diesel::dsl::sum(stock_movements::quantity),
+            ))
+            .into_boxed();
+
+        if let Some(wid) = warehouse_id {
+            query_builder = query_builder.filter(
+                stock_movements::warehouse_id.eq(wid)
+            );
+        }
+
+        if let Some(mid) = material_id {
+            query_builder = query_builder.filter(
+                stock_movements::material_id.eq(mid)
+            );
+        }
+
+        query_builder
+            .order((stock_movements::warehouse_id.asc(), stock_movements::material_id.asc()))
+            .load::<(i32, i32, Option<i64>)>(c)
+    }).await
+    .map(|rows| {
+        Json(rows
+            .into_iter()
+            .map(|(warehouse_id, material_id, quantity)| StockBalance {
+                warehouse_id,
+                material_id,
+                quantity: quantity.unwrap_or(0),
+                as_of,
+            })
+            .collect())
+    })
+    .map_err(ApiError::from)
+}
+
+// 丢弃 warehouse_stock 并根据流水重建
+#[post("/stock_movements/rebuild")]
+pub async fn rebuild_stock(conn: DbConn, _perm: RequirePermission<StockWrite>) -> Result<Json<Vec<WarehouseStock>>, ApiError> {
+    conn.run(|c| {
+        c.transaction(|c| {
+            let totals = stock_movements::table
+                .group_by((stock_movements::warehouse_id, stock_movements::material_id))
+                .select((
+                    stock_movements::warehouse_id,
+                    stock_movements::material_id,
+                    diesel::dsl::sum(stock_movements::quantity),
+                    diesel::dsl::max(stock_movements::created_at),
+                ))
+                .load::<(i32, i32, Option<i64>, Option<NaiveDateTime>)>(c)?;
+
+            diesel::delete(warehouse_stock::table).execute(c)?;
+
+            for (warehouse_id, material_id, quantity, last_updated) in totals {
+                diesel::insert_into(warehouse_stock::table)
+                    .values((
+                        warehouse_stock::warehouse_id.eq(warehouse_id),
+                        warehouse_stock::material_id.eq(material_id),
+                        warehouse_stock::quantity.eq(to_quantity(quantity.unwrap_or(0))?),
+                        warehouse_stock::last_updated.eq(last_updated),
+                    ))
+                    .execute(c)?;
+            }
+
+            let warehouse_ids = warehouses::table
+                .select(warehouses::warehouse_id)
+                .load::<i32>(c)?;
+            for warehouse_id in warehouse_ids {
+                sync_warehouse_total(c, warehouse_id)?;
+            }
+
+            let stock = warehouse_stock::table
+                .select(WarehouseStock::as_select())
+                .order((warehouse_stock::warehouse_id.asc(), warehouse_stock::material_id.asc()))
+                .load(c)?;
+            Ok::<_, StockError>(stock)
+        })
+    }).await
+    .map(Json)
+    .map_err(ApiError::from)
+}
@@ -0,0 +1,364 @@
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use rocket::serde::json::Json;
+use rocket::{get, post};
+use chrono::Utc;
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+
+use crate::error::ApiError;
+use crate::models::{NewStockMovement, NewStockTransfer, StockTransfer, DbConn};
+use crate::schema::{materials, stock_transfers, warehouse_stock};
+use crate::routers::stock_movement::{self, record_movement};
+use crate::routers::warehouse_stock::StockError;
+use crate::auth_guard::{RequirePermission, StockRead, StockWrite};
+use crate::transfer_protocol::{ACCEPTED, CANCELLED, INBOUND, IN_TRANSIT, OUTBOUND, RECEIVED, REQUESTED};
+use crate::message_auth;
+use crate::replication;
+use crate::warehouse;
+
+// 向其他节点发起调拨
+#[derive(Debug, Deserialize)]
+pub struct NewTransferRequest {
+    pub destination_peer: String,
+    pub material_id: i32,
+    pub quantity: i32,
+    pub note: Option<String>,
+}
+
+// 在途数量汇总
+#[derive(Debug, Serialize)]
+pub struct InTransitQuantity {
+    pub direction: String,
+    pub material_id: i32,
+    pub quantity: i64,
+}
+
+// 调拨流程错误
+#[derive(Debug)]
+pub enum TransferError {
+    NotFound,
+    InvalidTransition,
+    InsufficientStock,
+    QuantityOverflow,
+    Database(diesel::result::Error),
+}
+
+impl From<diesel::result::Error> for TransferError {
+    fn from(err: diesel::result::Error) -> Self {
+        match err {
+            diesel::result::Error::NotFound => TransferError::NotFound,
+            other => TransferError::Database(other),
+        }
+    }
+}
+
+impl From<StockError> for TransferError {
+    fn from(err: StockError) -> Self {
+        match err {
+            StockError::NotFound => TransferError::NotFound,
+            StockError::InsufficientStock => TransferError::InsufficientStock,
+            StockError::QuantityOverflow => TransferError::QuantityOverflow,
+            StockError::Database(e) => TransferError::Database(e),
+        }
+    }
+}
+
+impl From<TransferError> for ApiError {
+    fn from(err: TransferError) -> Self {
+        match err {
+            TransferError::NotFound => ApiError::not_found("stock transfer not found"),
+            TransferError::InvalidTransition => {
+                ApiError::conflict("invalid_transition", "transfer cannot change to this status").with_field("status")
+            }
+            TransferError::InsufficientStock => {
+                ApiError::conflict("insufficient_stock", "not enough stock").with_field("quantity")
+            }
+            TransferError::QuantityOverflow => StockError::QuantityOverflow.into(),
+            TransferError::Database(e) => e.into(),
+        }
+    }
+}
+
+fn load_transfer(c: &mut SqliteConnection, transfer_id: &str, direction: &str) -> Result<StockTransfer, TransferError> {
+    let transfer = stock_transfers::table
+        .filter(stock_transfers::transfer_id.eq(transfer_id))
+        .filter(stock_transfers::direction.eq(direction))
+        .select(StockTransfer::as_select())
+        .first(c)?;
+    Ok(transfer)
+}
+
+// 本地状态变更，acked 置为 false 由网络任务通知对端
+fn advance(c: &mut SqliteConnection, transfer_id: &str, from: &[&str], to: &str, acked: bool) -> Result<StockTransfer, TransferError> {
+    let updated = diesel::update(
+        stock_transfers::table
+            .filter(stock_transfers::transfer_id.eq(transfer_id))
+            .filter(stock_transfers::status.eq_any(from))
+    )
+    .set((
+        stock_transfers::status.eq(to),
+        stock_transfers::acked.eq(acked),
+        stock_transfers::attempts.eq(0),
+        stock_transfers::last_attempt_at.eq(None::<chrono::NaiveDateTime>),
+        stock_transfers::updated_at.eq(Utc::now().naive_utc()),
+    ))
+    .execute(c)?;
+
+    if updated == 0 {
+        return Err(TransferError::InvalidTransition);
+    }
+
+    let transfer = stock_transfers::table
+        .filter(stock_transfers::transfer_id.eq(transfer_id))
+        .select(StockTransfer::as_select())
+        .first(c)?;
+    Ok(transfer)
+}
+
+// 搜索调拨单
+#[get("/stock_transfers?<direction>&<status>&<material_id>&<peer_id>")]
+pub async fn search_stock_transfers(
+    conn: DbConn,
+    _perm: RequirePermission<StockRead>,
+    direction: Option<String>,
+    status: Option<String>,
+    material_id: Option<i32>,
+    peer_id: Option<String>
+) -> Result<Json<Vec<StockTransfer>>, ApiError> {
+    conn.run(move |c| {
+        let mut query_builder = stock_transfers::table
+            .into_boxed();
+
+        if let Some(d) = direction {
+            query_builder = query_builder.filter(
+                stock_transfers::direction.eq(d)
+            );
+        }
+
+        if let Some(s) = status {
+            query_builder = query_builder.filter(
+                stock_transfers::status.eq(s)
+            );
+        }
+
+        if let Some(mid) = material_id {
+            query_builder = query_builder.filter(
+                stock_transfers::material_id.eq(mid)
+            );
+        }
+
+        if let Some(p) = peer_id {
+            query_builder = query_builder.filter(
+                stock_transfers::peer_id.eq(p)
+            );
+        }
+
+        query_builder
+            .order(stock_transfers::created_at.desc())
+            .select(StockTransfer::as_select())
+            .load(c)
+    }).await
+    .map(Json)
+    .map_err(ApiError::from)
+}
+
+// 在途数量：调出方已发货、调入方尚未收货的调拨
+#[get("/stock_transfers/in_transit")]
+pub async fn get_in_transit(conn: DbConn, _perm: RequirePermission<StockRead>) -> Result<Json<Vec<InTransitQuantity>>, ApiError> {
+    conn.run(|c| {
+        stock_transfers::table
+            .filter(stock_transfers::status.eq(IN_TRANSIT))
+            .group_by((stock_transfers::direction, stock_transfers::material_id))
+            .select((
+                stock_transfers::direction,
+                stock_transfers::material_id,
+                diesel::dsl::sum(stock_transfers::quantity),
+            ))
+            .load::<(String, i32, Option<i64>)>(c)
+    }).await
+    .map(|rows| {
+        Json(rows
+            .into_iter()
+            .map(|(direction, material_id, quantity)| InTransitQuantity {
+                direction,
+                material_id,
+                quantity: quantity.unwrap_or(0),
+            })
+            .collect())
+    })
+    .map_err(ApiError::from)
+}
+
+#[get("/stock_transfers/<transfer_id>")]
+pub async fn get_stock_transfer(
+    conn: DbConn,
+    _perm: RequirePermission<StockRead>,
+    transfer_id: String
+) -> Result<Json<StockTransfer>, ApiError> {
+    conn.run(move |c| {
+        stock_transfers::table
+            .filter(stock_transfers::transfer_id.eq(transfer_id))
+            .select(StockTransfer::as_select())
+            .first(c)
+    }).await
+    .map(Json)
+    .map_err(ApiError::from)
+}
+
+// 发起调拨，对端接受后才能发货；此时只检查库存，不扣减
+#[post("/stock_transfers", data = "<request>")]
+pub async fn create_stock_transfer(
+    conn: DbConn,
+    perm: RequirePermission<StockWrite>,
+    request: Json<NewTransferRequest>
+) -> Result<Json<StockTransfer>, ApiError> {
+    let request = request.into_inner();
+    if request.quantity <= 0 {
+        return Err(ApiError::bad_request("quantity must be positive").with_field("quantity"));
+    }
+    let destination = request.destination_peer.parse::<PeerId>().map_err(|_| ApiError::bad_request("invalid peer id").with_field("destination_peer"))?;
+
+    let user_id = perm.user_id;
+    conn.run(move |c| {
+        if replication::local_peer_id(c)? == request.destination_peer {
+            return Err(TransferError::InvalidTransition);
+        }
+
+        // 对端不受信任时不会处理我们的请求
+        if !message_auth::is_trusted(c, &destination)? {
+            return Err(TransferError::InvalidTransition);
+        }
+
+        // 对端按 global_id 识别材料
+        let global_id = materials::table
+            .filter(materials::material_id.eq(request.material_id))
+            .filter(materials::deleted_at.is_null())
+            .select(materials::global_id)
+            .first::<Option<String>>(c)?;
+        if global_id.is_none() {
+            return Err(TransferError::InvalidTransition);
+        }
+
+        let warehouse_id = warehouse::this_warehouse_id(c)?;
+        let available = warehouse_stock::table
+            .filter(warehouse_stock::warehouse_id.eq(warehouse_id))
+            .filter(warehouse_stock::material_id.eq(request.material_id))
+            .select(warehouse_stock::quantity)
+            .first::<Option<i32>>(c)
+            .optional()?
+            .flatten()
+            .unwrap_or(0);
+        if available < request.quantity {
+            return Err(TransferError::InsufficientStock);
+        }
+
+        let transfer_id = replication::new_global_id();
+        let now = Utc::now().naive_utc();
+        diesel::insert_into(stock_transfers::table)
+            .values(NewStockTransfer {
+                transfer_id: transfer_id.clone(),
+                direction: OUTBOUND.to_string(),
+                peer_id: request.destination_peer,
+                warehouse_id,
+                material_id: request.material_id,
+                quantity: request.quantity,
+                status: REQUESTED.to_string(),
+                acked: false,
+                rejection_reason: None,
+                note: request.note,
+                requested_by: Some(user_id),
+                created_at: now,
+                updated_at: now,
+            })
+            .execute(c)?;
+
+        load_transfer(c, &transfer_id, OUTBOUND)
+    }).await
+    .map(Json)
+    .map_err(ApiError::from)
+}
+
+// 调出方发货：扣减本地库存，调拨进入在途状态
+#[post("/stock_transfers/<transfer_id>/ship")]
+pub async fn ship_stock_transfer(
+    conn: DbConn,
+    perm: RequirePermission<StockWrite>,
+    transfer_id: String
+) -> Result<Json<StockTransfer>, ApiError> {
+    let user_id = perm.user_id;
+    conn.run(move |c| {
+        c.transaction(|c| {
+            let transfer = load_transfer(c, &transfer_id, OUTBOUND)?;
+            if transfer.status != ACCEPTED {
+                return Err(TransferError::InvalidTransition);
+            }
+
+            record_movement(c, NewStockMovement {
+                warehouse_id: transfer.warehouse_id,
+                material_id: transfer.material_id,
+                movement_type: stock_movement::TRANSFER_OUT.to_string(),
+                quantity: -transfer.quantity,
+                request_id: None,
+                task_id: None,
+                performed_by: Some(user_id),
+                note: Some(format!("stock transfer {} to {}", transfer_id, transfer.peer_id)),
+            })?;
+
+            advance(c, &transfer_id, &[ACCEPTED], IN_TRANSIT, false)
+        })
+    }).await
+    .map(Json)
+    .map_err(ApiError::from)
+}
+
+// 调入方收货：增加本地库存并通知调出方
+#[post("/stock_transfers/<transfer_id>/receive")]
+pub async fn receive_stock_transfer(
+    conn: DbConn,
+    perm: RequirePermission<StockWrite>,
+    transfer_id: String
+) -> Result<Json<StockTransfer>, ApiError> {
+    let user_id = perm.user_id;
+    conn.run(move |c| {
+        c.transaction(|c| {
+            let transfer = load_transfer(c, &transfer_id, INBOUND)?;
+            if transfer.status != IN_TRANSIT {
+                return Err(TransferError::InvalidTransition);
+            }
+
+            record_movement(c, NewStockMovement {
+                warehouse_id: transfer.warehouse_id,
+                material_id: transfer.material_id,
+                movement_type: stock_movement::TRANSFER_IN.to_string(),
+                quantity: transfer.quantity,
+                request_id: None,
+                task_id: None,
+                performed_by: Some(user_id),
+                note: Some(format!("stock transfer {} from {}", transfer_id, transfer.peer_id)),
+            })?;
+
+            advance(c, &transfer_id, &[IN_TRANSIT], RECEIVED, false)
+        })
+    }).await
+    .map(Json)
+    .map_err(ApiError::from)
+}
+
+// 调出方在发货前取消；请求尚未发出时无需通知对端
+#[post("/stock_transfers/<transfer_id>/cancel")]
+pub async fn cancel_stock_transfer(
+    conn: DbConn,
+    _perm: RequirePermission<StockWrite>,
+    transfer_id: String
+) -> Result<Json<StockTransfer>, ApiError> {
+    conn.run(move |c| {
+        c.transaction(|c| {
+            let transfer = load_transfer(c, &transfer_id, OUTBOUND)?;
+            let never_sent = transfer.status == REQUESTED && transfer.attempts == 0;
+            advance(c, &transfer_id, &[REQUESTED, ACCEPTED], CANCELLED, never_sent)
+        })
+    }).await
+    .map(Json)
+    .map_err(ApiError::from)
+}
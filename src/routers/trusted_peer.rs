@@ -0,0 +1,80 @@
+use diesel::prelude::*;
+use rocket::serde::json::Json;
+use rocket::http::Status;
+use rocket::{delete, get, post};
+use libp2p::PeerId;
+use serde::Deserialize;
+
+use crate::error::ApiError;
+use crate::models::{NewTrustedPeer, TrustedPeer, DbConn};
+use crate::schema::trusted_peers;
+use crate::auth_guard::{RequirePermission, NetworkRead, NetworkWrite};
+
+// 添加受信任节点
+#[derive(Debug, Deserialize)]
+pub struct TrustPeerRequest {
+    pub peer_id: String,
+    pub label: Option<String>,
+}
+
+#[get("/trusted_peers")]
+pub async fn list_trusted_peers(
+    conn: DbConn,
+    _perm: RequirePermission<NetworkRead>,
+) -> Result<Json<Vec<TrustedPeer>>, ApiError> {
+    conn.run(|c| {
+        trusted_peers::table
+            .order(trusted_peers::created_at.asc())
+            .select(TrustedPeer::as_select())
+            .load(c)
+    }).await
+    .map(Json)
+    .map_err(ApiError::from)
+}
+
+// 信任后该节点的签名消息和调拨请求才会被处理
+#[post("/trusted_peers", data = "<peer>")]
+pub async fn add_trusted_peer(
+    conn: DbConn,
+    perm: RequirePermission<NetworkWrite>,
+    peer: Json<TrustPeerRequest>,
+) -> Result<Json<TrustedPeer>, ApiError> {
+    let peer = peer.into_inner();
+    // 只接受合法的 PeerId，统一保存其规范字符串形式
+    let peer_id = peer.peer_id.parse::<PeerId>().map_err(|_| ApiError::bad_request("invalid peer id").with_field("peer_id"))?.to_string();
+    let user_id = perm.user_id;
+
+    conn.run(move |c| {
+        diesel::insert_or_ignore_into(trusted_peers::table)
+            .values(NewTrustedPeer {
+                peer_id: peer_id.clone(),
+                label: peer.label,
+                added_by: Some(user_id),
+            })
+            .execute(c)?;
+        trusted_peers::table
+            .filter(trusted_peers::peer_id.eq(peer_id))
+            .select(TrustedPeer::as_select())
+            .first(c)
+    }).await
+    .map(Json)
+    .map_err(ApiError::from)
+}
+
+#[delete("/trusted_peers/<peer_id>")]
+pub async fn remove_trusted_peer(
+    conn: DbConn,
+    _perm: RequirePermission<NetworkWrite>,
+    peer_id: String,
+) -> Result<Status, ApiError> {
+    let result = conn.run(move |c| {
+        diesel::delete(trusted_peers::table.filter(trusted_peers::peer_id.eq(peer_id)))
+            .execute(c)
+    }).await;
+
+    match result {
+        Ok(0) => Err(ApiError::not_found("trusted peer not found")),
+        Ok(_) => Ok(Status::NoContent),
+        Err(e) => Err(e.into())
+    }
+}
@@ -2,14 +2,16 @@ use rocket::serde::json::Json;
 use rocket::http::Status;
 use rocket::{get, post, put, delete}; 
 use diesel::prelude::*;
+use crate::error::ApiError;
 use crate::models::{User, NewUser, DbConn};
 use crate::schema::users;
-use crate::token;
+use crate::auth_guard::{RequirePermission, UserRead, UserWrite};
+use crate::routers::dependents::{delete_checked, DeleteError};
 use serde::Deserialize;
 use rocket_dyn_templates::serde::Serialize;
 
 #[get("/users")]
-pub async fn get_users(conn: DbConn) -> Result<Json<Vec<User>>, Status> {
+pub async fn get_users(conn: DbConn, _perm: RequirePermission<UserRead>) -> Result<Json<Vec<User>>, ApiError> {
     let users = conn.run(|c| {
         users::table
             .select(User::as_select())
@@ -18,12 +20,12 @@ pub async fn get_users(conn: DbConn) -> Result<Json<Vec<User>>, Status> {
 
     match users {
         Ok(users_list) => Ok(Json(users_list)),
-        Err(_) => Err(Status::InternalServerError)
+        Err(e) => Err(e.into())
     }
 }
 
 #[get("/user/<user_id>")]
-pub async fn get_user(conn: DbConn, user_id: i32) -> Result<Json<User>, Status> {
+pub async fn get_user(conn: DbConn, _perm: RequirePermission<UserRead>, user_id: i32) -> Result<Json<User>, ApiError> {
     let user = conn.run(move |c| {
         users::table
             .find(user_id)
@@ -33,7 +35,7 @@ pub async fn get_user(conn: DbConn, user_id: i32) -> Result<Json<User>, Status>
 
     match user {
         Ok(user_data) => Ok(Json(user_data)),
-        Err(_) => Err(Status::NotFound)
+        Err(e) => Err(e.into())
     }
 }
 
@@ -41,13 +43,8 @@ pub async fn get_user(conn: DbConn, user_id: i32) -> Result<Json<User>, Status>
 pub async fn create_user(
     conn: DbConn,
     user: Json<NewUser>,
-    token: String,
-) -> Result<Status, Status> {
-    // Verify token
-    if token::decode_token(&token).is_none() {
-        return Err(Status::Unauthorized);
-    }
-
+    _perm: RequirePermission<UserWrite>,
+) -> Result<Status, ApiError> {
     let result = conn.run(move |c| {
         diesel::insert_into(users::table)
             .values(&user.into_inner())
@@ -56,7 +53,7 @@ pub async fn create_user(
 
     match result {
         Ok(_) => Ok(Status::Created),
-        Err(_) => Err(Status::InternalServerError)
+        Err(e) => Err(e.into())
     }
 }
 #[derive(Debug, Serialize, Deserialize, AsChangeset)]
@@ -73,13 +70,8 @@ pub async fn update_user(
     conn: DbConn,
     user_id: i32,
     user: Json<UpdateUser>,
-    token: String,
-) -> Result<Status, Status> {
-    // Verify token
-    if token::decode_token(&token).is_none() {
-        return Err(Status::Unauthorized);
-    }
-
+    _perm: RequirePermission<UserWrite>,
+) -> Result<Status, ApiError> {
     let result = conn.run(move |c| {
         diesel::update(users::table.find(user_id))
             .set(&user.into_inner())
@@ -88,8 +80,8 @@ pub async fn update_user(
 
     match result {
         Ok(count) if count > 0 => Ok(Status::Ok),
-        Ok(_) => Err(Status::NotFound),
-        Err(_) => Err(Status::InternalServerError)
+        Ok(_) => Err(ApiError::not_found("user not found")),
+        Err(e) => Err(e.into())
     }
 }
 
@@ -97,21 +89,14 @@ pub async fn update_user(
 pub async fn delete_user(
     conn: DbConn,
     user_id: i32,
-    token: String,
-) -> Result<Status, Status> {
-    // Verify token
-    if token::decode_token(&token).is_none() {
-        return Err(Status::Unauthorized);
-    }
-
-    let result = conn.run(move |c| {
-        diesel::delete(users::table.find(user_id))
-            .execute(c)
-    }).await;
-
-    match result {
-        Ok(count) if count > 0 => Ok(Status::Ok),
-        Ok(_) => Err(Status::NotFound),
-        Err(_) => Err(Status::InternalServerError)
-    }
+    _perm: RequirePermission<UserWrite>,
+) -> Result<Status, DeleteError> {
+    // 角色分配和刷新令牌随用户级联删除；有业务记录的用户应改为停用
+    conn.run(move |c| {
+        delete_checked(c, "users", user_id, |c| {
+            diesel::delete(users::table.filter(users::user_id.eq(user_id)))
+                .execute(c)
+        })
+    }).await?;
+    Ok(Status::Ok)
 }
@@ -3,23 +3,24 @@ use rocket::serde::json::Json;
 use rocket::http::Status;
 use rocket::{get, post, delete};
 
+use crate::error::ApiError;
 use crate::models::{UserRole, NewUserRole, DbConn};
 use crate::schema::user_roles;
-use crate::token::TokenGuard;
+use crate::auth_guard::{RequirePermission, UserRoleRead, UserRoleWrite};
 
 #[get("/user_roles")]
-pub async fn list_user_roles(conn: DbConn, _token: TokenGuard) -> Result<Json<Vec<UserRole>>, Status> {
+pub async fn list_user_roles(conn: DbConn, _perm: RequirePermission<UserRoleRead>) -> Result<Json<Vec<UserRole>>, ApiError> {
     conn.run(|c| {
         user_roles::table
             .select(UserRole::as_select())
             .load(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
 
 #[get("/user_roles/by_user/<user_id>")]
-pub async fn get_user_roles(conn: DbConn, _token: TokenGuard, user_id: i32) -> Result<Json<Vec<UserRole>>, Status> {
+pub async fn get_user_roles(conn: DbConn, _perm: RequirePermission<UserRoleRead>, user_id: i32) -> Result<Json<Vec<UserRole>>, ApiError> {
     conn.run(move |c| {
         user_roles::table
             .filter(user_roles::user_id.eq(user_id))
@@ -27,11 +28,11 @@ pub async fn get_user_roles(conn: DbConn, _token: TokenGuard, user_id: i32) -> R
             .load(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
 
 #[get("/user_roles/by_role/<role_id>")]
-pub async fn get_role_users(conn: DbConn, _token: TokenGuard, role_id: i32) -> Result<Json<Vec<UserRole>>, Status> {
+pub async fn get_role_users(conn: DbConn, _perm: RequirePermission<UserRoleRead>, role_id: i32) -> Result<Json<Vec<UserRole>>, ApiError> {
     conn.run(move |c| {
         user_roles::table
             .filter(user_roles::role_id.eq(role_id))
@@ -39,24 +40,23 @@ pub async fn get_role_users(conn: DbConn, _token: TokenGuard, role_id: i32) -> R
             .load(c)
     }).await
     .map(Json)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
 
 #[post("/user_roles", data = "<user_role>")]
-pub async fn create_user_role(conn: DbConn, _token: TokenGuard, user_role: Json<NewUserRole>) -> Result<Status, Status> {
+pub async fn create_user_role(conn: DbConn, _perm: RequirePermission<UserRoleWrite>, user_role: Json<NewUserRole>) -> Result<Status, ApiError> {
     // 首先检查是否已存在相同的用户角色关联
+    let (user_id, role_id) = (user_role.user_id, user_role.role_id);
     let exists = conn.run(move |c| {
         user_roles::table
-            .filter(user_roles::user_id.eq(user_role.user_id))
-            .filter(user_roles::role_id.eq(user_role.role_id))
+            .filter(user_roles::user_id.eq(user_id))
+            .filter(user_roles::role_id.eq(role_id))
             .count()
             .get_result::<i64>(c)
     }).await;
 
     match exists {
-        Ok(count) if count > 0 => {
-            return Err(Status::Conflict); // 返回409表示已存在
-        }
+        Ok(count) if count > 0 => Err(ApiError::conflict("already_assigned", "role is already assigned to the user")),
         Ok(_) => {
             // 不存在，继续创建
             conn.run(move |c| {
@@ -65,14 +65,14 @@ pub async fn create_user_role(conn: DbConn, _token: TokenGuard, user_role: Json<
                     .execute(c)
             }).await
             .map(|_| Status::Created)
-            .map_err(|_| Status::InternalServerError)
+            .map_err(ApiError::from)
         }
-        Err(_) => Err(Status::InternalServerError)
+        Err(e) => Err(e.into())
     }
 }
 
 #[delete("/user_roles/<user_id>/<role_id>")]
-pub async fn delete_user_role(conn: DbConn, _token: TokenGuard, user_id: i32, role_id: i32) -> Result<Status, Status> {
+pub async fn delete_user_role(conn: DbConn, _perm: RequirePermission<UserRoleWrite>, user_id: i32, role_id: i32) -> Result<Status, ApiError> {
     conn.run(move |c| {
         diesel::delete(
             user_roles::table
@@ -81,24 +81,24 @@ pub async fn delete_user_role(conn: DbConn, _token: TokenGuard, user_id: i32, ro
         )
         .execute(c)
     }).await
-    .map(|affected| {
+    .map_err(ApiError::from)
+    .and_then(|affected| {
         if affected > 0 {
-            Status::NoContent
+            Ok(Status::NoContent)
         } else {
-            Status::NotFound
+            Err(ApiError::not_found("user role not found"))
         }
     })
-    .map_err(|_| Status::InternalServerError)
 }
 
 // 批量设置用户角色（替换现有的所有角色）
 #[post("/user_roles/batch/<user_id>", data = "<role_ids>")]
 pub async fn set_user_roles(
     conn: DbConn,
-    _token: TokenGuard,
+    _perm: RequirePermission<UserRoleWrite>,
     user_id: i32,
     role_ids: Json<Vec<i32>>
-) -> Result<Status, Status> {
+) -> Result<Status, ApiError> {
     conn.run(move |c| {
         c.transaction(|c| {
             // 删除用户现有的所有角色
@@ -124,5 +124,5 @@ pub async fn set_user_roles(
         })
     }).await
     .map(|_| Status::Ok)
-    .map_err(|_| Status::InternalServerError)
+    .map_err(ApiError::from)
 }
@@ -2,40 +2,35 @@ use rocket::serde::json::Json;
 use rocket::http::Status;
 use rocket::{get, post, put, delete}; 
 use diesel::prelude::*;
-use chrono::Utc;
+use chrono::{NaiveDateTime, Utc};
+use crate::error::ApiError;
 use crate::models::{Warehouse, NewWarehouse, DbConn};
 use crate::schema::warehouses;
-use crate::token;
+use crate::auth_guard::{RequirePermission, WarehouseRead, WarehouseWrite};
 use serde::Deserialize;
 
-// Get all warehouses
-#[get("/warehouses")]
-pub async fn get_warehouses(conn: DbConn, token: String) -> Result<Json<Vec<Warehouse>>, Status> {
-    // Verify token
-    if token::decode_token(&token).is_none() {
-        return Err(Status::Unauthorized);
-    }
-
-    let warehouses = conn.run(|c| {
-        warehouses::table
+// Get all warehouses, deleted ones only with include_deleted=true
+#[get("/warehouses?<include_deleted>")]
+pub async fn get_warehouses(conn: DbConn, _perm: RequirePermission<WarehouseRead>, include_deleted: Option<bool>) -> Result<Json<Vec<Warehouse>>, ApiError> {
+    let warehouses = conn.run(move |c| {
+        let mut query = warehouses::table.into_boxed();
+        if !include_deleted.unwrap_or(false) {
+            query = query.filter(warehouses::deleted_at.is_null());
+        }
+        query
             .select(Warehouse::as_select())
             .load(c)
     }).await;
 
     match warehouses {
         Ok(warehouse_list) => Ok(Json(warehouse_list)),
-        Err(_) => Err(Status::InternalServerError)
+        Err(e) => Err(e.into())
     }
 }
 
 // Get single warehouse by ID
 #[get("/warehouse/<warehouse_id>")]
-pub async fn get_warehouse(conn: DbConn, warehouse_id: i32, token: String) -> Result<Json<Warehouse>, Status> {
-    // Verify token
-    if token::decode_token(&token).is_none() {
-        return Err(Status::Unauthorized);
-    }
-
+pub async fn get_warehouse(conn: DbConn, warehouse_id: i32, _perm: RequirePermission<WarehouseRead>) -> Result<Json<Warehouse>, ApiError> {
     let warehouse = conn.run(move |c| {
         warehouses::table
             .find(warehouse_id)
@@ -45,7 +40,7 @@ pub async fn get_warehouse(conn: DbConn, warehouse_id: i32, token: String) -> Re
 
     match warehouse {
         Ok(warehouse_data) => Ok(Json(warehouse_data)),
-        Err(_) => Err(Status::NotFound)
+        Err(e) => Err(e.into())
     }
 }
 
@@ -54,13 +49,8 @@ pub async fn get_warehouse(conn: DbConn, warehouse_id: i32, token: String) -> Re
 pub async fn create_warehouse(
     conn: DbConn,
     warehouse: Json<NewWarehouse>,
-    token: String,
-) -> Result<Status, Status> {
-    // Verify token
-    if token::decode_token(&token).is_none() {
-        return Err(Status::Unauthorized);
-    }
-
+    _perm: RequirePermission<WarehouseWrite>,
+) -> Result<Status, ApiError> {
     let result = conn.run(move |c| {
         diesel::insert_into(warehouses::table)
             .values(&*warehouse)
@@ -69,7 +59,7 @@ pub async fn create_warehouse(
 
     match result {
         Ok(_) => Ok(Status::Created),
-        Err(_) => Err(Status::InternalServerError)
+        Err(e) => Err(e.into())
     }
 }
 
@@ -89,15 +79,14 @@ pub async fn update_warehouse(
     conn: DbConn,
     warehouse_id: i32,
     warehouse: Json<UpdateWarehouse>,
-    token: String,
-) -> Result<Status, Status> {
-    // Verify token
-    if token::decode_token(&token).is_none() {
-        return Err(Status::Unauthorized);
-    }
-
+    _perm: RequirePermission<WarehouseWrite>,
+) -> Result<Status, ApiError> {
     let result = conn.run(move |c| {
-        diesel::update(warehouses::table.find(warehouse_id))
+        diesel::update(
+            warehouses::table
+                .filter(warehouses::warehouse_id.eq(warehouse_id))
+                .filter(warehouses::deleted_at.is_null())
+        )
             .set((
                 &*warehouse,
                 warehouses::last_updated.eq(Some(Utc::now().naive_utc()))
@@ -107,31 +96,70 @@ pub async fn update_warehouse(
 
     match result {
         Ok(rows) if rows > 0 => Ok(Status::Ok),
-        Ok(_) => Err(Status::NotFound),
-        Err(_) => Err(Status::InternalServerError)
+        Ok(_) => Err(ApiError::not_found("warehouse not found")),
+        Err(e) => Err(e.into())
     }
 }
 
-// Delete warehouse
+// Soft delete warehouse, stock and requests keep referencing it until it is purged
 #[delete("/warehouse/<warehouse_id>")]
 pub async fn delete_warehouse(
     conn: DbConn,
     warehouse_id: i32,
-    token: String,
-) -> Result<Status, Status> {
-    // Verify token
-    if token::decode_token(&token).is_none() {
-        return Err(Status::Unauthorized);
+    perm: RequirePermission<WarehouseWrite>,
+) -> Result<Status, ApiError> {
+    let user_id = perm.user_id;
+    let result = conn.run(move |c| {
+        diesel::update(
+            warehouses::table
+                .filter(warehouses::warehouse_id.eq(warehouse_id))
+                .filter(warehouses::deleted_at.is_null())
+                // 本节点仓库保存节点密钥，不能删除
+                .filter(warehouses::warehouse_name.ne("ThisWarehouse"))
+        )
+        .set((
+            warehouses::deleted_at.eq(Utc::now().naive_utc()),
+            warehouses::deleted_by.eq(user_id),
+        ))
+        .execute(c)
+    }).await;
+
+    match result {
+        Ok(rows) if rows > 0 => Ok(Status::NoContent),
+        Ok(_) => Err(ApiError::not_found("warehouse not found")),
+        Err(e) => Err(e.into())
     }
+}
 
+// Restore soft-deleted warehouse
+#[post("/warehouse/<warehouse_id>/restore")]
+pub async fn restore_warehouse(
+    conn: DbConn,
+    warehouse_id: i32,
+    _perm: RequirePermission<WarehouseWrite>,
+) -> Result<Json<Warehouse>, ApiError> {
     let result = conn.run(move |c| {
-        diesel::delete(warehouses::table.find(warehouse_id))
-            .execute(c)
+        let rows = diesel::update(
+            warehouses::table
+                .filter(warehouses::warehouse_id.eq(warehouse_id))
+                .filter(warehouses::deleted_at.is_not_null())
+        )
+        .set((
+            warehouses::deleted_at.eq(None::<NaiveDateTime>),
+            warehouses::deleted_by.eq(None::<i32>),
+        ))
+        .execute(c)?;
+        if rows == 0 {
+            return Err(diesel::result::Error::NotFound);
+        }
+        warehouses::table
+            .filter(warehouses::warehouse_id.eq(warehouse_id))
+            .select(Warehouse::as_select())
+            .first(c)
     }).await;
 
     match result {
-        Ok(rows) if rows > 0 => Ok(Status::NoContent),
-        Ok(_) => Err(Status::NotFound),
-        Err(_) => Err(Status::InternalServerError)
+        Ok(warehouse) => Ok(Json(warehouse)),
+        Err(e) => Err(e.into())
     }
-}
\ No newline at end of file
+}
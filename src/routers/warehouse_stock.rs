@@ -0,0 +1,306 @@
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use rocket::serde::json::Json;
+use rocket::{get, post, State};
+use chrono::Utc;
+use serde::Deserialize;
+
+use crate::error::ApiError;
+use crate::models::{WarehouseStock, NewStockMovement, DbConn};
+use crate::schema::{warehouse_stock, warehouses};
+use crate::soft_delete;
+use crate::routers::stock_movement::{self, record_movement};
+use crate::auth_guard::{RequirePermission, StockRead, StockWrite};
+use crate::events::{DomainEvent, EventSender};
+
+// 入库/出库请求
+#[derive(Debug, Deserialize)]
+pub struct StockAdjustment {
+    pub warehouse_id: i32,
+    pub material_id: i32,
+    pub quantity: i32,
+    pub request_id: Option<i32>,
+    pub task_id: Option<i32>,
+    pub note: Option<String>,
+}
+
+impl StockAdjustment {
+    fn into_movement(self, movement_type: &str, quantity: i32, performed_by: Option<i32>) -> NewStockMovement {
+        NewStockMovement {
+            warehouse_id: self.warehouse_id,
+            material_id: self.material_id,
+            movement_type: movement_type.to_string(),
+            quantity,
+            request_id: self.request_id,
+            task_id: self.task_id,
+            performed_by,
+            note: self.note,
+        }
+    }
+}
+
+// 库存变更错误
+#[derive(Debug)]
+pub enum StockError {
+    NotFound,
+    InsufficientStock,
+    // 数量超出 i32 范围
+    QuantityOverflow,
+    Database(diesel::result::Error),
+}
+
+impl From<diesel::result::Error> for StockError {
+    fn from(err: diesel::result::Error) -> Self {
+        match err {
+            diesel::result::Error::NotFound => StockError::NotFound,
+            other => StockError::Database(other),
+        }
+    }
+}
+
+impl From<StockError> for ApiError {
+    fn from(err: StockError) -> Self {
+        match err {
+            StockError::NotFound => ApiError::not_found("warehouse or material not found"),
+            StockError::InsufficientStock => {
+                ApiError::conflict("insufficient_stock", "not enough stock").with_field("quantity")
+            }
+            StockError::QuantityOverflow => {
+                ApiError::conflict("quantity_overflow", "stock quantity out of range").with_field("quantity")
+            }
+            StockError::Database(e) => e.into(),
+        }
+    }
+}
+
+// 数量汇总为 i64，写回 i32 列前检查范围
+pub fn to_quantity(total: i64) -> Result<i32, StockError> {
+    i32::try_from(total).map_err(|_| StockError::QuantityOverflow)
+}
+
+// 按 delta 调整某仓库某材料的库存投影，库存不能为负。
+// 只应由 stock_movement::record_movement 调用，否则投影会与流水不一致。
+pub fn apply_stock_change(
+    c: &mut SqliteConnection,
+    warehouse_id: i32,
+    material_id: i32,
+    delta: i32,
+) -> Result<WarehouseStock, StockError> {
+    let now = Utc::now().naive_utc();
+
+    // 已删除的仓库和材料不再产生新的流水
+    if !soft_delete::warehouse_active(c, warehouse_id)? || !soft_delete::material_active(c, material_id)? {
+        return Err(StockError::NotFound);
+    }
+
+    let current = warehouse_stock::table
+        .filter(warehouse_stock::warehouse_id.eq(warehouse_id))
+        .filter(warehouse_stock::material_id.eq(material_id))
+        .select(WarehouseStock::as_select())
+        .first(c)
+        .optional()?;
+
+    match current {
+        Some(stock) => {
+            let new_quantity = stock.quantity.unwrap_or(0)
+                .checked_add(delta)
+                .ok_or(StockError::QuantityOverflow)?;
+            if new_quantity < 0 {
+                return Err(StockError::InsufficientStock);
+            }
+            diesel::update(
+                warehouse_stock::table
+                    .filter(warehouse_stock::warehouse_id.eq(warehouse_id))
+                    .filter(warehouse_stock::material_id.eq(material_id))
+            )
+            .set((
+                warehouse_stock::quantity.eq(new_quantity),
+                warehouse_stock::last_updated.eq(now),
+            ))
+            .execute(c)?;
+        }
+        None => {
+            if delta < 0 {
+                return Err(StockError::InsufficientStock);
+            }
+            diesel::insert_into(warehouse_stock::table)
+                .values((
+                    warehouse_stock::warehouse_id.eq(warehouse_id),
+                    warehouse_stock::material_id.eq(material_id),
+                    warehouse_stock::quantity.eq(delta),
+                    warehouse_stock::last_updated.eq(now),
+                ))
+                .execute(c)?;
+        }
+    }
+
+    sync_warehouse_total(c, warehouse_id)?;
+
+    let stock = warehouse_stock::table
+        .filter(warehouse_stock::warehouse_id.eq(warehouse_id))
+        .filter(warehouse_stock::material_id.eq(material_id))
+        .select(WarehouseStock::as_select())
+        .first(c)?;
+    Ok(stock)
+}
+
+// 使 warehouses.current_stock 与该仓库所有库存行之和保持一致
+pub fn sync_warehouse_total(
+    c: &mut SqliteConnection,
+    warehouse_id: i32,
+) -> Result<(), StockError> {
+    let total: Option<i64> = warehouse_stock::table
+        .filter(warehouse_stock::warehouse_id.eq(warehouse_id))
+        .select(diesel::dsl::sum(warehouse_stock::quantity))
+        .first(c)?;
+
+    diesel::update(warehouses::table.find(warehouse_id))
+        .set((
+            warehouses::current_stock.eq(to_quantity(total.unwrap_or(0))?),
+            warehouses::last_updated.eq(Utc::now().naive_utc()),
+        ))
+        .execute(c)?;
+    Ok(())
+}
+
+#[get("/warehouse_stock/by_warehouse/<warehouse_id>")]
+pub async fn get_stock_by_warehouse(
+    conn: DbConn,
+    _perm: RequirePermission<StockRead>,
+    warehouse_id: i32
+) -> Result<Json<Vec<WarehouseStock>>, ApiError> {
+    conn.run(move |c| {
+        warehouse_stock::table
+            .filter(warehouse_stock::warehouse_id.eq(warehouse_id))
+            .select(WarehouseStock::as_select())
+            .order(warehouse_stock::material_id.asc())
+            .load(c)
+    }).await
+    .map(Json)
+    .map_err(ApiError::from)
+}
+
+#[get("/warehouse_stock/by_material/<material_id>")]
+pub async fn get_stock_by_material(
+    conn: DbConn,
+    _perm: RequirePermission<StockRead>,
+    material_id: i32
+) -> Result<Json<Vec<WarehouseStock>>, ApiError> {
+    conn.run(move |c| {
+        warehouse_stock::table
+            .filter(warehouse_stock::material_id.eq(material_id))
+            .select(WarehouseStock::as_select())
+            .order(warehouse_stock::warehouse_id.asc())
+            .load(c)
+    }).await
+    .map(Json)
+    .map_err(ApiError::from)
+}
+
+// 与 by_warehouse、by_material 路由冲突，排在它们之后匹配
+#[get("/warehouse_stock/<warehouse_id>/<material_id>", rank = 2)]
+pub async fn get_warehouse_stock(
+    conn: DbConn,
+    _perm: RequirePermission<StockRead>,
+    warehouse_id: i32,
+    material_id: i32
+) -> Result<Json<WarehouseStock>, ApiError> {
+    conn.run(move |c| {
+        warehouse_stock::table
+            .filter(warehouse_stock::warehouse_id.eq(warehouse_id))
+            .filter(warehouse_stock::material_id.eq(material_id))
+            .select(WarehouseStock::as_select())
+            .first(c)
+    }).await
+    .map(Json)
+    .map_err(ApiError::from)
+}
+
+// 入库
+#[post("/warehouse_stock/receive", data = "<adjustment>")]
+pub async fn receive_stock(
+    conn: DbConn,
+    perm: RequirePermission<StockWrite>,
+    events: &State<EventSender>,
+    adjustment: Json<StockAdjustment>
+) -> Result<Json<WarehouseStock>, ApiError> {
+    if adjustment.quantity <= 0 {
+        return Err(ApiError::bad_request("quantity must be positive").with_field("quantity"));
+    }
+
+    let user_id = perm.user_id;
+    let quantity = adjustment.quantity;
+    let stock = conn.run(move |c| {
+        c.transaction(|c| {
+            let performed_by = Some(user_id);
+            record_movement(c, adjustment.into_inner().into_movement(stock_movement::RECEIPT, quantity, performed_by))
+        })
+    }).await
+    .map_err(ApiError::from)?;
+
+    events.emit(DomainEvent::StockAdjusted {
+        movement_type: stock_movement::RECEIPT.to_string(),
+        quantity_change: quantity,
+        stock: stock.clone(),
+    }).await;
+    Ok(Json(stock))
+}
+
+// 出库，库存不足时返回 409
+#[post("/warehouse_stock/issue", data = "<adjustment>")]
+pub async fn issue_stock(
+    conn: DbConn,
+    perm: RequirePermission<StockWrite>,
+    events: &State<EventSender>,
+    adjustment: Json<StockAdjustment>
+) -> Result<Json<WarehouseStock>, ApiError> {
+    if adjustment.quantity <= 0 {
+        return Err(ApiError::bad_request("quantity must be positive").with_field("quantity"));
+    }
+
+    let user_id = perm.user_id;
+    let quantity = -adjustment.quantity;
+    let stock = conn.run(move |c| {
+        c.transaction(|c| {
+            let performed_by = Some(user_id);
+            record_movement(c, adjustment.into_inner().into_movement(stock_movement::ISSUE, quantity, performed_by))
+        })
+    }).await
+    .map_err(ApiError::from)?;
+
+    events.emit(DomainEvent::StockAdjusted {
+        movement_type: stock_movement::ISSUE.to_string(),
+        quantity_change: quantity,
+        stock: stock.clone(),
+    }).await;
+    Ok(Json(stock))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::schema::materials;
+    use crate::warehouse;
+
+    #[test]
+    fn overflowing_receipt_is_rejected() {
+        let mut c = db::memory_connection();
+        warehouse::generate_and_insert_new_local_key(&mut c);
+        let warehouse_id = warehouse::this_warehouse_id(&mut c).unwrap();
+        diesel::insert_into(materials::table)
+            .values(materials::material_name.eq("steel"))
+            .execute(&mut c)
+            .unwrap();
+        let material_id = materials::table
+            .select(materials::material_id)
+            .first::<Option<i32>>(&mut c)
+            .unwrap()
+            .unwrap();
+
+        apply_stock_change(&mut c, warehouse_id, material_id, i32::MAX).unwrap();
+        let result = apply_stock_change(&mut c, warehouse_id, material_id, 1);
+        assert!(matches!(result, Err(StockError::QuantityOverflow)));
+        assert!(matches!(to_quantity(i64::from(i32::MAX) + 1), Err(StockError::QuantityOverflow)));
+    }
+}
@@ -1,5 +1,24 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    change_log (seq) {
+        seq -> Integer,
+        table_name -> Text,
+        global_id -> Text,
+        hlc -> Nullable<Text>,
+        deleted -> Bool,
+        changed_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    inbox (message_id) {
+        message_id -> Text,
+        sender -> Text,
+        received_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     material_requests (request_id) {
         request_id -> Nullable<Integer>,
@@ -9,6 +28,9 @@ diesel::table! {
         warehouse_id -> Nullable<Integer>,
         request_date -> Nullable<Timestamp>,
         status -> Nullable<Text>,
+        reviewed_by -> Nullable<Integer>,
+        reviewed_at -> Nullable<Timestamp>,
+        rejection_reason -> Nullable<Text>,
     }
 }
 
@@ -22,6 +44,17 @@ diesel::table! {
         supplier -> Nullable<Text>,
         created_by -> Nullable<Integer>,
         created_at -> Nullable<Timestamp>,
+        global_id -> Nullable<Text>,
+        hlc -> Nullable<Text>,
+        deleted_at -> Nullable<Timestamp>,
+        deleted_by -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    node_key_history (public_key) {
+        public_key -> Text,
+        retired_at -> Timestamp,
     }
 }
 
@@ -31,6 +64,48 @@ diesel::table! {
         user_id -> Nullable<Integer>,
         action -> Nullable<Text>,
         timestamp -> Nullable<Timestamp>,
+        method -> Nullable<Text>,
+        path -> Nullable<Text>,
+        entity_type -> Nullable<Text>,
+        entity_id -> Nullable<Text>,
+        status -> Nullable<Integer>,
+        changes -> Nullable<Text>,
+        client_ip -> Nullable<Text>,
+        prev_hash -> Nullable<Text>,
+        hash -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    operation_log_signatures (signature_id) {
+        signature_id -> Nullable<Integer>,
+        log_id -> Integer,
+        hash -> Text,
+        public_key -> Text,
+        signature -> Text,
+        signed_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    outbox (message_id) {
+        message_id -> Text,
+        topic -> Text,
+        message_type -> Text,
+        content -> Text,
+        status -> Text,
+        attempts -> Integer,
+        last_attempt_at -> Nullable<Timestamp>,
+        delivered_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    outbox_acks (message_id, peer_id) {
+        message_id -> Text,
+        peer_id -> Text,
+        acked_at -> Timestamp,
     }
 }
 
@@ -57,6 +132,10 @@ diesel::table! {
         profit -> Nullable<Double>,
         created_by -> Nullable<Integer>,
         created_at -> Nullable<Timestamp>,
+        global_id -> Nullable<Text>,
+        hlc -> Nullable<Text>,
+        deleted_at -> Nullable<Timestamp>,
+        deleted_by -> Nullable<Integer>,
     }
 }
 
@@ -70,6 +149,10 @@ diesel::table! {
         dimensions -> Nullable<Text>,
         created_by -> Nullable<Integer>,
         created_at -> Nullable<Timestamp>,
+        global_id -> Nullable<Text>,
+        hlc -> Nullable<Text>,
+        deleted_at -> Nullable<Timestamp>,
+        deleted_by -> Nullable<Integer>,
     }
 }
 
@@ -80,6 +163,8 @@ diesel::table! {
         cost_per_unit -> Double,
         created_by -> Nullable<Integer>,
         created_at -> Nullable<Timestamp>,
+        global_id -> Nullable<Text>,
+        hlc -> Nullable<Text>,
     }
 }
 
@@ -95,6 +180,44 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    refresh_tokens (token_id) {
+        token_id -> Integer,
+        user_id -> Integer,
+        token_hash -> Text,
+        expires_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+        replaced_by -> Nullable<Integer>,
+        created_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    replication_conflicts (conflict_id) {
+        conflict_id -> Integer,
+        table_name -> Text,
+        global_id -> Text,
+        local_hlc -> Nullable<Text>,
+        remote_hlc -> Text,
+        local_data -> Nullable<Text>,
+        remote_data -> Nullable<Text>,
+        policy -> Text,
+        winner -> Text,
+        resolved_at -> Timestamp,
+        reviewed_by -> Nullable<Integer>,
+        reviewed_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    replication_tombstones (table_name, global_id) {
+        table_name -> Text,
+        global_id -> Text,
+        hlc -> Text,
+        deleted_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     role_permissions (role_id, permission_id) {
         role_id -> Nullable<Integer>,
@@ -110,6 +233,58 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    stock_movements (movement_id) {
+        movement_id -> Integer,
+        warehouse_id -> Integer,
+        material_id -> Integer,
+        movement_type -> Text,
+        quantity -> Integer,
+        request_id -> Nullable<Integer>,
+        task_id -> Nullable<Integer>,
+        performed_by -> Nullable<Integer>,
+        note -> Nullable<Text>,
+        created_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    stock_transfers (transfer_id) {
+        transfer_id -> Text,
+        direction -> Text,
+        peer_id -> Text,
+        warehouse_id -> Integer,
+        material_id -> Integer,
+        quantity -> Integer,
+        status -> Text,
+        acked -> Bool,
+        attempts -> Integer,
+        last_attempt_at -> Nullable<Timestamp>,
+        rejection_reason -> Nullable<Text>,
+        note -> Nullable<Text>,
+        requested_by -> Nullable<Integer>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    sync_state (peer_id) {
+        peer_id -> Text,
+        last_seq -> Integer,
+        synced_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    trusted_peers (peer_id) {
+        peer_id -> Text,
+        label -> Nullable<Text>,
+        added_by -> Nullable<Integer>,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     user_roles (user_id, role_id) {
         user_id -> Nullable<Integer>,
@@ -148,6 +323,8 @@ diesel::table! {
         capacity -> Nullable<Integer>,
         current_stock -> Nullable<Integer>,
         last_updated -> Nullable<Timestamp>,
+        deleted_at -> Nullable<Timestamp>,
+        deleted_by -> Nullable<Integer>,
     }
 }
 
@@ -155,30 +332,56 @@ diesel::joinable!(material_requests -> materials (material_id));
 diesel::joinable!(material_requests -> users (requested_by));
 diesel::joinable!(material_requests -> warehouses (warehouse_id));
 diesel::joinable!(materials -> users (created_by));
+diesel::joinable!(operation_log_signatures -> operation_logs (log_id));
 diesel::joinable!(operation_logs -> users (user_id));
+diesel::joinable!(outbox_acks -> outbox (message_id));
 diesel::joinable!(price_formulas -> users (created_by));
 diesel::joinable!(product_specifications -> users (created_by));
 diesel::joinable!(production_costs -> users (created_by));
 diesel::joinable!(production_tasks -> product_specifications (product_id));
 diesel::joinable!(production_tasks -> users (created_by));
+diesel::joinable!(refresh_tokens -> users (user_id));
+diesel::joinable!(replication_conflicts -> users (reviewed_by));
 diesel::joinable!(role_permissions -> permissions (permission_id));
 diesel::joinable!(role_permissions -> roles (role_id));
+diesel::joinable!(stock_movements -> material_requests (request_id));
+diesel::joinable!(stock_movements -> materials (material_id));
+diesel::joinable!(stock_movements -> production_tasks (task_id));
+diesel::joinable!(stock_movements -> users (performed_by));
+diesel::joinable!(stock_movements -> warehouses (warehouse_id));
+diesel::joinable!(stock_transfers -> materials (material_id));
+diesel::joinable!(stock_transfers -> users (requested_by));
+diesel::joinable!(stock_transfers -> warehouses (warehouse_id));
+diesel::joinable!(trusted_peers -> users (added_by));
 diesel::joinable!(user_roles -> roles (role_id));
 diesel::joinable!(user_roles -> users (user_id));
 diesel::joinable!(warehouse_stock -> materials (material_id));
 diesel::joinable!(warehouse_stock -> warehouses (warehouse_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    change_log,
+    inbox,
     material_requests,
     materials,
+    node_key_history,
+    operation_log_signatures,
     operation_logs,
+    outbox,
+    outbox_acks,
     permissions,
     price_formulas,
     product_specifications,
     production_costs,
     production_tasks,
+    refresh_tokens,
+    replication_conflicts,
+    replication_tombstones,
     role_permissions,
     roles,
+    stock_movements,
+    stock_transfers,
+    sync_state,
+    trusted_peers,
     user_roles,
     users,
     warehouse_stock,
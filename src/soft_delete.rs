@@ -0,0 +1,143 @@
+use std::env;
+use std::time::Duration;
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use diesel::sql_types::{Integer, Nullable, Text, Timestamp};
+use diesel::sqlite::SqliteConnection;
+use log::{error, info};
+use tokio::sync::broadcast;
+
+use crate::db;
+use crate::replication::{self, ReplicatedEntity};
+use crate::schema::{materials, product_specifications, warehouses};
+use crate::routers::dependents::blocking_dependents;
+
+// 使用软删除的表、主键和清理时额外的条件。本节点仓库（ThisWarehouse）保存节点密钥，永不清理
+const TABLES: &[(&str, &str, &str)] = &[
+    ("materials", "material_id", ""),
+    ("product_specifications", "product_id", ""),
+    ("price_formulas", "formula_id", ""),
+    ("warehouses", "warehouse_id", "AND warehouse_name <> 'ThisWarehouse'"),
+];
+
+const DEFAULT_RETENTION_DAYS: i64 = 90;
+const PURGE_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+#[derive(QueryableByName)]
+struct DeletedRow {
+    #[diesel(sql_type = Integer)]
+    id: i32,
+}
+
+#[derive(QueryableByName)]
+struct RowVersion {
+    #[diesel(sql_type = Nullable<Text>)]
+    global_id: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    hlc: Option<String>,
+}
+
+// 新的库存流水、领料单、调拨和生产任务只能引用未删除的记录
+pub fn material_active(c: &mut SqliteConnection, material_id: i32) -> QueryResult<bool> {
+    diesel::select(diesel::dsl::exists(
+        materials::table
+            .filter(materials::material_id.eq(material_id))
+            .filter(materials::deleted_at.is_null()),
+    ))
+    .get_result(c)
+}
+
+pub fn warehouse_active(c: &mut SqliteConnection, warehouse_id: i32) -> QueryResult<bool> {
+    diesel::select(diesel::dsl::exists(
+        warehouses::table
+            .filter(warehouses::warehouse_id.eq(warehouse_id))
+            .filter(warehouses::deleted_at.is_null()),
+    ))
+    .get_result(c)
+}
+
+pub fn product_active(c: &mut SqliteConnection, product_id: i32) -> QueryResult<bool> {
+    diesel::select(diesel::dsl::exists(
+        product_specifications::table
+            .filter(product_specifications::product_id.eq(product_id))
+            .filter(product_specifications::deleted_at.is_null()),
+    ))
+    .get_result(c)
+}
+
+// 软删除记录的保留天数，可通过 SOFT_DELETE_RETENTION_DAYS 配置
+pub fn retention_days() -> i64 {
+    env::var("SOFT_DELETE_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_RETENTION_DAYS)
+}
+
+// 物理删除 deleted_at 早于 before 的记录。仍被库存、领料单、生产任务引用的记录保留，
+// 等引用方清理后再删除。返回删除的行数
+pub fn purge(c: &mut SqliteConnection, before: NaiveDateTime) -> QueryResult<usize> {
+    let mut purged = 0;
+    for &(table, key, condition) in TABLES {
+        let rows = diesel::sql_query(format!(
+            "SELECT {} AS id FROM {} WHERE deleted_at IS NOT NULL AND deleted_at < ? {}",
+            key, table, condition
+        ))
+        .bind::<Timestamp, _>(before)
+        .load::<DeletedRow>(c)?;
+
+        for row in rows {
+            if !blocking_dependents(c, table, row.id)?.is_empty() {
+                continue;
+            }
+            let result = c.transaction(|c| {
+                // 复制的记录清理后留下墓碑，其他节点的旧更新不会让它复活
+                if let Some(entity) = ReplicatedEntity::from_table_name(table) {
+                    let version = diesel::sql_query(format!("SELECT global_id, hlc FROM {} WHERE {} = ?", table, key))
+                        .bind::<Integer, _>(row.id)
+                        .get_result::<RowVersion>(c)?;
+                    if let (Some(global_id), Some(hlc)) = (version.global_id, version.hlc) {
+                        replication::record_tombstone(c, entity, &global_id, &hlc)?;
+                    }
+                }
+                diesel::sql_query(format!("DELETE FROM {} WHERE {} = ?", table, key))
+                    .bind::<Integer, _>(row.id)
+                    .execute(c)
+            });
+            match result {
+                Ok(rows) => purged += rows,
+                // 检查之后新增了引用，下次再试
+                Err(DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    Ok(purged)
+}
+
+// 定期清理超过保留期的软删除记录，收到关闭信号时退出
+pub async fn run_purge_job(mut shutdown_rx: broadcast::Receiver<()>) {
+    let mut interval = tokio::time::interval(PURGE_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = shutdown_rx.recv() => {
+                info!("Shutting down purge job...");
+                break;
+            }
+            _ = interval.tick() => {
+                let before = Utc::now().naive_utc() - chrono::Duration::days(retention_days());
+                let result = tokio::task::spawn_blocking(move || {
+                    let mut c = db::establish_connection().map_err(|e| e.to_string())?;
+                    purge(&mut c, before).map_err(|e| e.to_string())
+                })
+                .await;
+                match result {
+                    Ok(Ok(0)) => {}
+                    Ok(Ok(purged)) => info!("Purged {} soft-deleted records deleted before {}", purged, before),
+                    Ok(Err(e)) => error!("Failed to purge soft-deleted records: {}", e),
+                    Err(e) => error!("Purge task panicked: {}", e),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,396 @@
+use std::io;
+use async_trait::async_trait;
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use futures::prelude::*;
+use libp2p::core::upgrade::{read_length_prefixed, write_length_prefixed, ProtocolName};
+use libp2p::request_response::{
+    RequestResponse, RequestResponseCodec, RequestResponseEvent, RequestResponseMessage,
+};
+use libp2p::PeerId;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::db;
+use crate::message_auth;
+use crate::models::ChangeLogEntry;
+use crate::replication::{self, ReplicatedEntity, ReplicationMessage};
+use crate::schema::{change_log, sync_state};
+
+// 每次应答最多包含的 change_log 条目数，超出时请求方继续请求下一页
+const PAGE_SIZE: i64 = 200;
+const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+// since 为请求方已追赶到的对端序号，0 表示全量
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncRequest {
+    pub since: i32,
+}
+
+// last_seq 为本页最后一条变更的序号，下次从这里继续
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncResponse {
+    pub changes: Vec<ReplicationMessage>,
+    pub last_seq: i32,
+    pub has_more: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct SyncProtocol;
+
+impl ProtocolName for SyncProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        b"/warehouse/sync/1.0.0"
+    }
+}
+
+// 带长度前缀的 JSON 编解码
+#[derive(Clone)]
+pub struct SyncCodec;
+
+fn invalid_data(e: serde_json::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+#[async_trait]
+impl RequestResponseCodec for SyncCodec {
+    type Protocol = SyncProtocol;
+    type Request = SyncRequest;
+    type Response = SyncResponse;
+
+    async fn read_request<T>(&mut self, _: &SyncProtocol, io: &mut T) -> io::Result<SyncRequest>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let bytes = read_length_prefixed(io, MAX_MESSAGE_SIZE).await?;
+        serde_json::from_slice(&bytes).map_err(invalid_data)
+    }
+
+    async fn read_response<T>(&mut self, _: &SyncProtocol, io: &mut T) -> io::Result<SyncResponse>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let bytes = read_length_prefixed(io, MAX_MESSAGE_SIZE).await?;
+        serde_json::from_slice(&bytes).map_err(invalid_data)
+    }
+
+    async fn write_request<T>(&mut self, _: &SyncProtocol, io: &mut T, request: SyncRequest) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_length_prefixed(io, serde_json::to_vec(&request).map_err(invalid_data)?).await?;
+        io.close().await
+    }
+
+    async fn write_response<T>(&mut self, _: &SyncProtocol, io: &mut T, response: SyncResponse) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_length_prefixed(io, serde_json::to_vec(&response).map_err(invalid_data)?).await?;
+        io.close().await
+    }
+}
+
+pub type SyncBehaviour = RequestResponse<SyncCodec>;
+pub type SyncEvent = RequestResponseEvent<SyncRequest, SyncResponse>;
+
+// 导出 since 之后的一页变更。同一记录多次变更只导出其当前状态
+pub fn export(c: &mut SqliteConnection, since: i32) -> QueryResult<SyncResponse> {
+    let entries = change_log::table
+        .filter(change_log::seq.gt(since))
+        .order(change_log::seq.asc())
+        .limit(PAGE_SIZE)
+        .select(ChangeLogEntry::as_select())
+        .load(c)?;
+    let has_more = entries.len() as i64 == PAGE_SIZE;
+    let last_seq = entries.last().map_or(since, |entry| entry.seq);
+
+    // 倒序去重保留每条记录的最后一次变更，再恢复变更顺序
+    let mut seen = std::collections::HashSet::new();
+    let mut latest: Vec<ChangeLogEntry> = entries
+        .into_iter()
+        .rev()
+        .filter(|entry| seen.insert((entry.table_name.clone(), entry.global_id.clone())))
+        .collect();
+    latest.reverse();
+
+    let mut changes = Vec::with_capacity(latest.len());
+    for entry in latest {
+        let entity = match ReplicatedEntity::from_table_name(&entry.table_name) {
+            Some(entity) => entity,
+            None => continue,
+        };
+        let change = match replication::current_record(c, entity, &entry.global_id)? {
+            Some(record) if !record.deleted() => ReplicationMessage::Upsert { record: Box::new(record), previous_hlc: None },
+            _ => ReplicationMessage::Delete {
+                entity,
+                global_id: entry.global_id,
+                hlc: entry.hlc.clone().unwrap_or_default(),
+                previous_hlc: entry.hlc,
+            },
+        };
+        changes.push(change);
+    }
+
+    Ok(SyncResponse { changes, last_seq, has_more })
+}
+
+// 在一个事务内应用一页变更并记录追赶进度
+pub fn import(c: &mut SqliteConnection, peer: &PeerId, response: SyncResponse) -> QueryResult<()> {
+    c.transaction(|c| {
+        for change in response.changes {
+            replication::apply_catch_up(c, change)?;
+        }
+        diesel::replace_into(sync_state::table)
+            .values((
+                sync_state::peer_id.eq(peer.to_string()),
+                sync_state::last_seq.eq(response.last_seq),
+                sync_state::synced_at.eq(Utc::now().naive_utc()),
+            ))
+            .execute(c)?;
+        Ok(())
+    })
+}
+
+// 向对端请求追赶，full 时忽略已记录的进度
+pub fn request_sync(behaviour: &mut SyncBehaviour, peer: &PeerId, full: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let since = if full {
+        0
+    } else {
+        let mut connection = db::establish_connection()?;
+        sync_state::table
+            .filter(sync_state::peer_id.eq(peer.to_string()))
+            .select(sync_state::last_seq)
+            .first::<i32>(&mut connection)
+            .optional()?
+            .unwrap_or(0)
+    };
+    info!("Requesting catch-up from {} since seq {}", peer, since);
+    behaviour.send_request(peer, SyncRequest { since });
+    Ok(())
+}
+
+// 处理请求-应答协议的事件。追赶期间收到的实时复制消息照常应用，二者都按时钟合并
+pub fn handle_event(behaviour: &mut SyncBehaviour, event: SyncEvent) -> Result<(), Box<dyn std::error::Error>> {
+    match event {
+        RequestResponseEvent::Message { peer, message } => {
+            let mut connection = db::establish_connection()?;
+            if !message_auth::is_trusted(&mut connection, &peer)? {
+                warn!("Ignoring sync message from untrusted peer {}", peer);
+                return Ok(());
+            }
+            match message {
+                RequestResponseMessage::Request { request, channel, .. } => {
+                    let response = export(&mut connection, request.since)?;
+                    debug!("Sending {} changes after seq {} to {}", response.changes.len(), request.since, peer);
+                    if behaviour.send_response(channel, response).is_err() {
+                        warn!("Failed to send sync response to {}", peer);
+                    }
+                }
+                RequestResponseMessage::Response { response, .. } => {
+                    let last_seq = response.last_seq;
+                    let has_more = response.has_more;
+                    import(&mut connection, &peer, response)?;
+                    if has_more {
+                        behaviour.send_request(&peer, SyncRequest { since: last_seq });
+                    } else {
+                        info!("Caught up with {} at seq {}", peer, last_seq);
+                    }
+                }
+            }
+        }
+        RequestResponseEvent::OutboundFailure { peer, error, .. } => {
+            warn!("Sync request to {} failed: {:?}", peer, error);
+        }
+        RequestResponseEvent::InboundFailure { peer, error, .. } => {
+            warn!("Sync request from {} failed: {:?}", peer, error);
+        }
+        RequestResponseEvent::ResponseSent { peer, .. } => {
+            debug!("Sync response sent to {}", peer);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replication::{ReplicatedEntity, ReplicationMessage};
+    use crate::schema::{materials, production_costs};
+    use crate::{soft_delete, warehouse};
+
+    fn node() -> SqliteConnection {
+        let mut c = db::memory_connection();
+        warehouse::generate_and_insert_new_local_key(&mut c);
+        c
+    }
+
+    // 导出 source 的全部变更并导入 target
+    fn round_trip(source: &mut SqliteConnection, target: &mut SqliteConnection) {
+        let peer = PeerId::random();
+        let mut since = 0;
+        loop {
+            let page = export(source, since).unwrap();
+            let has_more = page.has_more;
+            since = page.last_seq;
+            import(target, &peer, page).unwrap();
+            if !has_more {
+                break;
+            }
+        }
+    }
+
+    fn insert_material(c: &mut SqliteConnection, global_id: &str) {
+        let hlc = replication::next_hlc(c).unwrap();
+        diesel::insert_into(materials::table)
+            .values((
+                materials::material_name.eq(global_id),
+                materials::global_id.eq(global_id),
+                materials::hlc.eq(hlc),
+            ))
+            .execute(c)
+            .unwrap();
+    }
+
+    fn insert_cost(c: &mut SqliteConnection, global_id: &str) {
+        let hlc = replication::next_hlc(c).unwrap();
+        diesel::insert_into(production_costs::table)
+            .values((
+                production_costs::process_type.eq(global_id),
+                production_costs::cost_per_unit.eq(1.5),
+                production_costs::global_id.eq(global_id),
+                production_costs::hlc.eq(hlc),
+            ))
+            .execute(c)
+            .unwrap();
+    }
+
+    // None 表示本地没有该材料
+    fn material_deleted(c: &mut SqliteConnection, global_id: &str) -> Option<bool> {
+        materials::table
+            .filter(materials::global_id.eq(global_id))
+            .select(materials::deleted_at)
+            .first::<Option<chrono::NaiveDateTime>>(c)
+            .optional()
+            .unwrap()
+            .map(|deleted_at| deleted_at.is_some())
+    }
+
+    fn cost_exists(c: &mut SqliteConnection, global_id: &str) -> bool {
+        diesel::select(diesel::dsl::exists(
+            production_costs::table.filter(production_costs::global_id.eq(global_id)),
+        ))
+        .get_result(c)
+        .unwrap()
+    }
+
+    #[test]
+    fn upserts_round_trip() {
+        let (mut a, mut b) = (node(), node());
+        insert_material(&mut a, "m1");
+        insert_cost(&mut a, "c1");
+        round_trip(&mut a, &mut b);
+        assert_eq!(material_deleted(&mut b, "m1"), Some(false));
+        assert!(cost_exists(&mut b, "c1"));
+    }
+
+    #[test]
+    fn soft_delete_round_trips() {
+        let (mut a, mut b) = (node(), node());
+        insert_material(&mut a, "m1");
+        round_trip(&mut a, &mut b);
+
+        let hlc = replication::next_hlc(&mut a).unwrap();
+        diesel::update(materials::table.filter(materials::global_id.eq("m1")))
+            .set((materials::deleted_at.eq(Utc::now().naive_utc()), materials::hlc.eq(hlc)))
+            .execute(&mut a)
+            .unwrap();
+        round_trip(&mut a, &mut b);
+        assert_eq!(material_deleted(&mut b, "m1"), Some(true));
+    }
+
+    #[test]
+    fn hard_delete_with_fresh_hlc_round_trips() {
+        let (mut a, mut b) = (node(), node());
+        insert_cost(&mut a, "c1");
+        round_trip(&mut a, &mut b);
+
+        let hlc = replication::next_hlc(&mut a).unwrap();
+        let target = production_costs::table.filter(production_costs::global_id.eq("c1"));
+        diesel::update(target).set(production_costs::hlc.eq(hlc)).execute(&mut a).unwrap();
+        diesel::delete(target).execute(&mut a).unwrap();
+        round_trip(&mut a, &mut b);
+        assert!(!cost_exists(&mut b, "c1"));
+    }
+
+    #[test]
+    fn delete_wins_on_equal_hlc() {
+        let (mut a, mut b) = (node(), node());
+        insert_cost(&mut a, "c1");
+        round_trip(&mut a, &mut b);
+
+        // 删除前没有更新时钟，变更日志记下的是删除前的时钟
+        diesel::delete(production_costs::table.filter(production_costs::global_id.eq("c1")))
+            .execute(&mut a)
+            .unwrap();
+        round_trip(&mut a, &mut b);
+        assert!(!cost_exists(&mut b, "c1"));
+    }
+
+    #[test]
+    fn newer_local_edit_survives_older_delete() {
+        let (mut a, mut b) = (node(), node());
+        insert_cost(&mut a, "c1");
+        round_trip(&mut a, &mut b);
+
+        diesel::delete(production_costs::table.filter(production_costs::global_id.eq("c1")))
+            .execute(&mut a)
+            .unwrap();
+        let hlc = replication::next_hlc(&mut b).unwrap();
+        diesel::update(production_costs::table.filter(production_costs::global_id.eq("c1")))
+            .set(production_costs::hlc.eq(hlc))
+            .execute(&mut b)
+            .unwrap();
+        round_trip(&mut a, &mut b);
+        assert!(cost_exists(&mut b, "c1"));
+    }
+
+    #[test]
+    fn delete_of_unknown_record_blocks_older_upsert() {
+        let (mut a, mut b) = (node(), node());
+        insert_cost(&mut a, "c1");
+        let record = replication::current_record(&mut a, ReplicatedEntity::ProductionCost, "c1").unwrap().unwrap();
+        let hlc = replication::next_hlc(&mut a).unwrap();
+
+        // b 先收到删除，再收到删除前的版本
+        replication::apply(&mut b, ReplicationMessage::Delete {
+            entity: ReplicatedEntity::ProductionCost,
+            global_id: "c1".to_string(),
+            hlc,
+            previous_hlc: None,
+        })
+        .unwrap();
+        replication::apply(&mut b, ReplicationMessage::Upsert { record: Box::new(record), previous_hlc: None }).unwrap();
+        assert!(!cost_exists(&mut b, "c1"));
+    }
+
+    #[test]
+    fn purged_record_is_not_resurrected_by_older_upsert() {
+        let (mut a, mut b) = (node(), node());
+        insert_material(&mut a, "m1");
+        round_trip(&mut a, &mut b);
+        let record = replication::current_record(&mut a, ReplicatedEntity::Material, "m1").unwrap().unwrap();
+
+        let hlc = replication::next_hlc(&mut b).unwrap();
+        diesel::update(materials::table.filter(materials::global_id.eq("m1")))
+            .set((materials::deleted_at.eq(Utc::now().naive_utc()), materials::hlc.eq(hlc)))
+            .execute(&mut b)
+            .unwrap();
+        soft_delete::purge(&mut b, Utc::now().naive_utc() + chrono::Duration::days(1)).unwrap();
+        assert_eq!(material_deleted(&mut b, "m1"), None);
+
+        replication::apply(&mut b, ReplicationMessage::Upsert { record: Box::new(record), previous_hlc: None }).unwrap();
+        assert_eq!(material_deleted(&mut b, "m1"), None);
+    }
+}
@@ -1,20 +1,41 @@
-use jsonwebtoken::{encode, decode, DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::{encode, decode, decode_header, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use std::collections::HashMap;
 use std::time::{SystemTime, UNIX_EPOCH};
 use rocket::serde::{Deserialize, Serialize};
 use rocket::request::{FromRequest, Outcome};
+use rocket::fairing::{AdHoc, Fairing};
 use rocket::Request;
 use rocket::http::Status;
-pub struct TokenGuard(String);
+use log::error;
+use uuid::Uuid;
+
+pub struct TokenGuard(pub String);
+
+// 从请求中取出访问令牌：优先 Authorization 头（可带 Bearer 前缀），其次 token cookie
+pub fn extract_token(request: &Request<'_>) -> Option<String> {
+    if let Some(header) = request.headers().get_one("Authorization") {
+        let token = header.strip_prefix("Bearer ").unwrap_or(header).trim();
+        return Some(token.to_string());
+    }
+    request.cookies().get("token").map(|cookie| cookie.value().to_string())
+}
+
+// 取出并校验当前请求的访问令牌
+pub fn decode_request_token(request: &Request<'_>) -> Option<Claims> {
+    let keys = request.rocket().state::<JwtKeys>()?;
+    let token = extract_token(request)?;
+    keys.decode(&token)
+}
 
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for TokenGuard {
     type Error = String;
 
     async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        match request.headers().get_one("Authorization") {
+        match extract_token(request) {
             Some(token) => {
-                if crate::token::decode_token(token).is_some() {
-                    Outcome::Success(TokenGuard(token.to_string()))
+                if decode_request_token(request).is_some() {
+                    Outcome::Success(TokenGuard(token))
                 } else {
                     Outcome::Error((Status::Unauthorized, "Invalid token".to_string()))
                 }
@@ -24,59 +45,200 @@ impl<'r> FromRequest<'r> for TokenGuard {
     }
 }
 
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Claims {
-    sub: String,
-    exp: usize,
+// 访问令牌中的声明，TokenGuard、claims::JwtToken 和 auth_guard 共用
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Claims {
+    pub sub: String,
+    pub exp: usize,
+    pub iat: usize,
+    pub jti: String,
+    pub user_id: i32,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    // 签发节点对应的仓库
+    pub warehouse_id: Option<i32>,
 }
 
 impl Claims {
-    fn new(username: &str) -> Self {
-        let now = SystemTime::now();
-        let exp = now
+    pub fn new(
+        username: &str,
+        user_id: i32,
+        roles: Vec<String>,
+        warehouse_id: Option<i32>,
+        ttl_secs: u64,
+    ) -> Self {
+        let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .expect("Time went backwards")
-            .as_secs() + 1800; // Token expires in 30 minutes
+            .as_secs();
 
         Self {
             sub: username.to_owned(),
-            exp: exp as usize,
+            exp: (now + ttl_secs) as usize,
+            iat: now as usize,
+            jti: Uuid::new_v4().to_string(),
+            user_id,
+            roles,
+            warehouse_id,
+        }
+    }
+}
+
+// 用于验证旧令牌的公钥或密钥，轮换期间保留
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct JwtVerificationKey {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    pub secret: Option<String>,
+    pub public_key_path: Option<String>,
+}
+
+// JWT 配置，来自 Rocket figment 的 `jwt` 键（例如 APP_JWT__SECRET、APP_JWT__ALGORITHM）
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct JwtConfig {
+    pub algorithm: Algorithm,
+    pub kid: String,
+    pub secret: Option<String>,
+    pub private_key_path: Option<String>,
+    pub public_key_path: Option<String>,
+    pub previous_keys: Vec<JwtVerificationKey>,
+    pub access_ttl_secs: u64,
+    pub refresh_ttl_secs: u64,
+}
+
+impl Default for JwtConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: Algorithm::HS256,
+            kid: "default".to_string(),
+            secret: None,
+            private_key_path: None,
+            public_key_path: None,
+            previous_keys: Vec::new(),
+            access_ttl_secs: 1800,
+            refresh_ttl_secs: 7 * 24 * 3600,
+        }
+    }
+}
+
+fn read_key_file(path: &Option<String>, name: &str) -> Result<Vec<u8>, String> {
+    let path = path.as_ref().ok_or_else(|| format!("jwt.{} is required for this algorithm", name))?;
+    std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e))
+}
+
+fn decoding_key(algorithm: Algorithm, secret: &Option<String>, public_key_path: &Option<String>) -> Result<DecodingKey, String> {
+    match algorithm {
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => secret
+            .as_ref()
+            .map(|s| DecodingKey::from_secret(s.as_bytes()))
+            .ok_or_else(|| "jwt.secret is required for HMAC algorithms".to_string()),
+        Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 => {
+            DecodingKey::from_rsa_pem(&read_key_file(public_key_path, "public_key_path")?)
+                .map_err(|e| e.to_string())
+        }
+        Algorithm::EdDSA => {
+            DecodingKey::from_ed_pem(&read_key_file(public_key_path, "public_key_path")?)
+                .map_err(|e| e.to_string())
         }
+        other => Err(format!("Unsupported JWT algorithm: {:?}", other)),
     }
 }
 
-// 生成 JWT
-pub fn generate_token(username: &str) -> String {
-    let claims = Claims::new(username);
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret("your_secret_key".as_ref()), // 替换为你的密钥
-    )
-    .unwrap()
-} 
-
-pub fn decode_token(token: &str) -> Option<String> {
-    let token_data = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret("your_secret_key".as_ref()),
-        &Validation::default(),
-    );
-
-    match token_data {
-        Ok(token) => {
-            if token.claims.exp
-                >= SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs() as usize
-            {
-                Some(token.claims.sub)
-            } else {
-                None
+// 签名密钥和按 kid 索引的验证密钥
+pub struct JwtKeys {
+    algorithm: Algorithm,
+    kid: String,
+    encoding: EncodingKey,
+    decoding: HashMap<String, (Algorithm, DecodingKey)>,
+    pub access_ttl_secs: u64,
+    pub refresh_ttl_secs: u64,
+}
+
+impl JwtKeys {
+    // HMAC 算法必须配置 jwt.secret，缺少时点火失败，避免每次重启换密钥使令牌全部失效
+    pub fn from_config(config: JwtConfig) -> Result<Self, String> {
+        let encoding = match config.algorithm {
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => config
+                .secret
+                .as_ref()
+                .map(|secret| EncodingKey::from_secret(secret.as_bytes()))
+                .ok_or_else(|| "jwt.secret is required for HMAC algorithms (set APP_JWT__SECRET)".to_string())?,
+            Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 => {
+                EncodingKey::from_rsa_pem(&read_key_file(&config.private_key_path, "private_key_path")?)
+                    .map_err(|e| e.to_string())?
+            }
+            Algorithm::EdDSA => {
+                EncodingKey::from_ed_pem(&read_key_file(&config.private_key_path, "private_key_path")?)
+                    .map_err(|e| e.to_string())?
+            }
+            other => return Err(format!("Unsupported JWT algorithm: {:?}", other)),
+        };
+
+        let mut decoding = HashMap::new();
+        decoding.insert(
+            config.kid.clone(),
+            (config.algorithm, decoding_key(config.algorithm, &config.secret, &config.public_key_path)?),
+        );
+        for key in &config.previous_keys {
+            decoding.insert(
+                key.kid.clone(),
+                (key.algorithm, decoding_key(key.algorithm, &key.secret, &key.public_key_path)?),
+            );
+        }
+
+        Ok(Self {
+            algorithm: config.algorithm,
+            kid: config.kid,
+            encoding,
+            decoding,
+            access_ttl_secs: config.access_ttl_secs,
+            refresh_ttl_secs: config.refresh_ttl_secs,
+        })
+    }
+
+    // 从 figment 读取配置并作为 Rocket state 管理
+    pub fn fairing() -> impl Fairing {
+        AdHoc::try_on_ignite("JWT Keys", |rocket| async move {
+            let config = match rocket.figment().extract_inner::<JwtConfig>("jwt") {
+                Ok(config) => config,
+                Err(e) if e.missing() => JwtConfig::default(),
+                Err(e) => {
+                    error!("Invalid jwt configuration: {}", e);
+                    return Err(rocket);
+                }
+            };
+
+            match JwtKeys::from_config(config) {
+                Ok(keys) => Ok(rocket.manage(keys)),
+                Err(e) => {
+                    error!("Failed to load JWT keys: {}", e);
+                    Err(rocket)
+                }
             }
+        })
+    }
+
+    // 生成 JWT，头部带当前 kid
+    pub fn encode(&self, claims: &Claims) -> Result<String, jsonwebtoken::errors::Error> {
+        let mut header = Header::new(self.algorithm);
+        header.kid = Some(self.kid.clone());
+        encode(&header, claims, &self.encoding)
+    }
+
+    // 按 kid 选择验证密钥，没有 kid 的令牌使用当前密钥
+    pub fn decode(&self, token: &str) -> Option<Claims> {
+        let header = decode_header(token).ok()?;
+        let kid = header.kid.unwrap_or_else(|| self.kid.clone());
+        let (algorithm, key) = self.decoding.get(&kid)?;
+        if header.alg != *algorithm {
+            return None;
         }
-        Err(_) => None,
+
+        decode::<Claims>(token, key, &Validation::new(*algorithm))
+            .map(|data| data.claims)
+            .ok()
     }
 }
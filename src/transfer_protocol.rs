@@ -0,0 +1,479 @@
+use std::io;
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use futures::prelude::*;
+use libp2p::core::upgrade::{read_length_prefixed, write_length_prefixed, ProtocolName};
+use libp2p::request_response::{
+    RequestResponse, RequestResponseCodec, RequestResponseEvent, RequestResponseMessage,
+};
+use libp2p::PeerId;
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::message_auth;
+use crate::models::{NewStockTransfer, StockTransfer};
+use crate::schema::{materials, stock_transfers};
+use crate::warehouse;
+
+// 调拨方向，与 stock_transfers.direction 的 CHECK 约束一致
+pub const OUTBOUND: &str = "outbound";
+pub const INBOUND: &str = "inbound";
+
+// 调拨状态，与 stock_transfers.status 的 CHECK 约束一致
+pub const REQUESTED: &str = "requested";
+pub const ACCEPTED: &str = "accepted";
+pub const REJECTED: &str = "rejected";
+pub const IN_TRANSIT: &str = "in_transit";
+pub const RECEIVED: &str = "received";
+pub const CANCELLED: &str = "cancelled";
+
+const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+// 重试间隔按尝试次数翻倍，最长 10 分钟
+const RETRY_BASE_SECS: i64 = 10;
+const RETRY_MAX_SECS: i64 = 600;
+
+// 发往对端的调拨消息，每条都带 transfer_id，重复发送是幂等的
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TransferRequest {
+    // 调出方发起调拨
+    Request {
+        transfer_id: String,
+        material_global_id: String,
+        quantity: i32,
+        note: Option<String>,
+    },
+    // 调出方已扣减库存并发货
+    Shipped { transfer_id: String },
+    // 调出方在发货前取消
+    Cancelled { transfer_id: String },
+    // 调入方已收货入库
+    Received { transfer_id: String },
+}
+
+// status 为对端处理后该调拨的状态，用于确认对应的本地状态
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TransferResponse {
+    Accepted { transfer_id: String },
+    Rejected { transfer_id: String, reason: String },
+    Ack { transfer_id: String, status: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct TransferProtocol;
+
+impl ProtocolName for TransferProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        b"/warehouse/transfer/1.0.0"
+    }
+}
+
+// 带长度前缀的 JSON 编解码
+#[derive(Clone)]
+pub struct TransferCodec;
+
+fn invalid_data(e: serde_json::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+#[async_trait]
+impl RequestResponseCodec for TransferCodec {
+    type Protocol = TransferProtocol;
+    type Request = TransferRequest;
+    type Response = TransferResponse;
+
+    async fn read_request<T>(&mut self, _: &TransferProtocol, io: &mut T) -> io::Result<TransferRequest>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let bytes = read_length_prefixed(io, MAX_MESSAGE_SIZE).await?;
+        serde_json::from_slice(&bytes).map_err(invalid_data)
+    }
+
+    async fn read_response<T>(&mut self, _: &TransferProtocol, io: &mut T) -> io::Result<TransferResponse>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let bytes = read_length_prefixed(io, MAX_MESSAGE_SIZE).await?;
+        serde_json::from_slice(&bytes).map_err(invalid_data)
+    }
+
+    async fn write_request<T>(&mut self, _: &TransferProtocol, io: &mut T, request: TransferRequest) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_length_prefixed(io, serde_json::to_vec(&request).map_err(invalid_data)?).await?;
+        io.close().await
+    }
+
+    async fn write_response<T>(&mut self, _: &TransferProtocol, io: &mut T, response: TransferResponse) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_length_prefixed(io, serde_json::to_vec(&response).map_err(invalid_data)?).await?;
+        io.close().await
+    }
+}
+
+pub type TransferBehaviour = RequestResponse<TransferCodec>;
+pub type TransferEvent = RequestResponseEvent<TransferRequest, TransferResponse>;
+
+fn find_transfer(
+    c: &mut SqliteConnection,
+    transfer_id: &str,
+    direction: &str,
+    peer: &PeerId,
+) -> QueryResult<Option<StockTransfer>> {
+    stock_transfers::table
+        .filter(stock_transfers::transfer_id.eq(transfer_id))
+        .filter(stock_transfers::direction.eq(direction))
+        .filter(stock_transfers::peer_id.eq(peer.to_string()))
+        .select(StockTransfer::as_select())
+        .first(c)
+        .optional()
+}
+
+// 对端确认后的状态变更，不需要再通知对端
+fn set_status(c: &mut SqliteConnection, transfer_id: &str, status: &str) -> QueryResult<usize> {
+    diesel::update(stock_transfers::table.filter(stock_transfers::transfer_id.eq(transfer_id)))
+        .set((
+            stock_transfers::status.eq(status),
+            stock_transfers::acked.eq(true),
+            stock_transfers::updated_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(c)
+}
+
+fn rejected(transfer_id: String, reason: &str) -> TransferResponse {
+    TransferResponse::Rejected { transfer_id, reason: reason.to_string() }
+}
+
+fn ack(transfer_id: String, status: &str) -> TransferResponse {
+    TransferResponse::Ack { transfer_id, status: status.to_string() }
+}
+
+// 处理对端发来的调拨消息并给出应答
+pub fn handle_request(
+    c: &mut SqliteConnection,
+    peer: &PeerId,
+    request: TransferRequest,
+) -> QueryResult<TransferResponse> {
+    c.transaction(|c| match request {
+        TransferRequest::Request { transfer_id, material_global_id, quantity, note } => {
+            // 重复的请求按已记录的结果应答；已进入后续状态的返回当前状态，不能再回答“已接受”
+            if let Some(existing) = find_transfer(c, &transfer_id, INBOUND, peer)? {
+                return Ok(match existing.status.as_str() {
+                    REJECTED => rejected(transfer_id, existing.rejection_reason.as_deref().unwrap_or("rejected")),
+                    ACCEPTED => TransferResponse::Accepted { transfer_id },
+                    status => ack(transfer_id, status),
+                });
+            }
+            if quantity <= 0 {
+                return Ok(rejected(transfer_id, "invalid quantity"));
+            }
+
+            let material_id = materials::table
+                .filter(materials::global_id.eq(&material_global_id))
+                .filter(materials::deleted_at.is_null())
+                .select(materials::material_id)
+                .first::<Option<i32>>(c)
+                .optional()?
+                .flatten();
+            let material_id = match material_id {
+                Some(id) => id,
+                None => return Ok(rejected(transfer_id, "unknown material")),
+            };
+            let warehouse_id = match warehouse::this_warehouse_id(c).optional()? {
+                Some(id) => id,
+                None => return Ok(rejected(transfer_id, "no local warehouse")),
+            };
+
+            let now = Utc::now().naive_utc();
+            diesel::insert_into(stock_transfers::table)
+                .values(NewStockTransfer {
+                    transfer_id: transfer_id.clone(),
+                    direction: INBOUND.to_string(),
+                    peer_id: peer.to_string(),
+                    warehouse_id,
+                    material_id,
+                    quantity,
+                    status: ACCEPTED.to_string(),
+                    acked: true,
+                    rejection_reason: None,
+                    note,
+                    requested_by: None,
+                    created_at: now,
+                    updated_at: now,
+                })
+                .execute(c)?;
+            info!("Accepted inbound transfer {} from {}", transfer_id, peer);
+            Ok(TransferResponse::Accepted { transfer_id })
+        }
+        TransferRequest::Shipped { transfer_id } => {
+            let existing = match find_transfer(c, &transfer_id, INBOUND, peer)? {
+                Some(existing) => existing,
+                None => return Ok(rejected(transfer_id, "unknown transfer")),
+            };
+            match existing.status.as_str() {
+                ACCEPTED => {
+                    set_status(c, &transfer_id, IN_TRANSIT)?;
+                    Ok(ack(transfer_id, IN_TRANSIT))
+                }
+                IN_TRANSIT | RECEIVED => Ok(ack(transfer_id, IN_TRANSIT)),
+                _ => Ok(rejected(transfer_id, "transfer is not awaiting shipment")),
+            }
+        }
+        TransferRequest::Cancelled { transfer_id } => {
+            let existing = match find_transfer(c, &transfer_id, INBOUND, peer)? {
+                Some(existing) => existing,
+                None => return Ok(ack(transfer_id, CANCELLED)),
+            };
+            match existing.status.as_str() {
+                ACCEPTED => {
+                    set_status(c, &transfer_id, CANCELLED)?;
+                    Ok(ack(transfer_id, CANCELLED))
+                }
+                CANCELLED => Ok(ack(transfer_id, CANCELLED)),
+                _ => Ok(rejected(transfer_id, "transfer already shipped")),
+            }
+        }
+        TransferRequest::Received { transfer_id } => {
+            let existing = match find_transfer(c, &transfer_id, OUTBOUND, peer)? {
+                Some(existing) => existing,
+                None => return Ok(rejected(transfer_id, "unknown transfer")),
+            };
+            match existing.status.as_str() {
+                IN_TRANSIT => {
+                    set_status(c, &transfer_id, RECEIVED)?;
+                    Ok(ack(transfer_id, RECEIVED))
+                }
+                RECEIVED => Ok(ack(transfer_id, RECEIVED)),
+                _ => Ok(rejected(transfer_id, "transfer was not shipped")),
+            }
+        }
+    })
+}
+
+// 处理对端对本节点消息的应答
+pub fn handle_response(
+    c: &mut SqliteConnection,
+    peer: &PeerId,
+    response: TransferResponse,
+) -> QueryResult<()> {
+    let now = Utc::now().naive_utc();
+    match response {
+        TransferResponse::Accepted { transfer_id } => {
+            diesel::update(
+                stock_transfers::table
+                    .filter(stock_transfers::transfer_id.eq(&transfer_id))
+                    .filter(stock_transfers::direction.eq(OUTBOUND))
+                    .filter(stock_transfers::peer_id.eq(peer.to_string()))
+                    .filter(stock_transfers::status.eq(REQUESTED))
+            )
+            .set((
+                stock_transfers::status.eq(ACCEPTED),
+                stock_transfers::acked.eq(true),
+                stock_transfers::updated_at.eq(now),
+            ))
+            .execute(c)?;
+        }
+        TransferResponse::Rejected { transfer_id, reason } => {
+            let existing = stock_transfers::table
+                .filter(stock_transfers::transfer_id.eq(&transfer_id))
+                .filter(stock_transfers::peer_id.eq(peer.to_string()))
+                .select(StockTransfer::as_select())
+                .first(c)
+                .optional()?;
+            let existing = match existing {
+                Some(existing) => existing,
+                None => return Ok(()),
+            };
+            // 发起请求被拒绝是正常结果；其他步骤被拒绝说明两端状态不一致，停止重试并留给人工处理
+            let status = if existing.direction == OUTBOUND && existing.status == REQUESTED {
+                REJECTED.to_string()
+            } else {
+                error!("Peer {} rejected transfer {} in status {}: {}", peer, transfer_id, existing.status, reason);
+                existing.status
+            };
+            diesel::update(stock_transfers::table.filter(stock_transfers::transfer_id.eq(&transfer_id)))
+                .set((
+                    stock_transfers::status.eq(status),
+                    stock_transfers::acked.eq(true),
+                    stock_transfers::rejection_reason.eq(reason),
+                    stock_transfers::updated_at.eq(now),
+                ))
+                .execute(c)?;
+        }
+        TransferResponse::Ack { transfer_id, status } => {
+            // 只确认发送时的状态，期间本地状态已变化的会在下次重试中发送
+            diesel::update(
+                stock_transfers::table
+                    .filter(stock_transfers::transfer_id.eq(&transfer_id))
+                    .filter(stock_transfers::peer_id.eq(peer.to_string()))
+                    .filter(stock_transfers::status.eq(status))
+            )
+            .set((
+                stock_transfers::acked.eq(true),
+                stock_transfers::updated_at.eq(now),
+            ))
+            .execute(c)?;
+        }
+    }
+    Ok(())
+}
+
+// 取出尚未得到对端确认、且已到重试时间的调拨，并记录本次尝试
+pub fn pending_requests(c: &mut SqliteConnection) -> QueryResult<Vec<(String, TransferRequest)>> {
+    let now = Utc::now().naive_utc();
+    let pending = stock_transfers::table
+        .filter(stock_transfers::acked.eq(false))
+        .select(StockTransfer::as_select())
+        .load(c)?;
+
+    let mut requests = Vec::new();
+    for transfer in pending {
+        if let Some(last_attempt_at) = transfer.last_attempt_at {
+            let backoff = (RETRY_BASE_SECS << transfer.attempts.clamp(0, 6)).min(RETRY_MAX_SECS);
+            if last_attempt_at + Duration::seconds(backoff) > now {
+                continue;
+            }
+        }
+
+        let transfer_id = transfer.transfer_id.clone();
+        let request = match (transfer.direction.as_str(), transfer.status.as_str()) {
+            (OUTBOUND, REQUESTED) => {
+                let material_global_id = materials::table
+                    .filter(materials::material_id.eq(transfer.material_id))
+                    .select(materials::global_id)
+                    .first::<Option<String>>(c)
+                    .optional()?
+                    .flatten();
+                match material_global_id {
+                    Some(material_global_id) => TransferRequest::Request {
+                        transfer_id,
+                        material_global_id,
+                        quantity: transfer.quantity,
+                        note: transfer.note,
+                    },
+                    None => {
+                        warn!("Transfer {} refers to a material without global_id", transfer.transfer_id);
+                        continue;
+                    }
+                }
+            }
+            (OUTBOUND, IN_TRANSIT) => TransferRequest::Shipped { transfer_id },
+            (OUTBOUND, CANCELLED) => TransferRequest::Cancelled { transfer_id },
+            (INBOUND, RECEIVED) => TransferRequest::Received { transfer_id },
+            _ => continue,
+        };
+
+        diesel::update(stock_transfers::table.filter(stock_transfers::transfer_id.eq(&transfer.transfer_id)))
+            .set((
+                stock_transfers::attempts.eq(transfer.attempts + 1),
+                stock_transfers::last_attempt_at.eq(now),
+            ))
+            .execute(c)?;
+        requests.push((transfer.peer_id, request));
+    }
+    Ok(requests)
+}
+
+// 发送待确认的调拨消息，由 run_swarm 定时调用
+pub fn send_pending(c: &mut SqliteConnection, behaviour: &mut TransferBehaviour) -> Result<(), Box<dyn std::error::Error>> {
+    for (peer_id, request) in pending_requests(c)? {
+        match peer_id.parse::<PeerId>() {
+            Ok(peer) => {
+                debug!("Sending {:?} to {}", request, peer);
+                behaviour.send_request(&peer, request);
+            }
+            Err(e) => error!("Invalid peer id {} on transfer: {}", peer_id, e),
+        }
+    }
+    Ok(())
+}
+
+// 处理请求-应答协议的事件
+pub fn handle_event(c: &mut SqliteConnection, behaviour: &mut TransferBehaviour, event: TransferEvent) -> Result<(), Box<dyn std::error::Error>> {
+    match event {
+        RequestResponseEvent::Message { peer, message } => {
+            // 连接已由 noise 认证 PeerId，这里只需检查信任列表
+            if !message_auth::is_trusted(c, &peer)? {
+                warn!("Ignoring transfer message from untrusted peer {}", peer);
+                return Ok(());
+            }
+            match message {
+                RequestResponseMessage::Request { request, channel, .. } => {
+                    // 处理失败时不应答，对端会在之后重试
+                    let response = handle_request(c, &peer, request)?;
+                    if behaviour.send_response(channel, response).is_err() {
+                        warn!("Failed to send transfer response to {}", peer);
+                    }
+                }
+                RequestResponseMessage::Response { response, .. } => {
+                    handle_response(c, &peer, response)?;
+                }
+            }
+        }
+        RequestResponseEvent::OutboundFailure { peer, error, .. } => {
+            warn!("Transfer request to {} failed, will retry: {:?}", peer, error);
+        }
+        RequestResponseEvent::InboundFailure { peer, error, .. } => {
+            warn!("Transfer request from {} failed: {:?}", peer, error);
+        }
+        RequestResponseEvent::ResponseSent { peer, .. } => {
+            debug!("Transfer response sent to {}", peer);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{db, replication};
+
+    fn node() -> SqliteConnection {
+        let mut c = db::memory_connection();
+        warehouse::generate_and_insert_new_local_key(&mut c);
+        let hlc = replication::next_hlc(&mut c).unwrap();
+        diesel::insert_into(materials::table)
+            .values((
+                materials::material_name.eq("steel"),
+                materials::global_id.eq("steel"),
+                materials::hlc.eq(hlc),
+            ))
+            .execute(&mut c)
+            .unwrap();
+        c
+    }
+
+    fn request(transfer_id: &str) -> TransferRequest {
+        TransferRequest::Request {
+            transfer_id: transfer_id.to_string(),
+            material_global_id: "steel".to_string(),
+            quantity: 5,
+            note: None,
+        }
+    }
+
+    #[test]
+    fn duplicate_request_reports_recorded_state() {
+        let mut c = node();
+        let peer = PeerId::random();
+
+        let response = handle_request(&mut c, &peer, request("t1")).unwrap();
+        assert!(matches!(response, TransferResponse::Accepted { .. }));
+        let response = handle_request(&mut c, &peer, request("t1")).unwrap();
+        assert!(matches!(response, TransferResponse::Accepted { .. }));
+
+        let cancelled = TransferRequest::Cancelled { transfer_id: "t1".to_string() };
+        handle_request(&mut c, &peer, cancelled).unwrap();
+        match handle_request(&mut c, &peer, request("t1")).unwrap() {
+            TransferResponse::Ack { status, .. } => assert_eq!(status, CANCELLED),
+            other => panic!("unexpected response {:?}", other),
+        }
+    }
+}
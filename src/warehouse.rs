@@ -7,7 +7,7 @@ use libp2p::PeerId;
 use log::info;
 use crate::models::{Warehouse, NewWarehouse};
 use base64::engine::general_purpose;
-use crate::schema::warehouses;
+use crate::schema::{node_key_history, warehouses};
 use diesel::RunQueryDsl;
 use diesel::prelude::*;
 
@@ -18,7 +18,6 @@ pub fn generate_and_insert_new_local_key(conn: &mut SqliteConnection) -> ed25519
         "Generated and inserted new key {:?} for warehouse ThisWarehouse",
         local_key_base64
     );
-    let local_peer_id = PeerId::from(PublicKey::Ed25519(local_key.public()));
     let new_warehouse = NewWarehouse {
         localkey: Some(local_key_base64),
         warehouse_name: "ThisWarehouse".to_string(),
@@ -55,4 +54,40 @@ pub fn get_warehouse_id(
     let keypair =
         ed25519::Keypair::decode(local_key_bytes.as_mut_slice()).expect("Keypair decode error");
     Ok(keypair)
-}
\ No newline at end of file
+}
+
+// 公钥的 base64 编码，操作日志签名和密钥历史中使用
+pub fn encode_public_key(public_key: &ed25519::PublicKey) -> String {
+    general_purpose::STANDARD.encode(public_key.encode())
+}
+
+// 为 ThisWarehouse 换一把新密钥，PeerId 随之改变，其他节点需重新信任。
+// 旧公钥记入 node_key_history，之前的操作日志签名仍可校验
+pub fn rotate_local_key(conn: &mut SqliteConnection) -> Result<ed25519::Keypair, diesel::result::Error> {
+    conn.transaction(|conn| {
+        let old_key = get_warehouse_id(conn)?;
+        diesel::insert_or_ignore_into(node_key_history::table)
+            .values(node_key_history::public_key.eq(encode_public_key(&old_key.public())))
+            .execute(conn)?;
+
+        use self::warehouses::dsl::*;
+        let local_key = ed25519::Keypair::generate();
+        diesel::update(warehouses.filter(warehouse_name.eq("ThisWarehouse")))
+            .set(localkey.eq(general_purpose::STANDARD.encode(local_key.encode())))
+            .execute(conn)?;
+        info!(
+            "Rotated key for warehouse ThisWarehouse, new peer id {}",
+            PeerId::from(PublicKey::Ed25519(local_key.public()))
+        );
+        Ok(local_key)
+    })
+}
+
+// 本节点仓库（ThisWarehouse）的 warehouse_id
+pub fn this_warehouse_id(conn: &mut SqliteConnection) -> Result<i32, diesel::result::Error> {
+    use self::warehouses::dsl::*;
+    warehouses
+        .filter(warehouse_name.eq("ThisWarehouse"))
+        .select(warehouse_id)
+        .first(conn)
+}